@@ -1,24 +1,148 @@
 use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
-use libcvetracker::dependency_analyzer::DependencyAnalyzer;
+use libcvetracker::dependency_analyzer::{CancellationToken, DependencyAnalyzer};
 use libcvetracker::logger;
+use libcvetracker::metadata::FileMetadataSource;
 use std::env;
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// the exit code of a second Ctrl-C, which does not wait for the cleanup
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Stop the analysis cooperatively on the first Ctrl-C: the BFS stops, the
+/// in-flight call-cg4rs processes are killed and the manifest is written as
+/// `interrupted`. A second Ctrl-C exits immediately.
+async fn interrupt_on_ctrl_c(token: CancellationToken) {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::warn!("Failed to listen for Ctrl-C: {}", e);
+        return;
+    }
+    tracing::warn!("Received Ctrl-C, stopping the analysis (press again to exit immediately)");
+    token.interrupt();
+    if tokio::signal::ctrl_c().await.is_ok() {
+        eprintln!("received a second Ctrl-C, exit immediately");
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv::dotenv().ok();
-    let args: Vec<String> = env::args().collect();
+    let all_args: Vec<String> = env::args().collect();
+    libcvetracker::config::init_from_args(&all_args)?;
+    tokio::runtime::Runtime::new()?.block_on(run(all_args))
+}
+
+async fn run(all_args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    // the stats run at the end, check INPUT_GLOB before the analysis
+    libcvetracker::stats::input_glob()?;
+    // positional arguments, options like `--since=YYYY-MM-DD` are parsed separately
+    let args: Vec<&String> = all_args.iter().filter(|s| !s.starts_with("--")).collect();
+    let since = all_args
+        .iter()
+        .find_map(|s| s.strip_prefix("--since="))
+        .map(|s| {
+            chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map_err(|e| format!("--since 需要 YYYY-MM-DD 格式的日期: {}", e))
+        })
+        .transpose()?;
+    let explain = all_args.iter().find_map(|s| s.strip_prefix("--explain="));
+    let estimate = all_args.iter().any(|s| s == "--estimate");
+    let include_self = all_args.iter().any(|s| s == "--include-self");
+    // `--list-dependents` prints CSV, `--list-dependents=<path>` writes it to a file
+    let list_dependents = all_args.iter().find_map(|s| {
+        s.strip_prefix("--list-dependents")
+            .filter(|rest| rest.is_empty() || rest.starts_with('='))
+            .map(|rest| rest.strip_prefix('='))
+    });
+    let max_depth = all_args
+        .iter()
+        .find_map(|s| s.strip_prefix("--max-depth="))
+        .map(|s| {
+            s.parse::<usize>()
+                .map_err(|e| format!("--max-depth 需要非负整数: {}", e))
+        })
+        .transpose()?;
+    let root_features: Vec<String> = all_args
+        .iter()
+        .find_map(|s| s.strip_prefix("--root-features="))
+        .map(|s| {
+            s.split(',')
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    // the analysis reads the versions and reverse dependencies from these files
+    // instead of the database, both are needed
+    let reverse_deps_file = all_args
+        .iter()
+        .find_map(|s| s.strip_prefix("--reverse-deps-file="));
+    let versions_file = all_args
+        .iter()
+        .find_map(|s| s.strip_prefix("--versions-file="));
+    let metadata_files = match (reverse_deps_file, versions_file) {
+        (Some(reverse_deps), Some(versions)) => Some((reverse_deps, versions)),
+        (None, None) => None,
+        _ => return Err("--reverse-deps-file 与 --versions-file 需要同时指定".into()),
+    };
+    let functions_file = all_args
+        .iter()
+        .find_map(|s| s.strip_prefix("--functions-file="))
+        .map(std::path::Path::new);
 
     let cve_id = args.get(1).map(|s| s.as_str()).unwrap_or("CVE-2025-31130");
     let crate_name = args.get(2).map(|s| s.as_str()).unwrap_or("gix-features");
     let version_range = args.get(3).map(|s| s.as_str()).unwrap_or("<0.41.0");
-    let target_function_paths = args.get(4).map(|s| s.as_str()).unwrap_or(
-        "gix_features::hash::Hasher::digest,gix_features::hash::Hasher::update,gix_features::hash::Write::flush,gix_features::hash::Write::new,gix_features::hash::Write::write,gix_features::hash::bytes,gix_features::hash::bytes_of_filegix_features::hash::bytes_with_hasher,gix_features::hash::hasher",
-    );
+    let target_function_paths = match (args.get(4), functions_file) {
+        (None, None) => "gix_features::hash::Hasher::digest,gix_features::hash::Hasher::update,gix_features::hash::Write::flush,gix_features::hash::Write::new,gix_features::hash::Write::write,gix_features::hash::bytes,gix_features::hash::bytes_of_filegix_features::hash::bytes_with_hasher,gix_features::hash::hasher".to_string(),
+        // the inline list and the file are merged
+        (inline, file) => libcvetracker::config::merge_function_paths(inline.map(|s| s.as_str()), file)?,
+    };
+    let target_function_paths = target_function_paths.as_str();
 
-    let log_dir = std::env::var("LOG_DIR").expect("LOG_DIR is not set");
+    let log_dir = libcvetracker::config::var("LOG_DIR").expect("LOG_DIR is not set");
     let _guard = logger::Logger::new(log_dir).log_init(cve_id);
 
-    tracing::info!("Start to run the dependency analyzer\ncve_id: {}\ncrate_name: {}\nversion_range: {}\ntarget_function_path: {}\n", cve_id, crate_name, version_range, target_function_paths);
+    if let Some(crate_name) = explain {
+        for step in libcvetracker::explain::explain(cve_id, crate_name).await? {
+            println!("{}", step);
+        }
+        return Ok(());
+    }
+
+    if estimate {
+        let estimate = libcvetracker::estimate::estimate_affected_count(
+            cve_id,
+            crate_name,
+            version_range,
+            // the estimate is unbounded otherwise
+            max_depth.unwrap_or(3),
+        )
+        .await?;
+        println!("{}", serde_json::to_string_pretty(&estimate)?);
+        return Ok(());
+    }
+
+    if let Some(output) = list_dependents {
+        let rows =
+            libcvetracker::dependents::list_dependents(crate_name, version_range, since).await?;
+        let csv = libcvetracker::dependents::to_csv(&rows)?;
+        match output {
+            Some(path) => {
+                std::fs::write(path, csv)?;
+                tracing::info!("{} dependent(s) written to {}", rows.len(), path);
+            }
+            None => print!("{}", csv),
+        }
+        return Ok(());
+    }
+
+    tracing::info!(
+        "Start to run the dependency analyzer\ncve_id: {}\ncrate_name: {}\nversion_range: {}\ntarget_function_path: {}\nsince: {:?}\n",
+        cve_id,
+        crate_name,
+        version_range,
+        target_function_paths,
+        since
+    );
 
     // spinner for overall progress (固定在终端底部，绘制到 stderr)
     let mp = MultiProgress::with_draw_target(ProgressDrawTarget::stderr_with_hz(10));
@@ -30,7 +154,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
     spinner.enable_steady_tick(std::time::Duration::from_millis(100));
     spinner.set_message("初始化分析器...");
-    let analyzer = DependencyAnalyzer::new(cve_id).await?;
+    let analyzer = match metadata_files {
+        Some((reverse_deps, versions)) => {
+            let source = FileMetadataSource::load(
+                std::path::Path::new(reverse_deps),
+                std::path::Path::new(versions),
+            )
+            .await?;
+            DependencyAnalyzer::with_metadata_source(cve_id, std::sync::Arc::new(source)).await?
+        }
+        None => DependencyAnalyzer::new(cve_id).await?,
+    };
+    let analyzer = analyzer
+        .with_since(since)
+        .with_root_features(root_features)
+        .with_max_depth(max_depth)
+        .with_include_self(include_self);
+    let cancellation = CancellationToken::new();
+    tokio::spawn(interrupt_on_ctrl_c(cancellation.clone()));
+    let analyzer = analyzer.with_cancellation(cancellation);
     spinner.set_message("开始依赖分析...");
     analyzer
         .analyze(crate_name, version_range, target_function_paths)
@@ -44,5 +186,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     spinner.finish_with_message("分析完成");
 
     tracing::info!("Dependency analyzer finished successfully");
+
+    // RETAIN_RESULTS_DAYS / RETAIN_RESULTS_COUNT, this run's results are the newest
+    if let Err(e) = libcvetracker::stats::prune_old_results().await {
+        tracing::warn!("Failed to prune old analysis results: {}", e);
+    }
+
+    // the last stdout line, for CI
+    let summary = libcvetracker::manifest::RunSummary::load(cve_id).await?;
+    println!("{}", summary);
+    let code = summary.exit_code();
+    if code != 0 {
+        tracing::error!(
+            "{} crate(s) failed, more than FAIL_IF_ERRORS_OVER, exiting with code {}",
+            summary.failed,
+            code
+        );
+        // flush the log files, `exit` runs no destructors
+        drop(_guard);
+        std::process::exit(code);
+    }
     Ok(())
 }
@@ -3,33 +3,39 @@ use std::env;
 use std::fs::File;
 use std::io::Read;
 
-#[derive(Debug, serde::Deserialize)]
+/// A task. With a header row, columns are matched by name, so their order is
+/// free and unknown columns (e.g. notes) are ignored; without one, they are
+/// read in this order.
+#[derive(Debug, PartialEq, serde::Deserialize)]
 struct Row {
     cve_id: String,
     crate_name: String,
     version_range: String,
+    #[serde(default)]
     target_function_paths: String,
+    /// optional file with one function path per line, merged with `target_function_paths`
+    #[serde(default)]
+    target_functions_file: Option<String>,
+    /// optional `--max-depth` of the analysis
+    #[serde(default)]
+    max_depth: Option<usize>,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv::dotenv().ok();
-    let (csv_path, has_header) = get_args()?;
+    libcvetracker::config::init_from_args(&env::args().collect::<Vec<_>>())?;
+    tokio::runtime::Runtime::new()?.block_on(run())
+}
 
-    let mut rdr_builder = csv::ReaderBuilder::new();
-    rdr_builder.has_headers(has_header);
+async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let (csv_path, has_header) = get_args()?;
 
     let mut file = File::open(csv_path)?;
     let mut content = String::new();
     file.read_to_string(&mut content)?;
 
-    let mut rdr = rdr_builder.from_reader(content.as_bytes());
-
     // 预读取以统计总行数
-    let mut rows: Vec<Row> = Vec::new();
-    for result in rdr.deserialize::<Row>() {
-        rows.push(result?);
-    }
+    let rows = read_rows(&content, has_header)?;
 
     let total_rows = rows.len() as u64;
     // 固定在终端底部绘制进度条
@@ -59,13 +65,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             row.cve_id, row.crate_name, row.version_range, row.target_function_paths
         );
 
-        let mut cmd = std::process::Command::new("cvetracker4rs")
-            .args(&[
-                &row.cve_id,
-                &row.crate_name,
-                &row.version_range,
-                &row.target_function_paths,
-            ])
+        let mut cmd = std::process::Command::new("cvetracker4rs");
+        cmd.args([&row.cve_id, &row.crate_name, &row.version_range]);
+        if !row.target_function_paths.is_empty() {
+            cmd.arg(&row.target_function_paths);
+        }
+        if let Some(file) = row
+            .target_functions_file
+            .as_deref()
+            .filter(|f| !f.is_empty())
+        {
+            cmd.arg(format!("--functions-file={}", file));
+        }
+        if let Some(max_depth) = row.max_depth {
+            cmd.arg(format!("--max-depth={}", max_depth));
+        }
+        // the spawned cvetracker4rs gets the values of the selected config file and registry
+        let mut cmd = cmd
+            .envs(libcvetracker::config::current().file_values_unset_in_env())
             .env("LOG_DIR", &log_dir)
             .spawn()?;
 
@@ -86,6 +103,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// the tasks of a CSV, by column name with `has_header`, otherwise by position
+fn read_rows(content: &str, has_header: bool) -> Result<Vec<Row>, csv::Error> {
+    csv::ReaderBuilder::new()
+        .has_headers(has_header)
+        .flexible(true)
+        .from_reader(content.as_bytes())
+        .deserialize::<Row>()
+        .collect()
+}
+
 fn get_args() -> Result<(String, bool), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     let csv_path = args
@@ -104,3 +131,41 @@ fn get_args() -> Result<(String, bool), Box<dyn std::error::Error>> {
         .unwrap_or(true);
     Ok((csv_path.to_string(), has_header))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_are_read_by_header_name() {
+        let expected = [
+            Row {
+                cve_id: "CVE-1".to_string(),
+                crate_name: "foo".to_string(),
+                version_range: "<1.2.0".to_string(),
+                target_function_paths: "foo::parse".to_string(),
+                target_functions_file: None,
+                max_depth: Some(2),
+            },
+            Row {
+                cve_id: "CVE-2".to_string(),
+                crate_name: "bar".to_string(),
+                version_range: "<0.3".to_string(),
+                target_function_paths: String::new(),
+                target_functions_file: Some("bar.txt".to_string()),
+                max_depth: None,
+            },
+        ];
+        // reordered, with an unknown column and the optional ones left empty
+        let reordered = "notes,version_range,target_functions_file,crate_name,max_depth,cve_id,target_function_paths\n\
+                         first,<1.2.0,,foo,2,CVE-1,foo::parse\n\
+                         second,<0.3,bar.txt,bar,,CVE-2,\n";
+        assert_eq!(read_rows(reordered, true).unwrap(), expected);
+
+        // without a header, the columns are read in the order of `Row`
+        let positional = "CVE-1,foo,<1.2.0,foo::parse,,2\nCVE-2,bar,<0.3,,bar.txt\n";
+        assert_eq!(read_rows(positional, false).unwrap(), expected);
+
+        assert!(read_rows("cve_id,crate_name\nCVE-1,foo\n", true).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use libcvetracker::manifest::{
+    AnalysisEnvironment, BfsTreeNode, CallerConfirmation, DownloadFailure, RunManifest,
+};
+use libcvetracker::stats::{
+    CallerRecord, FunctionDetail, GlobalStats, RunningStats, SubjectLocations,
+};
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Write the JSON Schema of the result files into `schemas/` (or the given directory),
+/// so tools in other languages can validate them and generate bindings.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("schemas"));
+    for path in write_schemas(&out_dir)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// the schemas by file name, `<name>.schema.json`
+fn schemas() -> [(&'static str, RootSchema); 10] {
+    [
+        ("stats", schema_for!(GlobalStats)),
+        ("running_stats", schema_for!(RunningStats)),
+        ("manifest", schema_for!(RunManifest)),
+        ("environment", schema_for!(AnalysisEnvironment)),
+        ("download_failures", schema_for!(Vec<DownloadFailure>)),
+        ("confirmations", schema_for!(Vec<CallerConfirmation>)),
+        ("callers", schema_for!(Vec<CallerRecord>)),
+        ("function_detail", schema_for!(FunctionDetail)),
+        ("bfs_tree", schema_for!(Vec<BfsTreeNode>)),
+        ("locations", schema_for!(Vec<SubjectLocations>)),
+    ]
+}
+
+/// write every schema into `out_dir` and return the written files
+fn write_schemas(out_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(out_dir)?;
+    let mut written = Vec::new();
+    for (name, schema) in schemas() {
+        let path = out_dir.join(format!("{}.schema.json", name));
+        std::fs::write(&path, serde_json::to_string_pretty(&schema)?)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn read_json(path: &Path) -> Value {
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn the_written_schemas_are_valid_and_accept_the_result_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let written = write_schemas(dir.path()).unwrap();
+        assert_eq!(written.len(), schemas().len());
+        for path in &written {
+            let schema = read_json(path);
+            assert!(
+                jsonschema::meta::is_valid(&schema),
+                "{} is not a valid JSON Schema",
+                path.display()
+            );
+            jsonschema::validator_for(&schema).unwrap();
+        }
+
+        // files written by the tool validate against their schema
+        let validator = |name: &str| {
+            jsonschema::validator_for(&read_json(
+                &dir.path().join(format!("{}.schema.json", name)),
+            ))
+            .unwrap()
+        };
+        let stats = read_json(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/stats/stats-FIXTURE-0001.json"),
+        );
+        assert!(validator("stats").is_valid(&stats));
+        let manifest = serde_json::to_value(RunManifest::new("CVE-0000-0000")).unwrap();
+        assert!(validator("manifest").is_valid(&manifest));
+        // and a file of another shape does not
+        assert!(!validator("stats").is_valid(&manifest));
+    }
+}
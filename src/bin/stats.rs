@@ -1,17 +1,73 @@
 use std::env;
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv::dotenv().ok();
+    let all_args: Vec<String> = env::args().collect();
+    libcvetracker::config::init_from_args(&all_args)?;
+    tokio::runtime::Runtime::new()?.block_on(run(all_args))
+}
 
-    let args: Vec<String> = env::args().collect();
+async fn run(all_args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    // a typo in INPUT_GLOB fails here, not after the logger is set up
+    libcvetracker::stats::input_glob()?;
+    let args: Vec<&String> = all_args.iter().filter(|s| !s.starts_with("--")).collect();
     let cve_id = args.get(1).map(|s| s.as_str()).unwrap_or("CVE-2025-31130");
+    let mut formats = match all_args
+        .iter()
+        .find_map(|s| s.strip_prefix("--output-format="))
+    {
+        Some(list) => libcvetracker::stats::OutputFormats::parse(list)?,
+        None => libcvetracker::stats::OutputFormats::default(),
+    };
+    // `--html` predates `--output-format` and adds the report to the selected formats
+    formats.html |= all_args.iter().any(|s| s == "--html");
+    let function = all_args.iter().find_map(|s| s.strip_prefix("--function="));
+    let sqlite = all_args.iter().find_map(|s| s.strip_prefix("--sqlite="));
+    let baseline = all_args
+        .iter()
+        .find_map(|s| s.strip_prefix("--verify-baseline="));
 
-    let log_dir = std::env::var("LOG_DIR").expect("LOG_DIR is not set");
+    let log_dir = libcvetracker::config::var("LOG_DIR").expect("LOG_DIR is not set");
     let _guard = libcvetracker::logger::Logger::new(log_dir).log_init(cve_id);
     tracing::info!("Running stats-only for {}", cve_id);
 
-    libcvetracker::stats::compute_and_write_stats(cve_id).await?;
+    // compare with a committed stats JSON instead of writing anything
+    if let Some(baseline) = baseline {
+        let mismatches =
+            libcvetracker::stats::verify_against_baseline(cve_id, std::path::Path::new(baseline))
+                .await?;
+        for mismatch in &mismatches {
+            println!("{}", mismatch);
+        }
+        if !mismatches.is_empty() {
+            return Err(format!(
+                "{} value(s) differ from the baseline {}",
+                mismatches.len(),
+                baseline
+            )
+            .into());
+        }
+        println!("stats of {} match the baseline {}", cve_id, baseline);
+        return Ok(());
+    }
+
+    libcvetracker::stats::compute_and_write_stats_as(cve_id, formats).await?;
+    if formats.html {
+        libcvetracker::export::html(cve_id).await?;
+    }
+    if let Some(function) = function {
+        libcvetracker::stats::write_function_detail(cve_id, function).await?;
+    }
+    if let Some(db_path) = sqlite {
+        #[cfg(feature = "sqlite")]
+        libcvetracker::export::sqlite(cve_id, std::path::Path::new(db_path)).await?;
+        #[cfg(not(feature = "sqlite"))]
+        return Err(format!(
+            "--sqlite={} requires building with `--features sqlite`",
+            db_path
+        )
+        .into());
+    }
 
     tracing::info!("Stats completed for {}", cve_id);
     Ok(())
@@ -1,9 +1,17 @@
+use crate::error::CveTrackerError;
+use crate::manifest::{CallerConfirmation, ConfirmationStatus};
+use crate::metrics::{COUNTERS, RunCounters};
 use crate::model::Krate;
+use crate::stats::CallerRecord;
 use anyhow::Result;
 
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
 use serde_json;
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::fs::{self as tokio_fs, read_dir};
 use tokio::process::Command;
@@ -31,16 +39,265 @@ impl Drop for DirGuard {
     }
 }
 
+/// one `callers-*.json` file produced by the function analysis tool
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallgraphFile {
+    pub file: String,
+    #[serde(rename = "file-content")]
+    pub file_content: serde_json::Value,
+}
+
+/// Drop callers that appear more than once for the same target function,
+/// e.g. when two target paths alias the same item and their `callers-*.json`
+/// overlap. Callers are keyed by `(target, path)`, the target falling back to
+/// the file name. Return the number of callers removed.
+pub(crate) fn dedup_callers(files: &mut [CallgraphFile]) -> usize {
+    let mut seen = HashSet::new();
+    let mut removed = 0;
+    for file in files.iter_mut() {
+        let target = file
+            .file_content
+            .get("target")
+            .and_then(|v| v.as_str())
+            .unwrap_or(&file.file)
+            .to_string();
+        if let Some(callers) = file
+            .file_content
+            .get_mut("callers")
+            .and_then(|v| v.as_array_mut())
+        {
+            let before = callers.len();
+            callers.retain(|caller| match caller.get("path").and_then(|v| v.as_str()) {
+                Some(path) => seen.insert((target.clone(), path.to_string())),
+                None => true,
+            });
+            removed += before - callers.len();
+        }
+    }
+    removed
+}
+
+/// Merge `new` into the callgraph files of an earlier write of the same
+/// result: the callers of a file already in `existing` are appended to it,
+/// other files are added, then the duplicate callers are dropped with
+/// [`dedup_callers`]. Return the number of callers `new` added.
+pub(crate) fn merge_callers(existing: &mut Vec<CallgraphFile>, new: Vec<CallgraphFile>) -> usize {
+    let count = |files: &[CallgraphFile]| {
+        files
+            .iter()
+            .filter_map(|f| f.file_content.get("callers").and_then(|v| v.as_array()))
+            .map(|callers| callers.len())
+            .sum::<usize>()
+    };
+    let before = count(existing);
+    for file in new {
+        let existing_callers = existing
+            .iter_mut()
+            .find(|f| f.file == file.file)
+            .and_then(|f| f.file_content.get_mut("callers"))
+            .and_then(|v| v.as_array_mut());
+        match existing_callers {
+            Some(callers) => {
+                if let Some(new_callers) =
+                    file.file_content.get("callers").and_then(|v| v.as_array())
+                {
+                    callers.extend(new_callers.iter().cloned());
+                }
+            }
+            None => existing.push(file),
+        }
+    }
+    dedup_callers(existing);
+    count(existing) - before
+}
+
+/// Rewrite the absolute paths under `root` embedded in the callers files, e.g.
+/// source locations, relative to `root`, so results do not reveal where the
+/// crate was unpacked and can be shared between machines. Both `root` as given
+/// and its canonical form are stripped. Return the number of strings rewritten.
+pub(crate) fn relativize_paths(files: &mut [CallgraphFile], root: &Path) -> usize {
+    let mut prefixes = vec![format!("{}/", root.display())];
+    if let Ok(canonical) = std::fs::canonicalize(root) {
+        prefixes.push(format!("{}/", canonical.display()));
+    }
+    // the longest prefix first, so a nested one is stripped whole
+    prefixes.sort_by_key(|p| std::cmp::Reverse(p.len()));
+    prefixes.dedup();
+
+    fn visit(value: &mut serde_json::Value, prefixes: &[String]) -> usize {
+        match value {
+            serde_json::Value::String(s) => {
+                let mut rewritten = false;
+                for prefix in prefixes {
+                    if s.contains(prefix.as_str()) {
+                        *s = s.replace(prefix.as_str(), "");
+                        rewritten = true;
+                    }
+                }
+                rewritten as usize
+            }
+            serde_json::Value::Array(values) => values.iter_mut().map(|v| visit(v, prefixes)).sum(),
+            serde_json::Value::Object(map) => map.values_mut().map(|v| visit(v, prefixes)).sum(),
+            _ => 0,
+        }
+    }
+    files
+        .iter_mut()
+        .map(|file| visit(&mut file.file_content, &prefixes))
+        .sum()
+}
+
+/// `call-cg4rs` failed because a build script of the crate or of one of its
+/// dependencies failed, typically because it needs the network, which the
+/// analysis environment does not have
+#[derive(Debug)]
+pub(crate) struct BuildScriptFailed {
+    /// the package whose build script failed, e.g. `openssl-sys v0.9.80`
+    pub package: Option<String>,
+}
+
+impl std::fmt::Display for BuildScriptFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.package {
+            Some(package) => write!(f, "the build script of {} failed", package),
+            None => write!(f, "a build script failed"),
+        }
+    }
+}
+
+impl std::error::Error for BuildScriptFailed {}
+
+/// Recognize a build script failure in the error output of `call-cg4rs`,
+/// i.e. cargo's `failed to run custom build command for `<package>``
+pub(crate) fn detect_build_script_failure(output: &str) -> Option<BuildScriptFailed> {
+    const MARKER: &str = "failed to run custom build command for";
+    let line = output.lines().find(|line| line.contains(MARKER))?;
+    let package = line
+        .split_once(MARKER)
+        .map(|(_, rest)| rest.trim().trim_matches('`').to_string())
+        .filter(|package| !package.is_empty());
+    Some(BuildScriptFailed { package })
+}
+
+/// The crate has neither a `src` directory nor a target path in its
+/// Cargo.toml that exists, so there is nothing to grep nor to build
+#[derive(Debug)]
+pub(crate) struct NoSourceFound;
+
+impl std::fmt::Display for NoSourceFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no source directory found")
+    }
+}
+
+impl std::error::Error for NoSourceFound {}
+
+/// `call-cg4rs` failed on the crate for another reason than a build script:
+/// it exited with an error after all retries, timed out, or reported success
+/// without writing its output dir, so whether the crate calls the target
+/// functions is unknown
+#[derive(Debug)]
+pub(crate) struct AnalyzerFailed {
+    pub error: String,
+}
+
+impl std::fmt::Display for AnalyzerFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "call-cg4rs failed: {}", self.error)
+    }
+}
+
+impl std::error::Error for AnalyzerFailed {}
+
+/// A tool that finds the callers of the target functions in a crate.
+/// `function_paths` is the comma-separated list of target function paths.
+/// Return `Ok(None)` when the crate should be treated as not vulnerable,
+/// e.g. it does not contain the target functions or has no callers of them,
+/// a [`BuildScriptFailed`] error when it could not be built because of a build script,
+/// a [`NoSourceFound`] error when it has no sources, and an [`AnalyzerFailed`]
+/// error when the tool failed on it otherwise.
+pub trait CallerFinder: std::fmt::Debug + Send + Sync {
+    fn find_callers<'a>(
+        &'a self,
+        krate: &'a Krate,
+        function_paths: &'a str,
+        logs_dir: &'a Path,
+    ) -> BoxFuture<'a, Result<Option<Vec<CallgraphFile>>>>;
+}
+
+/// the default caller finder, which runs `call-cg4rs`
+#[derive(Debug, Default, Clone)]
+pub struct CallCg4rsFinder;
+
+impl CallerFinder for CallCg4rsFinder {
+    fn find_callers<'a>(
+        &'a self,
+        krate: &'a Krate,
+        function_paths: &'a str,
+        logs_dir: &'a Path,
+    ) -> BoxFuture<'a, Result<Option<Vec<CallgraphFile>>>> {
+        // the untyped error, so the analyzer can downcast the skip reasons
+        Box::pin(find_callers_with_cg4rs(krate, function_paths, logs_dir))
+    }
+}
+
+/// A caller finder returning canned results keyed by `<name>-<version>`,
+/// so the BFS can be exercised without building anything with `call-cg4rs`.
+/// Crates without a canned result are reported as not vulnerable.
+#[derive(Debug, Default, Clone)]
+pub struct MockCallerFinder {
+    results: HashMap<String, Vec<CallgraphFile>>,
+}
+
+impl MockCallerFinder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_result(mut self, name: &str, version: &str, files: Vec<CallgraphFile>) -> Self {
+        self.results.insert(format!("{}-{}", name, version), files);
+        self
+    }
+}
+
+impl CallerFinder for MockCallerFinder {
+    fn find_callers<'a>(
+        &'a self,
+        krate: &'a Krate,
+        _function_paths: &'a str,
+        _logs_dir: &'a Path,
+    ) -> BoxFuture<'a, Result<Option<Vec<CallgraphFile>>>> {
+        let key = format!("{}-{}", krate.name, krate.version);
+        let result = self.results.get(&key).cloned();
+        Box::pin(async move { Ok(result) })
+    }
+}
+
+/// Run `call-cg4rs` on `krate` to find the callers of the comma-separated
+/// `function_paths`, see [`CallerFinder`]. Every failure is a
+/// [`CveTrackerError::Analyzer`].
+pub async fn run_function_analysis(
+    krate: &Krate,
+    function_paths: &str,
+    logs_dir: &Path,
+) -> Result<Option<Vec<CallgraphFile>>, CveTrackerError> {
+    find_callers_with_cg4rs(krate, function_paths, logs_dir)
+        .await
+        .map_err(CveTrackerError::Analyzer)
+}
+
 // run function analysis tool
-pub(crate) async fn run_function_analysis(
+async fn find_callers_with_cg4rs(
     krate: &Krate,
     function_paths: &str,
-    logs_dir: &PathBuf,
-) -> Result<Option<String>> {
+    logs_dir: &Path,
+) -> Result<Option<Vec<CallgraphFile>>> {
     let crate_dir = krate.get_working_src_code_dir().await;
     let cargo_toml_path = krate.get_cargo_toml_path().await;
     let target_dir = krate.get_target_dir().await;
-    let src_dir = krate.get_src_dir().await;
+    let Some(src_dir) = krate.find_src_dir().await else {
+        return Err(NoSourceFound.into());
+    };
 
     tracing::debug!("Run function analysis tool for {}", crate_dir.display());
     // use directory guard to switch and restore directory
@@ -48,16 +305,17 @@ pub(crate) async fn run_function_analysis(
         .map_err(|e| anyhow::anyhow!(e))
         .unwrap();
 
-    // check if the src directory contains the target function by grep
-
-    if !check_src_contain_target_function(&src_dir.to_string_lossy(), function_paths).await? {
-        tracing::info!(
-            "Skip the function analysis, because {} does not contain the target function {}",
-            src_dir.display(),
-            function_paths
-        );
+    let Some(function_paths) = functions_to_analyze(
+        krate,
+        &src_dir,
+        function_paths,
+        skip_grep_precheck_enabled(),
+    )
+    .await?
+    else {
         return Ok(None);
-    }
+    };
+    let function_paths = function_paths.as_str();
 
     tracing::info!(
         "detect target function: {} in {}",
@@ -65,69 +323,133 @@ pub(crate) async fn run_function_analysis(
         src_dir.display()
     );
 
-    let callgraph4rs_log_flag = std::env::var("CG_RUST_LOG").unwrap_or("info".to_string());
-    let (log_file, error_output_file) = crate::logger::create_log_file(&logs_dir, krate)
+    // cargo applies the edition of each crate itself, it is logged so that
+    // failures can be correlated with old editions
+    let edition = match crate::utils::crate_edition(&cargo_toml_path).await {
+        Ok(edition) => edition,
+        Err(e) => {
+            warn!("failed to detect the edition of {}: {}", krate.name, e);
+            "unknown".to_string()
+        }
+    };
+    tracing::info!("[{}:{}] edition {}", krate.name, krate.version, edition);
+
+    let callgraph4rs_log_flag = crate::config::var("CG_RUST_LOG").unwrap_or_default();
+    let (log_file, error_output_file) = crate::logger::create_log_file(logs_dir, krate)
         .await
         .unwrap();
 
-    let mut child = Command::new("call-cg4rs")
-        .env("RUST_LOG", &callgraph4rs_log_flag)
-        .env("RUST_BACKTRACE", "1")
-        .args([
-            "--find-callers",
+    // a non-zero exit is often transient (lock contention, disk pressure, ICE),
+    // so it is retried on a clean target dir; timeouts are not retried
+    let retries = analyzer_retries();
+    let mut attempt = 0;
+    loop {
+        RunCounters::incr(&COUNTERS.analyzer_invocations);
+        let mut cmd = cg4rs_command(
             function_paths,
-            "--json-output",
-            "--manifest-path",
-            &cargo_toml_path.to_string_lossy(),
-            "--output-dir",
-            &target_dir.to_string_lossy(),
-        ])
-        .stdout(log_file)
-        .stderr(error_output_file)
-        .spawn()
-        .unwrap();
+            &cargo_toml_path,
+            &target_dir,
+            &callgraph4rs_log_flag,
+            crate::utils::rust_toolchain().as_deref(),
+        );
+        // all attempts append to the same log files
+        let mut child = cmd
+            .stdout(log_file.try_clone()?)
+            .stderr(error_output_file.try_clone()?)
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
 
-    let exit = tokio::select! {
-        exit = child.wait() => {
-            exit.map_err(|e| anyhow::anyhow!(e))
-        }
-        _ = sleep(Duration::from_secs(240)) => {
-            warn!("call-cg4rs analysis timeout (4 minutes), will shutdown");
-            Err(anyhow::anyhow!("call-cg4rs analysis timeout (4 minutes), process terminated"))
-        }
-    };
+        let exit = tokio::select! {
+            exit = child.wait() => {
+                exit.map_err(|e| anyhow::anyhow!(e))
+            }
+            _ = sleep(Duration::from_secs(240)) => {
+                warn!("call-cg4rs analysis timeout (4 minutes), will shutdown");
+                RunCounters::incr(&COUNTERS.analyzer_timeouts);
+                Err(anyhow::anyhow!("call-cg4rs analysis timeout (4 minutes), process terminated"))
+            }
+        };
 
-    match exit {
-        Ok(exit) => {
-            if !exit.success() {
+        match exit {
+            Ok(exit) if exit.success() => break,
+            Ok(exit) if attempt < retries => {
+                attempt += 1;
+                RunCounters::incr(&COUNTERS.analyzer_retries);
+                warn!(
+                    "call-cg4rs failed for {}: {:?}, retrying ({}/{})",
+                    krate.name, exit, attempt, retries
+                );
+                if let Err(e) = krate.cargo_clean().await {
+                    warn!("failed to clean {} before retrying: {}", krate.name, e);
+                }
+            }
+            Ok(exit) => {
+                RunCounters::incr(&COUNTERS.analyzer_failures);
+                warn!(
+                    "call-cg4rs failed for {} (edition {}) after {} attempt(s): {:?}, check logs in logs directory",
+                    krate.name,
+                    edition,
+                    attempt + 1,
+                    exit
+                );
+                // a build script failure is a false negative, not a clean crate
+                let error_output =
+                    tokio_fs::read_to_string(crate::logger::error_log_path(logs_dir, krate))
+                        .await
+                        .unwrap_or_default();
+                if let Some(failure) = detect_build_script_failure(&error_output) {
+                    RunCounters::incr(&COUNTERS.analyzer_build_script_failures);
+                    return Err(failure.into());
+                }
+                return Err(AnalyzerFailed {
+                    error: format!("{} after {} attempt(s)", exit, attempt + 1),
+                }
+                .into());
+            }
+            Err(e) => {
                 warn!(
                     "call-cg4rs failed for {}: {:?}, check logs in logs directory",
-                    krate.name, exit
+                    krate.name, e
                 );
-                return Ok(None);
+                return Err(AnalyzerFailed {
+                    error: e.to_string(),
+                }
+                .into());
             }
         }
-        Err(e) => {
-            warn!(
-                "call-cg4rs failed for {}: {:?}, check logs in logs directory",
-                krate.name, e
-            );
-            return Ok(None);
-        }
     }
 
+    collect_callers_files(krate, &crate_dir, &target_dir).await
+}
+
+/// The `callers-*.json` files a successful `call-cg4rs` run wrote into
+/// `target_dir`, `None` if it found no callers
+async fn collect_callers_files(
+    krate: &Krate,
+    crate_dir: &Path,
+    target_dir: &Path,
+) -> Result<Option<Vec<CallgraphFile>>> {
     // Find caller-*.json files
-    // If the target directory does not exist, it's ok
-    // because the call-cg4rs may have skipped analyzing it
-    let mut dir = match tokio_fs::read_dir(&target_dir).await {
+    // call-cg4rs exited successfully here, so it built the crate into the
+    // output dir: a missing one means the run did not do what it reported
+    let mut dir = match tokio_fs::read_dir(target_dir).await {
         Ok(dir) => dir,
         Err(e) => {
-            if read_dir(&crate_dir).await.is_err() {
+            if read_dir(crate_dir).await.is_err() {
                 warn!("{}: crate {} does not exist", e, crate_dir.display());
                 return Ok(None);
             }
-            warn!("{}: target dir{} does not exist", e, target_dir.display());
-            return Ok(None);
+            warn!(
+                "{}: call-cg4rs succeeded for {} but wrote no output dir {}, check logs in logs directory",
+                e,
+                krate.name,
+                target_dir.display()
+            );
+            return Err(AnalyzerFailed {
+                error: format!("no output dir {}", target_dir.display()),
+            }
+            .into());
         }
     };
     let mut files_vec = Vec::new();
@@ -143,36 +465,501 @@ pub(crate) async fn run_function_analysis(
                     .expect(&format!("Failed to read file: {}", path.display()));
                 let content_json: serde_json::Value =
                     serde_json::from_str(&content).unwrap_or(serde_json::Value::String(content));
-                let json_obj = serde_json::json!({
-                    "file": fname,
-                    "file-content": content_json
+                files_vec.push(CallgraphFile {
+                    file: fname.to_string(),
+                    file_content: content_json,
                 });
-                files_vec.push(json_obj);
             }
         }
     }
+    // the common case of a crate without callers, aggregated per BFS level
     if files_vec.is_empty() {
-        warn!(
+        tracing::debug!(
             "caller(s)-*.json file not found in {}, skip the crate",
             target_dir.display()
         );
+        RunCounters::incr(&COUNTERS.analyzer_no_output);
         return Ok(None);
     }
-    let callers_content = serde_json::to_string_pretty(&files_vec)?;
-    Ok(Some(callers_content))
+    Ok(Some(files_vec))
+}
+
+/// How many times a failed `call-cg4rs` run is retried, configured by
+/// `ANALYZER_RETRIES` (default 1)
+fn analyzer_retries() -> u32 {
+    crate::config::var("ANALYZER_RETRIES")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(1)
 }
 
+/// whether every dependent is sent to the analyzer even if grep finds no
+/// target function in it, controlled by `SKIP_GREP_PRECHECK=1`. Slower, but
+/// does not miss calls the grep cannot see, e.g. macro-generated ones.
+pub(crate) fn skip_grep_precheck_enabled() -> bool {
+    crate::config::var("SKIP_GREP_PRECHECK")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// the number of top callers re-checked after the BFS, configured by
+/// `CONFIRM_TOP_N` (unset or 0: no confirmation pass)
+pub(crate) fn confirm_top_n() -> Option<usize> {
+    crate::config::var("CONFIRM_TOP_N")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+}
+
+/// The confirmation pass: re-run `finder` on the crates of the `n` top
+/// callers, ranked by path constraints then package hops as in the function
+/// details, and annotate each caller. `krates` maps the subjects to the crates
+/// whose working trees are still there, the callers of other subjects are
+/// `unknown`. The finder runs once per subject.
+pub(crate) async fn confirm_callers(
+    finder: &dyn CallerFinder,
+    mut callers: Vec<CallerRecord>,
+    n: usize,
+    krates: &HashMap<String, Krate>,
+    function_paths: &str,
+    logs_dir: &Path,
+) -> Vec<CallerConfirmation> {
+    callers.sort_by(|a, b| {
+        b.path_constraints
+            .cmp(&a.path_constraints)
+            .then(b.path_package_num.cmp(&a.path_package_num))
+    });
+    callers.truncate(n);
+    // (function, caller path) found again per subject, `None` when the finder failed
+    let mut found: HashMap<String, Option<HashSet<(String, String)>>> = HashMap::new();
+    let mut confirmations = Vec::with_capacity(callers.len());
+    for caller in callers {
+        if !found.contains_key(&caller.subject) {
+            let result = match krates.get(&caller.subject) {
+                Some(krate) => match finder.find_callers(krate, function_paths, logs_dir).await {
+                    Ok(files) => Some(
+                        files
+                            .into_iter()
+                            .flatten()
+                            .flat_map(|file| {
+                                let function = serde_json::to_value(&file)
+                                    .map(|v| crate::stats::function_of_file(&v))
+                                    .unwrap_or_default();
+                                file.file_content
+                                    .get("callers")
+                                    .and_then(|v| v.as_array())
+                                    .into_iter()
+                                    .flatten()
+                                    .filter_map(|c| c.get("path").and_then(|v| v.as_str()))
+                                    .map(|path| (function.clone(), path.to_string()))
+                                    .collect::<Vec<_>>()
+                            })
+                            .collect(),
+                    ),
+                    Err(e) => {
+                        warn!("failed to confirm the callers of {}: {}", caller.subject, e);
+                        None
+                    }
+                },
+                None => None,
+            };
+            found.insert(caller.subject.clone(), result);
+        }
+        let status = match &found[&caller.subject] {
+            Some(paths)
+                if paths.contains(&(caller.function.clone(), caller.caller_path.clone())) =>
+            {
+                ConfirmationStatus::Confirmed
+            }
+            Some(_) => ConfirmationStatus::Unconfirmed,
+            None => ConfirmationStatus::Unknown,
+        };
+        confirmations.push(CallerConfirmation {
+            subject: caller.subject,
+            function: caller.function,
+            caller_path: caller.caller_path,
+            path_constraints: caller.path_constraints,
+            status,
+        });
+    }
+    confirmations
+}
+
+/// whether `pub use` re-exports of the target functions are followed into
+/// the dependents of the re-exporting crate, controlled by `FOLLOW_REEXPORTS=1`
+pub(crate) fn follow_reexports_enabled() -> bool {
+    crate::config::var("FOLLOW_REEXPORTS")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// Find the `pub use` re-exports of the target functions (or of a module or
+/// glob containing them) in the sources of `krate`, and return the new paths
+/// the functions are reachable under, e.g. `pub use vuln::hash::bytes as digest;`
+/// in `src/lib.rs` of `foo` gives `foo::digest` for the target `vuln::hash::bytes`.
+///
+/// This is a source-level heuristic: a re-export in a private module is
+/// reported too, and macros or `#[path]` modules are not seen.
+pub(crate) async fn find_reexported_targets(krate: &Krate, target_paths: &[String]) -> Vec<String> {
+    let own = krate.name.replace('-', "_");
+    let src_dir = krate.get_src_dir().await;
+    let mut found = Vec::new();
+    let mut files = std::pin::pin!(krate.source_files());
+    while let Some(path) = files.next().await {
+        // a source file with invalid UTF-8 is still scanned
+        let Ok(source) = tokio_fs::read(&path).await else {
+            continue;
+        };
+        let source = String::from_utf8_lossy(&source);
+        let module = module_path_of(&src_dir, &path);
+        let exported_in = std::iter::once(own.as_str())
+            .chain(module.iter().map(|s| s.as_str()))
+            .collect::<Vec<_>>()
+            .join("::");
+        for (used, name) in parse_uses(&source, true) {
+            for used in resolve_use_path(&used, &own, &module) {
+                for target in target_paths {
+                    let exported = if name == "*" {
+                        target
+                            .strip_prefix(&format!("{}::", used))
+                            .map(|rest| format!("{}::{}", exported_in, rest))
+                    } else if *target == used {
+                        Some(format!("{}::{}", exported_in, name))
+                    } else {
+                        target
+                            .strip_prefix(&format!("{}::", used))
+                            .map(|rest| format!("{}::{}::{}", exported_in, name, rest))
+                    };
+                    if let Some(exported) = exported
+                        && !target_paths.contains(&exported)
+                        && !found.contains(&exported)
+                    {
+                        found.push(exported);
+                    }
+                }
+            }
+        }
+    }
+    found
+}
+
+/// The `(alias, target)` pairs of the target functions imported under another
+/// name with `use <target> as <alias>;` in the sources of `krate`, e.g.
+/// `hash_bytes` for `use vuln::hash::bytes as hash_bytes;`. The grep pre-check
+/// looks for them in addition to the last segments of the targets.
+pub(crate) async fn find_target_aliases(
+    krate: &Krate,
+    target_paths: &[String],
+) -> Vec<(String, String)> {
+    let own = krate.name.replace('-', "_");
+    let src_dir = krate.get_src_dir().await;
+    let mut aliases = Vec::new();
+    let mut files = std::pin::pin!(krate.source_files());
+    while let Some(path) = files.next().await {
+        // a source file with invalid UTF-8 is still scanned
+        let Ok(source) = tokio_fs::read(&path).await else {
+            continue;
+        };
+        let source = String::from_utf8_lossy(&source);
+        let module = module_path_of(&src_dir, &path);
+        for (used, name) in parse_uses(&source, false) {
+            // not renamed
+            if name == "*" || used.rsplit("::").next() == Some(name.as_str()) {
+                continue;
+            }
+            let Some(target) = resolve_use_path(&used, &own, &module)
+                .into_iter()
+                .find(|used| target_paths.contains(used))
+            else {
+                continue;
+            };
+            if !aliases.contains(&(name.clone(), target.clone())) {
+                aliases.push((name, target));
+            }
+        }
+    }
+    aliases
+}
+
+/// the module path of a source file relative to `src`, e.g. `src/a/b.rs` and
+/// `src/a/b/mod.rs` are `["a", "b"]`, `src/lib.rs` is the crate root
+fn module_path_of(src_dir: &Path, file: &Path) -> Vec<String> {
+    let Ok(relative) = file.strip_prefix(src_dir) else {
+        return Vec::new();
+    };
+    let mut module: Vec<String> = relative
+        .with_extension("")
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    if module.last().is_some_and(|m| m == "mod") {
+        module.pop();
+    }
+    if module.len() == 1 && (module[0] == "lib" || module[0] == "main") {
+        module.clear();
+    }
+    module
+}
+
+/// The `(path, visible name)` pairs of the `use` items in `source`, with
+/// `{...}` groups expanded; the name is `*` for globs. With `pub_only`, only
+/// `pub use` re-exports are returned: `pub(crate) use` is not one. `as _`
+/// imports are skipped.
+fn parse_uses(source: &str, pub_only: bool) -> Vec<(String, String)> {
+    let code: String = source
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let keyword = if pub_only { "pub use " } else { "use " };
+    let mut uses = Vec::new();
+    let mut rest = code.as_str();
+    while let Some(start) = rest.find(keyword) {
+        let is_item = start == 0
+            || rest[..start].chars().last().is_some_and(|c| {
+                c.is_whitespace() || c == ';' || c == '}' || c == ']' || (!pub_only && c == ')')
+            });
+        rest = &rest[start + keyword.len()..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        if is_item {
+            let tree: String = rest[..end].split_whitespace().collect::<Vec<_>>().join(" ");
+            expand_use_tree("", &tree, &mut uses);
+        }
+        rest = &rest[end..];
+    }
+    uses
+}
+
+fn expand_use_tree(prefix: &str, tree: &str, out: &mut Vec<(String, String)>) {
+    let tree = tree.trim();
+    if let Some(open) = tree.find('{') {
+        let Some(close) = tree.rfind('}') else {
+            return;
+        };
+        let head = format!("{}{}", prefix, tree[..open].replace(' ', ""));
+        let inner = &tree[open + 1..close];
+        let mut depth = 0;
+        let mut item_start = 0;
+        for (i, c) in inner.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    expand_use_tree(&head, &inner[item_start..i], out);
+                    item_start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        expand_use_tree(&head, &inner[item_start..], out);
+        return;
+    }
+    if tree.is_empty() {
+        return;
+    }
+    let (path, alias) = match tree.split_once(" as ") {
+        Some((path, alias)) => (path.trim(), Some(alias.trim())),
+        None => (tree, None),
+    };
+    let path = if path == "self" {
+        prefix.trim_end_matches("::").to_string()
+    } else {
+        format!("{}{}", prefix, path.replace(' ', ""))
+    };
+    let name = alias
+        .map(|a| a.to_string())
+        .unwrap_or_else(|| path.rsplit("::").next().unwrap_or("").to_string());
+    if name == "_" || path.is_empty() {
+        return;
+    }
+    // a glob keeps the path of the module it imports from
+    let path = path
+        .strip_suffix("::*")
+        .map(|p| p.to_string())
+        .unwrap_or(path);
+    out.push((path, name));
+}
+
+/// The absolute paths a `use` path may refer to from `module` of crate `own`:
+/// `crate::`/`self::`/`super::` are resolved, other paths are either an
+/// external crate or relative to the module, so both are returned.
+fn resolve_use_path(used: &str, own: &str, module: &[String]) -> Vec<String> {
+    let used = used.trim_start_matches("::");
+    let mut base: Vec<&str> = module.iter().map(|s| s.as_str()).collect();
+    let mut rest = used;
+    let mut anchored = false;
+    loop {
+        if let Some(r) = rest.strip_prefix("crate::") {
+            base.clear();
+            rest = r;
+            anchored = true;
+        } else if let Some(r) = rest.strip_prefix("self::") {
+            rest = r;
+            anchored = true;
+        } else if let Some(r) = rest.strip_prefix("super::") {
+            base.pop();
+            rest = r;
+            anchored = true;
+        } else {
+            break;
+        }
+    }
+    let in_crate = std::iter::once(own)
+        .chain(base)
+        .chain(std::iter::once(rest))
+        .collect::<Vec<_>>()
+        .join("::");
+    if anchored {
+        vec![in_crate]
+    } else {
+        vec![rest.to_string(), in_crate]
+    }
+}
+
+/// The `call-cg4rs` invocation finding the callers of `function_paths` in the
+/// package of `cargo_toml_path`, on `toolchain` if set
+fn cg4rs_command(
+    function_paths: &str,
+    cargo_toml_path: &Path,
+    target_dir: &Path,
+    log_flag: &str,
+    toolchain: Option<&str>,
+) -> Command {
+    let mut cmd = Command::new("call-cg4rs");
+    // pin the compiler, overriding any rust-toolchain file of the crate
+    if let Some(toolchain) = toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    cmd.env("RUST_LOG", log_flag)
+        .env("RUST_BACKTRACE", "1")
+        .args([
+            "--find-callers",
+            function_paths,
+            "--json-output",
+            "--manifest-path",
+            &cargo_toml_path.to_string_lossy(),
+            "--output-dir",
+            &target_dir.to_string_lossy(),
+        ]);
+    // the lockfile was generated right after patching, build strictly against it
+    if crate::utils::lock_deps_enabled() {
+        cmd.arg("--locked");
+    }
+    if crate::utils::offline_build_enabled() {
+        cmd.arg("--offline").env("CARGO_NET_OFFLINE", "true");
+    }
+    cmd
+}
+
+/// The comma-separated target functions `call-cg4rs` looks for in `krate`: the
+/// ones the grep pre-check finds in `src_dir`, only those are analyzed, or all
+/// of them with `skip_precheck`, a heuristic that completeness audits can bypass.
+/// `None` if the pre-check finds none.
+async fn functions_to_analyze(
+    krate: &Krate,
+    src_dir: &Path,
+    function_paths: &str,
+    skip_precheck: bool,
+) -> Result<Option<String>> {
+    if skip_precheck {
+        tracing::debug!(
+            "SKIP_GREP_PRECHECK=1, analyze {} without the grep pre-check",
+            src_dir.display()
+        );
+        return Ok(Some(function_paths.to_string()));
+    }
+    let found = targets_found_in_src(krate, src_dir, function_paths).await?;
+    if found.is_empty() {
+        tracing::info!(
+            "Skip the function analysis, because {} does not contain the target function {}",
+            src_dir.display(),
+            function_paths
+        );
+        return Ok(None);
+    }
+    Ok(Some(found.join(",")))
+}
+
+/// The target functions of the comma-separated `function_paths` that the grep
+/// pre-check finds in `src_dir`, in the order of the list, either by their last
+/// segment or by a name they are imported under in `krate`. call-cg4rs resolves
+/// an aliased call to the original function, so only the grep needs the aliases.
+async fn targets_found_in_src(
+    krate: &Krate,
+    src_dir: &Path,
+    function_paths: &str,
+) -> Result<Vec<String>> {
+    let targets: Vec<String> = function_paths
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    let aliases = find_target_aliases(krate, &targets).await;
+    if !aliases.is_empty() {
+        tracing::debug!(
+            "[{}:{}] the target functions are imported as: {}",
+            krate.name,
+            krate.version,
+            aliases
+                .iter()
+                .map(|(alias, _)| alias.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let patterns = targets
+        .iter()
+        .chain(aliases.iter().map(|(alias, _)| alias))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(",");
+    let found = check_src_contain_target_function(&src_dir.to_string_lossy(), &patterns).await?;
+    Ok(targets
+        .into_iter()
+        .filter(|target| {
+            found.contains(target)
+                || aliases
+                    .iter()
+                    .any(|(alias, aliased)| aliased == target && found.contains(alias))
+        })
+        .collect())
+}
+
+/// The comma-separated target functions that `src` mentions, by their last
+/// segment. The per-function greps run concurrently (bounded by
+/// `MAX_CONCURRENT_GREP`, default 4), every one of them runs so that the whole
+/// subset is found.
 pub(crate) async fn check_src_contain_target_function(
     src: &str,
     target_function_paths: &str,
-) -> Result<bool> {
-    for path in target_function_paths.split(',') {
-        let path = path.trim();
-        if path.is_empty() {
-            continue;
-        }
-        match check_src_contain_target_function_single(src, path).await {
-            Ok(true) => return Ok(true),
+) -> Result<HashSet<String>> {
+    let paths: Vec<String> = target_function_paths
+        .split(',')
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+        .collect();
+    let mut greps = stream::iter(paths)
+        .map(|path| async move {
+            let result = check_src_contain_target_function_single(src, &path).await;
+            (path, result)
+        })
+        .buffer_unordered(
+            crate::config::var("MAX_CONCURRENT_GREP")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(4)
+                .max(1),
+        );
+
+    let mut found = HashSet::new();
+    // dropping the stream on an error kills the greps still running
+    while let Some((path, result)) = greps.next().await {
+        match result {
+            Ok(true) => {
+                found.insert(path);
+            }
             Ok(false) => continue,
             Err(e) => {
                 warn!(
@@ -183,7 +970,7 @@ pub(crate) async fn check_src_contain_target_function(
             }
         }
     }
-    Ok(false)
+    Ok(found)
 }
 
 async fn check_src_contain_target_function_single(
@@ -192,27 +979,432 @@ async fn check_src_contain_target_function_single(
 ) -> Result<bool> {
     let function_name = target_function_path.split("::").last().unwrap();
 
+    // `-I` skips binary files, e.g. blobs or non-UTF8 fixtures under src
     let args: Vec<String> = vec![
         "-r".to_string(),
         "-n".to_string(),
+        "-I".to_string(),
         "--color=always".to_string(),
         function_name.to_string(),
         src.to_owned(),
     ];
     let mut grep_cmd = Command::new("grep");
-    grep_cmd.args(args);
+    grep_cmd.args(args).kill_on_drop(true);
     let output = grep_cmd.output().await?;
     let status = output.status;
-    if !status.success() {
-        if output.stdout.is_empty() && status.code() == Some(1) {
-            return Ok(false);
-        } else {
-            return Err(anyhow::anyhow!(
-                "search process error in {}, exit code: {:?}",
+    match status.code() {
+        Some(0) => Ok(true),
+        Some(1) if output.stdout.is_empty() => Ok(false),
+        // some files could not be read (permissions, dangling symlinks, encoding);
+        // the readable ones were still searched, so it does not fail the crate
+        Some(2) => {
+            warn!(
+                "grep could not read some files in {}: {}",
                 src,
-                status.code()
-            ));
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            Ok(!output.stdout.is_empty())
+        }
+        _ => Err(anyhow::anyhow!(
+            "search process error in {}, exit code: {:?}",
+            src,
+            status.code()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reexports_of_the_targets_are_found_under_their_new_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::test_support::write_files(
+            dir.path(),
+            &[
+                (
+                    "src/lib.rs",
+                    "pub use vuln::hash::bytes as digest;\n\
+                     use vuln::hash::hasher;\n\
+                     pub mod util;\n\
+                     pub mod inner;\n",
+                ),
+                ("src/util/mod.rs", "pub use vuln::hash::*;\n"),
+                (
+                    "src/inner.rs",
+                    "pub use ::vuln::hash;\npub(crate) use vuln::hash::hasher;\n",
+                ),
+            ],
+        );
+        let krate = crate::test_support::krate_at("foo-bar", dir.path());
+        let targets = ["vuln::hash::bytes", "vuln::hash::hasher"].map(str::to_string);
+        let mut found = find_reexported_targets(&krate, &targets).await;
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                "foo_bar::digest",
+                "foo_bar::inner::hash::bytes",
+                "foo_bar::inner::hash::hasher",
+                "foo_bar::util::bytes",
+                "foo_bar::util::hasher",
+            ]
+        );
+        // a crate without re-exports
+        let plain = tempfile::tempdir().unwrap();
+        crate::test_support::write_files(
+            plain.path(),
+            &[("src/lib.rs", "use vuln::hash::bytes;\n")],
+        );
+        let plain = crate::test_support::krate_at("plain", plain.path());
+        assert!(find_reexported_targets(&plain, &targets).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn skipping_the_grep_precheck_analyzes_a_crate_it_would_skip() {
+        let dir = tempfile::tempdir().unwrap();
+        // the call is generated by a macro, grep cannot see it
+        crate::test_support::write_files(
+            dir.path(),
+            &[("src/lib.rs", "vuln::call_it!(hash, by, tes);\n")],
+        );
+        let krate = crate::test_support::krate_at("foo", dir.path());
+        let src_dir = dir.path().join("src");
+        let targets = "vuln::hash::bytes,vuln::hash::hasher";
+        assert_eq!(
+            functions_to_analyze(&krate, &src_dir, targets, false)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            functions_to_analyze(&krate, &src_dir, targets, true)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(targets)
+        );
+
+        // with the pre-check, only the targets grep finds are analyzed
+        crate::test_support::write_files(dir.path(), &[("src/lib.rs", "vuln::hash::hasher();\n")]);
+        assert_eq!(
+            functions_to_analyze(&krate, &src_dir, targets, false)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("vuln::hash::hasher")
+        );
+    }
+
+    #[tokio::test]
+    async fn aliased_imports_of_the_targets_are_found() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::test_support::write_files(
+            dir.path(),
+            &[
+                (
+                    "src/lib.rs",
+                    "use vuln::hash::{bytes as hash_bytes, hasher};\n\
+                     mod util;\n\
+                     pub fn run() { hash_bytes(b\"x\"); hasher(); }\n",
+                ),
+                (
+                    "src/util.rs",
+                    "use ::vuln::hash::hasher as make;\n\
+                     use vuln::hash::bytes;\n\
+                     use other::hash::bytes as other_bytes;\n\
+                     pub fn f() { make(); }\n",
+                ),
+            ],
+        );
+        let krate = crate::test_support::krate_at("foo", dir.path());
+        let targets = ["vuln::hash::bytes", "vuln::hash::hasher"].map(str::to_string);
+        let mut aliases = find_target_aliases(&krate, &targets).await;
+        aliases.sort();
+        assert_eq!(
+            aliases,
+            [
+                ("hash_bytes".to_string(), "vuln::hash::bytes".to_string()),
+                ("make".to_string(), "vuln::hash::hasher".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_grep_precheck_ignores_binary_files_under_src() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        std::fs::create_dir_all(src.join("fixtures")).unwrap();
+        std::fs::write(src.join("lib.rs"), "pub fn run() {}\n").unwrap();
+        // the name of a target inside a binary blob is not a call
+        std::fs::write(src.join("fixtures/blob.bin"), b"\x00\x01hasher\x00\xff").unwrap();
+        let src = src.to_string_lossy();
+        let targets = "vuln::hash::bytes,vuln::hash::hasher";
+        assert!(
+            check_src_contain_target_function(&src, targets)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        std::fs::write(dir.path().join("src/util.rs"), "fn f() { hasher(); }\n").unwrap();
+        assert_eq!(
+            check_src_contain_target_function(&src, targets)
+                .await
+                .unwrap(),
+            HashSet::from(["vuln::hash::hasher".to_string()])
+        );
+    }
+
+    /// counts the warnings and errors logged while it is the default subscriber
+    struct WarningCounter(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for WarningCounter {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if *event.metadata().level() <= tracing::Level::WARN {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
         }
     }
-    Ok(true)
+
+    #[tokio::test]
+    async fn a_successful_run_without_callers_logs_no_warning() {
+        use tracing_subscriber::prelude::*;
+
+        let warnings = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let _subscriber = tracing::subscriber::set_default(
+            tracing_subscriber::registry().with(WarningCounter(warnings.clone())),
+        );
+        let warnings = || warnings.load(std::sync::atomic::Ordering::SeqCst);
+        let dir = tempfile::tempdir().unwrap();
+        let krate = crate::test_support::krate_at("foo", dir.path());
+        let target_dir = dir.path().join("target");
+        std::fs::create_dir_all(target_dir.join("debug")).unwrap();
+
+        // call-cg4rs built the crate and found no callers
+        let files = collect_callers_files(&krate, dir.path(), &target_dir)
+            .await
+            .unwrap();
+        assert!(files.is_none());
+        assert_eq!(warnings(), 0);
+
+        std::fs::write(
+            target_dir.join("callers-vuln_hash_bytes.json"),
+            r#"{"target": "vuln::hash::bytes", "callers": []}"#,
+        )
+        .unwrap();
+        let files = collect_callers_files(&krate, dir.path(), &target_dir)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(warnings(), 0);
+
+        // a successful run without an output dir is reported
+        let error = collect_callers_files(&krate, dir.path(), &dir.path().join("missing"))
+            .await
+            .unwrap_err();
+        assert!(error.downcast_ref::<AnalyzerFailed>().is_some());
+        assert_eq!(warnings(), 1);
+    }
+
+    #[test]
+    fn the_pinned_toolchain_is_set_on_the_analyzer_command() {
+        let env_of = |cmd: &Command, key: &str| {
+            cmd.as_std()
+                .get_envs()
+                .find(|(k, _)| *k == key)
+                .and_then(|(_, v)| v)
+                .map(|v| v.to_string_lossy().to_string())
+        };
+        let manifest = Path::new("/work/foo-1.0.0/Cargo.toml");
+        let target = Path::new("/work/foo-1.0.0/target");
+        let pinned = cg4rs_command(
+            "vuln::f",
+            manifest,
+            target,
+            "info",
+            Some("nightly-2024-01-01"),
+        );
+        assert_eq!(
+            env_of(&pinned, "RUSTUP_TOOLCHAIN").as_deref(),
+            Some("nightly-2024-01-01")
+        );
+        assert_eq!(env_of(&pinned, "RUST_LOG").as_deref(), Some("info"));
+        let args = pinned
+            .as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            args[..7],
+            [
+                "--find-callers",
+                "vuln::f",
+                "--json-output",
+                "--manifest-path",
+                "/work/foo-1.0.0/Cargo.toml",
+                "--output-dir",
+                "/work/foo-1.0.0/target",
+            ]
+        );
+
+        // unpinned, the toolchain of the crate directory is left alone
+        let unpinned = cg4rs_command("vuln::f", manifest, target, "info", None);
+        assert_eq!(env_of(&unpinned, "RUSTUP_TOOLCHAIN"), None);
+    }
+
+    #[test]
+    fn relativize_paths_leaves_no_absolute_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let crate_dir = dir.path().join("unpacked/foo-1.0.0");
+        std::fs::create_dir_all(&crate_dir).unwrap();
+        // the crate is reached through a symlink, call-cg4rs may report either path
+        let root = dir.path().join("link");
+        std::os::unix::fs::symlink(&crate_dir, &root).unwrap();
+        let canonical = std::fs::canonicalize(&crate_dir).unwrap();
+
+        let mut files = vec![CallgraphFile {
+            file: "callers-foo_f.json".to_string(),
+            file_content: serde_json::json!({
+                "target": "foo::f",
+                "callers": [
+                    {"path": "foo::main", "location": format!("{}/src/main.rs:3:5", root.display())},
+                    {"path": "foo::g", "locations": [
+                        format!("{}/src/g.rs:1:1", canonical.display()),
+                        "src/lib.rs:2:1",
+                    ]},
+                ],
+            }),
+        }];
+        assert_eq!(relativize_paths(&mut files, &root), 2);
+        let content = files[0].file_content.to_string();
+        assert!(
+            !content.contains(&dir.path().display().to_string()),
+            "{content}"
+        );
+        assert!(
+            !content.contains(&canonical.display().to_string()),
+            "{content}"
+        );
+        let callers = &files[0].file_content["callers"];
+        assert_eq!(callers[0]["location"], "src/main.rs:3:5");
+        assert_eq!(
+            callers[1]["locations"],
+            serde_json::json!(["src/g.rs:1:1", "src/lib.rs:2:1"])
+        );
+        // nothing left to rewrite
+        assert_eq!(relativize_paths(&mut files, &root), 0);
+    }
+
+    #[test]
+    fn detect_build_script_failure_names_the_package() {
+        let stderr = "   Compiling openssl-sys v0.9.90\n\
+            error: failed to run custom build command for `openssl-sys v0.9.90`\n\
+            \n\
+            Caused by:\n  process didn't exit successfully";
+        assert_eq!(
+            detect_build_script_failure(stderr).and_then(|failure| failure.package),
+            Some("openssl-sys v0.9.90".to_string())
+        );
+        let unnamed = detect_build_script_failure("error: failed to run custom build command for");
+        assert_eq!(unnamed.map(|failure| failure.package), Some(None));
+        assert!(detect_build_script_failure("error[E0425]: cannot find value `x`").is_none());
+    }
+
+    #[tokio::test]
+    async fn confirm_callers_annotates_the_top_callers() {
+        let krate = |name: &str, version: &str| Krate {
+            name: name.to_string(),
+            version: version.to_string(),
+            dir_idx: 0,
+            working_dir: PathBuf::new(),
+            working_src_code_dir: PathBuf::new(),
+            package_dir: PathBuf::new(),
+            download_dir: PathBuf::new(),
+        };
+        let caller = |subject: &str, path: &str, constraints: i64| CallerRecord {
+            subject: subject.to_string(),
+            function: "foo::parse".to_string(),
+            caller_path: path.to_string(),
+            path_constraints: Some(constraints),
+            path_package_num: Some(1),
+        };
+        let finder = MockCallerFinder::new().with_result(
+            "a",
+            "1.0.0",
+            vec![CallgraphFile {
+                file: "callers-foo_parse.json".to_string(),
+                file_content: serde_json::json!({
+                    "target": "foo::parse",
+                    "callers": [{"path": "a::run"}],
+                }),
+            }],
+        );
+        let krates = HashMap::from([
+            ("a-1.0.0".to_string(), krate("a", "1.0.0")),
+            ("b-1.0.0".to_string(), krate("b", "1.0.0")),
+        ]);
+        let callers = vec![
+            caller("a-1.0.0", "a::run", 5),
+            caller("a-1.0.0", "a::other", 4),
+            caller("b-1.0.0", "b::run", 3),
+            caller("c-1.0.0", "c::run", 2),
+            caller("a-1.0.0", "a::low", 1),
+        ];
+        let confirmations =
+            confirm_callers(&finder, callers, 4, &krates, "foo::parse", Path::new("")).await;
+        let statuses: Vec<(&str, ConfirmationStatus)> = confirmations
+            .iter()
+            .map(|c| (c.caller_path.as_str(), c.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("a::run", ConfirmationStatus::Confirmed),
+                ("a::other", ConfirmationStatus::Unconfirmed),
+                // the finder ran and found no callers at all
+                ("b::run", ConfirmationStatus::Unconfirmed),
+                // no working tree to re-analyze
+                ("c::run", ConfirmationStatus::Unknown),
+            ]
+        );
+
+        // no confirmation finder, so no working tree is handed over
+        let confirmations = confirm_callers(
+            &finder,
+            vec![caller("a-1.0.0", "a::run", 5)],
+            4,
+            &HashMap::new(),
+            "foo::parse",
+            Path::new(""),
+        )
+        .await;
+        assert_eq!(confirmations[0].status, ConfirmationStatus::Unknown);
+    }
+
+    #[tokio::test]
+    async fn the_grep_finds_a_late_listed_function() {
+        let src = tempfile::tempdir().unwrap();
+        std::fs::write(
+            src.path().join("lib.rs"),
+            "pub fn run() {\n    vuln::codec::decode_frame(&[]);\n}\n",
+        )
+        .unwrap();
+        let found = check_src_contain_target_function(
+            &src.path().to_string_lossy(),
+            "vuln::hash::digest,vuln::hash::update,vuln::io::read_all,vuln::codec::decode_frame",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            found,
+            HashSet::from(["vuln::codec::decode_frame".to_string()])
+        );
+    }
 }
@@ -0,0 +1,531 @@
+use anyhow::{Context, Result};
+use std::env::VarError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, OnceLock};
+use toml_edit::{DocumentMut, Item, Value};
+
+/// the config files looked up in the current directory when `--config` is not given
+pub const DEFAULT_CONFIG_FILES: [&str; 2] = ["cvetracker.toml", "cvetracker.yaml"];
+
+/// The built-in defaults, the lowest layer under the config file and the environment
+pub const DEFAULTS: &[(&str, &str)] = &[
+    ("WORKING_DIR", "./downloads/working"),
+    ("DOWNLOAD_DIR", "./downloads"),
+    ("MAX_CONCURRENT_BFS_NODES", "32"),
+    ("MAX_CONCURRENT_DEP_DOWNLOAD", "32"),
+    ("PG_HOST", "localhost"),
+    ("PG_USER", "postgres"),
+    ("PG_PASSWORD", "postgres"),
+    ("PG_DATABASE", "crates_io"),
+    ("CG_RUST_LOG", "info"),
+];
+
+/// Settings loaded from a TOML or YAML config file, as an alternative to
+/// setting many environment variables. Every top-level key maps to the
+/// environment variable of the same name in upper case, e.g.
+/// `max_concurrent_bfs_nodes = 32` is `MAX_CONCURRENT_BFS_NODES=32`, so all
+/// existing variable names keep working. A setting is read from the
+/// environment first, then the file, then [`DEFAULTS`].
+///
+/// `[registries.<name>]` tables group the settings of one registry (download
+/// URL, token, database), selected with `--registry=<name>`.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub path: Option<PathBuf>,
+    pub values: Vec<(String, String)>,
+    pub registries: Vec<(String, Vec<(String, String)>)>,
+}
+
+/// a config file item: a scalar, as the string of its environment variable, or a table
+enum Entry {
+    Scalar(String),
+    Table(Vec<(String, Entry)>),
+}
+
+static INSTALLED: OnceLock<Arc<Config>> = OnceLock::new();
+/// the config of a process that installed none: the environment and the defaults
+static ENVIRONMENT_ONLY: LazyLock<Arc<Config>> = LazyLock::new(Arc::default);
+
+/// Make `config` the one the library reads its settings from, e.g. in the
+/// `main` of a binary. It can be installed once per process.
+pub fn install(config: Config) -> Result<Arc<Config>> {
+    INSTALLED
+        .set(Arc::new(config))
+        .map_err(|_| anyhow::anyhow!("a config is already installed"))?;
+    Ok(current())
+}
+
+/// the installed config, or the environment and the defaults alone if none is
+pub fn current() -> Arc<Config> {
+    INSTALLED.get().unwrap_or(&ENVIRONMENT_ONLY).clone()
+}
+
+/// `key` from the environment, the installed config file or the defaults
+pub fn var(key: &str) -> Result<String, VarError> {
+    INSTALLED.get().unwrap_or(&ENVIRONMENT_ONLY).var(key)
+}
+
+impl Config {
+    /// Load `path`, or `cvetracker.toml` (else `cvetracker.yaml`) in the current
+    /// directory if it exists. Return an empty config when none is present.
+    /// Files ending in `.yaml` or `.yml` are read as YAML, others as TOML.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match DEFAULT_CONFIG_FILES
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+            {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+        };
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml");
+        let entries = if yaml {
+            parse_yaml(&content)
+        } else {
+            parse_toml(&content)
+        }
+        .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        Self::from_entries(path, entries)
+    }
+
+    fn from_entries(path: PathBuf, entries: Vec<(String, Entry)>) -> Result<Self> {
+        let mut values = Vec::new();
+        let mut registries = Vec::new();
+        for (key, entry) in entries {
+            if key == "registries" {
+                let Entry::Table(table) = entry else {
+                    return Err(anyhow::anyhow!(
+                        "{}: `registries` must be a table",
+                        path.display()
+                    ));
+                };
+                for (name, registry) in table {
+                    let Entry::Table(registry) = registry else {
+                        return Err(anyhow::anyhow!(
+                            "{}: `registries.{}` must be a table",
+                            path.display(),
+                            name
+                        ));
+                    };
+                    let mut registry_values = Vec::new();
+                    for (key, entry) in registry {
+                        let key = format!("registries.{}.{}", name, key);
+                        registry_values.push(scalar_env_value(&path, &key, entry)?);
+                    }
+                    registries.push((name, registry_values));
+                }
+                continue;
+            }
+            values.push(scalar_env_value(&path, &key, entry)?);
+        }
+        Ok(Self {
+            path: Some(path),
+            values,
+            registries,
+        })
+    }
+
+    /// Put the settings of registry `name` in front of the top-level ones, so
+    /// they win when both set the same variable. Error if there is no such registry.
+    pub fn select_registry(&mut self, name: &str) -> Result<()> {
+        let Some((_, registry)) = self.registries.iter().find(|(n, _)| n == name) else {
+            return Err(anyhow::anyhow!(
+                "registry `{}` is not configured in {}",
+                name,
+                self.path
+                    .as_deref()
+                    .unwrap_or(Path::new(DEFAULT_CONFIG_FILES[0]))
+                    .display()
+            ));
+        };
+        let mut values = registry.clone();
+        values.append(&mut self.values);
+        self.values = values;
+        Ok(())
+    }
+
+    /// `key` from the environment, else the config file, else the defaults
+    pub fn var(&self, key: &str) -> Result<String, VarError> {
+        self.var_as(key, std::env::var(key))
+    }
+
+    /// [`Self::var`] with `env` as the value of the environment variable
+    pub(crate) fn var_as(
+        &self,
+        key: &str,
+        env: Result<String, VarError>,
+    ) -> Result<String, VarError> {
+        if !matches!(env, Err(VarError::NotPresent)) {
+            return env;
+        }
+        // the first value of a key wins
+        self.values
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.clone())
+            .or_else(|| {
+                DEFAULTS
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, value)| value.to_string())
+            })
+            .ok_or(VarError::NotPresent)
+    }
+
+    /// `key` parsed as a `T`, `None` if it is not set or does not parse
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.var(key).ok()?.parse().ok()
+    }
+
+    /// whether `key` is set to `1`
+    pub fn flag(&self, key: &str) -> bool {
+        self.var(key).is_ok_and(|v| v == "1")
+    }
+
+    /// the root of the CVE-scoped working directories, `WORKING_DIR`
+    pub fn working_dir(&self) -> PathBuf {
+        PathBuf::from(self.var("WORKING_DIR").unwrap_or_default())
+    }
+
+    /// where the crate archives are downloaded and extracted, `DOWNLOAD_DIR`
+    pub fn download_dir(&self) -> PathBuf {
+        PathBuf::from(self.var("DOWNLOAD_DIR").unwrap_or_default())
+    }
+
+    /// the crates of a BFS level processed concurrently, `MAX_CONCURRENT_BFS_NODES`
+    pub fn max_concurrent_bfs_nodes(&self) -> usize {
+        self.get("MAX_CONCURRENT_BFS_NODES").unwrap_or(32)
+    }
+
+    /// the dependents fetched concurrently, `MAX_CONCURRENT_DEP_DOWNLOAD`
+    pub fn max_concurrent_dep_download(&self) -> usize {
+        self.get("MAX_CONCURRENT_DEP_DOWNLOAD").unwrap_or(32)
+    }
+
+    /// the most crates processed at once across the BFS, from download to
+    /// cleanup, configured by `MAX_INFLIGHT_CRATES` (unset or 0: no cap beyond
+    /// `MAX_CONCURRENT_BFS_NODES`)
+    pub fn max_inflight_crates(&self) -> Option<usize> {
+        self.get("MAX_INFLIGHT_CRATES").filter(|&n| n > 0)
+    }
+
+    /// the pause between starting two crates of a BFS level, configured by
+    /// `INTER_CRATE_DELAY_MS` (default 0)
+    pub fn inter_crate_delay(&self) -> std::time::Duration {
+        self.get("INTER_CRATE_DELAY_MS")
+            .map(std::time::Duration::from_millis)
+            .unwrap_or_default()
+    }
+
+    /// The file values that are not set in the environment, to pass to a
+    /// spawned process of this crate, which reads the environment first
+    pub fn file_values_unset_in_env(&self) -> Vec<(String, String)> {
+        let mut exported: Vec<(String, String)> = Vec::new();
+        for (key, value) in &self.values {
+            if std::env::var_os(key).is_none() && !exported.iter().any(|(k, _)| k == key) {
+                exported.push((key.clone(), value.clone()));
+            }
+        }
+        exported
+    }
+}
+
+fn parse_toml(content: &str) -> Result<Vec<(String, Entry)>> {
+    fn entry(key: &str, item: &Item) -> Result<Entry> {
+        if let Some(table) = item.as_table_like() {
+            return Ok(Entry::Table(
+                table
+                    .iter()
+                    .map(|(key, item)| Ok((key.to_string(), entry(key, item)?)))
+                    .collect::<Result<_>>()?,
+            ));
+        }
+        Ok(Entry::Scalar(match item {
+            Item::Value(Value::String(s)) => s.value().clone(),
+            Item::Value(Value::Integer(i)) => i.value().to_string(),
+            Item::Value(Value::Float(f)) => f.value().to_string(),
+            Item::Value(Value::Boolean(b)) => if *b.value() { "1" } else { "0" }.to_string(),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "`{}` must be a string, number, boolean or table",
+                    key
+                ));
+            }
+        }))
+    }
+    let doc = content.parse::<DocumentMut>()?;
+    doc.iter()
+        .map(|(key, item)| Ok((key.to_string(), entry(key, item)?)))
+        .collect()
+}
+
+/// Parse the block mappings of YAML the config needs: `key: value` scalars,
+/// plain or quoted, and nested mappings by indentation, with `#` comments.
+/// Sequences, flow collections and multi-line scalars are not supported.
+fn parse_yaml(content: &str) -> Result<Vec<(String, Entry)>> {
+    /// a mapping being filled, with its indent and its key in the parent
+    struct Mapping {
+        indent: usize,
+        key: String,
+        entries: Vec<(String, Entry)>,
+    }
+    fn close(stack: &mut Vec<Mapping>) {
+        let mapping = stack.pop().unwrap();
+        let parent = stack.last_mut().unwrap();
+        parent
+            .entries
+            .push((mapping.key, Entry::Table(mapping.entries)));
+    }
+    // the document is the outermost mapping
+    let mut stack = vec![Mapping {
+        indent: 0,
+        key: String::new(),
+        entries: vec![],
+    }];
+    // a `key:` without a value opens a mapping, its indent is the next line's
+    let mut open: Option<(usize, String)> = None;
+    for (n, line) in content.lines().enumerate() {
+        let line = strip_yaml_comment(line).trim_end();
+        if line.trim().is_empty() || line == "---" {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        let Some((key, value)) = line.trim_start().split_once(':') else {
+            return Err(anyhow::anyhow!("line {}: expected `key: value`", n + 1));
+        };
+        if let Some((parent_indent, key)) = open.take() {
+            if indent <= parent_indent {
+                return Err(anyhow::anyhow!("line {}: `{}` is empty", n + 1, key));
+            }
+            stack.push(Mapping {
+                indent,
+                key,
+                entries: vec![],
+            });
+        }
+        while indent < stack.last().unwrap().indent {
+            close(&mut stack);
+        }
+        if indent != stack.last().unwrap().indent {
+            return Err(anyhow::anyhow!("line {}: unexpected indentation", n + 1));
+        }
+        let key = yaml_scalar(key.trim());
+        let value = value.trim();
+        if value.is_empty() {
+            open = Some((indent, key));
+            continue;
+        }
+        let value = match yaml_scalar(value).as_str() {
+            "true" => "1".to_string(),
+            "false" => "0".to_string(),
+            value => value.to_string(),
+        };
+        let mapping = stack.last_mut().unwrap();
+        mapping.entries.push((key, Entry::Scalar(value)));
+    }
+    if let Some((_, key)) = open {
+        return Err(anyhow::anyhow!("`{}` is empty", key));
+    }
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    Ok(stack.pop().unwrap().entries)
+}
+
+/// `line` without its comment, a `#` at the start or after a space outside quotes
+fn strip_yaml_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => return &line[..i],
+            None => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+/// a YAML scalar without its quotes
+fn yaml_scalar(value: &str) -> String {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner.to_string();
+        }
+    }
+    value.to_string()
+}
+
+/// A scalar config entry as an environment variable, `key` in upper case
+/// (the last dotted component for registry settings)
+fn scalar_env_value(path: &Path, key: &str, entry: Entry) -> Result<(String, String)> {
+    let Entry::Scalar(value) = entry else {
+        return Err(anyhow::anyhow!(
+            "{}: `{}` must be a string, number or boolean",
+            path.display(),
+            key
+        ));
+    };
+    let name = key.rsplit('.').next().unwrap_or(key);
+    Ok((name.to_uppercase(), value))
+}
+
+/// Load the config file given by `--config=<path>` (or the default one),
+/// select the registry given by `--registry=<name>` if any, and install it
+/// for the library, for the binaries.
+pub fn init_from_args(args: &[String]) -> Result<Arc<Config>> {
+    let path = args
+        .iter()
+        .find_map(|s| s.strip_prefix("--config="))
+        .map(Path::new);
+    let mut config = Config::load(path)?;
+    if let Some(registry) = args.iter().find_map(|s| s.strip_prefix("--registry=")) {
+        config.select_registry(registry)?;
+    }
+    install(config)
+}
+
+/// Read target function paths from a file, one per line. Blank lines and
+/// `#` comments (whole-line or trailing) are ignored.
+pub fn read_function_paths_file(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read function list {}", path.display()))?;
+    Ok(content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Merge the comma-separated inline function paths with the ones listed in
+/// `file`, dropping duplicates, into the comma-separated form the analyzer takes.
+pub fn merge_function_paths(inline: Option<&str>, file: Option<&Path>) -> Result<String> {
+    let mut paths: Vec<String> = inline
+        .into_iter()
+        .flat_map(|s| s.split(','))
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if let Some(file) = file {
+        paths.extend(read_function_paths_file(file)?);
+    }
+    let mut seen = std::collections::HashSet::new();
+    paths.retain(|p| seen.insert(p.clone()));
+    Ok(paths.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function_list(content: &str) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), content).unwrap();
+        file
+    }
+
+    #[test]
+    fn read_function_paths_file_skips_blank_lines_and_comments() {
+        let file = function_list(
+            "# gix-features\ngix_features::hash::hasher\n\n  gix_features::zlib::Inflate  # trailing\n#gix::open\n",
+        );
+        assert_eq!(
+            read_function_paths_file(file.path()).unwrap(),
+            ["gix_features::hash::hasher", "gix_features::zlib::Inflate"]
+        );
+        assert!(read_function_paths_file(Path::new("/nonexistent/functions.txt")).is_err());
+    }
+
+    #[test]
+    fn merge_function_paths_keeps_the_first_of_duplicates() {
+        let file = function_list("b::g\na::f\nc::h\n");
+        assert_eq!(
+            merge_function_paths(Some("a::f, b::g,,"), Some(file.path())).unwrap(),
+            "a::f,b::g,c::h"
+        );
+        assert_eq!(merge_function_paths(Some("a::f"), None).unwrap(), "a::f");
+        assert_eq!(
+            merge_function_paths(None, Some(file.path())).unwrap(),
+            "b::g,a::f,c::h"
+        );
+        assert_eq!(merge_function_paths(None, None).unwrap(), "");
+    }
+
+    fn config_file(name: &str, content: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn the_environment_overrides_the_file_which_overrides_the_defaults() {
+        let (_dir, path) = config_file(
+            "cvetracker.toml",
+            "max_concurrent_bfs_nodes = 8\nmatch_vulnerable_range = true\n",
+        );
+        let config = Config::load(Some(&path)).unwrap();
+        let unset = || Err(VarError::NotPresent);
+        assert_eq!(
+            config.var_as("MAX_CONCURRENT_BFS_NODES", Ok("4".to_string())),
+            Ok("4".to_string())
+        );
+        assert_eq!(
+            config.var_as("MAX_CONCURRENT_BFS_NODES", unset()),
+            Ok("8".to_string())
+        );
+        assert_eq!(
+            config.var_as("MAX_CONCURRENT_DEP_DOWNLOAD", unset()),
+            Ok("32".to_string())
+        );
+        assert_eq!(
+            config.var_as("MATCH_VULNERABLE_RANGE", unset()),
+            Ok("1".to_string())
+        );
+        assert_eq!(config.var_as("SAMPLING_SEED", unset()), unset());
+    }
+
+    #[test]
+    fn a_yaml_file_is_read_like_the_toml_one() {
+        let (_dir, path) = config_file(
+            "cvetracker.yaml",
+            "# the shared settings\nworking_dir: \"/data/working\"  # absolute\nmax_concurrent_bfs_nodes: 8\nmatch_vulnerable_range: true\nregistries:\n  internal:\n    crate_download_url: https://crates.example.com/{crate}/{version}\n    pg_host: 'db:5432'\n",
+        );
+        let mut config = Config::load(Some(&path)).unwrap();
+        assert_eq!(
+            config.values,
+            [
+                ("WORKING_DIR".to_string(), "/data/working".to_string()),
+                ("MAX_CONCURRENT_BFS_NODES".to_string(), "8".to_string()),
+                ("MATCH_VULNERABLE_RANGE".to_string(), "1".to_string()),
+            ]
+        );
+        config.select_registry("internal").unwrap();
+        assert_eq!(
+            config.var_as("PG_HOST", Err(VarError::NotPresent)),
+            Ok("db:5432".to_string())
+        );
+        assert_eq!(
+            config.var_as("CRATE_DOWNLOAD_URL", Err(VarError::NotPresent)),
+            Ok("https://crates.example.com/{crate}/{version}".to_string())
+        );
+
+        let (_dir, path) = config_file("bad.yml", "registries:\nworking_dir: /data\n");
+        assert!(Config::load(Some(&path)).is_err());
+    }
+}
@@ -1,24 +1,52 @@
-use std::env;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
 use sqlx::{postgres::PgPoolOptions, PgPool, Row};
 use tracing::info;
 
+use crate::config::Config;
+use crate::error::CveTrackerError;
 use crate::model::ReverseDependency;
 
+/// `dependencies.kind` in the crates.io dump
+pub(crate) const DEPENDENCY_KIND_NORMAL: i32 = 0;
+pub(crate) const DEPENDENCY_KIND_BUILD: i32 = 1;
+pub(crate) const DEPENDENCY_KIND_DEV: i32 = 2;
+pub(crate) const ALL_DEPENDENCY_KINDS: [i32; 3] = [
+    DEPENDENCY_KIND_NORMAL,
+    DEPENDENCY_KIND_BUILD,
+    DEPENDENCY_KIND_DEV,
+];
+
+/// the name of a dependency kind as used in `DEPENDENCY_KINDS`
+pub(crate) fn dependency_kind_name(kind: i32) -> &'static str {
+    match kind {
+        DEPENDENCY_KIND_NORMAL => "normal",
+        DEPENDENCY_KIND_BUILD => "build",
+        DEPENDENCY_KIND_DEV => "dev",
+        _ => "unknown",
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Database {
     pool: PgPool,
 }
 
 impl Database {
-    pub async fn new() -> Result<Self> {
-        // 从环境变量获取数据库连接信息
-        let db_host = env::var("PG_HOST").unwrap_or_else(|_| "localhost".to_string());
-        let db_user = env::var("PG_USER").unwrap_or_else(|_| "postgres".to_string());
-        let db_pass = env::var("PG_PASSWORD").unwrap_or_else(|_| "postgres".to_string());
-        let db_name = env::var("PG_DATABASE").unwrap_or_else(|_| "crates_io".to_string());
+    pub async fn new() -> Result<Self, CveTrackerError> {
+        Self::from_config(&crate::config::current()).await
+    }
+
+    /// connect with the `PG_*` settings of `config`
+    pub async fn from_config(config: &Config) -> Result<Self, CveTrackerError> {
+        // 从环境变量、配置文件或默认值获取数据库连接信息
+        let db_host = config.var("PG_HOST").unwrap_or_default();
+        let db_user = config.var("PG_USER").unwrap_or_default();
+        let db_pass = config.var("PG_PASSWORD").unwrap_or_default();
+        let db_name = config.var("PG_DATABASE").unwrap_or_default();
 
         let connection_string =
             format!("postgres://{}:{}@{}/{}", db_user, db_pass, db_host, db_name);
@@ -31,7 +59,8 @@ impl Database {
             .acquire_timeout(Duration::from_secs(3))
             .connect(&connection_string)
             .await
-            .context("无法连接到数据库")?;
+            .context("无法连接到数据库")
+            .map_err(CveTrackerError::Database)?;
 
         info!("数据库连接成功");
 
@@ -39,7 +68,10 @@ impl Database {
     }
 
     // 查询crate的所有版本
-    pub async fn query_crate_versions(&self, crate_name: &str) -> Result<Vec<String>> {
+    pub async fn query_crate_versions(
+        &self,
+        crate_name: &str,
+    ) -> Result<Vec<String>, CveTrackerError> {
         info!("查询crate {} 的所有版本", crate_name);
 
         let rows = sqlx::query(
@@ -51,7 +83,8 @@ impl Database {
         .bind(crate_name)
         .fetch_all(&self.pool)
         .await
-        .context("查询crate版本失败")?;
+        .context("查询crate版本失败")
+        .map_err(CveTrackerError::Database)?;
 
         let versions = rows.iter().map(|row| row.get::<String, _>("num")).collect();
 
@@ -59,39 +92,90 @@ impl Database {
         Ok(versions)
     }
 
-    // 查询依赖某个crate的所有crates
-    pub async fn query_dependents(&self, crate_name: &str) -> Result<Vec<ReverseDependency>> {
+    // 查询crate在某个日期（含）之后发布的版本
+    pub async fn query_crate_versions_since(
+        &self,
+        crate_name: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<String>, CveTrackerError> {
+        info!("查询crate {} 在 {} 之后发布的版本", crate_name, since);
+
+        let rows = sqlx::query(
+            "SELECT num FROM versions
+             JOIN crates ON versions.crate_id = crates.id
+             WHERE crates.name = $1
+             AND versions.created_at >= $2::date
+             ORDER BY versions.id DESC",
+        )
+        .bind(crate_name)
+        .bind(since.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .context("查询crate版本失败")
+        .map_err(CveTrackerError::Database)?;
+
+        let versions = rows.iter().map(|row| row.get::<String, _>("num")).collect();
+
+        info!("找到 {} 个版本", rows.len());
+        Ok(versions)
+    }
+
+    // 查询依赖某个crate的所有crates（一次性加载，适合依赖者较少的情况），参数同 stream_dependents
+    pub async fn query_dependents(
+        &self,
+        crate_name: &str,
+        since: Option<NaiveDate>,
+        kinds: &[i32],
+    ) -> Result<Vec<ReverseDependency>, CveTrackerError> {
+        let dependents: Vec<ReverseDependency> = self
+            .stream_dependents(crate_name, since, kinds)
+            .try_collect()
+            .await?;
+        info!("找到 {} 个依赖者", dependents.len());
+        Ok(dependents)
+    }
+
+    // 以流的形式逐行返回依赖某个crate的所有crates，避免为 serde 这类依赖者极多的crate一次性分配
+    // 若指定 since，只保留在该日期（含）之后发布的依赖者版本；只保留 kinds 中的依赖类型
+    // 同一版本以多种类型依赖时只返回一行，kind 取最小值（normal 优先）
+    pub fn stream_dependents<'a>(
+        &'a self,
+        crate_name: &'a str,
+        since: Option<NaiveDate>,
+        kinds: &[i32],
+    ) -> BoxStream<'a, Result<ReverseDependency, CveTrackerError>> {
         info!("查询依赖 {} 的所有crates", crate_name);
 
         let query = "WITH target_crate AS (
                 SELECT id FROM crates WHERE name = $1
             )
-            SELECT DISTINCT c.name, v.num, d.req
+            SELECT c.name, v.num, d.req, MIN(d.kind) AS kind
             FROM dependencies d
             JOIN versions v ON d.version_id = v.id
             JOIN crates c ON v.crate_id = c.id
             WHERE d.crate_id = (SELECT id FROM target_crate)
             AND d.req IS NOT NULL
+            AND ($2::date IS NULL OR v.created_at >= $2::date)
+            AND d.kind = ANY($3)
+            GROUP BY c.name, v.num, d.req
             ORDER BY c.name, v.num";
 
-        let rows = sqlx::query(query)
+        sqlx::query(query)
             .bind(crate_name)
-            .fetch_all(&self.pool)
-            .await
-            .context("查询依赖者失败")?;
-
-        let dependents = rows
-            .iter()
+            .bind(since.map(|d| d.to_string()))
+            .bind(kinds.to_vec())
+            .fetch(&self.pool)
             .map(|row| {
-                ReverseDependency::new(
+                let row = row
+                    .context("查询依赖者失败")
+                    .map_err(CveTrackerError::Database)?;
+                Ok(ReverseDependency::new(
                     row.get::<String, _>("name"),
                     row.get::<String, _>("num"),
                     row.get::<String, _>("req"),
-                )
+                    row.get::<i32, _>("kind"),
+                ))
             })
-            .collect();
-
-        info!("找到 {} 个依赖者", rows.len());
-        Ok(dependents)
+            .boxed()
     }
 }
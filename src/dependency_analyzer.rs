@@ -1,49 +1,438 @@
+use crate::callgraph::{
+    AnalyzerFailed, BuildScriptFailed, CallCg4rsFinder, CallerFinder, CallgraphFile, NoSourceFound,
+};
+use crate::config::Config;
 use crate::database::Database;
 use crate::dir::CrateWorkspaceFileSystemManager;
-use crate::model::Krate;
-use crate::{callgraph, utils};
+use crate::error::CveTrackerError;
+use crate::manifest::{
+    AffectedCrate, AnalysisEnvironment, BfsTreeEntry, BfsTreeNode, CallerConfirmation,
+    ConfirmationStatus, DownloadFailure, LevelSampling, MergedResult, RootVersionCheck,
+    RunManifest, RunTermination, SkipReason, SkippedCrate,
+};
+use crate::metadata::MetadataSource;
+use crate::metrics::{COUNTERS, RunCounters, RunMetrics};
+use crate::model::{CrateTooLarge, Krate, ReverseDependency};
+use crate::utils;
 use anyhow::Result;
+use chrono::NaiveDate;
+use futures::future::BoxFuture;
 use futures::stream::{self as futures_stream, StreamExt};
 use semver::Version;
-use std::collections::{HashSet, VecDeque};
-use std::env;
-use std::fs;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::{Mutex, Semaphore, watch};
 
 #[derive(Debug, Clone)]
 pub(crate) struct BFSNode {
     pub krate: Krate,
-    pub parent: Option<Arc<BFSNode>>,
+    pub parent: Option<BFSParent>,
+    /// paths the target functions were re-exported under by this crate or its
+    /// ancestors (`FOLLOW_REEXPORTS=1`), looked for in addition to the targets
+    pub reexported_targets: Vec<String>,
+    /// how the crate depends on its parent, `None` for a root version
+    pub dependency_kind: Option<i32>,
+    /// the root version of the vulnerable crate this crate was reached from
+    pub branch: String,
+}
+
+/// The parent of a [`BFSNode`]. A full node keeps its whole ancestor chain
+/// alive as long as any descendant is queued; with `COMPACT_BFS_PARENTS=1`
+/// children only keep what they need of the (already analyzed) parent.
+#[derive(Debug, Clone)]
+pub(crate) enum BFSParent {
+    Node(Arc<BFSNode>),
+    Summary {
+        name: String,
+        version: String,
+        depth: usize,
+    },
+}
+
+impl BFSParent {
+    /// the parent of a child of `parent`, a summary with `COMPACT_BFS_PARENTS=1`
+    fn new(parent: Arc<BFSNode>, config: &Config) -> Self {
+        if config.flag("COMPACT_BFS_PARENTS") {
+            Self::Summary {
+                name: parent.krate.name.clone(),
+                version: parent.krate.version.clone(),
+                depth: parent.depth(),
+            }
+        } else {
+            Self::Node(parent)
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Node(node) => &node.krate.name,
+            Self::Summary { name, .. } => name,
+        }
+    }
+
+    fn version(&self) -> &str {
+        match self {
+            Self::Node(node) => &node.krate.version,
+            Self::Summary { version, .. } => version,
+        }
+    }
+
+    fn depth(&self) -> usize {
+        match self {
+            Self::Node(node) => node.depth(),
+            Self::Summary { depth, .. } => *depth,
+        }
+    }
+}
+
+impl BFSNode {
+    /// 0 for a version of the vulnerable crate, 1 for its direct dependents, ...
+    fn depth(&self) -> usize {
+        self.parent.as_ref().map_or(0, |p| p.depth() + 1)
+    }
+
+    /// the comma-separated target functions to look for in this crate
+    fn function_paths(&self, target_function_paths: &str) -> String {
+        std::iter::once(target_function_paths)
+            .chain(self.reexported_targets.iter().map(|s| s.as_str()))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Cancels a running [`DependencyAnalyzer::analyze`] from another task, e.g. a
+/// stop button in a GUI or the binary's Ctrl-C handler. Clones share the same state.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<watch::Sender<bool>>,
+    /// cancelled by [`Self::interrupt`]
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self {
+            cancelled: Arc::new(watch::Sender::new(false)),
+            interrupted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    /// Cancel on behalf of the user, e.g. on Ctrl-C: the run is recorded as
+    /// `interrupted` rather than `cancelled`
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+        self.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// resolve once `cancel` has been called, immediately if it already was
+    pub async fn cancelled(&self) {
+        let mut receiver = self.cancelled.subscribe();
+        // the sender lives as long as `self`, so this cannot fail
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+/// `analyze` stopped because its [`CancellationToken`] was cancelled. The
+/// manifest, the download failures and the results written so far are kept.
+#[derive(Debug)]
+pub struct AnalysisCancelled {
+    pub cve_id: String,
+}
+
+impl std::fmt::Display for AnalysisCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Analysis of {} cancelled, results so far are kept",
+            self.cve_id
+        )
+    }
+}
+
+impl std::error::Error for AnalysisCancelled {}
+
+/// The outcome of one crate processed by the BFS, passed to the crate hook
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CrateAnalysis {
+    pub name: String,
+    pub version: String,
+    /// `<crate>-<version>` of the crate it was reached from, `None` for a
+    /// version of the vulnerable crate itself
+    pub parent: Option<String>,
+    /// whether the crate calls the target functions (always true for a version
+    /// of the vulnerable crate), so its dependents are analyzed next
+    pub vulnerable: bool,
+    /// the result file written for the crate, if callers were found
+    pub result_file: Option<PathBuf>,
+}
+
+/// A post-processing step run after each crate of the BFS is processed, e.g. to
+/// upload its result file. See [`DependencyAnalyzer::with_crate_hook`].
+pub trait CrateHook: std::fmt::Debug + Send + Sync {
+    fn after_crate<'a>(&'a self, analysis: &'a CrateAnalysis) -> BoxFuture<'a, ()>;
+}
+
+/// the default crate hook, which does nothing
+#[derive(Debug, Default, Clone)]
+pub struct NoopCrateHook;
+
+impl CrateHook for NoopCrateHook {
+    fn after_crate<'a>(&'a self, _analysis: &'a CrateAnalysis) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// what [`DependencyAnalyzer::start`] leaves for the BFS of a run
+struct RunStart {
+    /// the root versions of the vulnerable crate
+    roots: VecDeque<Arc<BFSNode>>,
+    logs_dir: PathBuf,
 }
 
 #[derive(Debug, Clone)]
 pub struct DependencyAnalyzer {
-    database: Arc<Database>,
+    /// where the versions and reverse dependencies come from
+    database: Arc<dyn MetadataSource>,
     fs_manager: Arc<Mutex<CrateWorkspaceFileSystemManager>>,
     cve_id: String,
+    /// only analyze versions published on or after this date
+    since: Option<NaiveDate>,
+    /// features of the vulnerable crate enabled in its direct dependents
+    root_features: Vec<String>,
+    /// the dependents of crates at this depth are not followed
+    max_depth: Option<usize>,
+    /// also analyze the callers inside the vulnerable crate itself
+    include_self: bool,
+    /// the tool used to find callers of the target functions
+    caller_finder: Arc<dyn CallerFinder>,
+    /// re-checks the top callers when `CONFIRM_TOP_N` is set, without it they are `unknown`
+    confirm_finder: Option<Arc<dyn CallerFinder>>,
+    manifest: Arc<Mutex<RunManifest>>,
+    /// set by `analyze` when `MATCH_VULNERABLE_RANGE=1`
+    vulnerable_range: Arc<Mutex<Option<utils::VulnerableRange>>>,
+    /// set by `analyze` when `RESOLVE_LATEST=1`, over all published versions
+    latest_resolution: Arc<Mutex<Option<utils::VulnerableRange>>>,
+    /// crates dropped from the analysis because they could not be fetched
+    download_failures: Arc<Mutex<Vec<DownloadFailure>>>,
+    /// every crate processed by the BFS, for bfs-tree-<cve>.json
+    bfs_tree: Arc<Mutex<Vec<BfsTreeEntry>>>,
+    /// the subjects written by this run and the branches that wrote them
+    written_results: Arc<Mutex<HashMap<String, Vec<String>>>>,
+    /// the crate of every result written by this run, for the confirmation pass
+    written_krates: Arc<Mutex<HashMap<String, Krate>>>,
+    /// set by `analyze` when `INCREMENTAL_STATS_SECS` is set
+    running_stats: Arc<Mutex<Option<crate::stats::RunningAggregate>>>,
+    /// caps the crates in flight (patch, analysis, clean and the download of
+    /// their dependents) when `MAX_INFLIGHT_CRATES` is set
+    inflight: Option<Arc<Semaphore>>,
+    /// stops `analyze` like Ctrl-C does, for embedders
+    cancellation: Option<CancellationToken>,
+    /// run after each crate is processed, for embedders
+    crate_hook: Arc<dyn CrateHook>,
+    /// the settings of the run, see [`Self::with_config`]
+    config: Arc<Config>,
 }
 
 impl DependencyAnalyzer {
-    pub async fn new(cve_id: &str) -> Result<Self> {
-        let database = Database::new().await?;
+    pub async fn new(cve_id: &str) -> Result<Self, CveTrackerError> {
+        let config = crate::config::current();
+        let database = Database::from_config(&config).await?;
+        Self::with_config(cve_id, Arc::new(database), config).await
+    }
+
+    /// Like [`Self::new`], but read the versions and reverse dependencies from
+    /// `source` instead of connecting to the database, e.g. a [`FileMetadataSource`]
+    ///
+    /// [`FileMetadataSource`]: crate::metadata::FileMetadataSource
+    pub async fn with_metadata_source(
+        cve_id: &str,
+        source: Arc<dyn MetadataSource>,
+    ) -> Result<Self, CveTrackerError> {
+        Self::with_config(cve_id, source, crate::config::current()).await
+    }
+
+    /// Like [`Self::with_metadata_source`], with the settings of `config`
+    /// (working and download directories, concurrency, sampling, ...) rather
+    /// than the process-wide ones installed by [`crate::config::install`]. The
+    /// environment variables still override the values of `config`.
+    pub async fn with_config(
+        cve_id: &str,
+        source: Arc<dyn MetadataSource>,
+        config: Arc<Config>,
+    ) -> Result<Self, CveTrackerError> {
         Ok(Self {
-            database: Arc::new(database),
+            database: source,
             fs_manager: Arc::new(Mutex::new(
-                CrateWorkspaceFileSystemManager::new(cve_id).await?,
+                CrateWorkspaceFileSystemManager::new(cve_id, &config)
+                    .await
+                    .map_err(CveTrackerError::Io)?,
             )),
             cve_id: cve_id.to_string(),
+            since: None,
+            root_features: Vec::new(),
+            max_depth: None,
+            include_self: false,
+            caller_finder: Arc::new(CallCg4rsFinder),
+            confirm_finder: None,
+            manifest: Arc::new(Mutex::new(RunManifest::new(cve_id))),
+            vulnerable_range: Arc::new(Mutex::new(None)),
+            latest_resolution: Arc::new(Mutex::new(None)),
+            download_failures: Arc::new(Mutex::new(Vec::new())),
+            bfs_tree: Arc::new(Mutex::new(Vec::new())),
+            written_results: Arc::new(Mutex::new(HashMap::new())),
+            written_krates: Arc::new(Mutex::new(HashMap::new())),
+            running_stats: Arc::new(Mutex::new(None)),
+            inflight: config
+                .max_inflight_crates()
+                .map(|n| Arc::new(Semaphore::new(n))),
+            cancellation: None,
+            crate_hook: Arc::new(NoopCrateHook),
+            config,
         })
     }
 
+    /// replace the default `call-cg4rs` caller finder
+    pub fn with_caller_finder(mut self, caller_finder: Arc<dyn CallerFinder>) -> Self {
+        self.caller_finder = caller_finder;
+        self
+    }
+
+    /// Re-check the top callers with `confirm_finder` when `CONFIRM_TOP_N` is
+    /// set, a stricter analysis than the caller finder's; without one the top
+    /// callers are all `unknown`
+    pub fn with_confirm_finder(mut self, confirm_finder: Arc<dyn CallerFinder>) -> Self {
+        self.confirm_finder = Some(confirm_finder);
+        self
+    }
+
+    /// restrict both the root version selection and the reverse-dependency
+    /// version selection to versions published on or after `since`
+    pub fn with_since(mut self, since: Option<NaiveDate>) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Build the direct dependents with these features of the vulnerable crate
+    /// enabled, for vulnerable code that only compiles with them. The features
+    /// are added to the dependency entries in the dependents' Cargo.toml, on
+    /// top of the ones the dependents already enable.
+    pub fn with_root_features(mut self, root_features: Vec<String>) -> Self {
+        self.root_features = root_features;
+        self
+    }
+
+    /// Stop the BFS at `max_depth` levels of dependents: crates at that depth
+    /// are analyzed, but their dependents are not fetched. `None` follows every level.
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Also run the caller finder on the vulnerable crate's own versions and
+    /// write their callers to `__self__-<version>.txt`, so the stats count the
+    /// internal uses of the target functions apart from the dependents.
+    pub fn with_include_self(mut self, include_self: bool) -> Self {
+        self.include_self = include_self;
+        self
+    }
+
+    /// Stop `analyze` when `token` is cancelled: the in-flight `call-cg4rs`
+    /// processes are killed, the manifest is written with the `cancelled`
+    /// termination and `analyze` returns [`CveTrackerError::Cancelled`]
+    /// ([`CancellationToken::interrupt`]: `interrupted` and [`CveTrackerError::Aborted`]).
+    /// The library installs no signal handler, the binary cancels this token on Ctrl-C.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Run `hook` after each crate the BFS processes, once per crate version,
+    /// whether or not it calls the target functions. Crates that fail to be
+    /// fetched or patched are not passed to it. Crates of a BFS level are
+    /// processed concurrently, so the hook may be called concurrently and in any
+    /// order; the dependents of a crate are only fetched once its hook returns.
+    pub fn with_crate_hook(mut self, hook: Arc<dyn CrateHook>) -> Self {
+        self.crate_hook = hook;
+        self
+    }
+
+    /// Analyze the dependents of `crate_name` in `version_range` calling
+    /// `function_paths`. The failures are sorted by [`CveTrackerError`] kind;
+    /// crates that cannot be fetched or analyzed are recorded and skipped
+    /// rather than failing the run.
     pub async fn analyze(
         &self,
         crate_name: &str,
         version_range: &str,
         function_paths: &str,
-    ) -> Result<()> {
+    ) -> Result<(), CveTrackerError> {
+        let started = std::time::Instant::now();
+        // the counters are process-wide, a previous run of this process is not this run's cost
+        COUNTERS.reset();
+        let run = self
+            .start(crate_name, version_range, function_paths)
+            .await?;
+        let termination = self
+            .run_until_stopped(async {
+                self.analyze_roots(&run.roots, function_paths, &run.logs_dir)
+                    .await?;
+                self.bfs(run.roots.clone(), function_paths, &run.logs_dir)
+                    .await
+            })
+            .await?;
+        self.finish_run(started, function_paths, &run.logs_dir, &termination)
+            .await?;
+        if let Err(e) = self.cleanup().await {
+            tracing::warn!(
+                "Failed to clean the working directory of {}: {}",
+                self.cve_id,
+                e
+            );
+        }
+        self.termination_result(termination)
+    }
+
+    /// Write the manifest and environment of a run and create the root versions
+    /// of the vulnerable crate, which start its BFS
+    async fn start(
+        &self,
+        crate_name: &str,
+        version_range: &str,
+        function_paths: &str,
+    ) -> Result<RunStart, CveTrackerError> {
+        // fail before the BFS rather than on every crate
+        let rust_toolchain = match utils::rust_toolchain() {
+            Some(toolchain) => {
+                let rustc = utils::check_rust_toolchain(&toolchain)
+                    .await
+                    .map_err(CveTrackerError::Analyzer)?;
+                tracing::info!(
+                    "call-cg4rs runs with RUST_TOOLCHAIN={} ({})",
+                    toolchain,
+                    rustc
+                );
+                Some(format!("{} ({})", toolchain, rustc))
+            }
+            None => None,
+        };
         // 为每个进程创建唯一的日志文件名
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -52,7 +441,35 @@ impl DependencyAnalyzer {
         let logs_dir = std::env::current_dir()
             .unwrap()
             .join(format!("logs_cg4rs/{}_{}", self.cve_id, timestamp));
-        let versions = self.database.query_crate_versions(crate_name).await?;
+        let versions = match self.since {
+            Some(since) => {
+                self.database
+                    .query_crate_versions_since(crate_name, since)
+                    .await
+            }
+            None => self.database.query_crate_versions(crate_name).await,
+        }
+        .map_err(CveTrackerError::Database)?;
+        // the direct dependents of the vulnerable crate are matched against the
+        // whole vulnerable range instead of the precise root version
+        if self.config.flag("MATCH_VULNERABLE_RANGE") {
+            *self.vulnerable_range.lock().await = Some(
+                utils::VulnerableRange::new(version_range, &versions)
+                    .map_err(CveTrackerError::Parse)?,
+            );
+        }
+        // a fresh resolution sees every published version, whatever `since` is
+        if utils::resolve_latest_enabled() {
+            let published = self
+                .database
+                .query_crate_versions(crate_name)
+                .await
+                .map_err(CveTrackerError::Database)?;
+            *self.latest_resolution.lock().await = Some(
+                utils::VulnerableRange::new(version_range, &published)
+                    .map_err(CveTrackerError::Parse)?,
+            );
+        }
         // select oldest and newest versions that match the version range
         let two_end_versions: Vec<(usize, Version)> =
             crate::utils::select_two_end_vers(versions, version_range).await;
@@ -62,23 +479,507 @@ impl DependencyAnalyzer {
         // push CVE node to bfs_queue
         for (_, version) in two_end_versions {
             let ver_str = &version.to_string();
-            let Ok(cve_krate) =
-                Krate::create(crate_name, ver_str, 0, self.fs_manager.clone()).await
-            else {
-                continue;
-            };
+            let cve_krate =
+                match Krate::create(crate_name, ver_str, 0, self.fs_manager.clone()).await {
+                    Ok(krate) => krate,
+                    Err(e) => {
+                        self.record_download_failure(crate_name, ver_str, None, &e)
+                            .await;
+                        continue;
+                    }
+                };
             let bfs_node = Arc::new(BFSNode {
                 krate: cve_krate,
                 parent: None,
+                reexported_targets: Vec::new(),
+                dependency_kind: None,
+                branch: ver_str.clone(),
             });
             bfs_queue.push_back(bfs_node);
         }
 
-        self.bfs(bfs_queue, function_paths, &logs_dir).await?;
+        {
+            let mut manifest = self.manifest.lock().await;
+            manifest.crate_name = crate_name.to_string();
+            manifest.version_range = version_range.to_string();
+            manifest.target_function_paths = function_paths.to_string();
+            manifest.root_features = self.root_features.clone();
+            manifest.max_depth = self.max_depth;
+            manifest.include_self = self.include_self;
+            manifest.grep_precheck_skipped = crate::callgraph::skip_grep_precheck_enabled();
+            manifest.rust_toolchain = rust_toolchain;
+            manifest.write().await.map_err(CveTrackerError::Io)?;
+        }
+        AnalysisEnvironment::capture()
+            .await
+            .write(&self.cve_id)
+            .await
+            .map_err(CveTrackerError::Io)?;
+        // running stats left by an earlier run would not match this one
+        let running_stats_path = crate::stats::RunningStats::path(&self.cve_id);
+        if running_stats_path.exists() {
+            tokio::fs::remove_file(&running_stats_path)
+                .await
+                .map_err(|e| CveTrackerError::Io(e.into()))?;
+        }
+        if let Some(interval) = crate::stats::incremental_stats_interval() {
+            let mut aggregate = crate::stats::RunningAggregate::new(&self.cve_id, interval)
+                .await
+                .map_err(CveTrackerError::Io)?;
+            aggregate.flush(true).await.map_err(CveTrackerError::Io)?;
+            *self.running_stats.lock().await = Some(aggregate);
+        }
+        Ok(RunStart {
+            roots: bfs_queue,
+            logs_dir,
+        })
+    }
+
+    /// the passes over the root versions themselves, before the BFS of their dependents
+    async fn analyze_roots(
+        &self,
+        roots: &VecDeque<Arc<BFSNode>>,
+        function_paths: &str,
+        logs_dir: &Path,
+    ) -> Result<()> {
+        if self.config.flag("SELF_ANALYSIS") {
+            self.self_analyze(roots, function_paths, logs_dir).await?;
+        }
+        if self.include_self {
+            self.analyze_self_callers(roots, function_paths, logs_dir)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Run `bfs` until it completes or the run is stopped: the BFS is bounded by
+    /// RUN_TIMEOUT_SECS and RUN_MEMORY_MB if they are set and can be stopped by
+    /// the cancellation token. Dropping the BFS future kills the in-flight
+    /// call-cg4rs processes, the manifest and download failures are still written.
+    async fn run_until_stopped(
+        &self,
+        bfs: impl Future<Output = Result<()>>,
+    ) -> Result<RunTermination, CveTrackerError> {
+        Ok(tokio::select! {
+            result = bfs => {
+                result.map_err(CveTrackerError::Analyzer)?;
+                RunTermination::Completed
+            }
+            timeout_secs = utils::run_timeout() => RunTermination::TimedOut { timeout_secs },
+            (limit_mb, rss_mb) = utils::memory_watchdog() => {
+                RunTermination::MemoryExceeded { limit_mb, rss_mb }
+            }
+            interrupted = async {
+                match &self.cancellation {
+                    Some(token) => {
+                        token.cancelled().await;
+                        token.interrupted.load(Ordering::SeqCst)
+                    }
+                    None => std::future::pending().await,
+                }
+            } => {
+                tracing::warn!("Cancelled, stopping the analysis");
+                if interrupted {
+                    RunTermination::Interrupted
+                } else {
+                    RunTermination::Cancelled
+                }
+            }
+        })
+    }
+
+    /// Write the artifacts of a run that ended with `termination`: the
+    /// confirmations, download failures, BFS tree, running stats and the
+    /// finished manifest. The working trees are still there, for the
+    /// confirmation pass, until the cleanup.
+    async fn finish_run(
+        &self,
+        started: std::time::Instant,
+        function_paths: &str,
+        logs_dir: &Path,
+        termination: &RunTermination,
+    ) -> Result<(), CveTrackerError> {
+        if let Some(n) = crate::callgraph::confirm_top_n()
+            && *termination == RunTermination::Completed
+        {
+            self.confirm_top_callers(n, function_paths, logs_dir)
+                .await
+                .map_err(CveTrackerError::Io)?;
+        }
+        let download_failures = self.download_failures.lock().await.clone();
+        if !download_failures.is_empty() {
+            tracing::warn!(
+                "{} crate(s) could not be fetched and were not analyzed, see {}",
+                download_failures.len(),
+                DownloadFailure::path(&self.cve_id).display()
+            );
+        }
+        DownloadFailure::write_all(&self.cve_id, &download_failures)
+            .await
+            .map_err(CveTrackerError::Io)?;
+        self.write_bfs_tree().await;
+        if let Some(aggregate) = self.running_stats.lock().await.as_mut()
+            && let Err(e) = aggregate.flush(true).await
+        {
+            tracing::warn!(
+                "Failed to write the running stats of {}: {}",
+                self.cve_id,
+                e
+            );
+        }
+        let mut written: Vec<String> = self.written_results.lock().await.keys().cloned().collect();
+        written.sort();
+        {
+            let mut manifest = self.manifest.lock().await;
+            manifest.written_results = written;
+            manifest
+                .finish(termination.clone(), RunMetrics::snapshot(started.elapsed()))
+                .await
+                .map_err(CveTrackerError::Io)?;
+        }
+        Ok(())
+    }
+
+    /// what `analyze` returns for a run that ended with `termination`
+    fn termination_result(&self, termination: RunTermination) -> Result<(), CveTrackerError> {
+        match termination {
+            RunTermination::Completed => Ok(()),
+            RunTermination::TimedOut { timeout_secs } => {
+                tracing::warn!(
+                    "Analysis of {} stopped after RUN_TIMEOUT_SECS={}s, results so far are kept",
+                    self.cve_id,
+                    timeout_secs
+                );
+                Ok(())
+            }
+            RunTermination::MemoryExceeded { limit_mb, rss_mb } => {
+                Err(CveTrackerError::Aborted(anyhow::anyhow!(
+                    "Analysis of {} aborted: resident memory {} MB exceeded RUN_MEMORY_MB={} MB",
+                    self.cve_id,
+                    rss_mb,
+                    limit_mb
+                )))
+            }
+            RunTermination::Interrupted => Err(CveTrackerError::Aborted(anyhow::anyhow!(
+                "Analysis of {} interrupted, results so far are kept",
+                self.cve_id
+            ))),
+            RunTermination::Cancelled => Err(AnalysisCancelled {
+                cve_id: self.cve_id.clone(),
+            }
+            .into()),
+        }
+    }
+
+    /// The confirmation pass of `CONFIRM_TOP_N`, written to confirmations-<cve>.json
+    async fn confirm_top_callers(
+        &self,
+        n: usize,
+        function_paths: &str,
+        logs_dir: &Path,
+    ) -> Result<()> {
+        let cveid = &self.cve_id;
+        let callers = crate::stats::load_callers(cveid).await.unwrap_or_default();
+        let krates = if self.confirm_finder.is_some() {
+            tracing::info!("[{cveid}] CONFIRM_TOP_N={}, re-checking the top callers", n);
+            self.written_krates.lock().await.clone()
+        } else {
+            // the caller finder run again with the same arguments only finds the same callers
+            tracing::warn!(
+                "[{cveid}] CONFIRM_TOP_N={} but no confirmation finder is configured, the top callers are marked unknown",
+                n
+            );
+            HashMap::new()
+        };
+        let finder = self.confirm_finder.as_ref().unwrap_or(&self.caller_finder);
+        let confirmations = crate::callgraph::confirm_callers(
+            finder.as_ref(),
+            callers,
+            n,
+            &krates,
+            function_paths,
+            logs_dir,
+        )
+        .await;
+        let confirmed = confirmations
+            .iter()
+            .filter(|c| c.status == ConfirmationStatus::Confirmed)
+            .count();
+        tracing::info!(
+            "[{cveid}] {} of {} top caller(s) confirmed",
+            confirmed,
+            confirmations.len()
+        );
+        CallerConfirmation::write_all(cveid, &confirmations).await
+    }
+
+    /// write the discovery tree of the crates processed so far, with the
+    /// caller counts of their result files, and the affected crates CSV
+    async fn write_bfs_tree(&self) {
+        let callers = match crate::stats::load_callers(&self.cve_id).await {
+            Ok(records) => records
+                .into_iter()
+                .fold(HashMap::new(), |mut counts, record| {
+                    *counts.entry(record.subject).or_insert(0) += 1;
+                    counts
+                }),
+            // no result file was written
+            Err(_) => HashMap::new(),
+        };
+        let entries = self.bfs_tree.lock().await;
+        let forest = BfsTreeNode::build_forest(&entries, &callers);
+        if let Err(e) = BfsTreeNode::write_forest(&self.cve_id, &forest).await {
+            tracing::warn!("Failed to write the BFS tree of {}: {}", self.cve_id, e);
+        }
+        let affected = AffectedCrate::collect(&entries, &callers);
+        if let Err(e) = AffectedCrate::write_csv(&self.cve_id, &affected).await {
+            tracing::warn!(
+                "Failed to write the affected crates of {}: {}",
+                self.cve_id,
+                e
+            );
+        }
+    }
+
+    /// remove this CVE's working tree under WORKING_DIR, unless `KEEP_WORKDIR=1`
+    pub async fn cleanup(&self) -> Result<()> {
+        if self.config.flag("KEEP_WORKDIR") {
+            tracing::info!(
+                "KEEP_WORKDIR=1, keep the working directory of {}",
+                self.cve_id
+            );
+            return Ok(());
+        }
+        self.fs_manager.lock().await.cleanup().await
+    }
+
+    /// Run the grep pre-check and the caller finder on the vulnerable crate's own
+    /// versions, recording in the manifest which of them really contain the target
+    /// functions. A version without them hints at a mis-specified range or path.
+    async fn self_analyze(
+        &self,
+        roots: &VecDeque<Arc<BFSNode>>,
+        target_function_paths: &str,
+        logs_dir: &Path,
+    ) -> Result<()> {
+        for root in roots {
+            let krate = &root.krate;
+            let src_dir = krate.get_src_dir().await;
+            let contains_target_functions = crate::callgraph::check_src_contain_target_function(
+                &src_dir.to_string_lossy(),
+                target_function_paths,
+            )
+            .await
+            .map(|found| !found.is_empty())
+            .unwrap_or_else(|e| {
+                tracing::warn!(
+                    "[{}:{}] Self-analysis grep failed: {}",
+                    krate.name,
+                    krate.version,
+                    e
+                );
+                false
+            });
+            let callers = if contains_target_functions {
+                let result = self
+                    .caller_finder
+                    .find_callers(krate, target_function_paths, logs_dir)
+                    .await;
+                krate.cargo_clean().await?;
+                match result {
+                    Ok(Some(files)) => Some(
+                        files
+                            .iter()
+                            .filter_map(|f| f.file_content.get("callers"))
+                            .filter_map(|c| c.as_array())
+                            .map(|c| c.len())
+                            .sum(),
+                    ),
+                    Ok(None) => None,
+                    Err(e) => {
+                        tracing::warn!(
+                            "[{}:{}] Self-analysis failed: {}",
+                            krate.name,
+                            krate.version,
+                            e
+                        );
+                        None
+                    }
+                }
+            } else {
+                tracing::warn!(
+                    "[{}:{}] is in the vulnerable range but does not contain any of {}, check the version range and function paths",
+                    krate.name,
+                    krate.version,
+                    target_function_paths
+                );
+                None
+            };
+            self.manifest
+                .lock()
+                .await
+                .root_versions
+                .push(RootVersionCheck {
+                    version: krate.version.clone(),
+                    contains_target_functions,
+                    callers,
+                });
+        }
+        self.manifest.lock().await.write().await
+    }
 
+    /// Find the callers inside each root version of the vulnerable crate and
+    /// write them under the `__self__` subject, see [`Self::with_include_self`]
+    async fn analyze_self_callers(
+        &self,
+        roots: &VecDeque<Arc<BFSNode>>,
+        target_function_paths: &str,
+        logs_dir: &Path,
+    ) -> Result<()> {
+        for root in roots {
+            let krate = &root.krate;
+            let result = self
+                .caller_finder
+                .find_callers(krate, target_function_paths, logs_dir)
+                .await;
+            krate.cargo_clean().await?;
+            match result {
+                Ok(Some(files)) => {
+                    let subject = format!("{}-{}", crate::stats::SELF_SUBJECT, krate.version);
+                    let result_file = self
+                        .write_result(&subject, files, krate, &krate.version)
+                        .await?;
+                    if let Some(aggregate) = self.running_stats.lock().await.as_mut() {
+                        aggregate.add_result_file(&result_file).await?;
+                    }
+                }
+                Ok(None) => tracing::info!(
+                    "[{}:{}] No callers of the target functions inside the vulnerable crate",
+                    krate.name,
+                    krate.version
+                ),
+                Err(e) => tracing::warn!(
+                    "[{}:{}] Analysis of the callers inside the vulnerable crate failed: {}",
+                    krate.name,
+                    krate.version,
+                    e
+                ),
+            }
+        }
         Ok(())
     }
 
+    /// Merge the duplicate callers of `analysis_result`, make its paths relative
+    /// to the crate root if asked to, and write it to analysis_results/<cve>/<subject>.txt,
+    /// with the edition and MSRV of `krate` next to it if `ANNOTATE_CRATE_INFO=1`.
+    /// If a result file of `subject` already exists, written by another root
+    /// version's `branch` or by an earlier run, the callers are merged into it
+    /// instead of overwriting it and the branches are recorded in the manifest.
+    /// Return the path actually written.
+    async fn write_result(
+        &self,
+        subject: &str,
+        mut analysis_result: Vec<CallgraphFile>,
+        krate: &Krate,
+        branch: &str,
+    ) -> Result<PathBuf> {
+        let cveid = &self.cve_id;
+        let crate_dir = &krate.get_working_src_code_dir().await;
+        let duplicates = crate::callgraph::dedup_callers(&mut analysis_result);
+        if duplicates > 0 {
+            tracing::info!(
+                "[{cveid}:{subject}] Merged {} duplicate caller(s)",
+                duplicates
+            );
+        }
+        if utils::relative_paths_enabled() {
+            let rewritten = crate::callgraph::relativize_paths(&mut analysis_result, crate_dir);
+            tracing::debug!(
+                "[{cveid}:{subject}] Made {} path(s) relative to the crate root",
+                rewritten
+            );
+        }
+        let result_dir = crate::stats::analysis_results_dir().join(cveid);
+        tokio::fs::create_dir_all(&result_dir).await?;
+        let filepath = result_dir.join(format!("{}.txt", subject));
+        tracing::info!("[{cveid}:{subject}] Writing result to: {:?}", filepath);
+        if crate::stats::annotate_crate_info_enabled() {
+            match utils::crate_info(&krate.get_cargo_toml_path().await).await {
+                Ok(info) => info.write(cveid, subject).await?,
+                Err(e) => tracing::warn!(
+                    "[{cveid}:{subject}] Failed to read the edition and MSRV: {}",
+                    e
+                ),
+            }
+        }
+        // held until the file is written, so concurrent writes of a subject are merged in turn
+        let mut written_results = self.written_results.lock().await;
+        self.written_krates
+            .lock()
+            .await
+            .insert(subject.to_string(), krate.clone());
+        let (written, added) = utils::write_merged_result_file(&filepath, analysis_result).await?;
+        let branches = written_results.entry(subject.to_string()).or_default();
+        // the first write of this run found the file of an earlier run
+        let previous_run = added.is_some() && branches.is_empty();
+        if !branches.iter().any(|b| b == branch) {
+            branches.push(branch.to_string());
+        }
+        if let Some(added) = added {
+            tracing::info!(
+                "[{cveid}:{subject}] Merged {} new caller(s) into the existing result",
+                added
+            );
+            let mut manifest = self.manifest.lock().await;
+            match manifest
+                .merged_results
+                .iter_mut()
+                .find(|merged| merged.subject == subject)
+            {
+                Some(merged) => merged.branches = branches.clone(),
+                None => manifest.merged_results.push(MergedResult {
+                    subject: subject.to_string(),
+                    branches: branches.clone(),
+                    previous_run,
+                }),
+            }
+        }
+        Ok(written)
+    }
+
+    async fn record_download_failure(
+        &self,
+        name: &str,
+        version: &str,
+        parent: Option<&Krate>,
+        error: &anyhow::Error,
+    ) {
+        if let Some(too_large) = error.downcast_ref::<CrateTooLarge>() {
+            tracing::warn!("Skipping crate {}:{}: {}", name, version, too_large);
+            self.manifest
+                .lock()
+                .await
+                .skipped_crates
+                .push(SkippedCrate {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                    parent: parent.map(|p| format!("{}-{}", p.name, p.version)),
+                    reason: SkipReason::SkippedTooLarge {
+                        size_bytes: too_large.size_bytes,
+                        limit_bytes: too_large.limit_bytes,
+                    },
+                });
+            return;
+        }
+        tracing::error!("Failed to fetch crate {}:{}: {:#}", name, version, error);
+        self.download_failures.lock().await.push(DownloadFailure {
+            name: name.to_string(),
+            version: version.to_string(),
+            parent: parent.map(|p| format!("{}-{}", p.name, p.version)),
+            error: format!("{:#}", error),
+        });
+    }
+
     async fn bfs(
         &self,
         mut queue: VecDeque<Arc<BFSNode>>,
@@ -86,17 +987,36 @@ impl DependencyAnalyzer {
         logs_dir: &PathBuf,
     ) -> Result<()> {
         let mut visited = HashSet::new();
+        let mut depth = 0;
         while !queue.is_empty() {
             let current_level = utils::pop_bfs_level(&mut queue).await;
+            let current_level = self
+                .sample_level(current_level, depth, |node| {
+                    format!("{}-{}", node.krate.name, node.krate.version)
+                })
+                .await;
+            depth += 1;
+            let no_output_before = RunCounters::get(&COUNTERS.analyzer_no_output);
             let results = self
                 .process_bfs_level(current_level, target_function_paths, &logs_dir)
                 .await?;
+            let no_output = RunCounters::get(&COUNTERS.analyzer_no_output) - no_output_before;
+            if no_output > 0 {
+                tracing::info!(
+                    "BFS level {}: skipped {} crate(s) for which call-cg4rs wrote no callers file (details at debug level)",
+                    depth - 1,
+                    no_output
+                );
+            }
 
             // filter out the nodes that have been visited
             let results_without_visited = results
                 .into_iter()
                 .filter(|node| {
-                    let key = (node.krate.name.clone(), node.krate.version.clone());
+                    let key = (
+                        node.krate.name.clone(),
+                        utils::normalize_version(&node.krate.version),
+                    );
                     if visited.contains(&key) {
                         false
                     } else {
@@ -111,6 +1031,43 @@ impl DependencyAnalyzer {
         Ok(())
     }
 
+    /// Keep at most `MAX_NODES_PER_LEVEL` items of the BFS level at `depth`,
+    /// sampled with `SAMPLING_SEED`, and record the sampling in the manifest
+    async fn sample_level<T>(
+        &self,
+        level: Vec<T>,
+        depth: usize,
+        key: impl Fn(&T) -> String,
+    ) -> Vec<T> {
+        let Some(cap) = self
+            .config
+            .get::<usize>("MAX_NODES_PER_LEVEL")
+            .filter(|cap| level.len() > *cap)
+        else {
+            return level;
+        };
+        let sampling_seed = self.config.get::<u64>("SAMPLING_SEED").unwrap_or(0);
+        let total = level.len();
+        let level = utils::sample_bfs_level(level, cap, sampling_seed, key);
+        tracing::info!(
+            "[{}] BFS level {} has {} nodes, sampled {} of them (seed {})",
+            self.cve_id,
+            depth,
+            total,
+            cap,
+            sampling_seed
+        );
+        let mut manifest = self.manifest.lock().await;
+        manifest.sampling_seed = Some(sampling_seed);
+        manifest.level_sampling.push(LevelSampling {
+            depth,
+            total,
+            sampled: cap,
+            fraction: cap as f64 / total as f64,
+        });
+        level
+    }
+
     /// process a level of BFS
     async fn process_bfs_level(
         &self,
@@ -119,8 +1076,21 @@ impl DependencyAnalyzer {
         logs_dir: &PathBuf,
     ) -> Result<Vec<Arc<BFSNode>>> {
         let analyzer = Arc::new(self.clone());
+        let delay = self.config.inter_crate_delay();
         Ok(futures_stream::iter(current_level)
+            // the next crate is pulled at most every `delay`, spacing the starts
+            .then(async |bfs_node| {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+                bfs_node
+            })
             .map(async |bfs_node| {
+                // the semaphore is never closed
+                let _permit = match &analyzer.inflight {
+                    Some(inflight) => inflight.acquire().await.ok(),
+                    None => None,
+                };
                 match analyzer
                     .process_single_bfs_node(bfs_node.clone(), target_function_paths, &logs_dir)
                     .await
@@ -136,12 +1106,7 @@ impl DependencyAnalyzer {
                     }
                 }
             })
-            .buffer_unordered(
-                env::var("MAX_CONCURRENT_BFS_NODES")
-                    .unwrap_or("32".to_string())
-                    .parse::<usize>()
-                    .unwrap(),
-            )
+            .buffer_unordered(self.config.max_concurrent_bfs_nodes())
             .collect::<Vec<_>>()
             .await
             .into_iter()
@@ -153,89 +1118,269 @@ impl DependencyAnalyzer {
         &self,
         bfs_node: Arc<BFSNode>,
         target_function_paths: &str,
-        logs_dir: &PathBuf,
+        logs_dir: &Path,
     ) -> Result<Vec<Arc<BFSNode>>> {
+        let function_paths = bfs_node.function_paths(target_function_paths);
+        self.record_bfs_tree_entry(&bfs_node).await;
+
         // check if the node is vulnerable
-        if !self
-            .check_bfs_node_vulnerable(
-                bfs_node.clone(),
-                target_function_paths,
-                &self.cve_id,
-                &logs_dir,
-            )
-            .await?
+        let analysis = self
+            .check_bfs_node_vulnerable(bfs_node.clone(), &function_paths, &self.cve_id, logs_dir)
+            .await?;
+        match self
+            .after_analysis(&bfs_node, &analysis, &function_paths)
+            .await
         {
-            return Ok(vec![]);
+            Some(reexported_targets) => self.expand_bfs_node(bfs_node, reexported_targets).await,
+            None => Ok(vec![]),
         }
+    }
 
-        // get reverse dependencies in range of vulnerable version
-        let selected_dependents =
-            utils::get_reverse_deps_for_krate(&self.database, &bfs_node.krate).await?;
-
-        // create new BFS nodes for reverse dependencies
-        let dependent_krates = futures_stream::iter(selected_dependents)
-            .map(|reverse_dependency| {
-                let rev_name = reverse_dependency.name.clone();
-                let rev_ver = reverse_dependency.version.clone();
-                let fs_manager = self.fs_manager.clone();
-                let parent = bfs_node.clone();
-                async move {
-                    Krate::create(&rev_name, &rev_ver, parent.krate.dir_idx, fs_manager)
-                        .await
-                        .ok()
-                        .map(|dep_krate| {
-                            Arc::new(BFSNode {
-                                krate: dep_krate,
-                                parent: Some(parent),
-                            })
-                        })
-                }
-            })
-            .buffer_unordered(
-                env::var("MAX_CONCURRENT_DEP_DOWNLOAD")
-                    .unwrap_or("32".to_string())
-                    .parse::<usize>()
-                    .unwrap(),
-            )
-            .filter_map(|x| async { x })
-            .collect::<Vec<_>>()
-            .await;
-
-        Ok(dependent_krates)
+    async fn record_bfs_tree_entry(&self, bfs_node: &BFSNode) {
+        self.bfs_tree.lock().await.push(BfsTreeEntry {
+            name: bfs_node.krate.name.clone(),
+            version: bfs_node.krate.version.clone(),
+            parent: bfs_node
+                .parent
+                .as_ref()
+                .map(|p| format!("{}-{}", p.name(), p.version())),
+            dependency_kind: bfs_node
+                .dependency_kind
+                .map(crate::database::dependency_kind_name),
+        });
     }
 
-    async fn check_bfs_node_vulnerable(
+    /// Run the crate hook and update the running stats once `bfs_node` is
+    /// analyzed, and decide whether its dependents are followed: return the
+    /// re-exported target paths they inherit, `None` if they are not followed
+    async fn after_analysis(
         &self,
-        bfs_node: Arc<BFSNode>,
-        target_function_paths: &str,
-        cveid: &str,
-        logs_dir: &PathBuf,
-    ) -> Result<bool> {
-        let krate_name = &bfs_node.krate.name;
-        let krate_version = &bfs_node.krate.version;
-
-        tracing::info!(
+        bfs_node: &BFSNode,
+        analysis: &CrateAnalysis,
+        function_paths: &str,
+    ) -> Option<Vec<String>> {
+        self.crate_hook.after_crate(analysis).await;
+        if let Some(result_file) = &analysis.result_file
+            && let Some(aggregate) = self.running_stats.lock().await.as_mut()
+        {
+            let updated = match aggregate.add_result_file(result_file).await {
+                Ok(()) => aggregate.flush(false).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = updated {
+                tracing::warn!(
+                    "[{}:{}] Failed to update the running stats: {}",
+                    bfs_node.krate.name,
+                    bfs_node.krate.version,
+                    e
+                );
+            }
+        }
+        let vulnerable = analysis.vulnerable;
+
+        // a crate that re-exports a target function exposes it to its dependents
+        // under a new path, even if it does not call it itself
+        let mut reexported_targets = bfs_node.reexported_targets.clone();
+        let mut reexports = false;
+        if crate::callgraph::follow_reexports_enabled() {
+            let targets: Vec<String> = function_paths
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .collect();
+            let found = crate::callgraph::find_reexported_targets(&bfs_node.krate, &targets).await;
+            if !found.is_empty() {
+                tracing::info!(
+                    "[{}:{}] Re-exports the target functions as: {}",
+                    bfs_node.krate.name,
+                    bfs_node.krate.version,
+                    found.join(", ")
+                );
+                reexported_targets.extend(found);
+                reexports = true;
+            }
+        }
+        if !vulnerable && !reexports {
+            return None;
+        }
+        if let Some(max_depth) = self.max_depth
+            && bfs_node.depth() >= max_depth
+        {
+            tracing::debug!(
+                "[{}:{}] at --max-depth={}, its dependents are not followed",
+                bfs_node.krate.name,
+                bfs_node.krate.version,
+                max_depth
+            );
+            return None;
+        }
+        Some(reexported_targets)
+    }
+
+    /// Create the BFS nodes of the reverse dependencies of a vulnerable node
+    async fn expand_bfs_node(
+        &self,
+        bfs_node: Arc<BFSNode>,
+        reexported_targets: Vec<String>,
+    ) -> Result<Vec<Arc<BFSNode>>> {
+        let selected_dependents = self.select_dependents(&bfs_node).await?;
+        Ok(self
+            .create_dependent_nodes(bfs_node, reexported_targets, selected_dependents)
+            .await)
+    }
+
+    /// the reverse dependencies of a vulnerable node to analyze next
+    async fn select_dependents(&self, bfs_node: &BFSNode) -> Result<Vec<ReverseDependency>> {
+        // get reverse dependencies in range of vulnerable version
+        let vulnerable_range = match bfs_node.parent {
+            None => self.vulnerable_range.lock().await.clone(),
+            Some(_) => None,
+        };
+        let mut selected_dependents = utils::get_reverse_deps_for_krate(
+            self.database.as_ref(),
+            &bfs_node.krate,
+            self.since,
+            vulnerable_range.as_ref(),
+        )
+        .await?;
+        // a direct dependent whose requirement now resolves to a fixed version
+        // is not vulnerable as published, only through an old lockfile
+        if bfs_node.parent.is_none()
+            && let Some(latest_resolution) = self.latest_resolution.lock().await.as_ref()
+        {
+            let total = selected_dependents.len();
+            selected_dependents
+                .retain(|revdep| latest_resolution.latest_resolution_vulnerable(revdep));
+            if selected_dependents.len() < total {
+                tracing::info!(
+                    "[{}:{}] {} of {} direct dependent version(s) resolve to a fixed version, skipped",
+                    bfs_node.krate.name,
+                    bfs_node.krate.version,
+                    total - selected_dependents.len(),
+                    total
+                );
+            }
+        }
+        Ok(selected_dependents)
+    }
+
+    /// Fetch the selected reverse dependencies of `bfs_node` and create their
+    /// BFS nodes, recording the ones that cannot be fetched
+    async fn create_dependent_nodes(
+        &self,
+        bfs_node: Arc<BFSNode>,
+        reexported_targets: Vec<String>,
+        selected_dependents: Vec<ReverseDependency>,
+    ) -> Vec<Arc<BFSNode>> {
+        futures_stream::iter(selected_dependents)
+            .map(|reverse_dependency| {
+                let rev_name = reverse_dependency.name.clone();
+                let rev_ver = reverse_dependency.version.clone();
+                let dependency_kind = reverse_dependency.kind;
+                let fs_manager = self.fs_manager.clone();
+                let parent = bfs_node.clone();
+                let reexported_targets = reexported_targets.clone();
+                async move {
+                    match Krate::create(&rev_name, &rev_ver, parent.krate.dir_idx, fs_manager).await
+                    {
+                        Ok(dep_krate) => Some(Arc::new(BFSNode {
+                            krate: dep_krate,
+                            branch: parent.branch.clone(),
+                            parent: Some(BFSParent::new(parent, &self.config)),
+                            reexported_targets,
+                            dependency_kind: Some(dependency_kind),
+                        })),
+                        Err(e) => {
+                            self.record_download_failure(
+                                &rev_name,
+                                &rev_ver,
+                                Some(&parent.krate),
+                                &e,
+                            )
+                            .await;
+                            None
+                        }
+                    }
+                }
+            })
+            .buffer_unordered(self.config.max_concurrent_dep_download())
+            .filter_map(|x| async { x })
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    async fn check_bfs_node_vulnerable(
+        &self,
+        bfs_node: Arc<BFSNode>,
+        target_function_paths: &str,
+        cveid: &str,
+        logs_dir: &Path,
+    ) -> Result<CrateAnalysis> {
+        let krate_name = &bfs_node.krate.name;
+        let krate_version = &bfs_node.krate.version;
+        tracing::info!(
             "[{}:{}] Starting vulnerability check",
             krate_name,
             krate_version
         );
+        if bfs_node.parent.is_none() {
+            return Ok(Self::crate_analysis(&bfs_node));
+        }
+        self.patch_parent(&bfs_node).await?;
+        let lockfile = Self::lock_dependencies(&bfs_node.krate).await?;
+
+        tracing::info!("[{cveid}:{krate_name}:{krate_version}] Starting function analysis");
+        let analysis_result = self
+            .caller_finder
+            .find_callers(&bfs_node.krate, target_function_paths, logs_dir)
+            .await;
+
+        tracing::debug!("[{cveid}:{krate_name}:{krate_version}] Cleaning cargo cache");
+        bfs_node.krate.cargo_clean().await?;
+
+        let lockfile = lockfile.as_deref();
+        match analysis_result {
+            Ok(files) => self.record_analysis(&bfs_node, Ok(files), lockfile).await,
+            Err(e) => self.record_analysis(&bfs_node, Err(&e), lockfile).await,
+        }
+    }
+
+    /// the analysis of `bfs_node` before it is checked: vulnerable, without a result file
+    fn crate_analysis(bfs_node: &BFSNode) -> CrateAnalysis {
+        CrateAnalysis {
+            name: bfs_node.krate.name.to_string(),
+            version: bfs_node.krate.version.to_string(),
+            parent: bfs_node
+                .parent
+                .as_ref()
+                .map(|p| format!("{}-{}", p.name(), p.version())),
+            vulnerable: true,
+            result_file: None,
+        }
+    }
+
+    /// Make the working copy of `bfs_node` build against the exact version of
+    /// its parent, with the required features of a vulnerable root version
+    async fn patch_parent(&self, bfs_node: &BFSNode) -> Result<()> {
+        let Some(parent) = &bfs_node.parent else {
+            return Ok(());
+        };
+        let krate_name = &bfs_node.krate.name;
+        let krate_version = &bfs_node.krate.version;
         let working_src_code_dir = bfs_node.krate.get_working_src_code_dir().await;
-        if let Some(parent) = &bfs_node.parent {
-            tracing::debug!(
-                "[{}:{}] Patching dependency {}:{}",
-                krate_name,
-                krate_version,
-                parent.krate.name,
-                parent.krate.version
-            );
+        let package_dir = bfs_node.krate.get_package_dir().await;
+        tracing::debug!(
+            "[{}:{}] Patching dependency {}:{}",
+            krate_name,
+            krate_version,
+            parent.name(),
+            parent.version()
+        );
 
-            // Vendor the exact parent version locally and patch Cargo.toml to use it.
-            // This ensures yanked versions remain resolvable and builds are deterministic/offline.
-            utils::vendor_and_patch_dep(
-                &working_src_code_dir,
-                &parent.krate.name,
-                &parent.krate.version,
-            )
+        // Vendor the exact parent version locally and patch Cargo.toml to use it.
+        // This ensures yanked versions remain resolvable and builds are deterministic/offline.
+        // cargo only reads `[patch]` from the workspace root, which is the working
+        // source directory even when the package is a member of a virtual workspace.
+        utils::vendor_and_patch_dep(&working_src_code_dir, parent.name(), parent.version())
             .await
             .map_err(|e| {
                 anyhow::anyhow!(
@@ -245,48 +1390,1255 @@ impl DependencyAnalyzer {
                 )
             })?;
 
-            tracing::info!("[{cveid}:{krate_name}:{krate_version}] Starting function analysis");
-            let analysis_result =
-                callgraph::run_function_analysis(&bfs_node.krate, target_function_paths, &logs_dir)
-                    .await;
+        // the parent is a vulnerable root version, enable the required features on it,
+        // in the manifest of the package that declares the dependency
+        if parent.depth() == 0 && !self.root_features.is_empty() {
+            let patched =
+                utils::enable_dep_features(&package_dir, parent.name(), &self.root_features)
+                    .await?;
+            tracing::debug!(
+                "[{}:{}] Enabled features {:?} of {} in {} dependency entr(ies)",
+                krate_name,
+                krate_version,
+                self.root_features,
+                parent.name(),
+                patched
+            );
+        }
+        Ok(())
+    }
 
-            tracing::debug!("[{cveid}:{krate_name}:{krate_version}] Cleaning cargo cache");
-            bfs_node.krate.cargo_clean().await?;
+    /// Freeze the whole dependency tree once, so unrelated transitive releases
+    /// do not make the results drift between runs (`LOCK_DEPS=1`)
+    async fn lock_dependencies(krate: &Krate) -> Result<Option<PathBuf>> {
+        if !utils::lock_deps_enabled() {
+            return Ok(None);
+        }
+        Ok(Some(
+            utils::generate_lockfile(&krate.get_working_src_code_dir().await).await?,
+        ))
+    }
 
-            match analysis_result {
-                Ok(Some(analysis_result)) => {
-                    tracing::info!(
-                        "[{cveid}:{krate_name}:{krate_version}] Function analysis completed successfully"
-                    );
-                    let result_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
-                        .join("analysis_results")
-                        .join(cveid);
-                    if !result_dir.exists() {
-                        fs::create_dir_all(&result_dir)?;
+    /// Write the callers the caller finder found in `bfs_node`, or record why it
+    /// was skipped, and return the analysis of the crate
+    async fn record_analysis(
+        &self,
+        bfs_node: &BFSNode,
+        analysis_result: Result<Option<Vec<CallgraphFile>>, &anyhow::Error>,
+        lockfile: Option<&Path>,
+    ) -> Result<CrateAnalysis> {
+        let cveid = &self.cve_id;
+        let krate_name = &bfs_node.krate.name;
+        let krate_version = &bfs_node.krate.version;
+        let mut analysis = Self::crate_analysis(bfs_node);
+        match analysis_result {
+            Ok(Some(analysis_result)) => {
+                tracing::info!(
+                    "[{cveid}:{krate_name}:{krate_version}] Function analysis completed successfully"
+                );
+                let subject = format!("{}-{}", bfs_node.krate.name, bfs_node.krate.version);
+                analysis.result_file = Some(
+                    self.write_result(&subject, analysis_result, &bfs_node.krate, &bfs_node.branch)
+                        .await?,
+                );
+                // keep the lockfile the result was computed with, for reproducibility
+                if let Some(lockfile) = lockfile {
+                    let result_dir = crate::stats::analysis_results_dir().join(cveid);
+                    let lock_path = result_dir.join(format!(
+                        "{}-{}.Cargo.lock",
+                        bfs_node.krate.name, bfs_node.krate.version
+                    ));
+                    if let Err(e) = tokio::fs::copy(lockfile, &lock_path).await {
+                        tracing::warn!(
+                            "[{cveid}:{krate_name}:{krate_version}] Failed to keep Cargo.lock: {}",
+                            e
+                        );
                     }
-                    let filename =
-                        format!("{}-{}.txt", bfs_node.krate.name, bfs_node.krate.version);
-                    let filepath = result_dir.join(filename);
-                    tracing::info!(
-                        "[{cveid}:{krate_name}:{krate_version}] Writing result to: {:?}",
-                        filepath
-                    );
-                    fs::write(filepath, &analysis_result)?;
-                    return Ok(true);
-                }
-                Ok(None) => {
-                    tracing::info!("[{cveid}:{krate_name}:{krate_version}] No function analysis result, skipping crate");
-                    return Ok(false);
                 }
-                Err(e) => {
+            }
+            Ok(None) => {
+                tracing::info!("[{cveid}:{krate_name}:{krate_version}] No function analysis result, skipping crate");
+                analysis.vulnerable = false;
+            }
+            Err(e) => {
+                let reason = if let Some(failure) = e.downcast_ref::<BuildScriptFailed>() {
+                    Some(SkipReason::BuildScriptFailed {
+                        package: failure.package.clone(),
+                    })
+                } else if e.is::<NoSourceFound>() {
+                    Some(SkipReason::NoSourceFound)
+                } else {
+                    e.downcast_ref::<AnalyzerFailed>()
+                        .map(|failure| SkipReason::AnalyzerFailed {
+                            error: failure.error.clone(),
+                        })
+                };
+                if let Some(reason) = reason {
+                    tracing::warn!("[{cveid}:{krate_name}:{krate_version}] {}", e);
+                    self.manifest
+                        .lock()
+                        .await
+                        .skipped_crates
+                        .push(SkippedCrate {
+                            name: krate_name.to_string(),
+                            version: krate_version.to_string(),
+                            parent: analysis.parent.clone(),
+                            reason,
+                        });
+                } else {
                     tracing::error!(
                         "[{cveid}:{krate_name}:{krate_version}] Function analysis failed: {}",
                         e
                     );
-                    return Ok(false);
+                }
+                analysis.vulnerable = false;
+            }
+        }
+        Ok(analysis)
+    }
+}
+
+/// One advisory of [`analyze_batch`]: the arguments of [`DependencyAnalyzer::analyze`]
+/// for the run of `cve_id`
+#[derive(Debug, Clone)]
+pub struct AnalysisTarget {
+    pub cve_id: String,
+    pub crate_name: String,
+    pub version_range: String,
+    pub function_paths: String,
+}
+
+/// Analyze several advisories in one process and one joint BFS, with one
+/// [`DependencyAnalyzer`] per target sharing `source`, e.g. a single database
+/// connection. `configure` is applied to every analyzer, to set the caller
+/// finder, cancellation token and other options; the caller finder,
+/// cancellation token and `MAX_INFLIGHT_CRATES` of the first target are the
+/// ones of the joint BFS.
+///
+/// Each target follows its own dependents with its own function paths and
+/// writes its own `analysis_results/<cve>/`, but a crate version reached by
+/// several targets at the same BFS level is fetched, patched with all their
+/// parents and analyzed once with the union of their function paths; its
+/// callers are then split per target. A target reaching it through another
+/// version of the same parent crate analyzes it separately. The analyses saved
+/// and the caller finder time they would have taken are logged and recorded in
+/// the `run_metrics` of every target, which are those of the whole batch.
+///
+/// Return the result of every target, in order. A target whose run cannot
+/// start fails alone; a cancelled or aborted batch stops every target.
+pub async fn analyze_batch(
+    source: Arc<dyn MetadataSource>,
+    targets: Vec<AnalysisTarget>,
+    configure: impl Fn(DependencyAnalyzer) -> DependencyAnalyzer,
+) -> Vec<(String, Result<(), CveTrackerError>)> {
+    let started = std::time::Instant::now();
+    // the counters are process-wide, the batch is one run
+    COUNTERS.reset();
+    let mut results = Vec::with_capacity(targets.len());
+    let mut members = Vec::new();
+    for target in targets {
+        let member = async {
+            let analyzer = configure(
+                DependencyAnalyzer::with_metadata_source(&target.cve_id, source.clone()).await?,
+            );
+            let run = analyzer
+                .start(
+                    &target.crate_name,
+                    &target.version_range,
+                    &target.function_paths,
+                )
+                .await?;
+            Ok(BatchMember {
+                analyzer,
+                function_paths: target.function_paths.clone(),
+                run,
+                result: results.len(),
+            })
+        }
+        .await;
+        match member {
+            // replaced once the joint BFS is done
+            Ok(member) => {
+                members.push(member);
+                results.push((target.cve_id, Ok(())));
+            }
+            Err(e) => results.push((target.cve_id, Err(e))),
+        }
+    }
+    if let Some(first) = members.first() {
+        match first.analyzer.run_until_stopped(joint_bfs(&members)).await {
+            Ok(termination) => {
+                // the confirmation pass of every target needs the shared working trees
+                let mut finished = Vec::with_capacity(members.len());
+                for member in &members {
+                    finished.push(
+                        member
+                            .analyzer
+                            .finish_run(
+                                started,
+                                &member.function_paths,
+                                &member.run.logs_dir,
+                                &termination,
+                            )
+                            .await,
+                    );
+                }
+                for (member, finished) in members.iter().zip(finished) {
+                    if let Err(e) = member.analyzer.cleanup().await {
+                        tracing::warn!(
+                            "Failed to clean the working directory of {}: {}",
+                            member.analyzer.cve_id,
+                            e
+                        );
+                    }
+                    results[member.result].1 = finished
+                        .and_then(|()| member.analyzer.termination_result(termination.clone()));
+                }
+            }
+            Err(e) => {
+                for member in &members {
+                    results[member.result].1 =
+                        Err(CveTrackerError::Analyzer(anyhow::anyhow!("{:#}", e)));
+                }
+            }
+        }
+        tracing::info!(
+            "Batch of {} target(s): {} crate analyses shared, about {:.1}s of call-cg4rs saved",
+            members.len(),
+            RunCounters::get(&COUNTERS.shared_analyses),
+            RunCounters::get(&COUNTERS.shared_analysis_millis) as f64 / 1000.0
+        );
+    }
+    for (cve_id, result) in &results {
+        if let Err(e) = result {
+            tracing::error!("Analysis of {} failed: {:#}", cve_id, e);
+        }
+    }
+    results
+}
+
+/// A target of [`analyze_batch`] whose run started
+struct BatchMember {
+    analyzer: DependencyAnalyzer,
+    function_paths: String,
+    run: RunStart,
+    /// its index in the results of the batch
+    result: usize,
+}
+
+/// A dependent a member of a joint BFS selected for one of its vulnerable
+/// nodes, not fetched yet
+struct Candidate {
+    member: usize,
+    parent: Arc<BFSNode>,
+    reexported_targets: Vec<String>,
+    dependency: ReverseDependency,
+}
+
+/// The BFS of all the members of a batch: every level is a list of crate
+/// versions, each with the nodes of the members that reached it. Each member
+/// keeps its own visited set and level sampling, so it analyzes the crates its
+/// own run would.
+async fn joint_bfs(members: &[BatchMember]) -> Result<()> {
+    let mut level = Vec::new();
+    for (i, member) in members.iter().enumerate() {
+        member
+            .analyzer
+            .analyze_roots(
+                &member.run.roots,
+                &member.function_paths,
+                &member.run.logs_dir,
+            )
+            .await?;
+        let roots = member
+            .analyzer
+            .sample_level(member.run.roots.iter().cloned().collect(), 0, |node| {
+                format!("{}-{}", node.krate.name, node.krate.version)
+            })
+            .await;
+        level.extend(roots.into_iter().map(|root| vec![(i, root)]));
+    }
+    let mut visited = vec![HashSet::new(); members.len()];
+    let mut depth = 0;
+    while !level.is_empty() {
+        let no_output_before = RunCounters::get(&COUNTERS.analyzer_no_output);
+        let candidates = process_joint_level(members, level).await;
+        let no_output = RunCounters::get(&COUNTERS.analyzer_no_output) - no_output_before;
+        if no_output > 0 {
+            tracing::info!(
+                "BFS level {}: skipped {} crate(s) for which call-cg4rs wrote no callers file (details at debug level)",
+                depth,
+                no_output
+            );
+        }
+        depth += 1;
+
+        let mut by_member: Vec<Vec<Candidate>> = members.iter().map(|_| Vec::new()).collect();
+        for candidate in candidates {
+            let key = (
+                candidate.dependency.name.clone(),
+                utils::normalize_version(&candidate.dependency.version),
+            );
+            if visited[candidate.member].insert(key) {
+                by_member[candidate.member].push(candidate);
+            }
+        }
+        let mut selected = Vec::new();
+        for (member, candidates) in members.iter().zip(by_member) {
+            selected.extend(
+                member
+                    .analyzer
+                    .sample_level(candidates, depth, |candidate| {
+                        format!(
+                            "{}-{}",
+                            candidate.dependency.name, candidate.dependency.version
+                        )
+                    })
+                    .await,
+            );
+        }
+        level = create_joint_nodes(members, selected).await;
+    }
+    Ok(())
+}
+
+/// process a level of the joint BFS, return the dependents selected for the next one
+async fn process_joint_level(
+    members: &[BatchMember],
+    level: Vec<Vec<(usize, Arc<BFSNode>)>>,
+) -> Vec<Candidate> {
+    let first = &members[0].analyzer;
+    let inflight = &first.inflight;
+    let delay = first.config.inter_crate_delay();
+    futures_stream::iter(level)
+        // the next crate is pulled at most every `delay`, spacing the starts
+        .then(async |group| {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            group
+        })
+        .map(async |group| {
+            // the semaphore is never closed
+            let _permit = match inflight {
+                Some(inflight) => inflight.acquire().await.ok(),
+                None => None,
+            };
+            process_joint_group(members, group).await
+        })
+        .buffer_unordered(first.config.max_concurrent_bfs_nodes())
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Analyze a crate version reached by one or more members and return the
+/// dependents they select next
+async fn process_joint_group(
+    members: &[BatchMember],
+    group: Vec<(usize, Arc<BFSNode>)>,
+) -> Vec<Candidate> {
+    for (i, bfs_node) in &group {
+        members[*i].analyzer.record_bfs_tree_entry(bfs_node).await;
+    }
+    let analyses = match group.as_slice() {
+        [(i, bfs_node)] => {
+            let member = &members[*i];
+            member
+                .analyzer
+                .check_bfs_node_vulnerable(
+                    bfs_node.clone(),
+                    &bfs_node.function_paths(&member.function_paths),
+                    &member.analyzer.cve_id,
+                    &member.run.logs_dir,
+                )
+                .await
+                .map(|analysis| vec![analysis])
+        }
+        _ => check_shared_bfs_nodes(members, &group).await,
+    };
+    let analyses = match analyses {
+        Ok(analyses) => analyses,
+        Err(e) => {
+            tracing::error!(
+                "Failed to process single BFS node {}: {}",
+                group[0].1.krate.name,
+                e
+            );
+            return vec![];
+        }
+    };
+    let mut candidates = Vec::new();
+    for ((i, bfs_node), analysis) in group.into_iter().zip(analyses) {
+        let analyzer = &members[i].analyzer;
+        let function_paths = bfs_node.function_paths(&members[i].function_paths);
+        let Some(reexported_targets) = analyzer
+            .after_analysis(&bfs_node, &analysis, &function_paths)
+            .await
+        else {
+            continue;
+        };
+        match analyzer.select_dependents(&bfs_node).await {
+            Ok(dependents) => {
+                candidates.extend(dependents.into_iter().map(|dependency| Candidate {
+                    member: i,
+                    parent: bfs_node.clone(),
+                    reexported_targets: reexported_targets.clone(),
+                    dependency,
+                }))
+            }
+            Err(e) => tracing::error!(
+                "[{}] Failed to process single BFS node {}: {}",
+                analyzer.cve_id,
+                bfs_node.krate.name,
+                e
+            ),
+        }
+    }
+    candidates
+}
+
+/// Patch the parents of every member into the working copy of a crate version
+/// they share, run the caller finder of the first one once with the union of
+/// their function paths and record for each member the callers of its own
+async fn check_shared_bfs_nodes(
+    members: &[BatchMember],
+    group: &[(usize, Arc<BFSNode>)],
+) -> Result<Vec<CrateAnalysis>> {
+    let (first, first_node) = &group[0];
+    let krate = &first_node.krate;
+    tracing::info!(
+        "[{}:{}] Reached by {} targets of the batch, analyzed once for all of them",
+        krate.name,
+        krate.version,
+        group.len()
+    );
+    for (i, bfs_node) in group {
+        members[*i].analyzer.patch_parent(bfs_node).await?;
+    }
+    let lockfile = DependencyAnalyzer::lock_dependencies(krate).await?;
+    let function_paths: Vec<String> = group
+        .iter()
+        .map(|(i, bfs_node)| bfs_node.function_paths(&members[*i].function_paths))
+        .collect();
+    let mut union: Vec<&str> = Vec::new();
+    for path in function_paths
+        .iter()
+        .flat_map(|paths| paths.split(','))
+        .map(str::trim)
+    {
+        if !path.is_empty() && !union.contains(&path) {
+            union.push(path);
+        }
+    }
+
+    let started = std::time::Instant::now();
+    let analysis_result = members[*first]
+        .analyzer
+        .caller_finder
+        .find_callers(krate, &union.join(","), &members[*first].run.logs_dir)
+        .await;
+    let shared = group.len() as u64 - 1;
+    RunCounters::add(&COUNTERS.shared_analyses, shared);
+    RunCounters::add(
+        &COUNTERS.shared_analysis_millis,
+        started.elapsed().as_millis() as u64 * shared,
+    );
+    krate.cargo_clean().await?;
+
+    let mut analyses = Vec::with_capacity(group.len());
+    for ((i, bfs_node), function_paths) in group.iter().zip(&function_paths) {
+        let targets: Vec<&str> = function_paths.split(',').map(str::trim).collect();
+        let member_result = match &analysis_result {
+            Ok(files) => Ok(files
+                .as_ref()
+                .map(|files| {
+                    files
+                        .iter()
+                        .filter(|file| file_targets_one_of(file, &targets))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+                .filter(|files| !files.is_empty())),
+            Err(e) => Err(e),
+        };
+        analyses.push(
+            members[*i]
+                .analyzer
+                .record_analysis(bfs_node, member_result, lockfile.as_deref())
+                .await?,
+        );
+    }
+    Ok(analyses)
+}
+
+/// whether `file` holds the callers of one of `targets`, by its `target` or
+/// else its `callers-<path>.json` name
+fn file_targets_one_of(file: &CallgraphFile, targets: &[&str]) -> bool {
+    let function = crate::stats::function_of_file(
+        &serde_json::json!({"file": file.file, "file-content": file.file_content}),
+    );
+    targets
+        .iter()
+        .any(|target| *target == function || target.replace("::", "_") == function)
+}
+
+/// Fetch the dependents selected at a level of the joint BFS, once per crate
+/// version, and group the nodes of the members that share them
+async fn create_joint_nodes(
+    members: &[BatchMember],
+    candidates: Vec<Candidate>,
+) -> Vec<Vec<(usize, Arc<BFSNode>)>> {
+    let mut groups: Vec<Vec<Candidate>> = Vec::new();
+    let mut by_version: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for candidate in candidates {
+        let key = (
+            candidate.dependency.name.clone(),
+            utils::normalize_version(&candidate.dependency.version),
+        );
+        let groups_of_version = by_version.entry(key).or_default();
+        // a working copy patches a single version of each parent crate
+        let joined = groups_of_version.iter().copied().find(|&g| {
+            groups[g].iter().all(|other| {
+                other.parent.krate.name != candidate.parent.krate.name
+                    || other.parent.krate.version == candidate.parent.krate.version
+            })
+        });
+        match joined {
+            Some(g) => groups[g].push(candidate),
+            None => {
+                groups_of_version.push(groups.len());
+                groups.push(vec![candidate]);
+            }
+        }
+    }
+    futures_stream::iter(groups)
+        .map(async |group| {
+            let first = &group[0];
+            let (name, version) = (&first.dependency.name, &first.dependency.version);
+            let fs_manager = members[first.member].analyzer.fs_manager.clone();
+            match Krate::create(name, version, first.parent.krate.dir_idx, fs_manager).await {
+                Ok(krate) => Some(
+                    group
+                        .into_iter()
+                        .map(|candidate| {
+                            (
+                                candidate.member,
+                                Arc::new(BFSNode {
+                                    krate: krate.clone(),
+                                    branch: candidate.parent.branch.clone(),
+                                    dependency_kind: Some(candidate.dependency.kind),
+                                    reexported_targets: candidate.reexported_targets,
+                                    parent: Some(BFSParent::new(
+                                        candidate.parent,
+                                        &members[candidate.member].analyzer.config,
+                                    )),
+                                }),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                Err(e) => {
+                    for candidate in &group {
+                        members[candidate.member]
+                            .analyzer
+                            .record_download_failure(
+                                name,
+                                version,
+                                Some(&candidate.parent.krate),
+                                &e,
+                            )
+                            .await;
+                    }
+                    None
                 }
             }
+        })
+        .buffer_unordered(members[0].analyzer.config.max_concurrent_dep_download())
+        .filter_map(|group| async { group })
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callgraph::MockCallerFinder;
+    use crate::metadata::FileMetadataSource;
+    use crate::test_support::{Dependent, Sandbox, callers_file, result_subjects, sandbox};
+
+    /// `<p>-vuln` 1.0.0 with the direct dependents `<p>-a` and `<p>-b`, `<p>-c`
+    /// depending on `<p>-a` and `<p>-d` on `<p>-b`, all at 0.1.0
+    async fn publish_tree(sandbox: &Sandbox, cve_id: &str, p: &str) -> FileMetadataSource {
+        let vuln = format!("{p}-vuln");
+        let (a, b, c, d) = (
+            format!("{p}-a"),
+            format!("{p}-b"),
+            format!("{p}-c"),
+            format!("{p}-d"),
+        );
+        sandbox.publish(&vuln, "1.0.0", &[], "pub fn parse() {}\n");
+        sandbox.publish(&a, "0.1.0", &[(&vuln, "1")], "");
+        sandbox.publish(&b, "0.1.0", &[(&vuln, "1")], "");
+        sandbox.publish(&c, "0.1.0", &[(&a, "0.1")], "");
+        sandbox.publish(&d, "0.1.0", &[(&b, "0.1")], "");
+        sandbox
+            .metadata(
+                cve_id,
+                &[(&vuln, &["1.0.0"])],
+                &[
+                    (&vuln, &[(&a, "0.1.0", "^1"), (&b, "0.1.0", "^1")]),
+                    (&a, &[(&c, "0.1.0", "^0.1")]),
+                    (&b, &[(&d, "0.1.0", "^0.1")]),
+                ],
+            )
+            .await
+    }
+
+    /// the mock reports callers in `<p>-a` and `<p>-c`
+    fn mock_finder(p: &str) -> MockCallerFinder {
+        let target = format!("{}_vuln::parse", p.replace('-', "_"));
+        MockCallerFinder::new()
+            .with_result(
+                &format!("{p}-a"),
+                "0.1.0",
+                vec![callers_file(&target, &["a::run"])],
+            )
+            .with_result(
+                &format!("{p}-c"),
+                "0.1.0",
+                vec![callers_file(&target, &["c::run"])],
+            )
+    }
+
+    async fn analyzer(cve_id: &str, source: FileMetadataSource, p: &str) -> DependencyAnalyzer {
+        DependencyAnalyzer::with_metadata_source(cve_id, Arc::new(source))
+            .await
+            .unwrap()
+            .with_caller_finder(Arc::new(mock_finder(p)))
+    }
+
+    /// a config file with the `key = value` lines
+    fn config_of(lines: &str) -> Arc<Config> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cvetracker.toml");
+        std::fs::write(&path, lines).unwrap();
+        Arc::new(Config::load(Some(&path)).unwrap())
+    }
+
+    #[tokio::test]
+    async fn the_bfs_follows_the_crates_the_mock_finds_callers_in() {
+        let (sandbox, _guard) = sandbox().await;
+        let (cve_id, p) = ("TEST-0606", "t606");
+        let source = publish_tree(sandbox, cve_id, p).await;
+        let analyzer = analyzer(cve_id, source, p).await;
+        analyzer
+            .analyze(&format!("{p}-vuln"), "<=1.0.0", "t606_vuln::parse")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result_subjects(&sandbox.results_dir(cve_id)),
+            ["t606-a-0.1.0", "t606-c-0.1.0"]
+        );
+        // `d` depends on `b`, which has no callers, so it is never reached
+        let mut processed: Vec<String> = analyzer
+            .bfs_tree
+            .lock()
+            .await
+            .iter()
+            .map(|entry| format!("{}-{}", entry.name, entry.version))
+            .collect();
+        processed.sort();
+        assert_eq!(
+            processed,
+            [
+                "t606-a-0.1.0",
+                "t606-b-0.1.0",
+                "t606-c-0.1.0",
+                "t606-vuln-1.0.0"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_run_metrics_count_the_crates_fetched_by_a_mocked_run() {
+        let (sandbox, _guard) = sandbox().await;
+        let (cve_id, p) = ("TEST-0608", "t608");
+        let source = publish_tree(sandbox, cve_id, p).await;
+        analyzer(cve_id, source, p)
+            .await
+            .analyze(&format!("{p}-vuln"), "<=1.0.0", "t608_vuln::parse")
+            .await
+            .unwrap();
+
+        // `d` is never reached, the mock stands in for call-cg4rs
+        let fetched = ["vuln-1.0.0", "a-0.1.0", "b-0.1.0", "c-0.1.0"];
+        let bytes: u64 = fetched
+            .iter()
+            .map(|krate| {
+                let archive = sandbox.root.join(format!("registry/{p}-{krate}.crate"));
+                std::fs::metadata(archive).unwrap().len()
+            })
+            .sum();
+        let metrics = RunManifest::load(cve_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .run_metrics
+            .unwrap();
+        assert_eq!(metrics.crates_downloaded, 4);
+        assert_eq!(metrics.bytes_downloaded, bytes);
+        assert_eq!(metrics.analyzer_invocations, 0);
+        assert!(metrics.wall_clock_secs > 0.0);
+    }
+
+    #[tokio::test]
+    async fn a_crate_that_never_downloads_is_recorded() {
+        let (sandbox, _guard) = sandbox().await;
+        let cve_id = "TEST-0610";
+        sandbox.publish("t610-vuln", "1.0.0", &[], "pub fn parse() {}\n");
+        sandbox.publish("t610-a", "0.1.0", &[("t610-vuln", "1")], "");
+        // `t610-gone` is in the database but not in the registry
+        let source = sandbox
+            .metadata(
+                cve_id,
+                &[("t610-vuln", &["1.0.0"])],
+                &[(
+                    "t610-vuln",
+                    &[("t610-a", "0.1.0", "^1"), ("t610-gone", "0.1.0", "^1")],
+                )],
+            )
+            .await;
+        let analyzer = analyzer(cve_id, source, "t610").await;
+        analyzer
+            .analyze("t610-vuln", "<=1.0.0", "t610_vuln::parse")
+            .await
+            .unwrap();
+
+        let failures = DownloadFailure::load_all(cve_id).await.unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(
+            (failures[0].name.as_str(), failures[0].version.as_str()),
+            ("t610-gone", "0.1.0")
+        );
+        assert_eq!(failures[0].parent.as_deref(), Some("t610-vuln-1.0.0"));
+        assert!(!failures[0].error.is_empty());
+        // the other dependent is still analyzed
+        assert!(
+            analyzer
+                .bfs_tree
+                .lock()
+                .await
+                .iter()
+                .any(|entry| entry.name == "t610-a")
+        );
+    }
+
+    #[tokio::test]
+    async fn the_working_root_of_the_cve_is_removed_after_the_run() {
+        let (sandbox, _guard) = sandbox().await;
+        let (cve_id, p) = ("TEST-0614", "t614");
+        let source = publish_tree(sandbox, cve_id, p).await;
+        let analyzer = analyzer(cve_id, source, p).await;
+        let working_root = crate::config::current().working_dir().join(cve_id);
+        assert!(working_root.is_dir());
+        analyzer
+            .analyze(&format!("{p}-vuln"), "<=1.0.0", "t614_vuln::parse")
+            .await
+            .unwrap();
+
+        assert!(!working_root.exists());
+        // the results and the downloads are kept
+        assert_eq!(
+            result_subjects(&sandbox.results_dir(cve_id)),
+            ["t614-a-0.1.0", "t614-c-0.1.0"]
+        );
+        assert!(
+            crate::config::current()
+                .download_dir()
+                .join("t614-a/t614-a-0.1.0.crate")
+                .exists()
+        );
+    }
+
+    /// a finder that holds every call for a while and records the most calls
+    /// in progress at once
+    #[derive(Debug, Default)]
+    struct InflightFinder {
+        current: std::sync::atomic::AtomicUsize,
+        max: std::sync::atomic::AtomicUsize,
+    }
+
+    impl CallerFinder for InflightFinder {
+        fn find_callers<'a>(
+            &'a self,
+            _krate: &'a Krate,
+            _function_paths: &'a str,
+            _logs_dir: &'a Path,
+        ) -> BoxFuture<'a, Result<Option<Vec<CallgraphFile>>>> {
+            Box::pin(async move {
+                let now = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                self.current.fetch_sub(1, Ordering::SeqCst);
+                Ok(None)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn no_more_than_max_inflight_crates_are_processed_at_once() {
+        let (sandbox, _guard) = sandbox().await;
+        let cve_id = "TEST-0665";
+        sandbox.publish("t665-vuln", "1.0.0", &[], "pub fn parse() {}\n");
+        let dependents: Vec<String> = (0..6).map(|i| format!("t665-d{i}")).collect();
+        for dependent in &dependents {
+            sandbox.publish(dependent, "0.1.0", &[("t665-vuln", "1")], "");
         }
-        Ok(true)
+        let level: Vec<(&str, &str, &str)> = dependents
+            .iter()
+            .map(|dependent| (dependent.as_str(), "0.1.0", "^1"))
+            .collect();
+        let source = sandbox
+            .metadata(
+                cve_id,
+                &[("t665-vuln", &["1.0.0"])],
+                &[("t665-vuln", &level)],
+            )
+            .await;
+        let config = config_of("max_inflight_crates = 2\n");
+
+        let finder = Arc::new(InflightFinder::default());
+        DependencyAnalyzer::with_config(cve_id, Arc::new(source), config)
+            .await
+            .unwrap()
+            .with_caller_finder(finder.clone())
+            .analyze("t665-vuln", "<=1.0.0", "t665_vuln::parse")
+            .await
+            .unwrap();
+
+        // the six dependents are one level, `MAX_CONCURRENT_BFS_NODES` alone allows 32
+        assert_eq!(finder.max.load(Ordering::SeqCst), 2);
+    }
+
+    /// a [`MockCallerFinder`] that never returns for the `hang` crate
+    #[derive(Debug)]
+    struct HangingFinder {
+        mock: MockCallerFinder,
+        hang: String,
+        started: tokio::sync::Notify,
+    }
+
+    impl CallerFinder for HangingFinder {
+        fn find_callers<'a>(
+            &'a self,
+            krate: &'a Krate,
+            function_paths: &'a str,
+            logs_dir: &'a Path,
+        ) -> BoxFuture<'a, Result<Option<Vec<CallgraphFile>>>> {
+            if krate.name != self.hang {
+                return self.mock.find_callers(krate, function_paths, logs_dir);
+            }
+            Box::pin(async move {
+                self.started.notify_one();
+                std::future::pending().await
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_run_stops_promptly_and_keeps_a_valid_manifest() {
+        let (sandbox, _guard) = sandbox().await;
+        let (cve_id, p) = ("TEST-0675", "t675");
+        let source = publish_tree(sandbox, cve_id, p).await;
+        let finder = Arc::new(HangingFinder {
+            mock: mock_finder(p),
+            hang: format!("{p}-b"),
+            started: tokio::sync::Notify::new(),
+        });
+        let token = CancellationToken::new();
+        let analyzer = DependencyAnalyzer::with_metadata_source(cve_id, Arc::new(source))
+            .await
+            .unwrap()
+            .with_caller_finder(finder.clone())
+            .with_cancellation(token.clone());
+
+        let (vuln, results_dir) = (format!("{p}-vuln"), sandbox.results_dir(cve_id));
+        let cancel = async {
+            // b hangs once a, analyzed next to it, has written its result
+            finder.started.notified().await;
+            while !result_subjects(&results_dir).contains(&format!("{p}-a-0.1.0")) {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            token.cancel();
+            std::time::Instant::now()
+        };
+        let (result, cancelled_at) = tokio::join!(
+            tokio::time::timeout(
+                std::time::Duration::from_secs(30),
+                analyzer.analyze(&vuln, "<=1.0.0", "t675_vuln::parse"),
+            ),
+            cancel
+        );
+        assert!(cancelled_at.elapsed() < std::time::Duration::from_secs(5));
+        let error = result.expect("the run did not stop").unwrap_err();
+        assert!(matches!(error, CveTrackerError::Cancelled(_)));
+
+        let manifest = RunManifest::load(cve_id).await.unwrap().unwrap();
+        assert_eq!(manifest.termination, Some(RunTermination::Cancelled));
+        assert!(manifest.finished_at.is_some());
+        assert_eq!(manifest.written_results, [format!("{p}-a-0.1.0")]);
+        assert_eq!(result_subjects(&results_dir), [format!("{p}-a-0.1.0")]);
+        let forest: Vec<BfsTreeNode> =
+            serde_json::from_str(&std::fs::read_to_string(BfsTreeNode::path(cve_id)).unwrap())
+                .unwrap();
+        assert_eq!(forest.len(), 1);
+    }
+
+    /// a hook that records every crate it is run for
+    #[derive(Debug, Default)]
+    struct RecordingHook {
+        crates: std::sync::Mutex<Vec<CrateAnalysis>>,
+    }
+
+    impl CrateHook for RecordingHook {
+        fn after_crate<'a>(&'a self, analysis: &'a CrateAnalysis) -> BoxFuture<'a, ()> {
+            self.crates.lock().unwrap().push(analysis.clone());
+            Box::pin(async {})
+        }
+    }
+
+    #[tokio::test]
+    async fn the_crate_hook_runs_once_per_processed_crate() {
+        let (sandbox, _guard) = sandbox().await;
+        let (cve_id, p) = ("TEST-0676", "t676");
+        let source = publish_tree(sandbox, cve_id, p).await;
+        let hook = Arc::new(RecordingHook::default());
+        analyzer(cve_id, source, p)
+            .await
+            .with_crate_hook(hook.clone())
+            .analyze(&format!("{p}-vuln"), "<=1.0.0", "t676_vuln::parse")
+            .await
+            .unwrap();
+
+        let mut crates = hook.crates.lock().unwrap().clone();
+        crates.sort_by(|a, b| a.name.cmp(&b.name));
+        let summary: Vec<(String, Option<String>, bool)> = crates
+            .iter()
+            .map(|c| {
+                (
+                    format!("{}-{}", c.name, c.version),
+                    c.parent.clone(),
+                    c.vulnerable,
+                )
+            })
+            .collect();
+        let vuln = Some(format!("{p}-vuln-1.0.0"));
+        // d is not reached, b has no callers
+        assert_eq!(
+            summary,
+            [
+                (format!("{p}-a-0.1.0"), vuln.clone(), true),
+                (format!("{p}-b-0.1.0"), vuln, false),
+                (format!("{p}-c-0.1.0"), Some(format!("{p}-a-0.1.0")), true),
+                (format!("{p}-vuln-1.0.0"), None, true),
+            ]
+        );
+        let results_dir = sandbox.results_dir(cve_id);
+        let result_files: Vec<PathBuf> = crates
+            .iter()
+            .filter_map(|c| c.result_file.clone())
+            .collect();
+        assert_eq!(
+            result_files,
+            [
+                results_dir.join(format!("{p}-a-0.1.0.txt")),
+                results_dir.join(format!("{p}-c-0.1.0.txt")),
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_parents_do_not_keep_a_deep_chain_alive() {
+        // a BFS path of `len` crates, the leaf and a weak reference to the root
+        let chain = |config: &Config, len: usize| {
+            let node = |name: String, parent: Option<BFSParent>| {
+                Arc::new(BFSNode {
+                    krate: crate::test_support::krate_at(&name, Path::new("")),
+                    parent,
+                    reexported_targets: Vec::new(),
+                    dependency_kind: None,
+                    branch: "1.0.0".to_string(),
+                })
+            };
+            let root = node("vuln".to_string(), None);
+            let weak_root = Arc::downgrade(&root);
+            let mut leaf = root;
+            for depth in 1..len {
+                let parent = BFSParent::new(leaf, config);
+                leaf = node(format!("c{depth}"), Some(parent));
+            }
+            (leaf, weak_root)
+        };
+
+        let (leaf, root) = chain(&config_of(""), 200);
+        assert_eq!(leaf.depth(), 199);
+        assert!(root.upgrade().is_some());
+
+        let (leaf, root) = chain(&config_of("compact_bfs_parents = 1\n"), 200);
+        assert_eq!(leaf.depth(), 199);
+        let parent = leaf.parent.as_ref().unwrap();
+        assert_eq!((parent.name(), parent.version()), ("c198", "1.0.0"));
+        assert!(root.upgrade().is_none());
+    }
+
+    #[tokio::test]
+    async fn compact_parents_rebuild_the_same_tree_of_a_linear_chain() {
+        let (sandbox, _guard) = sandbox().await;
+        // t689-vuln <- t689-l1 <- ... <- t689-l6, every crate calls the target
+        let names: Vec<String> = std::iter::once("t689-vuln".to_string())
+            .chain((1..=6).map(|i| format!("t689-l{i}")))
+            .collect();
+        let mut finder = MockCallerFinder::new();
+        for pair in names.windows(2) {
+            sandbox.publish(&pair[1], "1.0.0", &[(&pair[0], "1")], "");
+            finder = finder.with_result(
+                &pair[1],
+                "1.0.0",
+                vec![callers_file("t689_vuln::parse", &["run"])],
+            );
+        }
+        sandbox.publish(&names[0], "1.0.0", &[], "pub fn parse() {}\n");
+        let dependents: Vec<(String, [Dependent<'_>; 1])> = names
+            .windows(2)
+            .map(|pair| (pair[0].clone(), [(pair[1].as_str(), "1.0.0", "^1")]))
+            .collect();
+        let dependents: Vec<(&str, &[Dependent<'_>])> = dependents
+            .iter()
+            .map(|(name, level)| (name.as_str(), level.as_slice()))
+            .collect();
+
+        let mut trees = Vec::new();
+        for (cve_id, config) in [
+            ("TEST-0689-FULL", ""),
+            ("TEST-0689-COMPACT", "compact_bfs_parents = 1\n"),
+        ] {
+            let source = sandbox
+                .metadata(cve_id, &[(&names[0], &["1.0.0"])], &dependents)
+                .await;
+            DependencyAnalyzer::with_config(cve_id, Arc::new(source), config_of(config))
+                .await
+                .unwrap()
+                .with_caller_finder(Arc::new(finder.clone()))
+                .analyze(&names[0], "<=1.0.0", "t689_vuln::parse")
+                .await
+                .unwrap();
+            trees.push(std::fs::read_to_string(BfsTreeNode::path(cve_id)).unwrap());
+        }
+        assert_eq!(trees[0], trees[1]);
+
+        let forest: Vec<BfsTreeNode> = serde_json::from_str(&trees[1]).unwrap();
+        let mut path = Vec::new();
+        let mut node = &forest[0];
+        loop {
+            path.push((node.crate_name.clone(), node.depth));
+            match node.children.as_slice() {
+                [child] => node = child,
+                [] => break,
+                _ => panic!("a linear chain has one child per crate"),
+            }
+        }
+        let expected: Vec<(String, usize)> = names.iter().cloned().zip(0..).collect();
+        assert_eq!(path, expected);
+    }
+
+    #[tokio::test]
+    async fn self_callers_are_analyzed_only_with_include_self() {
+        let (sandbox, _guard) = sandbox().await;
+        let p = "t694";
+        let finder = mock_finder(p).with_result(
+            &format!("{p}-vuln"),
+            "1.0.0",
+            vec![callers_file(
+                "t694_vuln::parse",
+                &["t694_vuln::run", "t694_vuln::helper"],
+            )],
+        );
+        let mut subjects = Vec::new();
+        for (cve_id, include_self) in [("TEST-0694-OFF", false), ("TEST-0694-ON", true)] {
+            let source = publish_tree(sandbox, cve_id, p).await;
+            DependencyAnalyzer::with_metadata_source(cve_id, Arc::new(source))
+                .await
+                .unwrap()
+                .with_caller_finder(Arc::new(finder.clone()))
+                .with_include_self(include_self)
+                .analyze(&format!("{p}-vuln"), "<=1.0.0", "t694_vuln::parse")
+                .await
+                .unwrap();
+            let global = crate::stats::compute_stats(cve_id).await.unwrap();
+            let manifest = RunManifest::load(cve_id).await.unwrap().unwrap();
+            assert_eq!(manifest.include_self, include_self);
+            subjects.push((
+                result_subjects(&sandbox.results_dir(cve_id)),
+                global.self_callers,
+                global.total_callers,
+            ));
+        }
+        let dependents = [format!("{p}-a-0.1.0"), format!("{p}-c-0.1.0")];
+        assert_eq!(subjects[0], (dependents.to_vec(), 0, 2));
+        assert_eq!(
+            subjects[1],
+            (
+                [vec!["__self__-1.0.0".to_string()], dependents.to_vec()].concat(),
+                2,
+                4
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn the_bfs_runs_on_the_metadata_fixture_files() {
+        let (sandbox, _guard) = sandbox().await;
+        let cve_id = "TEST-0698";
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/metadata");
+        let source = FileMetadataSource::load(
+            &fixture.join("reverse-deps.json"),
+            &fixture.join("versions.json"),
+        )
+        .await
+        .unwrap();
+        // the crates the BFS fetches; fx-build and fx-fixed are never selected
+        for version in ["1.0.0", "1.1.0"] {
+            sandbox.publish("fx-vuln", version, &[], "pub fn parse() {}\n");
+        }
+        sandbox.publish("fx-app", "0.1.0", &[("fx-vuln", "1")], "");
+        sandbox.publish("fx-app", "0.2.0", &[("fx-vuln", "1.1")], "");
+        sandbox.publish("fx-cli", "1.0.0", &[("fx-app", "0.2")], "");
+        let target = "fx_vuln::parse";
+        let finder = MockCallerFinder::new()
+            .with_result("fx-app", "0.1.0", vec![callers_file(target, &["app::old"])])
+            .with_result("fx-app", "0.2.0", vec![callers_file(target, &["app::new"])])
+            .with_result(
+                "fx-cli",
+                "1.0.0",
+                vec![callers_file(target, &["cli::main"])],
+            );
+
+        DependencyAnalyzer::with_metadata_source(cve_id, Arc::new(source))
+            .await
+            .unwrap()
+            .with_caller_finder(Arc::new(finder))
+            .analyze("fx-vuln", "<2.0.0", target)
+            .await
+            .unwrap();
+
+        println!("{:?}", result_subjects(&sandbox.results_dir(cve_id)));
+        println!(
+            "{}",
+            std::fs::read_to_string(BfsTreeNode::path(cve_id)).unwrap()
+        );
+    }
+
+    /// a [`MockCallerFinder`] that records the function paths of every call
+    #[derive(Debug, Default)]
+    struct CountingFinder {
+        mock: MockCallerFinder,
+        calls: std::sync::Mutex<Vec<(String, String)>>,
+    }
+
+    impl CallerFinder for CountingFinder {
+        fn find_callers<'a>(
+            &'a self,
+            krate: &'a Krate,
+            function_paths: &'a str,
+            logs_dir: &'a Path,
+        ) -> BoxFuture<'a, Result<Option<Vec<CallgraphFile>>>> {
+            self.calls.lock().unwrap().push((
+                format!("{}-{}", krate.name, krate.version),
+                function_paths.to_string(),
+            ));
+            self.mock.find_callers(krate, function_paths, logs_dir)
+        }
+    }
+
+    #[tokio::test]
+    async fn a_batch_analyzes_a_shared_dependent_once_and_splits_its_callers() {
+        let (sandbox, _guard) = sandbox().await;
+        let (x, y, d, e) = ("t627-x", "t627-y", "t627-d", "t627-e");
+        sandbox.publish(x, "1.0.0", &[], "pub fn parse() {}\n");
+        sandbox.publish(y, "1.0.0", &[], "pub fn parse() {}\n");
+        sandbox.publish(d, "0.1.0", &[(x, "1"), (y, "1")], "");
+        sandbox.publish(e, "0.1.0", &[(x, "1")], "");
+        let source = sandbox
+            .metadata(
+                "TEST-0627",
+                &[(x, &["1.0.0"]), (y, &["1.0.0"])],
+                &[
+                    (x, &[(d, "0.1.0", "^1"), (e, "0.1.0", "^1")]),
+                    (y, &[(d, "0.1.0", "^1")]),
+                ],
+            )
+            .await;
+        // `d` calls both targets, `e` only the one of `x`
+        let finder = Arc::new(CountingFinder {
+            mock: MockCallerFinder::new()
+                .with_result(
+                    d,
+                    "0.1.0",
+                    vec![
+                        callers_file("t627_x::parse", &["d::run_x"]),
+                        callers_file("t627_y::parse", &["d::run_y"]),
+                    ],
+                )
+                .with_result(e, "0.1.0", vec![callers_file("t627_x::parse", &["e::run"])]),
+            ..Default::default()
+        });
+        let target = |cve_id: &str, crate_name: &str, function_paths: &str| AnalysisTarget {
+            cve_id: cve_id.to_string(),
+            crate_name: crate_name.to_string(),
+            version_range: "<=1.0.0".to_string(),
+            function_paths: function_paths.to_string(),
+        };
+        let results = analyze_batch(
+            Arc::new(source),
+            vec![
+                target("TEST-0627-X", x, "t627_x::parse"),
+                target("TEST-0627-Y", y, "t627_y::parse"),
+            ],
+            |analyzer| analyzer.with_caller_finder(finder.clone()),
+        )
+        .await;
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+
+        let mut calls = finder.calls.lock().unwrap().clone();
+        calls.sort();
+        assert_eq!(
+            calls,
+            [
+                (
+                    "t627-d-0.1.0".to_string(),
+                    "t627_x::parse,t627_y::parse".to_string()
+                ),
+                ("t627-e-0.1.0".to_string(), "t627_x::parse".to_string()),
+            ]
+        );
+        assert_eq!(RunCounters::get(&COUNTERS.shared_analyses), 1);
+        assert_eq!(
+            result_subjects(&sandbox.results_dir("TEST-0627-X")),
+            ["t627-d-0.1.0", "t627-e-0.1.0"]
+        );
+        assert_eq!(
+            result_subjects(&sandbox.results_dir("TEST-0627-Y")),
+            ["t627-d-0.1.0"]
+        );
+        let read = |cve_id: &str| {
+            std::fs::read_to_string(sandbox.results_dir(cve_id).join("t627-d-0.1.0.txt")).unwrap()
+        };
+        let (d_of_x, d_of_y) = (read("TEST-0627-X"), read("TEST-0627-Y"));
+        assert!(d_of_x.contains("d::run_x") && !d_of_x.contains("d::run_y"));
+        assert!(d_of_y.contains("d::run_y") && !d_of_y.contains("d::run_x"));
     }
 }
@@ -0,0 +1,124 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::database::{Database, dependency_kind_name};
+use crate::metadata::MetadataSource;
+use crate::utils;
+
+/// A direct dependent of the vulnerable crate, as the first BFS level would
+/// select it, read from the database alone: nothing is downloaded and
+/// `call-cg4rs` is not run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DependentRow {
+    /// the analyzed version of the vulnerable crate the dependent was selected for
+    pub target_version: String,
+    #[serde(rename = "crate")]
+    pub name: String,
+    pub version: String,
+    /// the version requirement on the vulnerable crate
+    pub req: String,
+    pub dependency_kind: &'static str,
+    /// the newest published version of the vulnerable crate matching `req`,
+    /// which a fresh resolution of the dependent would use
+    pub resolved_target_version: Option<String>,
+}
+
+/// List the dependents of `crate_name` selected for the analyzed versions in
+/// `version_range`, with the same rules as the BFS (`DEPENDENCY_KINDS`,
+/// `MATCH_VULNERABLE_RANGE`, `VERSION_ENDPOINTS`). `PRECHECK_CRATE_EXISTS` is
+/// not applied, so nothing but the database is queried.
+pub async fn list_dependents(
+    crate_name: &str,
+    version_range: &str,
+    since: Option<NaiveDate>,
+) -> Result<Vec<DependentRow>> {
+    let database = Database::new().await?;
+    list_dependents_from(&database, crate_name, version_range, since).await
+}
+
+/// [`list_dependents`] with the dependency graph of `source`
+pub async fn list_dependents_from(
+    source: &dyn MetadataSource,
+    crate_name: &str,
+    version_range: &str,
+    since: Option<NaiveDate>,
+) -> Result<Vec<DependentRow>> {
+    let versions = match since {
+        Some(since) => source.query_crate_versions_since(crate_name, since).await?,
+        None => source.query_crate_versions(crate_name).await?,
+    };
+    let vulnerable_range = if crate::config::var("MATCH_VULNERABLE_RANGE").is_ok_and(|v| v == "1") {
+        Some(utils::VulnerableRange::new(version_range, &versions)?)
+    } else {
+        None
+    };
+
+    let mut rows = Vec::new();
+    for (_, target) in utils::select_two_end_vers(versions, version_range).await {
+        let target = target.to_string();
+        let dependents = utils::get_reverse_deps(
+            source,
+            crate_name,
+            &target,
+            since,
+            vulnerable_range.as_ref(),
+        )
+        .await?;
+        rows.extend(dependents.into_iter().map(|revdep| DependentRow {
+            target_version: target.clone(),
+            name: revdep.name,
+            version: revdep.version,
+            req: revdep.req,
+            dependency_kind: dependency_kind_name(revdep.kind),
+            resolved_target_version: revdep.resolved_target_version,
+        }));
+    }
+    Ok(rows)
+}
+
+/// The rows as CSV with a header line
+pub fn to_csv(rows: &[DependentRow]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    let content = writer.into_inner().map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(String::from_utf8(content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_dependents_of_each_analyzed_version_are_listed() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        let source = sandbox
+            .metadata(
+                "CVE-T673",
+                &[("t673-vuln", &["2.0.0", "1.1.0", "1.0.0"])],
+                &[(
+                    "t673-vuln",
+                    &[
+                        ("t673-a", "1.0.0", "^1"),
+                        ("t673-b", "0.1.0", "^1.1"),
+                        ("t673-c", "3.0.0", "^2"),
+                    ],
+                )],
+            )
+            .await;
+
+        let rows = list_dependents_from(&source, "t673-vuln", "<2.0.0", None)
+            .await
+            .unwrap();
+        // c requires the fixed 2.x, b only the newer vulnerable version
+        assert_eq!(
+            to_csv(&rows).unwrap(),
+            "target_version,crate,version,req,dependency_kind,resolved_target_version\n\
+             1.0.0,t673-a,1.0.0,^1,normal,1.1.0\n\
+             1.1.0,t673-a,1.0.0,^1,normal,1.1.0\n\
+             1.1.0,t673-b,0.1.0,^1.1,normal,1.1.0\n"
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use crate::config::Config;
+use std::path::{Path, PathBuf};
 
 use tokio::fs;
 
@@ -37,11 +38,8 @@ pub(crate) struct CrateVersionDir {
 }
 
 impl CrateVersionDir {
-    pub async fn root(cve_id: &str) -> Self {
-        let path = PathBuf::from(
-            &std::env::var("WORKING_DIR").unwrap_or_else(|_| "./downloads/working".to_string()),
-        )
-        .join(cve_id);
+    pub async fn root(cve_id: &str, working_dir: &Path) -> Self {
+        let path = working_dir.join(cve_id);
         fs::create_dir_all(&path).await.unwrap();
         Self {
             cve_id: cve_id.to_owned(),
@@ -68,15 +66,17 @@ impl CrateVersionDir {
 pub(crate) struct CrateWorkspaceFileSystemManager {
     workspaces: Vec<CrateWorkspace>,
     version_dirs: Vec<CrateVersionDir>,
+    /// `DOWNLOAD_DIR` of the config the manager was created with
+    download_dir: PathBuf,
 }
 
 impl CrateWorkspaceFileSystemManager {
     /// create a new crate workspace file system manager
-    pub async fn new(cve_id: &str) -> anyhow::Result<Self> {
+    pub async fn new(cve_id: &str, config: &Config) -> anyhow::Result<Self> {
         let workspaces = Vec::new();
         let mut version_dirs = Vec::new();
 
-        let pseudo_root_version_dir = CrateVersionDir::root(cve_id).await;
+        let pseudo_root_version_dir = CrateVersionDir::root(cve_id, &config.working_dir()).await;
         version_dirs.push(pseudo_root_version_dir);
 
         assert_eq!(version_dirs.len(), 1);
@@ -84,6 +84,7 @@ impl CrateWorkspaceFileSystemManager {
         Ok(Self {
             workspaces,
             version_dirs,
+            download_dir: config.download_dir(),
         })
     }
 
@@ -116,6 +117,46 @@ impl CrateWorkspaceFileSystemManager {
         Ok((self.workspaces.len() - 1, self.version_dirs.len() - 1))
     }
 
+    /// Remove the CVE-scoped working root ($WORKING_DIR/<cve_id>) with all the
+    /// nested workspaces. Refuse to delete anything that contains the download
+    /// directory or the analysis results.
+    pub async fn cleanup(&self) -> anyhow::Result<()> {
+        let root = &self.version_dirs[0];
+        if !root.path.exists() {
+            return Ok(());
+        }
+        let root_path = fs::canonicalize(&root.path).await?;
+        if root_path.file_name().and_then(|n| n.to_str()) != Some(root.cve_id.as_str()) {
+            return Err(anyhow::anyhow!(
+                "refuse to clean {}: not a CVE-scoped working directory",
+                root_path.display()
+            ));
+        }
+        let protected = [
+            self.download_dir.clone(),
+            crate::stats::analysis_results_dir(),
+        ];
+        for dir in protected {
+            if let Ok(dir) = fs::canonicalize(&dir).await
+                && dir.starts_with(&root_path)
+            {
+                return Err(anyhow::anyhow!(
+                    "refuse to clean {}: it contains {}",
+                    root_path.display(),
+                    dir.display()
+                ));
+            }
+        }
+        tracing::info!("Removing working directory {}", root_path.display());
+        fs::remove_dir_all(&root_path).await?;
+        Ok(())
+    }
+
+    /// where the crate archives of the run are downloaded
+    pub fn download_dir(&self) -> &Path {
+        &self.download_dir
+    }
+
     pub async fn get_krate_working_dir(&self, version_dir_index: CrateVersionDirIndex) -> PathBuf {
         let version_dir = self
             .version_dirs
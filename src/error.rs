@@ -0,0 +1,98 @@
+use crate::dependency_analyzer::AnalysisCancelled;
+
+/// The kinds of failure of the public API (`DependencyAnalyzer`, the database,
+/// `Krate::fetch_and_unzip_crate`, `run_function_analysis`), so embedders can
+/// retry or skip per kind. The wrapped error keeps the details and is what
+/// gets displayed; the crate works with `anyhow` internally.
+#[derive(Debug)]
+pub enum CveTrackerError {
+    /// querying the versions or reverse dependencies failed
+    Database(anyhow::Error),
+    /// a crate could not be downloaded, including a crate larger than `MAX_CRATE_SIZE_MB`
+    Download(anyhow::Error),
+    /// a downloaded crate could not be extracted or has no Cargo.toml
+    Extract(anyhow::Error),
+    /// `call-cg4rs`, the toolchain it runs with or the caller finder failed
+    Analyzer(anyhow::Error),
+    /// an input could not be parsed, e.g. the version range
+    Parse(anyhow::Error),
+    /// the results, the manifest or the working directory could not be written
+    Io(anyhow::Error),
+    /// the run was stopped by Ctrl-C or `RUN_MEMORY_MB`, the results so far are kept
+    Aborted(anyhow::Error),
+    /// the run was stopped through its `CancellationToken`
+    Cancelled(AnalysisCancelled),
+}
+
+impl CveTrackerError {
+    /// the wrapped error, `None` for `Cancelled`
+    fn wrapped(&self) -> Option<&anyhow::Error> {
+        match self {
+            Self::Database(e)
+            | Self::Download(e)
+            | Self::Extract(e)
+            | Self::Analyzer(e)
+            | Self::Parse(e)
+            | Self::Io(e)
+            | Self::Aborted(e) => Some(e),
+            Self::Cancelled(_) => None,
+        }
+    }
+
+    /// the wrapped error, e.g. to `downcast_ref` the underlying cause
+    pub fn into_inner(self) -> anyhow::Error {
+        match self {
+            Self::Database(e)
+            | Self::Download(e)
+            | Self::Extract(e)
+            | Self::Analyzer(e)
+            | Self::Parse(e)
+            | Self::Io(e)
+            | Self::Aborted(e) => e,
+            Self::Cancelled(e) => e.into(),
+        }
+    }
+}
+
+/// Transparent over the wrapped error: the message is its own and the source
+/// is its cause, so reporters printing the source chain show every cause once.
+/// `{:#}` prints the whole chain, like `anyhow`.
+impl std::fmt::Display for CveTrackerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self, self.wrapped()) {
+            (_, Some(e)) if f.alternate() => write!(f, "{:#}", e),
+            (_, Some(e)) => write!(f, "{}", e),
+            (Self::Cancelled(e), None) => write!(f, "{}", e),
+            (_, None) => unreachable!("only `Cancelled` wraps no anyhow error"),
+        }
+    }
+}
+
+impl std::error::Error for CveTrackerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.wrapped().and_then(|e| e.source())
+    }
+}
+
+impl From<AnalysisCancelled> for CveTrackerError {
+    fn from(cancelled: AnalysisCancelled) -> Self {
+        Self::Cancelled(cancelled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn the_source_is_the_cause_of_the_wrapped_error() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        let e = CveTrackerError::Io(anyhow::Error::new(io).context("Failed to write manifest"));
+        assert!(matches!(e, CveTrackerError::Io(_)));
+        assert_eq!(e.to_string(), "Failed to write manifest");
+        assert_eq!(format!("{:#}", e), "Failed to write manifest: no such file");
+        assert_eq!(e.source().unwrap().to_string(), "no such file");
+        assert!(e.source().unwrap().source().is_none());
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::database::Database;
+use crate::metadata::MetadataSource;
+use crate::utils;
+
+/// How many crates the analysis of a CVE could reach, from the dependency
+/// graph in the database alone: nothing is downloaded and `call-cg4rs` is not run.
+///
+/// The expansion selects dependents like the BFS does (`DEPENDENCY_KINDS`,
+/// `MATCH_VULNERABLE_RANGE`, `VERSION_ENDPOINTS`), but follows every dependent
+/// rather than only the ones with callers, so it is an upper bound.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AffectedEstimate {
+    pub cve_id: String,
+    pub crate_name: String,
+    pub version_range: String,
+    pub max_depth: usize,
+    /// crate versions first reached at each depth, index 0 is the analyzed
+    /// versions of the vulnerable crate
+    pub counts_by_depth: Vec<usize>,
+    /// dependent crate versions reached at depth 1 to `max_depth`
+    pub total: usize,
+    /// distinct crate names among them
+    pub distinct_crates: usize,
+    /// the expansion stopped at `max_depth` while the deepest level was not empty,
+    /// so more crates may be reachable
+    pub truncated: bool,
+}
+
+/// Expand the reverse dependencies of `crate_name` in `version_range` up to
+/// `max_depth` levels and count the crate versions reached at each depth.
+/// A crate version reached at several depths is counted at the first one.
+pub async fn estimate_affected_count(
+    cve_id: &str,
+    crate_name: &str,
+    version_range: &str,
+    max_depth: usize,
+) -> Result<AffectedEstimate> {
+    let database = Database::new().await?;
+    estimate_affected_count_from(&database, cve_id, crate_name, version_range, max_depth).await
+}
+
+/// [`estimate_affected_count`] with the dependency graph of `source`
+pub async fn estimate_affected_count_from(
+    source: &dyn MetadataSource,
+    cve_id: &str,
+    crate_name: &str,
+    version_range: &str,
+    max_depth: usize,
+) -> Result<AffectedEstimate> {
+    let versions = source.query_crate_versions(crate_name).await?;
+    let vulnerable_range = if crate::config::var("MATCH_VULNERABLE_RANGE").is_ok_and(|v| v == "1") {
+        Some(utils::VulnerableRange::new(version_range, &versions)?)
+    } else {
+        None
+    };
+
+    let mut level: Vec<(String, String)> = utils::select_two_end_vers(versions, version_range)
+        .await
+        .into_iter()
+        .map(|(_, v)| (crate_name.to_string(), v.to_string()))
+        .collect();
+    let mut seen: HashSet<(String, String)> = level.iter().cloned().collect();
+    let mut counts_by_depth = vec![level.len()];
+    let mut names = HashSet::new();
+
+    for depth in 1..=max_depth {
+        if level.is_empty() {
+            break;
+        }
+        // the direct dependents are matched against the whole range, like in the BFS
+        let range = if depth == 1 {
+            vulnerable_range.as_ref()
+        } else {
+            None
+        };
+        let dependents = stream::iter(&level)
+            .map(|(name, version)| utils::get_reverse_deps(source, name, version, None, range))
+            // at most the size of the connection pool
+            .buffer_unordered(5)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut next = Vec::new();
+        for dependent in dependents {
+            for revdep in dependent? {
+                let key = (revdep.name, revdep.version);
+                if !seen.contains(&key) {
+                    next.push(key);
+                }
+            }
+        }
+        next.sort();
+        next.dedup();
+        tracing::info!(
+            "[{}] {} crate version(s) at depth {}",
+            cve_id,
+            next.len(),
+            depth
+        );
+        seen.extend(next.iter().cloned());
+        names.extend(next.iter().map(|(name, _)| name.clone()));
+        counts_by_depth.push(next.len());
+        level = next;
+    }
+
+    let truncated = counts_by_depth.len() > max_depth && !level.is_empty();
+    Ok(AffectedEstimate {
+        cve_id: cve_id.to_string(),
+        crate_name: crate_name.to_string(),
+        version_range: version_range.to_string(),
+        max_depth,
+        total: counts_by_depth.iter().skip(1).sum(),
+        distinct_crates: names.len(),
+        counts_by_depth,
+        truncated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_estimate_counts_the_versions_first_reached_at_each_depth() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        // vuln <- {a, b}, a <- c <- d; b only accepts vuln 1.1
+        let source = sandbox
+            .metadata(
+                "CVE-T662",
+                &[
+                    ("t662-vuln", &["1.1.0", "1.0.0"]),
+                    ("t662-a", &["1.0.0"]),
+                    ("t662-c", &["1.0.0"]),
+                ],
+                &[
+                    (
+                        "t662-vuln",
+                        &[("t662-a", "1.0.0", "^1"), ("t662-b", "1.0.0", "^1.1")],
+                    ),
+                    ("t662-a", &[("t662-c", "1.0.0", "^1")]),
+                    ("t662-c", &[("t662-d", "1.0.0", "^1")]),
+                ],
+            )
+            .await;
+
+        let estimate = estimate_affected_count_from(&source, "CVE-T662", "t662-vuln", "<2.0.0", 2)
+            .await
+            .unwrap();
+        // a is reached from both vulnerable versions and counted once
+        assert_eq!(estimate.counts_by_depth, [2, 2, 1]);
+        assert_eq!((estimate.total, estimate.distinct_crates), (3, 3));
+        assert!(estimate.truncated);
+
+        let whole = estimate_affected_count_from(&source, "CVE-T662", "t662-vuln", "<2.0.0", 5)
+            .await
+            .unwrap();
+        assert_eq!(whole.counts_by_depth, [2, 2, 1, 1, 0]);
+        assert_eq!(whole.total, 4);
+        assert!(!whole.truncated);
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use futures::stream::StreamExt;
+use semver::{Version, VersionReq};
+
+use crate::database::{ALL_DEPENDENCY_KINDS, Database};
+use crate::manifest::{DownloadFailure, RunManifest};
+use crate::model::ReverseDependency;
+use crate::stats::{analysis_results_dir, load_callers};
+use crate::utils;
+
+/// Trace one crate through the artifacts of a finished run and the database,
+/// and return a step-by-step explanation of why it does or does not appear in
+/// the results of `cve_id`.
+///
+/// Only direct dependents of the vulnerable crate can be traced through the
+/// database, since the edges of deeper BFS levels are not persisted.
+pub async fn explain(cve_id: &str, crate_name: &str) -> Result<Vec<String>> {
+    let mut steps = Vec::new();
+
+    let Some(manifest) = RunManifest::load(cve_id).await? else {
+        steps.push(format!(
+            "no run manifest for {}, run the analysis first",
+            cve_id
+        ));
+        return Ok(steps);
+    };
+    steps.push(format!(
+        "{} affects {} {}, target functions: {}",
+        cve_id, manifest.crate_name, manifest.version_range, manifest.target_function_paths
+    ));
+
+    // 1. result files written for the crate
+    let analyzed = analyzed_versions(cve_id, crate_name).await?;
+    if !analyzed.is_empty() {
+        let callers = load_callers(cve_id).await?;
+        for subject in &analyzed {
+            let count = callers.iter().filter(|c| &c.subject == subject).count();
+            steps.push(format!(
+                "{} was analyzed and has {} caller(s) of the target functions",
+                subject, count
+            ));
+        }
+    }
+
+    // 2. fetch failures
+    let failures_path = DownloadFailure::path(cve_id);
+    if failures_path.exists() {
+        let content = tokio::fs::read_to_string(&failures_path)
+            .await
+            .with_context(|| format!("Failed to read {}", failures_path.display()))?;
+        let failures: Vec<DownloadFailure> = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", failures_path.display()))?;
+        for failure in failures.iter().filter(|f| f.name == crate_name) {
+            steps.push(format!(
+                "{}-{} could not be fetched (reached via {}): {}",
+                failure.name,
+                failure.version,
+                failure
+                    .parent
+                    .as_deref()
+                    .unwrap_or("the vulnerable crate itself"),
+                failure.error
+            ));
+        }
+    }
+
+    // 3. is it a direct dependent of the vulnerable versions?
+    let database = Database::new().await?;
+    let versions = database.query_crate_versions(&manifest.crate_name).await?;
+    let roots: Vec<Version> = utils::select_two_end_vers(versions, &manifest.version_range)
+        .await
+        .into_iter()
+        .map(|(_, v)| v)
+        .collect();
+    steps.push(format!(
+        "analyzed versions of {}: {}",
+        manifest.crate_name,
+        roots
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+
+    let kinds = utils::dependency_kinds();
+    let mut followed = Vec::new();
+    let mut other_kinds = Vec::new();
+    for (kinds, rows) in [
+        (&kinds[..], &mut followed),
+        (&ALL_DEPENDENCY_KINDS[..], &mut other_kinds),
+    ] {
+        let mut stream = database.stream_dependents(&manifest.crate_name, None, kinds);
+        while let Some(revdep) = stream.next().await {
+            let revdep: ReverseDependency = revdep?;
+            if revdep.name == crate_name {
+                rows.push(revdep);
+            }
+        }
+    }
+
+    if followed.is_empty() {
+        if other_kinds.is_empty() {
+            steps.push(format!(
+                "{} is not a direct dependent of {}; if it depends on it transitively, check its dependencies that appear in the results",
+                crate_name, manifest.crate_name
+            ));
+        } else {
+            steps.push(format!(
+                "{} depends on {} only through dependency kinds that DEPENDENCY_KINDS does not follow",
+                crate_name, manifest.crate_name
+            ));
+        }
+        return Ok(steps);
+    }
+
+    let matching: Vec<&ReverseDependency> = followed
+        .iter()
+        .filter(|revdep| {
+            VersionReq::parse(&revdep.req).is_ok_and(|req| roots.iter().any(|v| req.matches(v)))
+        })
+        .collect();
+    steps.push(format!(
+        "{} has {} version(s) depending on {}, {} of them with a requirement matching an analyzed version",
+        crate_name,
+        followed.len(),
+        manifest.crate_name,
+        matching.len()
+    ));
+    if matching.is_empty() {
+        if let Some(revdep) = followed.last() {
+            steps.push(format!(
+                "e.g. {}-{} requires {} {}, so it never builds against a vulnerable version that was analyzed",
+                revdep.name, revdep.version, manifest.crate_name, revdep.req
+            ));
+        }
+        return Ok(steps);
+    }
+    for revdep in &matching {
+        steps.push(format!(
+            "  {}-{} requires {} {}",
+            revdep.name, revdep.version, manifest.crate_name, revdep.req
+        ));
+    }
+
+    if analyzed.is_empty() {
+        steps.push(format!(
+            "{} was a candidate but has no result file: only its oldest and newest matching versions are analyzed, \
+             and none of them passed the grep pre-check, built successfully and had callers; \
+             see logs_cg4rs/{}_*/ for the call-cg4rs logs",
+            crate_name, cve_id
+        ));
+    }
+    Ok(steps)
+}
+
+/// the `<crate>-<version>` subjects with a result file in analysis_results/<cve>
+async fn analyzed_versions(cve_id: &str, crate_name: &str) -> Result<Vec<String>> {
+    let dir = analysis_results_dir().join(cve_id);
+    let mut subjects = Vec::new();
+    let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+        return Ok(subjects);
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        let Some(subject) = file_name
+            .strip_suffix(".txt.gz")
+            .or_else(|| file_name.strip_suffix(".txt"))
+        else {
+            continue;
+        };
+        // split at the version, so `foo` does not match `foo-bar-1.0.0`
+        if utils::split_subject(subject).is_some_and(|(name, _)| name == crate_name) {
+            subjects.push(subject.to_string());
+        }
+    }
+    subjects.sort();
+    Ok(subjects)
+}
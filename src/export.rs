@@ -0,0 +1,352 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::path::PathBuf;
+
+use crate::stats::{GlobalStats, analysis_results_dir};
+use crate::utils;
+
+/// load `stats-<cve>.json` (or `.json.gz`) written by `compute_and_write_stats`
+async fn load_global_stats(cve_id: &str) -> Result<GlobalStats> {
+    let dir = analysis_results_dir().join(cve_id);
+    let plain = dir.join(format!("stats-{}.json", cve_id));
+    let path = if plain.exists() {
+        plain
+    } else {
+        dir.join(format!("stats-{}.json.gz", cve_id))
+    };
+    let content = utils::read_result_file(&path)
+        .await
+        .context("stats file not found, run the stats computation first")?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Write a self-contained `report-<cve>.html` with an interactive graph of
+/// the dependents (subjects) and the target functions they call. Target
+/// functions are highlighted; clicking a node lists its callers. The layout
+/// script is inlined so the file opens from the filesystem without network.
+pub async fn html(cve_id: &str) -> Result<PathBuf> {
+    let global = load_global_stats(cve_id).await?;
+
+    let mut nodes = Vec::new();
+    let mut links = Vec::new();
+    for (func, fs) in &global.functions {
+        let callers = fs
+            .top_callers_by_constraints
+            .iter()
+            .map(|s| {
+                json!({
+                    "subject": s.subject,
+                    "caller": s.caller_path,
+                    "pc": s.path_constraints,
+                    "pkg": s.path_package_num,
+                })
+            })
+            .collect::<Vec<_>>();
+        nodes.push(json!({
+            "id": func,
+            "kind": "function",
+            "callers": fs.total_callers,
+            "details": callers,
+        }));
+    }
+    for subject in &global.subjects {
+        let details = subject
+            .per_function_callers
+            .iter()
+            .map(|(func, cnt)| json!({ "function": func, "callers": cnt }))
+            .collect::<Vec<_>>();
+        nodes.push(json!({
+            "id": subject.subject,
+            "kind": "subject",
+            "callers": subject.total_callers,
+            "details": details,
+        }));
+        for (func, cnt) in &subject.per_function_callers {
+            if *cnt > 0 && global.functions.contains_key(func) {
+                links.push(json!({ "source": subject.subject, "target": func, "value": cnt }));
+            }
+        }
+    }
+
+    let data = json!({
+        "cve_id": cve_id,
+        "total_subjects": global.total_subjects,
+        "total_callers": global.total_callers,
+        "nodes": nodes,
+        "links": links,
+    });
+    // keep `</script>` in crate or function names from closing the script tag
+    let data = serde_json::to_string(&data)?.replace("</", "<\\/");
+    let page = HTML_TEMPLATE
+        .replace("__CVE_ID__", cve_id)
+        .replace("__GRAPH_DATA__", &data);
+
+    let out_path = analysis_results_dir()
+        .join(cve_id)
+        .join(format!("report-{}.html", cve_id));
+    tokio::fs::write(&out_path, page).await?;
+    tracing::info!("html report written: {:?}", out_path);
+    Ok(out_path)
+}
+
+/// Insert the callers found for `cve_id` into the SQLite database at
+/// `db_path`, created if missing, for ad-hoc SQL across CVEs. The tables are
+/// `crates(cve_id, name, version)`, `functions(cve_id, path)` and
+/// `callers(crate_id, function_id, caller_path, path_constraints, path_package_num)`.
+/// The rows of a previous export of the same CVE are replaced.
+/// Return the number of callers inserted.
+#[cfg(feature = "sqlite")]
+pub async fn sqlite(cve_id: &str, db_path: &std::path::Path) -> Result<usize> {
+    let records = crate::stats::load_callers(cve_id).await?;
+    let cve_id = cve_id.to_string();
+    let db_path = db_path.to_path_buf();
+    // rusqlite is blocking
+    tokio::task::spawn_blocking(move || write_sqlite(&cve_id, &db_path, &records)).await?
+}
+
+#[cfg(feature = "sqlite")]
+fn write_sqlite(
+    cve_id: &str,
+    db_path: &std::path::Path,
+    records: &[crate::stats::CallerRecord],
+) -> Result<usize> {
+    use rusqlite::params;
+
+    let mut conn = rusqlite::Connection::open(db_path)
+        .with_context(|| format!("Failed to open {}", db_path.display()))?;
+    conn.execute_batch(
+        "PRAGMA foreign_keys = ON;
+        CREATE TABLE IF NOT EXISTS crates (
+            id INTEGER PRIMARY KEY,
+            cve_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            version TEXT NOT NULL,
+            UNIQUE (cve_id, name, version)
+        );
+        CREATE TABLE IF NOT EXISTS functions (
+            id INTEGER PRIMARY KEY,
+            cve_id TEXT NOT NULL,
+            path TEXT NOT NULL,
+            UNIQUE (cve_id, path)
+        );
+        CREATE TABLE IF NOT EXISTS callers (
+            id INTEGER PRIMARY KEY,
+            crate_id INTEGER NOT NULL REFERENCES crates(id) ON DELETE CASCADE,
+            function_id INTEGER NOT NULL REFERENCES functions(id) ON DELETE CASCADE,
+            caller_path TEXT NOT NULL,
+            path_constraints INTEGER,
+            path_package_num INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS crates_name ON crates(name);
+        CREATE INDEX IF NOT EXISTS functions_path ON functions(path);
+        CREATE INDEX IF NOT EXISTS callers_crate ON callers(crate_id);
+        CREATE INDEX IF NOT EXISTS callers_function ON callers(function_id);",
+    )?;
+
+    let tx = conn.transaction()?;
+    // the callers of the previous export go with their crates and functions
+    tx.execute("DELETE FROM crates WHERE cve_id = ?1", params![cve_id])?;
+    tx.execute("DELETE FROM functions WHERE cve_id = ?1", params![cve_id])?;
+    {
+        let mut insert_crate = tx.prepare(
+            "INSERT INTO crates (cve_id, name, version) VALUES (?1, ?2, ?3)
+             ON CONFLICT (cve_id, name, version) DO UPDATE SET name = excluded.name
+             RETURNING id",
+        )?;
+        let mut insert_function = tx.prepare(
+            "INSERT INTO functions (cve_id, path) VALUES (?1, ?2)
+             ON CONFLICT (cve_id, path) DO UPDATE SET path = excluded.path
+             RETURNING id",
+        )?;
+        let mut insert_caller = tx.prepare(
+            "INSERT INTO callers (crate_id, function_id, caller_path, path_constraints, path_package_num)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for record in records {
+            let (name, version) = crate::utils::split_subject(&record.subject)
+                .unwrap_or((record.subject.as_str(), ""));
+            let crate_id: i64 =
+                insert_crate.query_row(params![cve_id, name, version], |row| row.get(0))?;
+            let function_id: i64 =
+                insert_function.query_row(params![cve_id, record.function], |row| row.get(0))?;
+            insert_caller.execute(params![
+                crate_id,
+                function_id,
+                record.caller_path,
+                record.path_constraints,
+                record.path_package_num
+            ])?;
+        }
+    }
+    tx.commit()?;
+    tracing::info!(
+        "{} caller(s) of {} exported to {}",
+        records.len(),
+        cve_id,
+        db_path.display()
+    );
+    Ok(records.len())
+}
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Propagation report for __CVE_ID__</title>
+<style>
+  body { font-family: sans-serif; margin: 0; display: flex; height: 100vh; }
+  #graph { flex: 1; }
+  #side { width: 380px; overflow: auto; padding: 12px; border-left: 1px solid #ccc; font-size: 13px; }
+  .function { fill: #d62728; }
+  .subject { fill: #1f77b4; }
+  line { stroke: #999; stroke-opacity: 0.5; }
+  text { font-size: 10px; pointer-events: none; }
+</style>
+</head>
+<body>
+<svg id="graph"></svg>
+<div id="side"><h3>__CVE_ID__</h3><div id="summary"></div><div id="detail">Click a node to see its callers.</div></div>
+<script>
+const data = __GRAPH_DATA__;
+const svg = document.getElementById("graph");
+const NS = "http://www.w3.org/2000/svg";
+const W = svg.clientWidth || 900, H = svg.clientHeight || 700;
+document.getElementById("summary").textContent =
+  `${data.total_subjects} subjects, ${data.total_callers} callers`;
+
+const byId = new Map(data.nodes.map((n, i) => [n.id, i]));
+const nodes = data.nodes.map(n => ({ ...n, x: W / 2 + (Math.random() - 0.5) * W / 2,
+  y: H / 2 + (Math.random() - 0.5) * H / 2, vx: 0, vy: 0,
+  r: 4 + Math.sqrt(n.callers) }));
+const links = data.links.filter(l => byId.has(l.source) && byId.has(l.target))
+  .map(l => ({ s: nodes[byId.get(l.source)], t: nodes[byId.get(l.target)] }));
+
+const lineEls = links.map(() => svg.appendChild(document.createElementNS(NS, "line")));
+const nodeEls = nodes.map(n => {
+  const g = svg.appendChild(document.createElementNS(NS, "g"));
+  const c = g.appendChild(document.createElementNS(NS, "circle"));
+  c.setAttribute("r", n.r);
+  c.setAttribute("class", n.kind);
+  c.style.cursor = "pointer";
+  c.addEventListener("click", () => showDetail(n));
+  const t = g.appendChild(document.createElementNS(NS, "text"));
+  t.setAttribute("dx", n.r + 2);
+  t.textContent = n.kind === "function" ? n.id : "";
+  return g;
+});
+
+function showDetail(n) {
+  const rows = n.details.map(d => n.kind === "function"
+    ? `<li>[${d.subject}] ${d.caller} (pc=${d.pc}, pkg=${d.pkg})</li>`
+    : `<li>${d.function}: ${d.callers}</li>`).join("");
+  const el = document.getElementById("detail");
+  el.innerHTML = `<h4></h4><p>${n.kind}, ${n.callers} callers</p><ul>${rows}</ul>`;
+  el.querySelector("h4").textContent = n.id;
+}
+
+// a small force-directed layout: pairwise repulsion, spring links, centering
+function tick() {
+  for (let i = 0; i < nodes.length; i++) {
+    for (let j = i + 1; j < nodes.length; j++) {
+      const a = nodes[i], b = nodes[j];
+      let dx = a.x - b.x, dy = a.y - b.y, d2 = dx * dx + dy * dy + 0.01;
+      const f = 400 / d2;
+      a.vx += dx * f; a.vy += dy * f; b.vx -= dx * f; b.vy -= dy * f;
+    }
+  }
+  for (const l of links) {
+    const dx = l.t.x - l.s.x, dy = l.t.y - l.s.y;
+    const d = Math.sqrt(dx * dx + dy * dy) || 1, f = (d - 60) * 0.01;
+    l.s.vx += dx / d * f; l.s.vy += dy / d * f; l.t.vx -= dx / d * f; l.t.vy -= dy / d * f;
+  }
+  for (const n of nodes) {
+    n.vx += (W / 2 - n.x) * 0.002; n.vy += (H / 2 - n.y) * 0.002;
+    n.vx *= 0.8; n.vy *= 0.8;
+    n.x = Math.max(n.r, Math.min(W - n.r, n.x + n.vx));
+    n.y = Math.max(n.r, Math.min(H - n.r, n.y + n.vy));
+  }
+  links.forEach((l, i) => {
+    lineEls[i].setAttribute("x1", l.s.x); lineEls[i].setAttribute("y1", l.s.y);
+    lineEls[i].setAttribute("x2", l.t.x); lineEls[i].setAttribute("y2", l.t.y);
+  });
+  nodes.forEach((n, i) => nodeEls[i].setAttribute("transform", `translate(${n.x},${n.y})`));
+}
+let steps = 0;
+(function loop() { tick(); if (++steps < 300) requestAnimationFrame(loop); })();
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_sqlite_export_can_be_queried_back() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        let fixture =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/stats/FIXTURE-0001");
+        utils::copy_dir(&fixture, &sandbox.results_dir("CVE-T663"), true)
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("findings.db");
+        assert_eq!(sqlite("CVE-T663", &db_path).await.unwrap(), 6);
+        // exporting again replaces the rows
+        assert_eq!(sqlite("CVE-T663", &db_path).await.unwrap(), 6);
+
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        let count = |table: &str| -> i64 {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        assert_eq!(
+            (count("crates"), count("functions"), count("callers")),
+            (3, 2, 6)
+        );
+
+        let mut statement = conn
+            .prepare(
+                "SELECT c.name, c.version, k.caller_path, k.path_constraints, k.path_package_num
+                 FROM callers k
+                 JOIN crates c ON k.crate_id = c.id
+                 JOIN functions f ON k.function_id = f.id
+                 WHERE c.cve_id = ?1 AND f.path = ?2
+                 ORDER BY k.path_constraints DESC",
+            )
+            .unwrap();
+        let rows = statement
+            .query_map(["CVE-T663", "vuln::hash::bytes"], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let row = |name: &str, version: &str, caller: &str, pc: i64, pkg: i64| {
+            (
+                name.to_string(),
+                version.to_string(),
+                caller.to_string(),
+                Some(pc),
+                Some(pkg),
+            )
+        };
+        assert_eq!(
+            rows,
+            [
+                row("foo-bar", "2.1.0-rc.1", "foo_bar::run", 5, 3),
+                row("foo", "1.0.0", "foo::main", 3, 2),
+                row("foo", "1.0.0", "foo::util::<u8>::digest", 1, 1),
+            ]
+        );
+    }
+}
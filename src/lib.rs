@@ -1,8 +1,22 @@
 pub mod callgraph;
-mod database;
+pub mod config;
+pub mod database;
 pub mod dependency_analyzer;
+pub mod dependents;
 mod dir;
+pub mod error;
+pub mod estimate;
+pub mod explain;
+pub mod export;
 pub mod logger;
+pub mod manifest;
+pub mod metadata;
+pub mod metrics;
 mod model;
 pub mod stats;
+#[cfg(test)]
+mod test_support;
 mod utils;
+
+pub use error::CveTrackerError;
+pub use model::{Krate, ReverseDependency};
@@ -1,6 +1,6 @@
 use crate::model::Krate;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::fs as tokio_fs;
 use tracing_log::LogTracer;
 use tracing_subscriber::prelude::*;
@@ -33,7 +33,7 @@ impl Logger {
         tracing::dispatcher::DefaultGuard,
     ) {
         // 当环境变量 DISABLE_STDOUT_LOG=1 时，不往控制台输出日志，避免打断进度条
-        let use_stdout = std::env::var("DISABLE_STDOUT_LOG")
+        let use_stdout = crate::config::var("DISABLE_STDOUT_LOG")
             .map(|v| v != "1")
             .unwrap_or(true);
         let std_writer = tracing_subscriber::fmt::writer::BoxMakeWriter::new(move || {
@@ -69,11 +69,16 @@ impl Logger {
     }
 }
 
+/// the error log file of `call-cg4rs` for a crate, see [`create_log_file`]
+pub fn error_log_path(logs_dir: &Path, krate: &Krate) -> PathBuf {
+    logs_dir.join(format!("cg4rs_{}_{}_error.log", krate.name, krate.version))
+}
+
 /// create log file for each process, and return the log file and error log file
 /// log file name: logs_cg4rs/{cve_id}_{timestamp}/cg4rs_{krate_name}_{krate_version}.log
 /// error log file name: logs_cg4rs/{cve_id}_{timestamp}/cg4rs_{krate_name}_{krate_version}_error.log
 pub async fn create_log_file(
-    logs_dir: &PathBuf,
+    logs_dir: &Path,
     krate: &Krate,
 ) -> anyhow::Result<(std::fs::File, std::fs::File)> {
     // 创建日志目录（使用绝对路径）
@@ -82,7 +87,7 @@ pub async fn create_log_file(
     let logs_file_name_suffix = format!("{}_{}", krate.name, krate.version);
     let logs_filepath = logs_dir.join(format!("cg4rs_{}.log", logs_file_name_suffix));
 
-    let error_output_filepath = logs_dir.join(format!("cg4rs_{}_error.log", logs_file_name_suffix));
+    let error_output_filepath = error_log_path(logs_dir, krate);
 
     // 创建日志文件 - 使用 std::fs::File 而不是 tokio::fs::File
     let log_file = std::fs::File::create(&logs_filepath)?;
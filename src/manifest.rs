@@ -0,0 +1,821 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::fs as tokio_fs;
+
+use crate::metrics::RunMetrics;
+use crate::stats::analysis_results_dir;
+
+/// how an analysis run ended
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RunTermination {
+    Completed,
+    /// the run exceeded `RUN_TIMEOUT_SECS`
+    TimedOut {
+        timeout_secs: u64,
+    },
+    /// the resident memory exceeded `RUN_MEMORY_MB`
+    MemoryExceeded {
+        limit_mb: u64,
+        rss_mb: u64,
+    },
+    /// the user pressed Ctrl-C, see `CancellationToken::interrupt`
+    Interrupted,
+    /// the embedder cancelled the run through its `CancellationToken`
+    Cancelled,
+}
+
+/// why a crate reached by the BFS was not analyzed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SkipReason {
+    /// the archive is larger than `MAX_CRATE_SIZE_MB`
+    SkippedTooLarge { size_bytes: u64, limit_bytes: u64 },
+    /// `call-cg4rs` failed on a build script, e.g. one that needs the network,
+    /// so the crate may be a false negative
+    BuildScriptFailed { package: Option<String> },
+    /// neither `src` nor the target paths of Cargo.toml exist
+    NoSourceFound,
+    /// `call-cg4rs` failed otherwise, timed out or wrote no output, so
+    /// whether the crate is affected is unknown
+    AnalyzerFailed { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SkippedCrate {
+    pub name: String,
+    pub version: String,
+    /// the crate that led the BFS to this one, `None` for the vulnerable crate itself
+    pub parent: Option<String>,
+    #[serde(flatten)]
+    pub reason: SkipReason,
+}
+
+/// A result file that already existed when it was written, e.g. left by an
+/// earlier run of the same CVE: the caller sets of the writes are merged into
+/// it rather than overwritten
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MergedResult {
+    /// `<name>-<version>` of the result file
+    pub subject: String,
+    /// the root versions of the vulnerable crate whose BFS branches of this
+    /// run contributed callers
+    pub branches: Vec<String>,
+    /// the callers of an earlier run were merged in as well
+    #[serde(default)]
+    pub previous_run: bool,
+}
+
+/// a crate that could not be fetched and prepared after all retries,
+/// written to analysis_results/<cve>/download_failures-<cve>.json
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DownloadFailure {
+    pub name: String,
+    pub version: String,
+    /// the crate that led the BFS to this one, `None` for the vulnerable crate itself
+    pub parent: Option<String>,
+    pub error: String,
+}
+
+impl DownloadFailure {
+    pub fn path(cve_id: &str) -> PathBuf {
+        analysis_results_dir()
+            .join(cve_id)
+            .join(format!("download_failures-{}.json", cve_id))
+    }
+
+    /// the failures of a previous run, empty if none were written
+    pub async fn load_all(cve_id: &str) -> Result<Vec<Self>> {
+        let path = Self::path(cve_id);
+        let Ok(content) = tokio_fs::read_to_string(&path).await else {
+            return Ok(Vec::new());
+        };
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub async fn write_all(cve_id: &str, failures: &[DownloadFailure]) -> Result<()> {
+        let path = Self::path(cve_id);
+        if let Some(parent) = path.parent() {
+            tokio_fs::create_dir_all(parent).await?;
+        }
+        tokio_fs::write(&path, serde_json::to_string_pretty(failures)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// How a top caller fared in the confirmation pass (`CONFIRM_TOP_N`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmationStatus {
+    /// the confirming caller finder found the caller again
+    Confirmed,
+    /// the confirming caller finder ran on the crate without finding the caller
+    Unconfirmed,
+    /// the crate could not be re-analyzed, e.g. its result was left by an
+    /// earlier run or the confirming caller finder failed on it
+    Unknown,
+}
+
+impl std::fmt::Display for ConfirmationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Confirmed => write!(f, "confirmed"),
+            Self::Unconfirmed => write!(f, "unconfirmed"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// One of the top callers re-checked by the confirmation pass, written to
+/// analysis_results/<cve>/confirmations-<cve>.json
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CallerConfirmation {
+    /// `<name>-<version>` of the result file
+    pub subject: String,
+    /// the target function the caller reaches
+    pub function: String,
+    pub caller_path: String,
+    pub path_constraints: Option<i64>,
+    pub status: ConfirmationStatus,
+}
+
+impl CallerConfirmation {
+    pub fn path(cve_id: &str) -> PathBuf {
+        analysis_results_dir()
+            .join(cve_id)
+            .join(format!("confirmations-{}.json", cve_id))
+    }
+
+    /// the confirmations of a previous run, empty if none were written
+    pub async fn load_all(cve_id: &str) -> Result<Vec<Self>> {
+        let path = Self::path(cve_id);
+        let Ok(content) = tokio_fs::read_to_string(&path).await else {
+            return Ok(Vec::new());
+        };
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub async fn write_all(cve_id: &str, confirmations: &[CallerConfirmation]) -> Result<()> {
+        let path = Self::path(cve_id);
+        if let Some(parent) = path.parent() {
+            tokio_fs::create_dir_all(parent).await?;
+        }
+        tokio_fs::write(&path, serde_json::to_string_pretty(confirmations)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// a crate processed by the BFS, recorded to rebuild the discovery tree
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BfsTreeEntry {
+    pub name: String,
+    pub version: String,
+    /// `<name>-<version>` of the crate that led the BFS here, `None` for a root version
+    pub parent: Option<String>,
+    /// how the crate depends on its parent, `None` for a root version
+    pub dependency_kind: Option<&'static str>,
+}
+
+/// The edition and MSRV of an analyzed crate, written next to its result file
+/// as analysis_results/<cve>/<name>-<version>.crate-info.json (`ANNOTATE_CRATE_INFO=1`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CrateInfo {
+    /// `package.edition`, 2015 when missing
+    pub edition: String,
+    /// `package.rust-version`, `None` when the crate declares no MSRV
+    pub rust_version: Option<String>,
+}
+
+impl CrateInfo {
+    pub fn path(cve_id: &str, subject: &str) -> PathBuf {
+        analysis_results_dir()
+            .join(cve_id)
+            .join(format!("{}.crate-info.json", subject))
+    }
+
+    /// the info written for `subject`, `None` if there is none
+    pub async fn load(cve_id: &str, subject: &str) -> Result<Option<Self>> {
+        let path = Self::path(cve_id, subject);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = tokio_fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let info = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Some(info))
+    }
+
+    pub async fn write(&self, cve_id: &str, subject: &str) -> Result<()> {
+        let path = Self::path(cve_id, subject);
+        tokio_fs::write(&path, serde_json::to_string_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// One crate of the BFS discovery tree, written as a forest rooted at the
+/// analyzed versions of the vulnerable crate to analysis_results/<cve>/bfs-tree-<cve>.json
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BfsTreeNode {
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    pub version: String,
+    /// 0 for the vulnerable crate itself
+    pub depth: usize,
+    /// callers of the target functions in the result file of the crate
+    pub callers_found: usize,
+    pub children: Vec<BfsTreeNode>,
+}
+
+impl BfsTreeNode {
+    pub fn path(cve_id: &str) -> PathBuf {
+        analysis_results_dir()
+            .join(cve_id)
+            .join(format!("bfs-tree-{}.json", cve_id))
+    }
+
+    /// Rebuild the forest from the parent links of the processed crates, one
+    /// tree per root version. `callers` maps `<name>-<version>` to its caller count.
+    /// Children are sorted by name and version, entries whose parent was never
+    /// recorded are dropped.
+    pub(crate) fn build_forest(
+        entries: &[BfsTreeEntry],
+        callers: &HashMap<String, usize>,
+    ) -> Vec<BfsTreeNode> {
+        let mut children: HashMap<Option<&str>, Vec<&BfsTreeEntry>> = HashMap::new();
+        for entry in entries {
+            children
+                .entry(entry.parent.as_deref())
+                .or_default()
+                .push(entry);
+        }
+        for list in children.values_mut() {
+            list.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        }
+
+        fn build(
+            entry: &BfsTreeEntry,
+            depth: usize,
+            children: &HashMap<Option<&str>, Vec<&BfsTreeEntry>>,
+            callers: &HashMap<String, usize>,
+        ) -> BfsTreeNode {
+            let id = format!("{}-{}", entry.name, entry.version);
+            BfsTreeNode {
+                crate_name: entry.name.clone(),
+                version: entry.version.clone(),
+                depth,
+                callers_found: callers.get(&id).copied().unwrap_or(0),
+                children: children
+                    .get(&Some(id.as_str()))
+                    .into_iter()
+                    .flatten()
+                    .map(|child| build(child, depth + 1, children, callers))
+                    .collect(),
+            }
+        }
+
+        children
+            .get(&None)
+            .into_iter()
+            .flatten()
+            .map(|root| build(root, 0, &children, callers))
+            .collect()
+    }
+
+    pub async fn write_forest(cve_id: &str, forest: &[BfsTreeNode]) -> Result<()> {
+        let path = Self::path(cve_id);
+        if let Some(parent) = path.parent() {
+            tokio_fs::create_dir_all(parent).await?;
+        }
+        tokio_fs::write(&path, serde_json::to_string_pretty(forest)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// One crate with callers of the target functions, a row of
+/// analysis_results/<cve>/affected-<cve>.csv for advisory tooling
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AffectedCrate {
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    pub version: String,
+    /// 0 for the vulnerable crate itself
+    pub depth: usize,
+    pub reachable_callers: usize,
+    /// `normal`, `build` or `dev`, empty for the vulnerable crate itself
+    pub dependency_kind: String,
+    /// `<name>-<version>` of the crate it was reached through, empty for the vulnerable crate itself
+    pub via_parent: String,
+}
+
+impl AffectedCrate {
+    pub fn path(cve_id: &str) -> PathBuf {
+        analysis_results_dir()
+            .join(cve_id)
+            .join(format!("affected-{}.csv", cve_id))
+    }
+
+    /// The processed crates with at least one caller, by reachable callers
+    /// descending, then name and version. `callers` maps `<name>-<version>`
+    /// to its caller count.
+    pub(crate) fn collect(
+        entries: &[BfsTreeEntry],
+        callers: &HashMap<String, usize>,
+    ) -> Vec<AffectedCrate> {
+        let parents: HashMap<String, Option<&str>> = entries
+            .iter()
+            .map(|e| (format!("{}-{}", e.name, e.version), e.parent.as_deref()))
+            .collect();
+        fn depth_of<'a>(
+            mut parent: Option<&'a str>,
+            parents: &HashMap<String, Option<&'a str>>,
+        ) -> usize {
+            let mut depth = 0;
+            // bounded, in case a crate was reached through a cycle
+            while let Some(id) = parent
+                && depth < parents.len()
+            {
+                depth += 1;
+                parent = parents.get(id).copied().flatten();
+            }
+            depth
+        }
+
+        let mut affected: Vec<AffectedCrate> = entries
+            .iter()
+            .filter_map(|entry| {
+                let id = format!("{}-{}", entry.name, entry.version);
+                let reachable_callers = callers.get(&id).copied().unwrap_or(0);
+                (reachable_callers > 0).then(|| AffectedCrate {
+                    crate_name: entry.name.clone(),
+                    version: entry.version.clone(),
+                    depth: depth_of(entry.parent.as_deref(), &parents),
+                    reachable_callers,
+                    dependency_kind: entry.dependency_kind.unwrap_or_default().to_string(),
+                    via_parent: entry.parent.clone().unwrap_or_default(),
+                })
+            })
+            .collect();
+        affected.sort_by(|a, b| {
+            b.reachable_callers
+                .cmp(&a.reachable_callers)
+                .then_with(|| (&a.crate_name, &a.version).cmp(&(&b.crate_name, &b.version)))
+        });
+        affected.dedup_by(|a, b| a.crate_name == b.crate_name && a.version == b.version);
+        affected
+    }
+
+    /// Write the rows as CSV with a header, also when there is no affected crate
+    pub async fn write_csv(cve_id: &str, affected: &[AffectedCrate]) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(Vec::new());
+        writer.write_record([
+            "crate",
+            "version",
+            "depth",
+            "reachable_callers",
+            "dependency_kind",
+            "via_parent",
+        ])?;
+        for row in affected {
+            writer.serialize(row)?;
+        }
+        let content = writer.into_inner().map_err(|e| anyhow::anyhow!("{}", e))?;
+
+        let path = Self::path(cve_id);
+        if let Some(parent) = path.parent() {
+            tokio_fs::create_dir_all(parent).await?;
+        }
+        tokio_fs::write(&path, content)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// The exit code of the analyze binary when the run completed but more crates
+/// failed than `FAIL_IF_ERRORS_OVER`, distinct from 1 for a run that aborted with an error
+pub const EXIT_TOO_MANY_ERRORS: i32 = 2;
+
+/// The outcome of a run in a few numbers, printed as the last stdout line of
+/// the analyze binary for CI: `cvetracker4rs: cve=<id> affected=N failed=M skipped=K`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub cve_id: String,
+    /// crate versions this run found callers of the target functions in,
+    /// see [`RunManifest::written_results`]
+    pub affected: usize,
+    /// crates that could not be fetched, plus the crates `call-cg4rs` failed on
+    /// ([`SkipReason::AnalyzerFailed`])
+    pub failed: usize,
+    /// the other crates not analyzed, see [`RunManifest::skipped_crates`]
+    pub skipped: usize,
+}
+
+impl RunSummary {
+    /// Summarize the artifacts of the run of `cve_id`
+    pub async fn load(cve_id: &str) -> Result<Self> {
+        let manifest = RunManifest::load(cve_id).await?.unwrap_or_default();
+        let download_failures = DownloadFailure::load_all(cve_id).await?.len();
+        Ok(Self::from_manifest(cve_id, &manifest, download_failures))
+    }
+
+    /// Summarize a run from its manifest: the result files of earlier runs
+    /// left in the results directory are not counted as affected
+    pub fn from_manifest(cve_id: &str, manifest: &RunManifest, download_failures: usize) -> Self {
+        let analyzer_failed = manifest.analyzer_failures();
+        Self {
+            cve_id: cve_id.to_string(),
+            affected: manifest.written_results.len(),
+            failed: download_failures + analyzer_failed,
+            skipped: manifest.skipped_crates.len() - analyzer_failed,
+        }
+    }
+
+    /// the exit code of the analyze binary, [`EXIT_TOO_MANY_ERRORS`] if the
+    /// failures exceed `FAIL_IF_ERRORS_OVER` (unset: never), 0 otherwise
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code_as(
+            crate::config::var("FAIL_IF_ERRORS_OVER")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok()),
+        )
+    }
+
+    pub(crate) fn exit_code_as(&self, threshold: Option<usize>) -> i32 {
+        if threshold.is_some_and(|threshold| self.failed > threshold) {
+            EXIT_TOO_MANY_ERRORS
+        } else {
+            0
+        }
+    }
+}
+
+impl std::fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cvetracker4rs: cve={} affected={} failed={} skipped={}",
+            self.cve_id, self.affected, self.failed, self.skipped
+        )
+    }
+}
+
+/// a BFS level that exceeded `MAX_NODES_PER_LEVEL` and was sampled,
+/// stats over sampled levels can be scaled by `1 / fraction`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LevelSampling {
+    /// 0 is the vulnerable crate itself
+    pub depth: usize,
+    pub total: usize,
+    pub sampled: usize,
+    pub fraction: f64,
+}
+
+/// the self-analysis of one version of the vulnerable crate (`SELF_ANALYSIS=1`),
+/// checking the version range and function paths against the code itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RootVersionCheck {
+    pub version: String,
+    /// whether grep finds any of the target functions in the sources
+    pub contains_target_functions: bool,
+    /// callers found by the caller finder inside the crate itself,
+    /// `None` if it was not run or found nothing
+    pub callers: Option<usize>,
+}
+
+/// The tools and host an analysis ran with, written to
+/// analysis_results/<cve>/environment-<cve>.json so results can be reproduced
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AnalysisEnvironment {
+    pub tool_version: String,
+    /// `rustc --version` of the toolchain call-cg4rs runs with, `None` if it could not be run
+    pub rustc_version: Option<String>,
+    /// `call-cg4rs --version`, `None` if it could not be run or reports no version
+    pub call_cg4rs_version: Option<String>,
+    pub os: String,
+    pub arch: String,
+    pub captured_at: String,
+}
+
+impl AnalysisEnvironment {
+    pub fn path(cve_id: &str) -> PathBuf {
+        analysis_results_dir()
+            .join(cve_id)
+            .join(format!("environment-{}.json", cve_id))
+    }
+
+    /// Query the tool versions. A tool that cannot report one is recorded as `None`.
+    pub async fn capture() -> Self {
+        let (rustc_version, call_cg4rs_version) = tokio::join!(
+            crate::utils::tool_version("rustc"),
+            crate::utils::tool_version("call-cg4rs")
+        );
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            rustc_version,
+            call_cg4rs_version,
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            captured_at: chrono::Local::now().to_rfc3339(),
+        }
+    }
+
+    /// load the environment of a previous run, `None` if it was not recorded
+    pub async fn load(cve_id: &str) -> Result<Option<Self>> {
+        let path = Self::path(cve_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = tokio_fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let environment = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Some(environment))
+    }
+
+    pub async fn write(&self, cve_id: &str) -> Result<()> {
+        let path = Self::path(cve_id);
+        if let Some(parent) = path.parent() {
+            tokio_fs::create_dir_all(parent).await?;
+        }
+        tokio_fs::write(&path, serde_json::to_string_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Run manifest, written to analysis_results/<cve>/manifest-<cve>.json.
+/// It records what was analyzed and how the run ended, so the result
+/// directory can be interpreted later without the logs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RunManifest {
+    pub cve_id: String,
+    /// version of cvetracker4rs that produced the results
+    #[serde(default)]
+    pub tool_version: String,
+    pub crate_name: String,
+    pub version_range: String,
+    pub target_function_paths: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub termination: Option<RunTermination>,
+    pub run_metrics: Option<RunMetrics>,
+    /// the `SAMPLING_SEED` used if any level was sampled
+    pub sampling_seed: Option<u64>,
+    pub level_sampling: Vec<LevelSampling>,
+    #[serde(default)]
+    pub root_versions: Vec<RootVersionCheck>,
+    /// features of the vulnerable crate enabled in its direct dependents (`--root-features`)
+    #[serde(default)]
+    pub root_features: Vec<String>,
+    /// crates reached by the BFS but deliberately not analyzed
+    #[serde(default)]
+    pub skipped_crates: Vec<SkippedCrate>,
+    /// every dependent was analyzed without the grep pre-check (`SKIP_GREP_PRECHECK=1`)
+    #[serde(default)]
+    pub grep_precheck_skipped: bool,
+    /// `RUST_TOOLCHAIN` and its `rustc --version`, if the toolchain was pinned
+    #[serde(default)]
+    pub rust_toolchain: Option<String>,
+    /// the dependents of crates at this depth were not followed (`--max-depth`)
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// the callers inside the vulnerable crate itself were analyzed (`--include-self`)
+    #[serde(default)]
+    pub include_self: bool,
+    /// result files merged from several writes, see [`MergedResult`]
+    #[serde(default)]
+    pub merged_results: Vec<MergedResult>,
+    /// the subjects (`<name>-<version>`) this run wrote a result file for, sorted
+    #[serde(default)]
+    pub written_results: Vec<String>,
+}
+
+impl RunManifest {
+    pub fn new(cve_id: &str) -> Self {
+        Self {
+            cve_id: cve_id.to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: chrono::Local::now().to_rfc3339(),
+            ..Default::default()
+        }
+    }
+
+    pub fn path(cve_id: &str) -> PathBuf {
+        analysis_results_dir()
+            .join(cve_id)
+            .join(format!("manifest-{}.json", cve_id))
+    }
+
+    /// the skipped crates `call-cg4rs` failed on, counted as failures rather
+    /// than as skipped in the [`RunSummary`]
+    pub fn analyzer_failures(&self) -> usize {
+        self.skipped_crates
+            .iter()
+            .filter(|c| matches!(c.reason, SkipReason::AnalyzerFailed { .. }))
+            .count()
+    }
+
+    /// load the manifest of a previous run, `None` if there is none
+    pub async fn load(cve_id: &str) -> Result<Option<Self>> {
+        let path = Self::path(cve_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = tokio_fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let manifest = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Some(manifest))
+    }
+
+    pub async fn write(&self) -> Result<()> {
+        let path = Self::path(&self.cve_id);
+        if let Some(parent) = path.parent() {
+            tokio_fs::create_dir_all(parent).await?;
+        }
+        tokio_fs::write(&path, serde_json::to_string_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// mark the run as finished and write the manifest
+    pub async fn finish(
+        &mut self,
+        termination: RunTermination,
+        run_metrics: RunMetrics,
+    ) -> Result<()> {
+        self.finished_at = Some(chrono::Local::now().to_rfc3339());
+        self.termination = Some(termination);
+        self.run_metrics = Some(run_metrics);
+        self.write().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyzer_failures_are_recorded_and_counted_apart() {
+        let skipped = |reason| SkippedCrate {
+            name: "foo".to_string(),
+            version: "0.1.0".to_string(),
+            parent: None,
+            reason,
+        };
+        let mut manifest = RunManifest::new("CVE-0000-0000");
+        manifest
+            .skipped_crates
+            .push(skipped(SkipReason::NoSourceFound));
+        manifest
+            .skipped_crates
+            .push(skipped(SkipReason::AnalyzerFailed {
+                error: "exit status: 101 after 1 attempt(s)".to_string(),
+            }));
+        assert_eq!(manifest.analyzer_failures(), 1);
+
+        let json = serde_json::to_value(&manifest.skipped_crates[1]).unwrap();
+        assert_eq!(json["reason"], "analyzer_failed");
+        assert_eq!(json["error"], "exit status: 101 after 1 attempt(s)");
+        let back: SkippedCrate = serde_json::from_value(json).unwrap();
+        assert_eq!(back, manifest.skipped_crates[1]);
+    }
+
+    #[tokio::test]
+    async fn the_affected_csv_has_a_header_and_a_row_per_crate_with_callers() {
+        let (_sandbox, _guard) = crate::test_support::sandbox().await;
+        let entry = |name: &str, parent: Option<&str>, kind| BfsTreeEntry {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            parent: parent.map(str::to_string),
+            dependency_kind: kind,
+        };
+        let entries = [
+            entry("vuln", None, None),
+            entry("a", Some("vuln-1.0.0"), Some("normal")),
+            entry("b", Some("a-1.0.0"), Some("build")),
+            entry("quiet", Some("vuln-1.0.0"), Some("normal")),
+        ];
+        let callers = HashMap::from([("a-1.0.0".to_string(), 1), ("b-1.0.0".to_string(), 4)]);
+        let affected = AffectedCrate::collect(&entries, &callers);
+        AffectedCrate::write_csv("CVE-T660", &affected)
+            .await
+            .unwrap();
+
+        let csv = std::fs::read_to_string(AffectedCrate::path("CVE-T660")).unwrap();
+        assert_eq!(
+            csv,
+            "crate,version,depth,reachable_callers,dependency_kind,via_parent\n\
+             b,1.0.0,2,4,build,a-1.0.0\n\
+             a,1.0.0,1,1,normal,vuln-1.0.0\n"
+        );
+
+        // the header is written without any affected crate
+        AffectedCrate::write_csv("CVE-T660", &[]).await.unwrap();
+        let csv = std::fs::read_to_string(AffectedCrate::path("CVE-T660")).unwrap();
+        assert_eq!(
+            csv,
+            "crate,version,depth,reachable_callers,dependency_kind,via_parent\n"
+        );
+    }
+
+    #[test]
+    fn the_summary_counts_this_run_and_exits_with_2_over_the_threshold() {
+        let mut manifest = RunManifest::new("CVE-0000-0000");
+        manifest.written_results = vec!["bar-0.2.0".to_string(), "foo-0.1.0".to_string()];
+        manifest.skipped_crates.push(SkippedCrate {
+            name: "baz".to_string(),
+            version: "0.3.0".to_string(),
+            parent: None,
+            reason: SkipReason::AnalyzerFailed {
+                error: "no output dir".to_string(),
+            },
+        });
+        let summary = RunSummary::from_manifest("CVE-0000-0000", &manifest, 1);
+        assert_eq!(
+            summary.to_string(),
+            "cvetracker4rs: cve=CVE-0000-0000 affected=2 failed=2 skipped=0"
+        );
+        assert_eq!(summary.exit_code_as(None), 0);
+        assert_eq!(summary.exit_code_as(Some(2)), 0);
+        assert_eq!(summary.exit_code_as(Some(1)), EXIT_TOO_MANY_ERRORS);
+    }
+
+    #[tokio::test]
+    async fn the_environment_file_has_the_expected_keys() {
+        let (_sandbox, _guard) = crate::test_support::sandbox().await;
+        let environment = AnalysisEnvironment::capture().await;
+        environment.write("CVE-T690").await.unwrap();
+
+        let content = std::fs::read_to_string(AnalysisEnvironment::path("CVE-T690")).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        let mut keys: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "arch",
+                "call_cg4rs_version",
+                "captured_at",
+                "os",
+                "rustc_version",
+                "tool_version"
+            ]
+        );
+        assert_eq!(json["tool_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["os"], std::env::consts::OS);
+        assert_eq!(json["arch"], std::env::consts::ARCH);
+        chrono::DateTime::parse_from_rfc3339(json["captured_at"].as_str().unwrap()).unwrap();
+        // a tool that cannot be run is recorded as null, not left out
+        for tool in ["rustc_version", "call_cg4rs_version"] {
+            assert!(json[tool].is_null() || json[tool].is_string());
+        }
+
+        assert_eq!(
+            AnalysisEnvironment::load("CVE-T690").await.unwrap(),
+            Some(environment)
+        );
+        assert_eq!(
+            AnalysisEnvironment::load("CVE-T690-NONE").await.unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{self as futures_stream, BoxStream, StreamExt, TryStreamExt};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::database::{DEPENDENCY_KIND_NORMAL, Database};
+use crate::model::ReverseDependency;
+
+/// Where the published versions and the reverse dependencies of crates come
+/// from: the crates.io database, or files exported from it ([`FileMetadataSource`]).
+/// Versions are listed newest published first.
+pub trait MetadataSource: std::fmt::Debug + Send + Sync {
+    fn query_crate_versions<'a>(
+        &'a self,
+        crate_name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>>>;
+
+    /// the versions published on or after `since`
+    fn query_crate_versions_since<'a>(
+        &'a self,
+        crate_name: &'a str,
+        since: NaiveDate,
+    ) -> BoxFuture<'a, Result<Vec<String>>>;
+
+    /// The versions of other crates depending on `crate_name` with one of
+    /// `kinds`, published on or after `since` if set
+    fn stream_dependents<'a>(
+        &'a self,
+        crate_name: &'a str,
+        since: Option<NaiveDate>,
+        kinds: &[i32],
+    ) -> BoxStream<'a, Result<ReverseDependency>>;
+
+    /// [`MetadataSource::stream_dependents`] loaded at once, for crates with
+    /// few dependents
+    fn query_dependents<'a>(
+        &'a self,
+        crate_name: &'a str,
+        since: Option<NaiveDate>,
+        kinds: &'a [i32],
+    ) -> BoxFuture<'a, Result<Vec<ReverseDependency>>> {
+        self.stream_dependents(crate_name, since, kinds)
+            .try_collect()
+            .boxed()
+    }
+}
+
+impl MetadataSource for Database {
+    fn query_crate_versions<'a>(
+        &'a self,
+        crate_name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>>> {
+        Box::pin(async move { Ok(Database::query_crate_versions(self, crate_name).await?) })
+    }
+
+    fn query_crate_versions_since<'a>(
+        &'a self,
+        crate_name: &'a str,
+        since: NaiveDate,
+    ) -> BoxFuture<'a, Result<Vec<String>>> {
+        Box::pin(
+            async move { Ok(Database::query_crate_versions_since(self, crate_name, since).await?) },
+        )
+    }
+
+    fn stream_dependents<'a>(
+        &'a self,
+        crate_name: &'a str,
+        since: Option<NaiveDate>,
+        kinds: &[i32],
+    ) -> BoxStream<'a, Result<ReverseDependency>> {
+        Database::stream_dependents(self, crate_name, since, kinds)
+            .map(|revdep| Ok(revdep?))
+            .boxed()
+    }
+
+    fn query_dependents<'a>(
+        &'a self,
+        crate_name: &'a str,
+        since: Option<NaiveDate>,
+        kinds: &'a [i32],
+    ) -> BoxFuture<'a, Result<Vec<ReverseDependency>>> {
+        Box::pin(
+            async move { Ok(Database::query_dependents(self, crate_name, since, kinds).await?) },
+        )
+    }
+}
+
+/// one entry of a reverse-dependencies file
+#[derive(Debug, Clone, Deserialize)]
+struct DependentEntry {
+    name: String,
+    version: String,
+    req: String,
+    /// `normal`, `build` or `dev`, `normal` when missing
+    #[serde(default)]
+    kind: Option<String>,
+}
+
+/// Versions and reverse dependencies read from JSON files instead of the
+/// database (`--reverse-deps-file` and `--versions-file`), for users without
+/// database access or with a pre-exported graph:
+/// - the reverse-dependencies file maps a crate to the versions depending on
+///   it, `{"<crate>": [{"name", "version", "req", "kind"?}, ...]}`;
+/// - the versions file maps a crate to its published versions, newest first,
+///   `{"<crate>": ["1.2.0", "1.1.0", ...]}`.
+///
+/// A crate missing from a file has no versions or no dependents. The files
+/// carry no publication dates, so `--since` is not supported.
+#[derive(Debug, Clone, Default)]
+pub struct FileMetadataSource {
+    dependents: HashMap<String, Vec<DependentEntry>>,
+    versions: HashMap<String, Vec<String>>,
+}
+
+impl FileMetadataSource {
+    pub async fn load(reverse_deps_file: &Path, versions_file: &Path) -> Result<Self> {
+        Ok(Self {
+            dependents: read_json(reverse_deps_file).await?,
+            versions: read_json(versions_file).await?,
+        })
+    }
+
+    fn versions(&self, crate_name: &str) -> Vec<String> {
+        self.versions.get(crate_name).cloned().unwrap_or_default()
+    }
+}
+
+async fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// the `dependencies.kind` of a kind name, see `DEPENDENCY_KINDS`
+fn dependency_kind_of(name: &str) -> Result<i32> {
+    crate::database::ALL_DEPENDENCY_KINDS
+        .into_iter()
+        .find(|kind| crate::database::dependency_kind_name(*kind) == name)
+        .with_context(|| format!("unknown dependency kind: {}", name))
+}
+
+fn since_unsupported(since: NaiveDate) -> anyhow::Error {
+    anyhow::anyhow!(
+        "--since={} needs publication dates, which the metadata files do not have",
+        since
+    )
+}
+
+impl MetadataSource for FileMetadataSource {
+    fn query_crate_versions<'a>(
+        &'a self,
+        crate_name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>>> {
+        Box::pin(async move { Ok(self.versions(crate_name)) })
+    }
+
+    fn query_crate_versions_since<'a>(
+        &'a self,
+        _crate_name: &'a str,
+        since: NaiveDate,
+    ) -> BoxFuture<'a, Result<Vec<String>>> {
+        Box::pin(async move { Err(since_unsupported(since)) })
+    }
+
+    fn stream_dependents<'a>(
+        &'a self,
+        crate_name: &'a str,
+        since: Option<NaiveDate>,
+        kinds: &[i32],
+    ) -> BoxStream<'a, Result<ReverseDependency>> {
+        if let Some(since) = since {
+            return futures_stream::once(async move { Err(since_unsupported(since)) }).boxed();
+        }
+        let kinds = kinds.to_vec();
+        let dependents = self
+            .dependents
+            .get(crate_name)
+            .map(|entries| entries.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(move |entry| {
+                let kind = match entry.kind.as_deref() {
+                    Some(name) => match dependency_kind_of(name) {
+                        Ok(kind) => kind,
+                        Err(e) => return Some(Err(e)),
+                    },
+                    None => DEPENDENCY_KIND_NORMAL,
+                };
+                kinds.contains(&kind).then(|| {
+                    Ok(ReverseDependency::new(
+                        entry.name.clone(),
+                        entry.version.clone(),
+                        entry.req.clone(),
+                        kind,
+                    ))
+                })
+            });
+        futures_stream::iter(dependents).boxed()
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// process-wide counters updated while a CVE is being analyzed, reset when
+/// `analyze` starts so that each run of a process reports only its own cost;
+/// runs of the same process must therefore not overlap
+pub(crate) struct RunCounters {
+    pub crates_downloaded: AtomicU64,
+    pub bytes_downloaded: AtomicU64,
+    pub analyzer_invocations: AtomicU64,
+    pub analyzer_timeouts: AtomicU64,
+    pub analyzer_failures: AtomicU64,
+    pub analyzer_retries: AtomicU64,
+    /// analyzer failures caused by a build script, counted in `analyzer_failures` too
+    pub analyzer_build_script_failures: AtomicU64,
+    /// the analyzer succeeded but wrote no callers file, logged once per BFS level
+    pub analyzer_no_output: AtomicU64,
+    /// analyses a joint BFS of `analyze_batch` did not repeat, one per extra target of a shared crate
+    pub shared_analyses: AtomicU64,
+    /// the caller finder time those analyses would have taken, in milliseconds
+    pub shared_analysis_millis: AtomicU64,
+}
+
+pub(crate) static COUNTERS: RunCounters = RunCounters::new();
+
+impl RunCounters {
+    const fn new() -> Self {
+        Self {
+            crates_downloaded: AtomicU64::new(0),
+            bytes_downloaded: AtomicU64::new(0),
+            analyzer_invocations: AtomicU64::new(0),
+            analyzer_timeouts: AtomicU64::new(0),
+            analyzer_failures: AtomicU64::new(0),
+            analyzer_retries: AtomicU64::new(0),
+            analyzer_build_script_failures: AtomicU64::new(0),
+            analyzer_no_output: AtomicU64::new(0),
+            shared_analyses: AtomicU64::new(0),
+            shared_analysis_millis: AtomicU64::new(0),
+        }
+    }
+
+    pub fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+
+    /// zero every counter, at the start of a run
+    pub fn reset(&self) {
+        for counter in [
+            &self.crates_downloaded,
+            &self.bytes_downloaded,
+            &self.analyzer_invocations,
+            &self.analyzer_timeouts,
+            &self.analyzer_failures,
+            &self.analyzer_retries,
+            &self.analyzer_build_script_failures,
+            &self.analyzer_no_output,
+            &self.shared_analyses,
+            &self.shared_analysis_millis,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// what a run cost, recorded in the manifest and reported in the stats
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RunMetrics {
+    pub wall_clock_secs: f64,
+    /// crates actually fetched from crates.io (cached archives are not counted)
+    pub crates_downloaded: u64,
+    pub bytes_downloaded: u64,
+    pub analyzer_invocations: u64,
+    pub analyzer_timeouts: u64,
+    /// crates the analyzer still failed on after all retries, timeouts excluded
+    pub analyzer_failures: u64,
+    /// re-runs of the analyzer after a non-zero exit
+    #[serde(default)]
+    pub analyzer_retries: u64,
+    /// analyzer failures caused by a failing build script, included in `analyzer_failures`
+    #[serde(default)]
+    pub build_script_failures: u64,
+    /// crate analyses shared between the targets of an `analyze_batch`, which
+    /// reports the metrics of the whole batch for each of its targets
+    #[serde(default)]
+    pub shared_analyses: u64,
+    /// the caller finder time saved by the shared analyses
+    #[serde(default)]
+    pub shared_analysis_secs_saved: f64,
+}
+
+impl RunMetrics {
+    /// snapshot the process-wide counters
+    pub(crate) fn snapshot(wall_clock: Duration) -> Self {
+        Self {
+            wall_clock_secs: wall_clock.as_secs_f64(),
+            crates_downloaded: COUNTERS.crates_downloaded.load(Ordering::Relaxed),
+            bytes_downloaded: COUNTERS.bytes_downloaded.load(Ordering::Relaxed),
+            analyzer_invocations: COUNTERS.analyzer_invocations.load(Ordering::Relaxed),
+            analyzer_timeouts: COUNTERS.analyzer_timeouts.load(Ordering::Relaxed),
+            analyzer_failures: COUNTERS.analyzer_failures.load(Ordering::Relaxed),
+            analyzer_retries: COUNTERS.analyzer_retries.load(Ordering::Relaxed),
+            build_script_failures: COUNTERS
+                .analyzer_build_script_failures
+                .load(Ordering::Relaxed),
+            shared_analyses: COUNTERS.shared_analyses.load(Ordering::Relaxed),
+            shared_analysis_secs_saved: COUNTERS.shared_analysis_millis.load(Ordering::Relaxed)
+                as f64
+                / 1000.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_zeroes_the_counters_of_a_previous_run() {
+        let counters = RunCounters::new();
+        RunCounters::incr(&counters.analyzer_invocations);
+        RunCounters::add(&counters.bytes_downloaded, 1024);
+        RunCounters::incr(&counters.analyzer_no_output);
+        counters.reset();
+        assert_eq!(RunCounters::get(&counters.analyzer_invocations), 0);
+        assert_eq!(RunCounters::get(&counters.bytes_downloaded), 0);
+        assert_eq!(RunCounters::get(&counters.analyzer_no_output), 0);
+    }
+}
@@ -1,6 +1,10 @@
 use crate::dir::{CrateVersionDirIndex, CrateWorkspaceFileSystemManager};
+use crate::error::CveTrackerError;
+use crate::metrics::{COUNTERS, RunCounters};
 use crate::utils;
 use anyhow::{Context, Result};
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs as tokio_fs;
@@ -17,8 +21,34 @@ pub struct Krate {
     pub(crate) dir_idx: CrateVersionDirIndex,
     pub(crate) working_dir: PathBuf, // XXX-workspace/XXX-0.1.1/
     pub(crate) working_src_code_dir: PathBuf, // XXX-workspace/XXX-0.1.1/XXX-0.1.1
+    /// the directory of the package itself, resolved once the crate is copied:
+    /// the working source directory unless the archive is a virtual workspace
+    /// with the package in a member
+    pub(crate) package_dir: PathBuf,
+    /// `DOWNLOAD_DIR` of the analyzer that created the crate
+    pub(crate) download_dir: PathBuf,
 }
 
+/// The crate archive is larger than `MAX_CRATE_SIZE_MB`, so it is not
+/// extracted nor analyzed
+#[derive(Debug)]
+pub(crate) struct CrateTooLarge {
+    pub size_bytes: u64,
+    pub limit_bytes: u64,
+}
+
+impl std::fmt::Display for CrateTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the crate archive is {} bytes, larger than the limit of {} bytes",
+            self.size_bytes, self.limit_bytes
+        )
+    }
+}
+
+impl std::error::Error for CrateTooLarge {}
+
 impl Krate {
     /// This function is used to create a krate
     /// 1. create a krate workspace and version directory
@@ -30,6 +60,25 @@ impl Krate {
         version: &str,
         parent_version_dir_index: CrateVersionDirIndex,
         fs_manager: Arc<Mutex<CrateWorkspaceFileSystemManager>>,
+    ) -> Result<Self> {
+        Self::create_as(
+            name,
+            version,
+            parent_version_dir_index,
+            fs_manager,
+            utils::prune_extract_after_copy_enabled(),
+        )
+        .await
+    }
+
+    /// [`Self::create`], removing the extract directory after the copy or not
+    /// whatever `PRUNE_EXTRACT_AFTER_COPY` is
+    async fn create_as(
+        name: &str,
+        version: &str,
+        parent_version_dir_index: CrateVersionDirIndex,
+        fs_manager: Arc<Mutex<CrateWorkspaceFileSystemManager>>,
+        prune_extract: bool,
     ) -> Result<Self> {
         let (_ws_idx, dir_idx) = fs_manager
             .lock()
@@ -37,20 +86,40 @@ impl Krate {
             .create_krate_working_dir(parent_version_dir_index, name, version)
             .await
             .unwrap();
-        let working_dir = fs_manager.lock().await.get_krate_working_dir(dir_idx).await;
+        let (working_dir, download_dir) = {
+            let fs_manager = fs_manager.lock().await;
+            (
+                fs_manager.get_krate_working_dir(dir_idx).await,
+                fs_manager.download_dir().to_path_buf(),
+            )
+        };
         let working_src_code_dir = working_dir.join(format!("{}-{}", name, version));
-        let krate = Self {
+        let mut krate = Self {
             name: name.to_owned(),
             version: version.to_owned(),
             dir_idx,
             working_dir,
+            package_dir: working_src_code_dir.clone(),
             working_src_code_dir,
+            download_dir,
         };
         tracing::debug!("!working dir: {:?}", krate.working_dir);
         tracing::debug!("!working src code dir: {:?}", krate.working_src_code_dir);
 
+        // the same crate version may be created concurrently from several parents,
+        // its extract directory must not be pruned while another one copies it
+        let extract_lock = krate.extract_lock().await;
+        let _extract_guard = if prune_extract {
+            Some(extract_lock.lock().await)
+        } else {
+            None
+        };
+
         // download into download directory and unzip into extract directory
-        krate.fetch_and_unzip_crate().await?;
+        krate
+            .fetch_and_unzip_crate()
+            .await
+            .map_err(CveTrackerError::into_inner)?;
         // copy the crate to the working directory
         // now, we have a copy of the crate in the
         // working directory, which can be modified anyway
@@ -63,14 +132,43 @@ impl Krate {
             );
             return Err(e.context("Failed to copy crate to working directory"));
         }
+        if let Some(member) =
+            utils::resolve_workspace_member(&krate.working_src_code_dir, &krate.name).await
+        {
+            krate.package_dir = member;
+        }
+        // the working copy is the only consumer of the extract directory
+        if prune_extract {
+            let extract_dir = krate.get_extract_crate_dir_path().await;
+            match tokio_fs::remove_dir_all(&extract_dir).await {
+                Ok(()) => tracing::debug!(
+                    "PRUNE_EXTRACT_AFTER_COPY=1, removed {}",
+                    extract_dir.display()
+                ),
+                Err(e) => tracing::warn!("Failed to remove {}: {}", extract_dir.display(), e),
+            }
+        }
         Ok(krate)
     }
 
+    /// the lock serializing the extraction, copy and pruning of this crate version
+    async fn extract_lock(&self) -> Arc<Mutex<()>> {
+        static EXTRACT_LOCKS: once_cell::sync::Lazy<
+            std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+        > = once_cell::sync::Lazy::new(Default::default);
+        let extract_dir = self.get_extract_crate_dir_path().await;
+        EXTRACT_LOCKS
+            .lock()
+            .unwrap()
+            .entry(extract_dir)
+            .or_default()
+            .clone()
+    }
+
     /// obtain the download directory
     /// $DOWNLOAD_DIR/crate_name/ ,such as /home/rust/xinshi/download/crossbeam-channel/
     async fn get_download_crate_dir_path(&self) -> PathBuf {
-        let base_dir = std::env::var("DOWNLOAD_DIR").unwrap_or_else(|_| "./downloads".to_string());
-        Path::new(&base_dir).join(&self.name)
+        self.download_dir.join(&self.name)
     }
 
     /// obtain the crate file path
@@ -91,16 +189,61 @@ impl Krate {
         self.working_src_code_dir.clone()
     }
 
+    /// the directory of the package itself, which is the working source directory
+    /// unless the archive is a virtual workspace with the package in a member
+    pub(crate) async fn get_package_dir(&self) -> PathBuf {
+        self.package_dir.clone()
+    }
+
     pub(crate) async fn get_cargo_toml_path(&self) -> PathBuf {
-        self.working_src_code_dir.join("Cargo.toml")
+        self.get_package_dir().await.join("Cargo.toml")
     }
 
     pub(crate) async fn get_target_dir(&self) -> PathBuf {
-        self.working_src_code_dir.join("target")
+        self.get_package_dir().await.join("target")
     }
 
+    /// the source directory of the package, `src` unless Cargo.toml points
+    /// the targets elsewhere, see [`Krate::find_src_dir`]
     pub(crate) async fn get_src_dir(&self) -> PathBuf {
-        self.working_src_code_dir.join("src")
+        match self.find_src_dir().await {
+            Some(src_dir) => src_dir,
+            None => self.get_package_dir().await.join("src"),
+        }
+    }
+
+    /// the source directory of the package, `None` if it has no sources
+    pub(crate) async fn find_src_dir(&self) -> Option<PathBuf> {
+        utils::resolve_src_dir(&self.get_package_dir().await).await
+    }
+
+    /// All `.rs` files under the src directory of the package (the workspace member
+    /// if the archive is a virtual workspace), for additional source-level passes.
+    /// Unreadable directories are skipped.
+    pub fn source_files(&self) -> impl Stream<Item = PathBuf> + '_ {
+        stream::once(self.get_src_dir())
+            .map(|src_dir| {
+                stream::unfold(vec![src_dir], |mut dirs| async move {
+                    while let Some(dir) = dirs.pop() {
+                        let Ok(mut entries) = tokio_fs::read_dir(&dir).await else {
+                            continue;
+                        };
+                        let mut files = Vec::new();
+                        while let Ok(Some(entry)) = entries.next_entry().await {
+                            let path = entry.path();
+                            if path.is_dir() {
+                                dirs.push(path);
+                            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                                files.push(path);
+                            }
+                        }
+                        return Some((stream::iter(files), dirs));
+                    }
+                    None
+                })
+                .flatten()
+            })
+            .flatten()
     }
 
     pub async fn has_cargo_toml_in_extract_dir(&self) -> bool {
@@ -118,10 +261,16 @@ impl Krate {
         let crate_file_path = self.get_download_crate_file_path().await;
         let extract_dir_path = self.get_extract_crate_dir_path().await;
 
+        let max_size = utils::max_crate_size_bytes();
+
         // check if the crate-version.crate file already exists
         // we don't need to download the crate file again
         if crate_file_path.exists() && !force {
             tracing::debug!("{} exists, skip the download", extract_dir_path.display());
+            if let Some(limit) = max_size {
+                let size = tokio_fs::metadata(&crate_file_path).await?.len();
+                Self::check_size(size, limit)?;
+            }
             return Ok(());
         }
 
@@ -134,24 +283,53 @@ impl Krate {
 
         // download the crate file
         tracing::debug!("Downloading the crate file: {}", crate_file_path.display());
-        let download_url = format!(
-            "https://crates.io/api/v1/crates/{}/{}/download",
-            self.name, self.version
-        );
+        let download_url = utils::crate_download_url(&self.name, &self.version);
 
-        let download_result = Command::new("curl")
-            .args([
-                "-L",
-                &download_url,
-                "-o",
-                &crate_file_path.to_string_lossy(),
-            ])
-            .output()
-            .await;
+        // skip the fetch entirely when the server announces the size
+        if let Some(limit) = max_size
+            && let Some(size) = utils::remote_content_length(&download_url).await
+        {
+            Self::check_size(size, limit)?;
+        }
 
-        if let Err(e) = download_result {
-            return Err(anyhow::anyhow!("Failed to download the crate: {}", e));
+        // download into `<crate>.part` and only move it into place once it is verified,
+        // so an interrupted download can be resumed instead of fetched from scratch
+        let part_file_path = crate_file_path.with_extension("crate.part");
+        let expected_cksum = utils::expected_crate_cksum(&self.name, &self.version).await;
+        // a part file completed by an earlier attempt, curl cannot resume past its end
+        let complete = part_file_path.exists()
+            && utils::verify_crate_archive(&part_file_path, expected_cksum.as_deref())
+                .await
+                .is_ok();
+        if !complete {
+            if part_file_path.exists() {
+                tracing::info!(
+                    "Resuming the partial download: {}",
+                    part_file_path.display()
+                );
+            }
+            Self::fetch_part(&download_url, &part_file_path).await?;
+            if let Err(e) =
+                utils::verify_crate_archive(&part_file_path, expected_cksum.as_deref()).await
+            {
+                // the transfer completed but the data is wrong, e.g. a corrupted
+                // partial file or a server ignoring the range, so it is discarded
+                tracing::warn!(
+                    "{} did not verify, re-downloading from scratch: {}",
+                    part_file_path.display(),
+                    e
+                );
+                tokio_fs::remove_file(&part_file_path).await?;
+                Self::curl_download(&download_url, &part_file_path, false).await?;
+                utils::verify_crate_archive(&part_file_path, expected_cksum.as_deref()).await?;
+            }
         }
+        tokio_fs::rename(&part_file_path, &crate_file_path)
+            .await
+            .context(format!(
+                "Failed to move {} into place",
+                part_file_path.display()
+            ))?;
 
         // check the file size
         let metadata = tokio_fs::metadata(&crate_file_path).await.context(format!(
@@ -165,7 +343,64 @@ impl Krate {
                 crate_file_path.display()
             ));
         }
+        RunCounters::incr(&COUNTERS.crates_downloaded);
+        RunCounters::add(&COUNTERS.bytes_downloaded, metadata.len());
+        if let Some(limit) = max_size {
+            Self::check_size(metadata.len(), limit)?;
+        }
+
+        Ok(())
+    }
+
+    /// a `CrateTooLarge` error if the archive is larger than `limit` bytes
+    fn check_size(size: u64, limit: u64) -> Result<()> {
+        if size > limit {
+            return Err(CrateTooLarge {
+                size_bytes: size,
+                limit_bytes: limit,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Fetch `url` into the part file `path`, resuming it if it exists. A failed
+    /// resume, e.g. a server rejecting range requests, falls back to a full
+    /// download. A failed full download keeps what it fetched for the retries
+    /// of `fetch_and_unzip_crate` to resume.
+    async fn fetch_part(url: &str, path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Self::curl_download(url, path, false).await;
+        }
+        if let Err(e) = Self::curl_download(url, path, true).await {
+            tracing::warn!(
+                "Failed to resume {}, downloading from scratch: {}",
+                path.display(),
+                e
+            );
+            tokio_fs::remove_file(path).await?;
+            Self::curl_download(url, path, false).await?;
+        }
+        Ok(())
+    }
 
+    /// fetch `url` into `path` with curl, continuing from the end of `path` if `resume`
+    async fn curl_download(url: &str, path: &Path, resume: bool) -> Result<()> {
+        let output = Command::new("curl")
+            .arg("-fL")
+            .args(if resume { &["-C", "-"][..] } else { &[] })
+            .args([url, "-o", &path.to_string_lossy()])
+            .args(utils::crate_download_curl_args())
+            .output()
+            .await
+            .context("Failed to execute curl")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Failed to download the crate: curl exited with {:?}: {}",
+                output.status.code(),
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
         Ok(())
     }
 
@@ -207,21 +442,7 @@ impl Krate {
             download_dir.display()
         );
 
-        let unzip_result = Command::new("tar")
-            .args(["-xf", &crate_file_path.to_string_lossy()])
-            .current_dir(&download_dir)
-            .output()
-            .await
-            .context("Failed to execute tar command")?;
-
-        if !unzip_result.status.success() {
-            let stderr = String::from_utf8_lossy(&unzip_result.stderr);
-            return Err(anyhow::anyhow!(
-                "Extract {} failed: {}",
-                crate_file_path.display(),
-                stderr
-            ));
-        }
+        utils::extract_crate_archive(&crate_file_path, &download_dir).await?;
 
         // check if the directory exists
         if !extract_dir_path.exists() {
@@ -263,7 +484,10 @@ impl Krate {
     }
 
     /// download and unzip the crate, return the path to the extracted directory
-    pub async fn fetch_and_unzip_crate(&self) -> Result<PathBuf> {
+    /// Download and extract the crate, retrying up to 3 times. Fails with
+    /// [`CveTrackerError::Download`] (wrapping [`CrateTooLarge`], which is not
+    /// retried) or [`CveTrackerError::Extract`].
+    pub async fn fetch_and_unzip_crate(&self) -> Result<PathBuf, CveTrackerError> {
         let extract_dir_path = self.get_extract_crate_dir_path().await;
         let mut last_err = None;
         for attempt in 0..3 {
@@ -273,7 +497,10 @@ impl Krate {
                 // if the attempt is greater than 0, we need to force the download and unzip
                 let force = attempt > 0;
                 if let Err(e) = self.download(force).await {
-                    return Err(anyhow::anyhow!("download() failed: {}", e));
+                    if e.is::<CrateTooLarge>() {
+                        return Err(CveTrackerError::Download(e));
+                    }
+                    return Err(CveTrackerError::Download(anyhow::anyhow!("download() failed: {}", e)));
                 }
 
                 if let Err(e) = self.unzip(force).await {
@@ -281,12 +508,12 @@ impl Krate {
                         "Failed to unzip the crate {}: {e}",
                         extract_dir_path.display()
                     );
-                    return Err(anyhow::anyhow!("unzip() failed: {}", e));
+                    return Err(CveTrackerError::Extract(anyhow::anyhow!("unzip() failed: {}", e)));
                 }
 
                 // 检查是否有 Cargo.toml
                 if !self.has_cargo_toml_in_extract_dir().await {
-                    return Err(anyhow::anyhow!("No Cargo.toml found in {}, will retry if attempts remain", extract_dir_path.display()));
+                    return Err(CveTrackerError::Extract(anyhow::anyhow!("No Cargo.toml found in {}, will retry if attempts remain", extract_dir_path.display())));
                 }else{
                     tracing::info!("Successfully fetch and unzip the crate to: {}", extract_dir_path.display());
                 }
@@ -297,6 +524,10 @@ impl Krate {
 
             match result {
                 Ok(path) => return Ok(path),
+                // retrying does not make it smaller
+                Err(CveTrackerError::Download(e)) if e.is::<CrateTooLarge>() => {
+                    return Err(CveTrackerError::Download(e));
+                }
                 Err(e) => {
                     tracing::warn!(
                         "fetch_and_unzip_crate: failed to download and unzip crate {} (attempt {}/3), will retry if attempts remain: {:?}",
@@ -310,8 +541,11 @@ impl Krate {
                 }
             }
         }
-        Err(last_err
-            .unwrap_or_else(|| anyhow::anyhow!("fetch_and_unzip_crate failed for unknown reason")))
+        Err(last_err.unwrap_or_else(|| {
+            CveTrackerError::Download(anyhow::anyhow!(
+                "fetch_and_unzip_crate failed for unknown reason"
+            ))
+        }))
     }
 
     async fn cp_crate_to_working_dir(&self) -> Result<()> {
@@ -396,15 +630,229 @@ impl Krate {
 pub struct ReverseDependency {
     // the crate name of the reverse dependency
     pub name: String,
-    // the version of the reverse dependency
+    // the published version of the reverse dependency itself,
+    // not a version of the crate it depends on
     pub version: String,
     // the version requirement of the dependency
     // i.e. `[dependencies]  "dep_name" = "1.0.0"` in `Cargo.toml`
     pub req: String,
+    // the kind of the dependency, `dependencies.kind` in the crates.io dump
+    pub kind: i32,
+    // the newest published version of the depended-on crate matching `req`,
+    // i.e. what a fresh resolution of the dependent would pick; the BFS still
+    // patches the dependent to the exact version being analyzed.
+    // `None` until resolved by `get_reverse_deps`, or if nothing matches
+    pub resolved_target_version: Option<String>,
 }
 
 impl ReverseDependency {
-    pub fn new(name: String, version: String, req: String) -> Self {
-        Self { name, version, req }
+    pub fn new(name: String, version: String, req: String, kind: i32) -> Self {
+        Self {
+            name,
+            version,
+            req,
+            kind,
+            resolved_target_version: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+
+    /// a gzipped tarball of a `foo-1.0.0` crate
+    fn crate_archive() -> Vec<u8> {
+        let mut tar = tar::Builder::new(Vec::new());
+        let content = b"[package]\nname = \"foo\"\nversion = \"1.0.0\"\n".repeat(64);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "foo-1.0.0/Cargo.toml", content.as_slice())
+            .unwrap();
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&tar.into_inner().unwrap()).unwrap();
+        gzip.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn an_interrupted_download_is_resumed_and_verified() {
+        let archive = crate_archive();
+        let cksum = utils::sha256_hex(&archive);
+
+        let dir = tempfile::tempdir().unwrap();
+        let remote = dir.path().join("foo-1.0.0.crate");
+        std::fs::write(&remote, &archive).unwrap();
+        // the transfer stopped half way
+        let part = dir.path().join("foo-1.0.0.crate.part");
+        std::fs::write(&part, &archive[..archive.len() / 2]).unwrap();
+        assert!(
+            utils::verify_crate_archive(&part, Some(&cksum))
+                .await
+                .is_err()
+        );
+
+        let url = format!("file://{}", remote.display());
+        Krate::fetch_part(&url, &part).await.unwrap();
+        assert_eq!(std::fs::read(&part).unwrap(), archive);
+        utils::verify_crate_archive(&part, Some(&cksum))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_server_without_ranges_falls_back_to_a_full_download() {
+        use std::io::{BufRead, BufReader};
+
+        let archive = crate_archive();
+        // answers every request with the whole archive, ignoring `Range`
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let body = archive.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                    line.clear();
+                }
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let part = dir.path().join("foo-1.0.0.crate.part");
+        std::fs::write(&part, &archive[..archive.len() / 2]).unwrap();
+        let url = format!("http://{}/foo-1.0.0.crate", addr);
+        assert!(Krate::curl_download(&url, &part, true).await.is_err());
+
+        Krate::fetch_part(&url, &part).await.unwrap();
+        assert_eq!(std::fs::read(&part).unwrap(), archive);
+    }
+
+    #[test]
+    fn an_archive_of_exactly_the_limit_is_not_too_large() {
+        let limit = 2 * 1024 * 1024;
+        Krate::check_size(limit, limit).unwrap();
+        Krate::check_size(0, limit).unwrap();
+
+        let error = Krate::check_size(limit + 1, limit).unwrap_err();
+        let too_large = error.downcast_ref::<CrateTooLarge>().unwrap();
+        assert_eq!(
+            (too_large.size_bytes, too_large.limit_bytes),
+            (limit + 1, limit)
+        );
+        assert_eq!(
+            error.to_string(),
+            "the crate archive is 2097153 bytes, larger than the limit of 2097152 bytes"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_src_dir_follows_a_lib_path_outside_src() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::test_support::write_files(
+            dir.path(),
+            &[
+                (
+                    "Cargo.toml",
+                    "[package]\nname = \"foo\"\nversion = \"1.0.0\"\n\n[lib]\npath = \"lib/foo.rs\"\n",
+                ),
+                ("lib/foo.rs", "pub mod bar;\n"),
+                ("lib/bar.rs", ""),
+            ],
+        );
+        let krate = crate::test_support::krate_at("foo", dir.path());
+        assert_eq!(krate.find_src_dir().await, Some(dir.path().join("lib")));
+        let mut files: Vec<PathBuf> = krate.source_files().collect().await;
+        files.sort();
+        assert_eq!(
+            files,
+            ["lib/bar.rs", "lib/foo.rs"].map(|f| dir.path().join(f))
+        );
+
+        // a non-empty `src` wins over the manifest
+        crate::test_support::write_files(dir.path(), &[("src/lib.rs", "")]);
+        assert_eq!(krate.find_src_dir().await, Some(dir.path().join("src")));
+
+        // a lib path that does not exist is no source
+        let missing = tempfile::tempdir().unwrap();
+        crate::test_support::write_files(
+            missing.path(),
+            &[(
+                "Cargo.toml",
+                "[package]\nname = \"foo\"\nversion = \"1.0.0\"\n\n[lib]\npath = \"lib/foo.rs\"\n",
+            )],
+        );
+        let krate = crate::test_support::krate_at("foo", missing.path());
+        assert_eq!(krate.find_src_dir().await, None);
+    }
+
+    #[tokio::test]
+    async fn source_files_lists_the_rust_files_under_src() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = |path| (path, "");
+        crate::test_support::write_files(
+            dir.path(),
+            &[
+                "src/lib.rs",
+                "src/net/mod.rs",
+                "src/net/tcp/stream.rs",
+                "src/net/README.md",
+                "src/data.bin",
+                "build.rs",
+                "tests/it.rs",
+            ]
+            .map(empty),
+        );
+        let krate = crate::test_support::krate_at("foo", dir.path());
+        let mut files: Vec<PathBuf> = krate.source_files().collect().await;
+        files.sort();
+        assert_eq!(
+            files,
+            ["src/lib.rs", "src/net/mod.rs", "src/net/tcp/stream.rs"].map(|f| dir.path().join(f))
+        );
+    }
+
+    #[tokio::test]
+    async fn pruning_removes_the_extract_dir_and_keeps_the_archive() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        sandbox.publish("t697-foo", "1.0.0", &[], "pub fn f() {}\n");
+        sandbox.publish("t697-foo", "1.1.0", &[], "pub fn f() {}\n");
+        let config = crate::config::Config::default();
+        let fs_manager = Arc::new(Mutex::new(
+            CrateWorkspaceFileSystemManager::new("CVE-T697", &config)
+                .await
+                .unwrap(),
+        ));
+
+        for (version, prune) in [("1.0.0", false), ("1.1.0", true)] {
+            let krate = Krate::create_as("t697-foo", version, 0, fs_manager.clone(), prune)
+                .await
+                .unwrap();
+            assert!(krate.package_dir.join("src/lib.rs").exists());
+            let archive = config
+                .download_dir()
+                .join("t697-foo")
+                .join(format!("t697-foo-{}.crate", version));
+            assert!(archive.exists());
+            assert_eq!(krate.get_extract_crate_dir_path().await.exists(), !prune);
+        }
+
+        // a pruned crate is extracted again from its archive when it is needed again
+        let again = Krate::create_as("t697-foo", "1.1.0", 0, fs_manager, true)
+            .await
+            .unwrap();
+        assert!(again.package_dir.join("src/lib.rs").exists());
+        assert!(!again.get_extract_crate_dir_path().await.exists());
     }
 }
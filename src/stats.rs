@@ -1,12 +1,24 @@
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{BTreeMap, BTreeSet, HashMap};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use tokio::fs as tokio_fs;
 
+use crate::manifest::{
+    AnalysisEnvironment, BfsTreeNode, CallerConfirmation, ConfirmationStatus, CrateInfo,
+    RunManifest,
+};
+use crate::metrics::RunMetrics;
+use crate::utils;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct CallerSample {
     pub subject: String,
     pub caller_path: String,
@@ -14,7 +26,81 @@ pub struct CallerSample {
     pub path_package_num: Option<i64>,
 }
 
+/// One caller finding as recorded in a result file, see [`load_callers`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CallerRecord {
+    /// e.g., "cargo-audit-0.21.2"
+    pub subject: String,
+    /// the target function the caller reaches
+    pub function: String,
+    pub caller_path: String,
+    pub path_constraints: Option<i64>,
+    pub path_package_num: Option<i64>,
+}
+
+/// All callers of one target function, see [`function_detail`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FunctionDetail {
+    pub function: String,
+    pub total_callers: usize,
+    /// subjects with at least one caller, sorted by name
+    pub subjects: Vec<String>,
+    /// sorted by path_constraints, then package hops, descending
+    pub callers: Vec<CallerRecord>,
+}
+
+/// What produced the results, taken from the run manifest, so archived
+/// stats describe themselves
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RunInfo {
+    /// empty for manifests written before the version was recorded
+    pub tool_version: String,
+    pub crate_name: String,
+    pub version_range: String,
+    pub target_function_paths: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+}
+
+/// One bin of a bucketed histogram, covering `lower <= value < upper`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HistogramBucket {
+    pub lower: i64,
+    pub upper: i64,
+    pub count: usize,
+}
+
+/// How far the BFS got at one depth, from bfs-tree-<cve>.json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DepthWidth {
+    /// 0 for the vulnerable crate itself
+    pub depth: usize,
+    /// crate versions processed at this depth
+    pub processed: usize,
+    /// of which with callers of the target functions
+    pub with_callers: usize,
+}
+
+/// The callers found at one BFS depth, joining the subjects with their depth
+/// in bfs-tree-<cve>.json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DepthCallers {
+    /// 0 for the vulnerable crate itself (`__self__` subjects)
+    pub depth: usize,
+    /// callers in the subjects at this depth
+    pub callers: usize,
+    /// subjects with callers at this depth
+    pub subjects: usize,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct FunctionStats {
     pub function_file: String,
     pub total_callers: usize,
@@ -33,36 +119,533 @@ pub struct FunctionStats {
     pub package_hops_p90: Option<f64>,
     pub package_hops_p95: Option<f64>,
     pub package_hops_p99: Option<f64>,
+    /// exact value -> callers, empty unless `HISTOGRAM_MODE=exact`
     pub path_constraints_histogram: BTreeMap<i64, usize>,
     pub package_hops_histogram: BTreeMap<i64, usize>,
+    /// the histograms bucketed by `HISTOGRAM_MODE`, empty in `exact` mode
+    #[serde(default)]
+    pub path_constraints_buckets: Vec<HistogramBucket>,
+    #[serde(default)]
+    pub package_hops_buckets: Vec<HistogramBucket>,
     pub top_callers_by_constraints: Vec<CallerSample>,
     pub top_callers_by_package_hops: Vec<CallerSample>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SubjectStats {
     /// e.g., "cargo-audit-0.21.2" (filename without -CVE.txt)
     pub subject: String,
     pub total_callers: usize,
     pub per_function_callers: BTreeMap<String, usize>,
+    /// the edition of the crate, only known with `ANNOTATE_CRATE_INFO=1`
+    #[serde(default)]
+    pub edition: Option<String>,
+    /// the MSRV (`rust-version`) of the crate, only known with `ANNOTATE_CRATE_INFO=1`
+    #[serde(default)]
+    pub rust_version: Option<String>,
+}
+
+/// All analyzed versions of one dependent crate merged into a single entry.
+/// The versions are usually the oldest and newest ones picked by
+/// `select_two_end_vers`, so the callers are max-ed rather than summed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CrateStats {
+    pub crate_name: String,
+    pub versions: Vec<String>,
+    /// the highest total callers among the versions
+    pub total_callers: usize,
+    /// per function, the highest callers among the versions
+    pub per_function_callers: BTreeMap<String, usize>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct GlobalStats {
     pub cve_id: String,
     pub total_subjects: usize,
     pub total_function_result_files: usize,
     pub total_callers: usize,
+    /// the part of `total_callers` inside the vulnerable crate itself, from
+    /// its `__self__-<version>` subjects (`--include-self`)
+    #[serde(default)]
+    pub self_callers: usize,
+    /// how the histograms are bucketed: `exact`, `log2` or `linear:N`
+    #[serde(default)]
+    pub histogram_mode: String,
     pub path_constraints_histogram: BTreeMap<i64, usize>,
     pub package_hops_histogram: BTreeMap<i64, usize>,
+    #[serde(default)]
+    pub path_constraints_buckets: Vec<HistogramBucket>,
+    #[serde(default)]
+    pub package_hops_buckets: Vec<HistogramBucket>,
     pub functions: BTreeMap<String, FunctionStats>,
     pub subjects: Vec<SubjectStats>,
     /// Top subjects by callers
     pub top_subjects_by_callers: Vec<(String, usize)>,
+    /// Subjects collapsed by crate name, only filled with `COLLAPSE_SUBJECTS=1`
+    #[serde(default)]
+    pub crates: Vec<CrateStats>,
+    /// Top crates by callers, only filled with `COLLAPSE_SUBJECTS=1`
+    #[serde(default)]
+    pub top_crates_by_callers: Vec<(String, usize)>,
+    /// What the analysis run cost, taken from the run manifest if present
+    pub run_metrics: Option<RunMetrics>,
+    /// What produced the results, taken from the run manifest if present
+    #[serde(default)]
+    pub run_info: Option<RunInfo>,
+    /// The tools and host of the run, from environment-<cve>.json if present
+    #[serde(default)]
+    pub environment: Option<AnalysisEnvironment>,
+    /// Target functions from the run manifest with no caller in any subject,
+    /// possibly a dead API or a wrong path
+    #[serde(default)]
+    pub unused_target_functions: Vec<String>,
+    /// crates processed and with callers at each BFS depth, empty when the
+    /// run wrote no BFS tree
+    #[serde(default)]
+    pub propagation_by_depth: Vec<DepthWidth>,
+    /// callers and subjects with callers at each BFS depth, empty when the
+    /// run wrote no BFS tree; subjects missing from the tree are not counted
+    #[serde(default)]
+    pub callers_by_depth: Vec<DepthCallers>,
+    /// affected subjects per edition, only filled with `ANNOTATE_CRATE_INFO=1`
+    #[serde(default)]
+    pub editions: BTreeMap<String, usize>,
+    /// affected subjects per MSRV, `unknown` for crates declaring none,
+    /// only filled with `ANNOTATE_CRATE_INFO=1`
+    #[serde(default)]
+    pub rust_versions: BTreeMap<String, usize>,
+    /// the top callers re-checked by the confirmation pass (`CONFIRM_TOP_N`),
+    /// from confirmations-<cve>.json if present
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub confirmations: Vec<CallerConfirmation>,
+}
+
+impl GlobalStats {
+    /// Merge the stats of another shard of the result files of the same CVE,
+    /// e.g. computed on another machine. Counts and histograms are summed,
+    /// subjects are combined and the top-N lists are recomputed.
+    ///
+    /// Limitations, as the summaries do not keep the raw values:
+    /// - percentiles and averages are recomputed exactly from the exact-value
+    ///   histograms; with a bucketed `HISTOGRAM_MODE` the percentiles are dropped
+    ///   and the averages are weighted by the caller counts;
+    /// - `unique_call_paths` is summed, an upper bound when the shards share call paths.
+    pub fn merge(&mut self, other: GlobalStats) {
+        if self.cve_id.is_empty() {
+            self.cve_id = other.cve_id;
+        }
+        if self.histogram_mode.is_empty() {
+            self.histogram_mode = other.histogram_mode;
+        }
+        self.total_subjects += other.total_subjects;
+        self.total_function_result_files += other.total_function_result_files;
+        self.total_callers += other.total_callers;
+        self.self_callers += other.self_callers;
+        merge_histogram(
+            &mut self.path_constraints_histogram,
+            other.path_constraints_histogram,
+        );
+        merge_histogram(
+            &mut self.package_hops_histogram,
+            other.package_hops_histogram,
+        );
+        merge_buckets(
+            &mut self.path_constraints_buckets,
+            other.path_constraints_buckets,
+        );
+        merge_buckets(&mut self.package_hops_buckets, other.package_hops_buckets);
+
+        for (func, fs) in other.functions {
+            match self.functions.get_mut(&func) {
+                Some(existing) => existing.merge(fs),
+                None => {
+                    self.functions.insert(func, fs);
+                }
+            }
+        }
+
+        let mut subjects: BTreeMap<String, SubjectStats> = BTreeMap::new();
+        for subject in std::mem::take(&mut self.subjects)
+            .into_iter()
+            .chain(other.subjects)
+        {
+            let entry = subjects
+                .entry(subject.subject.clone())
+                .or_insert_with(|| SubjectStats {
+                    subject: subject.subject.clone(),
+                    ..Default::default()
+                });
+            entry.total_callers += subject.total_callers;
+            for (func, cnt) in subject.per_function_callers {
+                *entry.per_function_callers.entry(func).or_insert(0) += cnt;
+            }
+            if entry.edition.is_none() {
+                entry.edition = subject.edition;
+                entry.rust_version = subject.rust_version;
+            }
+        }
+        self.subjects = subjects.into_values().collect();
+        self.subjects
+            .sort_by_key(|s| std::cmp::Reverse(s.total_callers));
+        self.top_subjects_by_callers = self
+            .subjects
+            .iter()
+            .take(20)
+            .map(|s| (s.subject.clone(), s.total_callers))
+            .collect();
+        if !self.crates.is_empty() || !other.crates.is_empty() {
+            self.crates = collapse_subjects(&self.subjects);
+            self.top_crates_by_callers = self
+                .crates
+                .iter()
+                .take(20)
+                .map(|c| (c.crate_name.clone(), c.total_callers))
+                .collect();
+        }
+
+        if self.run_metrics.is_none() {
+            self.run_metrics = other.run_metrics;
+        }
+        let mut unused: BTreeSet<String> = std::mem::take(&mut self.unused_target_functions)
+            .into_iter()
+            .chain(other.unused_target_functions)
+            .collect();
+        unused.retain(|f| self.functions.get(f).is_none_or(|fs| fs.total_callers == 0));
+        self.unused_target_functions = unused.into_iter().collect();
+        self.confirmations.extend(other.confirmations);
+
+        for width in other.propagation_by_depth {
+            match self
+                .propagation_by_depth
+                .iter_mut()
+                .find(|w| w.depth == width.depth)
+            {
+                Some(existing) => {
+                    existing.processed += width.processed;
+                    existing.with_callers += width.with_callers;
+                }
+                None => self.propagation_by_depth.push(width),
+            }
+        }
+        self.propagation_by_depth.sort_by_key(|w| w.depth);
+        for depth_callers in other.callers_by_depth {
+            match self
+                .callers_by_depth
+                .iter_mut()
+                .find(|d| d.depth == depth_callers.depth)
+            {
+                Some(existing) => {
+                    existing.callers += depth_callers.callers;
+                    existing.subjects += depth_callers.subjects;
+                }
+                None => self.callers_by_depth.push(depth_callers),
+            }
+        }
+        self.callers_by_depth.sort_by_key(|d| d.depth);
+        if !self.editions.is_empty() || !other.editions.is_empty() {
+            (self.editions, self.rust_versions) = crate_info_distribution(&self.subjects);
+        }
+    }
+}
+
+impl FunctionStats {
+    /// Merge the stats of the same function from another shard, see [`GlobalStats::merge`]
+    fn merge(&mut self, other: FunctionStats) {
+        // weights of the averages, used when the histograms are bucketed
+        let pc_weights = (
+            self.path_constraints_histogram.values().sum::<usize>()
+                + self
+                    .path_constraints_buckets
+                    .iter()
+                    .map(|b| b.count)
+                    .sum::<usize>(),
+            other.path_constraints_histogram.values().sum::<usize>()
+                + other
+                    .path_constraints_buckets
+                    .iter()
+                    .map(|b| b.count)
+                    .sum::<usize>(),
+        );
+        let pkg_weights = (
+            self.package_hops_histogram.values().sum::<usize>()
+                + self
+                    .package_hops_buckets
+                    .iter()
+                    .map(|b| b.count)
+                    .sum::<usize>(),
+            other.package_hops_histogram.values().sum::<usize>()
+                + other
+                    .package_hops_buckets
+                    .iter()
+                    .map(|b| b.count)
+                    .sum::<usize>(),
+        );
+
+        self.total_callers += other.total_callers;
+        self.unique_call_paths += other.unique_call_paths;
+        self.path_constraints_min = min_opt(self.path_constraints_min, other.path_constraints_min);
+        self.path_constraints_max = self.path_constraints_max.max(other.path_constraints_max);
+        self.package_hops_min = min_opt(self.package_hops_min, other.package_hops_min);
+        self.package_hops_max = self.package_hops_max.max(other.package_hops_max);
+        merge_histogram(
+            &mut self.path_constraints_histogram,
+            other.path_constraints_histogram,
+        );
+        merge_histogram(
+            &mut self.package_hops_histogram,
+            other.package_hops_histogram,
+        );
+        merge_buckets(
+            &mut self.path_constraints_buckets,
+            other.path_constraints_buckets,
+        );
+        merge_buckets(&mut self.package_hops_buckets, other.package_hops_buckets);
+
+        match histogram_summary(&self.path_constraints_histogram) {
+            Some((avg, [p50, p90, p95, p99])) if self.path_constraints_buckets.is_empty() => {
+                self.path_constraints_avg = avg;
+                self.path_constraints_p50 = Some(p50);
+                self.path_constraints_p90 = Some(p90);
+                self.path_constraints_p95 = Some(p95);
+                self.path_constraints_p99 = Some(p99);
+            }
+            _ => {
+                self.path_constraints_avg = weighted_avg(
+                    (self.path_constraints_avg, pc_weights.0),
+                    (other.path_constraints_avg, pc_weights.1),
+                )
+                .unwrap_or(0.0);
+                self.path_constraints_p50 = None;
+                self.path_constraints_p90 = None;
+                self.path_constraints_p95 = None;
+                self.path_constraints_p99 = None;
+            }
+        }
+        match histogram_summary(&self.package_hops_histogram) {
+            Some((avg, [p50, p90, p95, p99])) if self.package_hops_buckets.is_empty() => {
+                self.package_hops_avg = Some(avg);
+                self.package_hops_p50 = Some(p50);
+                self.package_hops_p90 = Some(p90);
+                self.package_hops_p95 = Some(p95);
+                self.package_hops_p99 = Some(p99);
+            }
+            _ => {
+                self.package_hops_avg = weighted_avg(
+                    (self.package_hops_avg.unwrap_or(0.0), pkg_weights.0),
+                    (other.package_hops_avg.unwrap_or(0.0), pkg_weights.1),
+                );
+                self.package_hops_p50 = None;
+                self.package_hops_p90 = None;
+                self.package_hops_p95 = None;
+                self.package_hops_p99 = None;
+            }
+        }
+
+        self.top_callers_by_constraints
+            .extend(other.top_callers_by_constraints);
+        self.top_callers_by_constraints
+            .sort_by_key(|s| std::cmp::Reverse(s.path_constraints));
+        self.top_callers_by_constraints.truncate(10);
+        self.top_callers_by_package_hops
+            .extend(other.top_callers_by_package_hops);
+        self.top_callers_by_package_hops
+            .sort_by_key(|s| std::cmp::Reverse(s.path_package_num));
+        self.top_callers_by_package_hops.truncate(10);
+    }
+}
+
+fn merge_histogram(into: &mut BTreeMap<i64, usize>, other: BTreeMap<i64, usize>) {
+    for (value, count) in other {
+        *into.entry(value).or_insert(0) += count;
+    }
+}
+
+fn merge_buckets(into: &mut Vec<HistogramBucket>, other: Vec<HistogramBucket>) {
+    let mut bins: BTreeMap<(i64, i64), usize> = BTreeMap::new();
+    for b in std::mem::take(into).into_iter().chain(other) {
+        *bins.entry((b.lower, b.upper)).or_insert(0) += b.count;
+    }
+    *into = bins
+        .into_iter()
+        .map(|((lower, upper), count)| HistogramBucket {
+            lower,
+            upper,
+            count,
+        })
+        .collect();
+}
+
+fn min_opt(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn weighted_avg(a: (f64, usize), b: (f64, usize)) -> Option<f64> {
+    let total = a.1 + b.1;
+    (total > 0).then(|| (a.0 * a.1 as f64 + b.0 * b.1 as f64) / total as f64)
+}
+
+/// The average and the p50/p90/p95/p99 of the values of an exact histogram,
+/// with the same nearest-rank definition as for the raw values.
+fn histogram_summary(hist: &BTreeMap<i64, usize>) -> Option<(f64, [f64; 4])> {
+    let n: usize = hist.values().sum();
+    if n == 0 {
+        return None;
+    }
+    let sum: i64 = hist.iter().map(|(v, c)| v * *c as i64).sum();
+    let nth = |p: f64| -> f64 {
+        let idx = ((n as f64 - 1.0) * p).round() as usize;
+        let mut seen = 0;
+        for (value, count) in hist {
+            seen += count;
+            if idx < seen {
+                return *value as f64;
+            }
+        }
+        unreachable!("index within the histogram")
+    };
+    Some((
+        sum as f64 / n as f64,
+        [nth(0.50), nth(0.90), nth(0.95), nth(0.99)],
+    ))
+}
+
+/// The crate name of the subjects holding the callers inside the vulnerable
+/// crate itself (`__self__-<version>`), written with `--include-self`
+pub const SELF_SUBJECT: &str = "__self__";
+
+/// whether `subject` holds callers inside the vulnerable crate rather than in a dependent
+fn is_self_subject(subject: &str) -> bool {
+    utils::split_subject(subject).is_some_and(|(name, _)| name == SELF_SUBJECT)
+}
+
+/// Where the results are written and read: `ANALYSIS_RESULTS_DIR` (a relative
+/// path is relative to the working directory), by default `analysis_results/`
+/// in the source tree
+pub(crate) fn analysis_results_dir() -> PathBuf {
+    crate::config::var("ANALYSIS_RESULTS_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("analysis_results"))
+}
+
+/// How long `analysis_results/<cve>` directories are kept: `RETAIN_RESULTS_DAYS`
+/// removes the ones not modified for that many days, `RETAIN_RESULTS_COUNT`
+/// keeps only the most recently modified N. Neither is set by default.
+fn results_retention() -> (Option<u64>, Option<usize>) {
+    let days = crate::config::var("RETAIN_RESULTS_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok());
+    let count = crate::config::var("RETAIN_RESULTS_COUNT")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0);
+    (days, count)
+}
+
+/// the newest mtime of `dir` and the files directly in it
+async fn last_modified(dir: &Path) -> Option<SystemTime> {
+    let mut newest = tokio_fs::metadata(dir).await.ok()?.modified().ok()?;
+    let mut entries = tokio_fs::read_dir(dir).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Ok(modified) = entry.metadata().await.and_then(|m| m.modified()) {
+            newest = newest.max(modified);
+        }
+    }
+    Some(newest)
+}
+
+/// Remove the result directories outside the retention policy of
+/// `RETAIN_RESULTS_DAYS` / `RETAIN_RESULTS_COUNT` and return them. Does nothing
+/// when neither is set.
+///
+/// A directory whose manifest has no `finished_at` belongs to a run that is
+/// still writing it and is kept, unless it has not changed for longer than
+/// `RETAIN_RESULTS_DAYS`, i.e. the run crashed.
+pub async fn prune_old_results() -> Result<Vec<PathBuf>> {
+    let (days, count) = results_retention();
+    prune_old_results_in(&analysis_results_dir(), days, count).await
+}
+
+/// [`prune_old_results`] of the result directories in `results_dir`, keeping
+/// those modified in the last `days` days and the `count` newest
+async fn prune_old_results_in(
+    results_dir: &Path,
+    days: Option<u64>,
+    count: Option<usize>,
+) -> Result<Vec<PathBuf>> {
+    if days.is_none() && count.is_none() {
+        return Ok(Vec::new());
+    }
+    let Ok(mut entries) = tokio_fs::read_dir(results_dir).await else {
+        return Ok(Vec::new());
+    };
+    let mut dirs = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !entry.file_type().await?.is_dir() {
+            continue;
+        }
+        let Some(modified) = last_modified(&path).await else {
+            continue;
+        };
+        dirs.push((path, modified));
+    }
+    // newest first
+    dirs.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+
+    let now = SystemTime::now();
+    let mut removed = Vec::new();
+    for (i, (path, modified)) in dirs.into_iter().enumerate() {
+        let age = now.duration_since(modified).unwrap_or_default();
+        let expired = days.is_some_and(|d| age.as_secs() > d * 24 * 60 * 60);
+        let beyond_count = count.is_some_and(|n| i >= n);
+        if !expired && !beyond_count {
+            continue;
+        }
+        let cve_id = path.file_name().unwrap_or_default().to_string_lossy();
+        let manifest_path = path.join(format!("manifest-{}.json", cve_id));
+        let running = tokio_fs::read_to_string(&manifest_path)
+            .await
+            .ok()
+            .and_then(|content| serde_json::from_str::<RunManifest>(&content).ok())
+            .is_some_and(|manifest| manifest.finished_at.is_none());
+        if running && !expired {
+            tracing::info!(
+                "keep {}: its run has not finished, it may still be writing",
+                path.display()
+            );
+            continue;
+        }
+        match tokio_fs::remove_dir_all(&path).await {
+            Ok(()) => {
+                tracing::info!(
+                    "removed {} (last modified {} day(s) ago)",
+                    path.display(),
+                    age.as_secs() / (24 * 60 * 60)
+                );
+                removed.push(path);
+            }
+            Err(e) => tracing::warn!("Failed to remove {}: {}", path.display(), e),
+        }
+    }
+    Ok(removed)
 }
 
-fn analysis_results_dir() -> PathBuf {
-    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("analysis_results")
+/// Minimum number of callers a function or subject needs to be listed in the
+/// Markdown summary, configured by `MIN_CALLERS` (default 0, i.e. show all).
+/// Elided entries are still counted in the totals.
+fn min_callers_threshold() -> usize {
+    crate::config::var("MIN_CALLERS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0)
 }
 
 fn function_from_file_key(file_key: &str) -> String {
@@ -73,51 +656,444 @@ fn function_from_file_key(file_key: &str) -> String {
         .to_string()
 }
 
+/// whether caller paths are normalized before counting unique call paths,
+/// controlled by `NORMALIZE_CALLER_PATHS=1`
+fn normalize_caller_paths_enabled() -> bool {
+    crate::config::var("NORMALIZE_CALLER_PATHS")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
 
-pub async fn compute_and_write_stats(cve_id: &str) -> Result<()> {
-    let dir = analysis_results_dir().join(cve_id);
-    if !dir.exists() {
-        tracing::info!("analysis_results not found, skip stats");
-        return Ok(());
+/// How the path_constraints and package_hops histograms are binned,
+/// configured by `HISTOGRAM_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HistogramMode {
+    /// one bin per exact value (default)
+    Exact,
+    /// bins of powers of two: [0, 1), [1, 2), [2, 4), [4, 8), ...
+    Log2,
+    /// fixed-width bins of the given size: [0, N), [N, 2N), ...
+    Linear(i64),
+}
+
+impl HistogramMode {
+    fn from_env() -> Self {
+        let mode = crate::config::var("HISTOGRAM_MODE").unwrap_or_default();
+        match mode.trim() {
+            "" | "exact" => Self::Exact,
+            "log2" => Self::Log2,
+            other => match other
+                .strip_prefix("linear:")
+                .and_then(|n| n.parse::<i64>().ok())
+            {
+                Some(width) if width > 0 => Self::Linear(width),
+                _ => {
+                    tracing::warn!("invalid HISTOGRAM_MODE {:?}, using exact", other);
+                    Self::Exact
+                }
+            },
+        }
     }
 
-    let mut dir_entries = tokio_fs::read_dir(&dir).await?;
+    /// the `[lower, upper)` bin containing `value`
+    fn bucket_of(self, value: i64) -> (i64, i64) {
+        match self {
+            Self::Exact => (value, value + 1),
+            // 0 and negative values (not expected) keep their own bin
+            Self::Log2 if value <= 0 => (value, value + 1),
+            Self::Log2 => {
+                let lower = 1i64 << value.ilog2();
+                (lower, lower.saturating_mul(2))
+            }
+            Self::Linear(width) => {
+                let lower = value.div_euclid(width) * width;
+                (lower, lower + width)
+            }
+        }
+    }
 
-    let mut global = GlobalStats {
-        cve_id: cve_id.to_string(),
-        ..Default::default()
-    };
+    /// Merge an exact-value histogram into the bins of this mode
+    fn bucket(self, hist: &BTreeMap<i64, usize>) -> Vec<HistogramBucket> {
+        let mut bins: BTreeMap<(i64, i64), usize> = BTreeMap::new();
+        for (value, count) in hist {
+            *bins.entry(self.bucket_of(*value)).or_insert(0) += count;
+        }
+        bins.into_iter()
+            .map(|((lower, upper), count)| HistogramBucket {
+                lower,
+                upper,
+                count,
+            })
+            .collect()
+    }
+}
 
-    // function aggregations
-    let mut function_total_callers: HashMap<String, usize> = HashMap::new();
-    let mut function_unique_paths: HashMap<String, BTreeSet<String>> = HashMap::new();
+impl std::fmt::Display for HistogramMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact => write!(f, "exact"),
+            Self::Log2 => write!(f, "log2"),
+            Self::Linear(width) => write!(f, "linear:{}", width),
+        }
+    }
+}
 
-    let mut function_path_constraints_values: HashMap<String, Vec<i64>> = HashMap::new();
-    let mut function_package_hops_values: HashMap<String, Vec<i64>> = HashMap::new();
-    let mut function_path_constraints_hist: HashMap<String, BTreeMap<i64, usize>> = HashMap::new();
-    let mut function_package_hops_hist: HashMap<String, BTreeMap<i64, usize>> = HashMap::new();
-    let mut function_top_constraints_samples: HashMap<String, Vec<CallerSample>> = HashMap::new();
-    let mut function_top_pkg_samples: HashMap<String, Vec<CallerSample>> = HashMap::new();
+/// In a bucketed mode, move the exact histogram into its buckets
+fn apply_histogram_mode(
+    mode: HistogramMode,
+    hist: &mut BTreeMap<i64, usize>,
+    buckets: &mut Vec<HistogramBucket>,
+) {
+    if mode != HistogramMode::Exact {
+        *buckets = mode.bucket(hist);
+        hist.clear();
+    }
+}
 
-    // subject aggregations
-    let mut subjects_map: BTreeMap<String, SubjectStats> = BTreeMap::new();
+/// Render a histogram as Markdown list items, using the buckets if present
+fn histogram_md(hist: &BTreeMap<i64, usize>, buckets: &[HistogramBucket], indent: &str) -> String {
+    let mut md = String::new();
+    for (k, v) in hist {
+        md.push_str(&format!("{}- {}: {}\n", indent, k, v));
+    }
+    for b in buckets {
+        md.push_str(&format!(
+            "{}- [{}, {}): {}\n",
+            indent, b.lower, b.upper, b.count
+        ));
+    }
+    md
+}
+
+/// the width of the longest bar of the Markdown charts, configured by
+/// `MD_CHART_WIDTH` (default 40, 0 disables the charts)
+fn chart_width() -> usize {
+    crate::config::var("MD_CHART_WIDTH")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(40)
+}
+
+/// A horizontal Unicode bar chart in a fenced block, one labeled bar per row
+/// followed by its count. Bars are scaled so the largest count is `width`
+/// wide; a non-zero count gets at least one block.
+fn bar_chart_md(rows: &[(String, usize)], width: usize) -> String {
+    let max = rows.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    if width == 0 || max == 0 {
+        return String::new();
+    }
+    let label_width = rows
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut md = String::from("```text\n");
+    for (label, count) in rows {
+        let blocks = (count * width).div_ceil(max);
+        let sep = if blocks > 0 { " " } else { "" };
+        md.push_str(&format!(
+            "{:<label_width$} | {}{}{}\n",
+            label,
+            "█".repeat(blocks),
+            sep,
+            count
+        ));
+    }
+    md.push_str("```\n");
+    md
+}
+
+/// the rows of a histogram chart, labeled by value or by bucket
+fn histogram_chart_rows(
+    hist: &BTreeMap<i64, usize>,
+    buckets: &[HistogramBucket],
+) -> Vec<(String, usize)> {
+    hist.iter()
+        .map(|(k, v)| (k.to_string(), *v))
+        .chain(
+            buckets
+                .iter()
+                .map(|b| (format!("[{}, {})", b.lower, b.upper), b.count)),
+        )
+        .collect()
+}
+
+/// Count the crates of the BFS tree at each depth
+fn propagation_by_depth(forest: &[BfsTreeNode]) -> Vec<DepthWidth> {
+    fn visit(node: &BfsTreeNode, widths: &mut Vec<DepthWidth>) {
+        while widths.len() <= node.depth {
+            widths.push(DepthWidth {
+                depth: widths.len(),
+                processed: 0,
+                with_callers: 0,
+            });
+        }
+        widths[node.depth].processed += 1;
+        if node.callers_found > 0 {
+            widths[node.depth].with_callers += 1;
+        }
+        for child in &node.children {
+            visit(child, widths);
+        }
+    }
+    let mut widths = Vec::new();
+    for root in forest {
+        visit(root, &mut widths);
+    }
+    widths
+}
+
+/// Sum the callers of the subjects at each depth of the BFS tree. The
+/// `__self__` subjects are at depth 0, subjects not in the tree are skipped.
+fn callers_by_depth(forest: &[BfsTreeNode], subjects: &[SubjectStats]) -> Vec<DepthCallers> {
+    fn visit(node: &BfsTreeNode, depths: &mut HashMap<String, usize>) {
+        depths.insert(format!("{}-{}", node.crate_name, node.version), node.depth);
+        for child in &node.children {
+            visit(child, depths);
+        }
+    }
+    let mut depths = HashMap::new();
+    for root in forest {
+        visit(root, &mut depths);
+    }
+    let mut by_depth: BTreeMap<usize, DepthCallers> = BTreeMap::new();
+    for subject in subjects.iter().filter(|s| s.total_callers > 0) {
+        let depth = if is_self_subject(&subject.subject) {
+            0
+        } else {
+            match depths.get(&subject.subject) {
+                Some(depth) => *depth,
+                None => continue,
+            }
+        };
+        let entry = by_depth.entry(depth).or_insert(DepthCallers {
+            depth,
+            callers: 0,
+            subjects: 0,
+        });
+        entry.callers += subject.total_callers;
+        entry.subjects += 1;
+    }
+    by_depth.into_values().collect()
+}
+
+/// whether the edition and MSRV of each analyzed crate are recorded next to its
+/// result file and summarized in the stats, controlled by `ANNOTATE_CRATE_INFO=1`
+pub(crate) fn annotate_crate_info_enabled() -> bool {
+    crate::config::var("ANNOTATE_CRATE_INFO")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// The affected subjects (with callers, outside the vulnerable crate itself)
+/// per edition and per MSRV, over the subjects whose edition is known
+fn crate_info_distribution<'a>(
+    subjects: impl IntoIterator<Item = &'a SubjectStats>,
+) -> (BTreeMap<String, usize>, BTreeMap<String, usize>) {
+    let mut editions = BTreeMap::new();
+    let mut rust_versions = BTreeMap::new();
+    for subject in subjects {
+        if subject.total_callers == 0 || is_self_subject(&subject.subject) {
+            continue;
+        }
+        let Some(edition) = &subject.edition else {
+            continue;
+        };
+        *editions.entry(edition.clone()).or_insert(0) += 1;
+        let rust_version = subject.rust_version.as_deref().unwrap_or("unknown");
+        *rust_versions.entry(rust_version.to_string()).or_insert(0) += 1;
+    }
+    (editions, rust_versions)
+}
+
+/// whether the stats also list the subjects collapsed by crate name, so a crate
+/// analyzed in two versions counts once, controlled by `COLLAPSE_SUBJECTS=1`
+fn collapse_subjects_enabled() -> bool {
+    crate::config::var("COLLAPSE_SUBJECTS")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// Merge the versions of each crate into one entry, taking the max callers
+/// over the versions, sorted by callers.
+fn collapse_subjects(subjects: &[SubjectStats]) -> Vec<CrateStats> {
+    let mut crates: BTreeMap<&str, CrateStats> = BTreeMap::new();
+    for subject in subjects {
+        let (name, version) =
+            utils::split_subject(&subject.subject).unwrap_or((&subject.subject, ""));
+        let entry = crates.entry(name).or_insert_with(|| CrateStats {
+            crate_name: name.to_string(),
+            ..Default::default()
+        });
+        entry.versions.push(version.to_string());
+        entry.total_callers = entry.total_callers.max(subject.total_callers);
+        for (func, cnt) in &subject.per_function_callers {
+            let c = entry.per_function_callers.entry(func.clone()).or_insert(0);
+            *c = (*c).max(*cnt);
+        }
+    }
+    let mut crates: Vec<CrateStats> = crates.into_values().collect();
+    for krate in &mut crates {
+        krate.versions.sort_by(
+            |a, b| match (utils::parse_version(a), utils::parse_version(b)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            },
+        );
+    }
+    crates.sort_by_key(|c| std::cmp::Reverse(c.total_callers));
+    crates
+}
+
+/// Collapse monomorphizations of one caller into a single path: drop the
+/// `::<...>` generic arguments and a trailing symbol hash like `::h1a2b3c4d5e6f7a8b`.
+/// e.g. `foo::bar::<u8>::baz::h0123456789abcdef` -> `foo::bar::baz`
+pub(crate) fn normalize_caller_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut depth = 0usize;
+    let mut rest = path;
+    while let Some(c) = rest.chars().next() {
+        if depth == 0 && rest.starts_with("::<") {
+            depth = 1;
+            rest = &rest[3..];
+            continue;
+        }
+        if depth > 0 {
+            match c {
+                '<' => depth += 1,
+                '>' => depth -= 1,
+                _ => {}
+            }
+        } else {
+            out.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    if let Some((head, hash)) = out.rsplit_once("::")
+        && hash.len() == 17
+        && hash.starts_with('h')
+        && hash[1..].chars().all(|c| c.is_ascii_hexdigit())
+    {
+        out.truncate(head.len());
+    }
+    out
+}
+
+/// a function path as a file name component, e.g. `gix::open` -> `gix__open`
+fn sanitize_file_component(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Render the joint distribution of (package hops, path constraints) as CSV:
+/// one row per package_hops value, one column per path_constraints value,
+/// cells are caller counts. Bins are the exact values, like the 1D histograms.
+fn heatmap_csv(joint: &BTreeMap<(i64, i64), usize>) -> String {
+    let pkg_bins: BTreeSet<i64> = joint.keys().map(|(pkg, _)| *pkg).collect();
+    let pc_bins: BTreeSet<i64> = joint.keys().map(|(_, pc)| *pc).collect();
+
+    let mut csv = String::from("package_hops\\path_constraints");
+    for pc in &pc_bins {
+        csv.push_str(&format!(",{}", pc));
+    }
+    csv.push('\n');
+    for pkg in &pkg_bins {
+        csv.push_str(&pkg.to_string());
+        for pc in &pc_bins {
+            let count = joint.get(&(*pkg, *pc)).copied().unwrap_or(0);
+            csv.push_str(&format!(",{}", count));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+/// the parsed result files of a directory, see [`load_result_files`]
+type ResultFiles = Arc<Vec<(String, Vec<Value>)>>;
+
+/// (path, size, mtime) of every result file, a change of any of them
+/// invalidates the cached parse
+type ResultFingerprint = Vec<(PathBuf, u64, Option<SystemTime>)>;
+
+/// Parsed result files per directory, so the stats, the function detail and
+/// `--explain` do not re-read and re-parse the whole directory in one process.
+static RESULT_FILES_CACHE: Lazy<Mutex<HashMap<PathBuf, (ResultFingerprint, ResultFiles)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// `INPUT_GLOB`: only the `.txt(.gz)` files in analysis_results/<cve> whose
+/// name also matches this glob (e.g. `*-[0-9]*.txt*`) are read as result
+/// files, so notes kept next to them are not. `None` when unset: every one is read.
+pub fn input_glob() -> Result<Option<glob::Pattern>> {
+    input_glob_as(crate::config::var("INPUT_GLOB").ok().as_deref())
+}
+
+fn input_glob_as(pattern: Option<&str>) -> Result<Option<glob::Pattern>> {
+    match pattern {
+        Some(pattern) if !pattern.is_empty() => glob::Pattern::new(pattern)
+            .map(Some)
+            .with_context(|| format!("INPUT_GLOB={} is not a valid glob", pattern)),
+        _ => Ok(None),
+    }
+}
 
+/// Read every `<name>-<version>.txt(.gz)` result file in `dir` matching
+/// `INPUT_GLOB`, return `(subject, json array of callgraph files)` per subject.
+/// Unreadable or malformed files are skipped with a warning.
+///
+/// The parse is cached in memory until a result file is added, removed or modified.
+async fn load_result_files(dir: &Path) -> Result<ResultFiles> {
+    load_result_files_as(dir, input_glob()?.as_ref()).await
+}
+
+/// [`load_result_files`] reading only the files matching `input_glob`, if any
+async fn load_result_files_as(
+    dir: &Path,
+    input_glob: Option<&glob::Pattern>,
+) -> Result<ResultFiles> {
+    let mut fingerprint: ResultFingerprint = Vec::new();
+    let mut dir_entries = tokio_fs::read_dir(dir).await?;
     while let Some(entry) = dir_entries.next_entry().await? {
         let path = entry.path();
-        if !path.is_file() {
+        let Ok(meta) = entry.metadata().await else {
+            continue;
+        };
+        if !meta.is_file() || result_file_subject(&path).is_none() {
             continue;
         }
-
-        // crate name - version.txt
-        let cnv = if let Some(s) = path.file_name().and_then(|s| s.to_str())
-            && let Some(s) = s.strip_suffix(".txt")
+        if let Some(input_glob) = input_glob
+            && !path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| input_glob.matches(name))
         {
-            s.to_string()
-        } else {
+            tracing::debug!("{} does not match INPUT_GLOB, ignored", path.display());
+            continue;
+        }
+        fingerprint.push((path, meta.len(), meta.modified().ok()));
+    }
+    fingerprint.sort();
+
+    if let Some((cached, files)) = RESULT_FILES_CACHE.lock().unwrap().get(dir)
+        && *cached == fingerprint
+    {
+        tracing::debug!("result files of {} loaded from cache", dir.display());
+        return Ok(files.clone());
+    }
+
+    let mut results = Vec::new();
+    for (path, _, _) in &fingerprint {
+        let Some(cnv) = result_file_subject(path) else {
             continue;
         };
 
-        let content = match tokio_fs::read_to_string(&path).await {
+        let content = match utils::read_result_file(path).await {
             Ok(s) => s,
             Err(e) => {
                 tracing::warn!("failed to read {:?}: {}", path, e);
@@ -133,33 +1109,710 @@ pub async fn compute_and_write_stats(cve_id: &str) -> Result<()> {
             }
         };
 
-        if !json.is_array() {
-            continue;
+        if let Value::Array(files) = json {
+            results.push((cnv, files));
         }
+    }
+    let results = Arc::new(results);
+    RESULT_FILES_CACHE
+        .lock()
+        .unwrap()
+        .insert(dir.to_path_buf(), (fingerprint, results.clone()));
+    Ok(results)
+}
 
-        let subject_entry = subjects_map
-            .entry(cnv.clone())
-            .or_insert_with(|| SubjectStats {
-                subject: cnv.clone(),
-                ..Default::default()
+/// the `<name>-<version>` subject of a result file, `None` for other files
+fn result_file_subject(path: &Path) -> Option<String> {
+    // crate name - version.txt (or .txt.gz when COMPRESS_RESULTS=1)
+    let name = path.file_name()?.to_str()?;
+    name.strip_suffix(".txt.gz")
+        .or_else(|| name.strip_suffix(".txt"))
+        .map(|s| s.to_string())
+}
+
+/// `INCREMENTAL_STATS_SECS`: maintain [`RunningStats`] while the BFS runs and
+/// flush them at most every that many seconds (0: after every crate). Unset: off.
+pub(crate) fn incremental_stats_interval() -> Option<std::time::Duration> {
+    crate::config::var("INCREMENTAL_STATS_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(std::time::Duration::from_secs)
+}
+
+/// Caller counts and histograms of the result files written so far, flushed
+/// to analysis_results/<cve>/running-stats-<cve>.json during the run
+/// (`INCREMENTAL_STATS_SECS`), so a run that is killed still leaves usable
+/// totals. For a finished run they equal the same fields of [`GlobalStats`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RunningStats {
+    pub cve_id: String,
+    pub total_subjects: usize,
+    pub total_function_result_files: usize,
+    pub total_callers: usize,
+    /// callers per target function, as `functions.<f>.total_callers`
+    pub function_callers: BTreeMap<String, usize>,
+    pub path_constraints_histogram: BTreeMap<i64, usize>,
+    pub package_hops_histogram: BTreeMap<i64, usize>,
+}
+
+impl RunningStats {
+    pub fn path(cve_id: &str) -> PathBuf {
+        analysis_results_dir()
+            .join(cve_id)
+            .join(format!("running-stats-{}.json", cve_id))
+    }
+
+    /// load the running stats of a run, `None` if none were flushed
+    pub async fn load(cve_id: &str) -> Result<Option<Self>> {
+        let path = Self::path(cve_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = tokio_fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let stats = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Some(stats))
+    }
+
+    /// the same totals taken from the full computation
+    pub fn from_global(global: &GlobalStats) -> Self {
+        Self {
+            cve_id: global.cve_id.clone(),
+            total_subjects: global.total_subjects,
+            total_function_result_files: global.total_function_result_files,
+            total_callers: global.total_callers,
+            function_callers: global
+                .functions
+                .iter()
+                .map(|(f, stats)| (f.clone(), stats.total_callers))
+                .collect(),
+            path_constraints_histogram: global.path_constraints_histogram.clone(),
+            package_hops_histogram: global.package_hops_histogram.clone(),
+        }
+    }
+}
+
+/// The counts of one result file, kept per subject so a re-written result
+/// file replaces its earlier counts instead of adding to them
+#[derive(Debug, Clone, Default)]
+struct SubjectTally {
+    function_result_files: usize,
+    function_callers: BTreeMap<String, usize>,
+    path_constraints: BTreeMap<i64, usize>,
+    package_hops: BTreeMap<i64, usize>,
+}
+
+impl SubjectTally {
+    /// count like [`compute`] does for one subject
+    fn of(files: &[Value]) -> Self {
+        let mut tally = Self::default();
+        for file_obj in files {
+            tally.function_result_files += 1;
+            let Some(file_content) = file_obj.get("file-content") else {
+                continue;
+            };
+            let callers = file_content
+                .get("callers")
+                .and_then(|v| v.as_array())
+                .map(|c| c.as_slice())
+                .unwrap_or_default();
+            *tally
+                .function_callers
+                .entry(function_of_file(file_obj))
+                .or_insert(0) += callers.len();
+            for caller in callers {
+                if let Some(pc) = caller.get("path_constraints").and_then(|v| v.as_i64()) {
+                    *tally.path_constraints.entry(pc).or_insert(0) += 1;
+                }
+                if let Some(pkg) = caller.get("path_package_num").and_then(|v| v.as_i64()) {
+                    *tally.package_hops.entry(pkg).or_insert(0) += 1;
+                }
+            }
+        }
+        tally
+    }
+}
+
+/// Builds [`RunningStats`] as result files are written
+#[derive(Debug)]
+pub(crate) struct RunningAggregate {
+    cve_id: String,
+    subjects: BTreeMap<String, SubjectTally>,
+    interval: std::time::Duration,
+    last_flush: Option<std::time::Instant>,
+}
+
+impl RunningAggregate {
+    /// Start from the result files already in analysis_results/<cve>, which
+    /// the final stats include too
+    pub async fn new(cve_id: &str, interval: std::time::Duration) -> Result<Self> {
+        let mut aggregate = Self {
+            cve_id: cve_id.to_string(),
+            subjects: BTreeMap::new(),
+            interval,
+            last_flush: None,
+        };
+        let dir = analysis_results_dir().join(cve_id);
+        if dir.exists() {
+            for (subject, files) in load_result_files(&dir).await?.iter() {
+                aggregate
+                    .subjects
+                    .insert(subject.clone(), SubjectTally::of(files));
+            }
+        }
+        Ok(aggregate)
+    }
+
+    /// Count a result file the analysis just wrote, skipped like the stats
+    /// loader skips it if it is not a caller result
+    pub async fn add_result_file(&mut self, path: &Path) -> Result<()> {
+        let Some(subject) = result_file_subject(path) else {
+            return Ok(());
+        };
+        if let Some(input_glob) = input_glob()?
+            && !path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| input_glob.matches(name))
+        {
+            return Ok(());
+        }
+        let content = utils::read_result_file(path).await?;
+        if let Value::Array(files) = serde_json::from_str(&content)? {
+            self.subjects.insert(subject, SubjectTally::of(&files));
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> RunningStats {
+        let mut stats = RunningStats {
+            cve_id: self.cve_id.clone(),
+            total_subjects: self.subjects.len(),
+            ..Default::default()
+        };
+        for tally in self.subjects.values() {
+            stats.total_function_result_files += tally.function_result_files;
+            for (f, count) in &tally.function_callers {
+                stats.total_callers += count;
+                *stats.function_callers.entry(f.clone()).or_insert(0) += count;
+            }
+            merge_histogram(
+                &mut stats.path_constraints_histogram,
+                tally.path_constraints.clone(),
+            );
+            merge_histogram(
+                &mut stats.package_hops_histogram,
+                tally.package_hops.clone(),
+            );
+        }
+        stats
+    }
+
+    /// Write the running stats if the flush interval has passed, or always with `force`
+    pub async fn flush(&mut self, force: bool) -> Result<()> {
+        if !force && self.last_flush.is_some_and(|t| t.elapsed() < self.interval) {
+            return Ok(());
+        }
+        let path = RunningStats::path(&self.cve_id);
+        if let Some(parent) = path.parent() {
+            tokio_fs::create_dir_all(parent).await?;
+        }
+        utils::write_atomic(&path, serde_json::to_string_pretty(&self.stats())?).await?;
+        self.last_flush = Some(std::time::Instant::now());
+        Ok(())
+    }
+}
+
+/// Compare the running stats flushed by the analysis with the full
+/// computation, warn about every value that differs
+async fn reconcile_running_stats(global: &GlobalStats) {
+    let running = match RunningStats::load(&global.cve_id).await {
+        Ok(Some(running)) => running,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!("{}", e);
+            return;
+        }
+    };
+    let (Ok(expected), Ok(actual)) = (
+        serde_json::to_value(RunningStats::from_global(global)),
+        serde_json::to_value(&running),
+    ) else {
+        return;
+    };
+    let mut mismatches = Vec::new();
+    diff_values("", &expected, &actual, &mut mismatches);
+    if mismatches.is_empty() {
+        tracing::debug!(
+            "running stats of {} match the full computation",
+            global.cve_id
+        );
+    }
+    for mismatch in mismatches {
+        tracing::warn!(
+            "running stats of {} differ from the full computation: {}",
+            global.cve_id,
+            mismatch
+        );
+    }
+}
+
+/// the target function of a callgraph file, falling back to its file name
+pub(crate) fn function_of_file(file_obj: &Value) -> String {
+    let file_key = file_obj.get("file").and_then(|v| v.as_str()).unwrap_or("");
+    file_obj
+        .get("file-content")
+        .and_then(|c| c.get("target"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| function_from_file_key(file_key))
+}
+
+/// List every caller finding of a CVE with its metadata, read from the
+/// result files in `analysis_results/<cve>`, so callers can be queried
+/// without going through the aggregated stats.
+pub async fn load_callers(cve_id: &str) -> Result<Vec<CallerRecord>> {
+    let dir = analysis_results_dir().join(cve_id);
+    let mut records = Vec::new();
+    for (subject, files) in load_result_files(&dir).await?.iter() {
+        for file_obj in files {
+            let function = function_of_file(file_obj);
+            let callers = file_obj
+                .get("file-content")
+                .and_then(|c| c.get("callers"))
+                .and_then(|v| v.as_array());
+            for caller in callers.into_iter().flatten() {
+                let Some(caller_path) = caller.get("path").and_then(|v| v.as_str()) else {
+                    continue;
+                };
+                records.push(CallerRecord {
+                    subject: subject.clone(),
+                    function: function.clone(),
+                    caller_path: caller_path.to_string(),
+                    path_constraints: caller.get("path_constraints").and_then(|v| v.as_i64()),
+                    path_package_num: caller.get("path_package_num").and_then(|v| v.as_i64()),
+                });
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// The callers of one subject grouped by the source file they are defined in,
+/// see [`caller_locations`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SubjectLocations {
+    pub subject: String,
+    /// source file -> the distinct caller paths defined in it, sorted
+    pub callers_by_file: BTreeMap<String, Vec<String>>,
+    /// callers whose record carries no location
+    pub without_location: usize,
+}
+
+/// The source file of a caller: the `location`, `span` or `file` field of
+/// the record if `call-cg4rs` wrote one, otherwise a leading
+/// `<file>.rs:<line>[:<col>]` segment of its path. The line and column are dropped.
+fn caller_location(caller: &Value) -> Option<String> {
+    fn file_of(location: &str) -> Option<&str> {
+        let end = location.find(".rs")? + ".rs".len();
+        let rest = &location[end..];
+        // only a location if the file name is followed by nothing or `:<line>`
+        (rest.is_empty() || rest.starts_with(':')).then(|| location[..end].trim())
+    }
+    ["location", "span", "file"]
+        .iter()
+        .filter_map(|key| caller.get(*key).and_then(|v| v.as_str()))
+        .find_map(file_of)
+        .or_else(|| {
+            let path = caller.get("path").and_then(|v| v.as_str())?;
+            let first = path.split_whitespace().next()?;
+            file_of(first.trim_end_matches(':'))
+        })
+        .map(|file| file.to_string())
+}
+
+/// Group the callers of each subject by the source file they are defined in,
+/// for studying where in a code base the target functions are reached.
+pub async fn caller_locations(cve_id: &str) -> Result<Vec<SubjectLocations>> {
+    let dir = analysis_results_dir().join(cve_id);
+    let mut subjects: BTreeMap<String, SubjectLocations> = BTreeMap::new();
+    for (subject, files) in load_result_files(&dir).await?.iter() {
+        let entry = subjects
+            .entry(subject.clone())
+            .or_insert_with(|| SubjectLocations {
+                subject: subject.clone(),
+                ..Default::default()
+            });
+        let callers = files
+            .iter()
+            .filter_map(|f| f.get("file-content").and_then(|c| c.get("callers")))
+            .filter_map(|v| v.as_array())
+            .flatten();
+        for caller in callers {
+            let Some(caller_path) = caller.get("path").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            match caller_location(caller) {
+                Some(file) => entry
+                    .callers_by_file
+                    .entry(file)
+                    .or_default()
+                    .push(caller_path.to_string()),
+                None => entry.without_location += 1,
+            }
+        }
+    }
+    let mut subjects: Vec<SubjectLocations> = subjects.into_values().collect();
+    for subject in &mut subjects {
+        for paths in subject.callers_by_file.values_mut() {
+            paths.sort();
+            paths.dedup();
+        }
+    }
+    Ok(subjects)
+}
+
+/// The full caller list of one target function, ranked by path constraints.
+/// A function without callers gives an empty detail rather than an error.
+pub async fn function_detail(cve_id: &str, function: &str) -> Result<FunctionDetail> {
+    let mut callers: Vec<CallerRecord> = load_callers(cve_id)
+        .await?
+        .into_iter()
+        .filter(|c| c.function == function)
+        .collect();
+    callers.sort_by(|a, b| {
+        b.path_constraints
+            .cmp(&a.path_constraints)
+            .then(b.path_package_num.cmp(&a.path_package_num))
+    });
+    let subjects: BTreeSet<String> = callers.iter().map(|c| c.subject.clone()).collect();
+    Ok(FunctionDetail {
+        function: function.to_string(),
+        total_callers: callers.len(),
+        subjects: subjects.into_iter().collect(),
+        callers,
+    })
+}
+
+/// Markdown listing of every caller in `detail`
+pub fn render_function_detail(cve_id: &str, detail: &FunctionDetail) -> String {
+    let mut md = String::new();
+    md.push_str(&format!("# {} in {}\n\n", detail.function, cve_id));
+    if detail.callers.is_empty() {
+        md.push_str("No callers of this function were found.\n");
+        return md;
+    }
+    md.push_str(&format!("- Total callers: {}\n", detail.total_callers));
+    md.push_str(&format!("- Subjects: {}\n", detail.subjects.len()));
+    md.push_str("\n## Callers\n\n");
+    for c in &detail.callers {
+        md.push_str(&format!(
+            "- [{}] {} (pc={:?}, pkg={:?})\n",
+            c.subject, c.caller_path, c.path_constraints, c.path_package_num
+        ));
+    }
+    md
+}
+
+/// Write `function-<function>-<cve>.md` with every caller of `function`
+pub async fn write_function_detail(cve_id: &str, function: &str) -> Result<PathBuf> {
+    let detail = function_detail(cve_id, function).await?;
+    let path = analysis_results_dir().join(cve_id).join(format!(
+        "function-{}-{}.md",
+        sanitize_file_component(function),
+        cve_id
+    ));
+    tokio_fs::write(&path, render_function_detail(cve_id, &detail)).await?;
+    tracing::info!("function detail written: {:?}", path);
+    Ok(path)
+}
+
+/// joint (package_hops, path_constraints) histograms, only exported as CSV
+struct Heatmaps {
+    per_function: HashMap<String, BTreeMap<(i64, i64), usize>>,
+    global: BTreeMap<(i64, i64), usize>,
+}
+
+/// Aggregate the result files in `analysis_results/<cve>` into [`GlobalStats`]
+/// without writing anything, e.g. to render it with a custom template.
+pub async fn compute_stats(cve_id: &str) -> Result<GlobalStats> {
+    Ok(compute(cve_id).await?.0)
+}
+
+/// A key aggregate that differs between freshly computed stats and a baseline,
+/// `path` is the dotted location in stats-<cve>.json, e.g. `functions.foo::bar.total_callers`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineMismatch {
+    pub path: String,
+    /// `null` when the baseline does not have it
+    pub expected: Value,
+    /// `null` when the recomputed stats do not have it
+    pub actual: Value,
+}
+
+impl std::fmt::Display for BaselineMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.path, self.expected, self.actual
+        )
+    }
+}
+
+/// the fields of [`GlobalStats`] that describe the run rather than the results,
+/// ignored by [`verify_against_baseline`]
+const VOLATILE_STATS_FIELDS: [&str; 3] = ["run_metrics", "run_info", "environment"];
+
+/// Recompute the stats of `analysis_results/<cve>` and compare them with a
+/// baseline stats-<cve>.json (plain or gzip-compressed) written by an earlier
+/// version, to catch accidental changes in the aggregation. Return every
+/// mismatching value, empty when they agree. The run metrics, run info and
+/// environment are not compared. The baseline must have been computed with the same
+/// `HISTOGRAM_MODE`, `COLLAPSE_SUBJECTS` and caller path settings.
+pub async fn verify_against_baseline(
+    cve_id: &str,
+    baseline_path: &Path,
+) -> Result<Vec<BaselineMismatch>> {
+    let content = utils::read_result_file(baseline_path)
+        .await
+        .with_context(|| format!("Failed to read baseline {}", baseline_path.display()))?;
+    let mut expected: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse baseline {}", baseline_path.display()))?;
+    let mut actual = serde_json::to_value(compute_stats(cve_id).await?)?;
+    for value in [&mut expected, &mut actual] {
+        if let Some(object) = value.as_object_mut() {
+            for field in VOLATILE_STATS_FIELDS {
+                object.remove(field);
+            }
+        }
+    }
+    let mut mismatches = Vec::new();
+    diff_values("", &expected, &actual, &mut mismatches);
+    Ok(mismatches)
+}
+
+fn diff_values(path: &str, expected: &Value, actual: &Value, out: &mut Vec<BaselineMismatch>) {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            let keys: BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+            for key in keys {
+                diff_values(
+                    &child(key),
+                    expected.get(key).unwrap_or(&Value::Null),
+                    actual.get(key).unwrap_or(&Value::Null),
+                    out,
+                );
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for i in 0..expected.len().max(actual.len()) {
+                diff_values(
+                    &child(&i.to_string()),
+                    expected.get(i).unwrap_or(&Value::Null),
+                    actual.get(i).unwrap_or(&Value::Null),
+                    out,
+                );
+            }
+        }
+        (expected, actual) if expected != actual => out.push(BaselineMismatch {
+            path: path.to_string(),
+            expected: expected.clone(),
+            actual: actual.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// The raw values gathered for one target function, turned into its
+/// [`FunctionStats`] by [`FunctionValues::finalize`]
+#[derive(Debug, Default)]
+struct FunctionValues {
+    func_key: String,
+    total_callers: usize,
+    unique_paths: usize,
+    path_constraints: Vec<i64>,
+    package_hops: Vec<i64>,
+    path_constraints_histogram: BTreeMap<i64, usize>,
+    package_hops_histogram: BTreeMap<i64, usize>,
+    top_constraints: Vec<CallerSample>,
+    top_package_hops: Vec<CallerSample>,
+}
+
+/// p50, p90, p95 and p99
+type Percentiles = (Option<f64>, Option<f64>, Option<f64>, Option<f64>);
+
+/// min, max, average and percentiles of `vals`, sorting them in place
+fn summarize_values(vals: &mut [i64]) -> Option<(i64, i64, f64, Percentiles)> {
+    if vals.is_empty() {
+        return None;
+    }
+    vals.sort_unstable();
+    let sum: i64 = vals.iter().sum();
+    let avg = sum as f64 / vals.len() as f64;
+    let nth = |p: f64| -> f64 {
+        let idx = ((vals.len() as f64 - 1.0) * p).round() as usize;
+        vals[idx] as f64
+    };
+    Some((
+        vals[0],
+        vals[vals.len() - 1],
+        avg,
+        (
+            Some(nth(0.50)),
+            Some(nth(0.90)),
+            Some(nth(0.95)),
+            Some(nth(0.99)),
+        ),
+    ))
+}
+
+impl FunctionValues {
+    fn finalize(mut self) -> FunctionStats {
+        let (pc_min, pc_max, pc_avg, (pc_p50, pc_p90, pc_p95, pc_p99)) =
+            match summarize_values(&mut self.path_constraints) {
+                Some((min, max, avg, percentiles)) => (Some(min), Some(max), avg, percentiles),
+                None => (None, None, 0.0, (None, None, None, None)),
+            };
+        let (pkg_min, pkg_max, pkg_avg, (pkg_p50, pkg_p90, pkg_p95, pkg_p99)) =
+            match summarize_values(&mut self.package_hops) {
+                Some((min, max, avg, percentiles)) => {
+                    (Some(min), Some(max), Some(avg), percentiles)
+                }
+                None => (None, None, None, (None, None, None, None)),
+            };
+
+        // Top-N 样本（约束与包跳数各取前 10）
+        self.top_constraints
+            .sort_by(|a, b| b.path_constraints.cmp(&a.path_constraints));
+        self.top_constraints.truncate(10);
+        self.top_package_hops
+            .sort_by(|a, b| b.path_package_num.cmp(&a.path_package_num));
+        self.top_package_hops.truncate(10);
+
+        FunctionStats {
+            function_file: self.func_key,
+            total_callers: self.total_callers,
+            unique_call_paths: self.unique_paths,
+
+            path_constraints_min: pc_min,
+            path_constraints_max: pc_max,
+            path_constraints_avg: pc_avg,
+            path_constraints_p50: pc_p50,
+            path_constraints_p90: pc_p90,
+            path_constraints_p95: pc_p95,
+            path_constraints_p99: pc_p99,
+            package_hops_min: pkg_min,
+            package_hops_max: pkg_max,
+            package_hops_avg: pkg_avg,
+            package_hops_p50: pkg_p50,
+            package_hops_p90: pkg_p90,
+            package_hops_p95: pkg_p95,
+            package_hops_p99: pkg_p99,
+            path_constraints_histogram: self.path_constraints_histogram,
+            package_hops_histogram: self.package_hops_histogram,
+            path_constraints_buckets: Vec::new(),
+            package_hops_buckets: Vec::new(),
+            top_callers_by_constraints: self.top_constraints,
+            top_callers_by_package_hops: self.top_package_hops,
+        }
+    }
+}
+
+/// Finalize every function in parallel, or one after the other with
+/// `STATS_THREADS=1`. Both give the same map.
+fn finalize_functions(values: Vec<FunctionValues>) -> BTreeMap<String, FunctionStats> {
+    finalize_functions_as(values, stats_threads())
+}
+
+/// [`finalize_functions`] on `threads` threads, rayon's default if `None`
+fn finalize_functions_as(
+    values: Vec<FunctionValues>,
+    threads: Option<usize>,
+) -> BTreeMap<String, FunctionStats> {
+    let finalize = |v: FunctionValues| {
+        let stats = v.finalize();
+        (stats.function_file.clone(), stats)
+    };
+    match threads {
+        Some(1) => values.into_iter().map(finalize).collect(),
+        Some(n) => match rayon::ThreadPoolBuilder::new().num_threads(n).build() {
+            Ok(pool) => pool.install(|| values.into_par_iter().map(finalize).collect()),
+            Err(e) => {
+                tracing::warn!("Failed to start {} stats threads: {}", n, e);
+                values.into_par_iter().map(finalize).collect()
+            }
+        },
+        None => values.into_par_iter().map(finalize).collect(),
+    }
+}
+
+/// `STATS_THREADS`: the number of threads finalizing the function stats,
+/// rayon's default (one per CPU) when unset
+fn stats_threads() -> Option<usize> {
+    crate::config::var("STATS_THREADS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0)
+}
+
+async fn compute(cve_id: &str) -> Result<(GlobalStats, Heatmaps)> {
+    let dir = analysis_results_dir().join(cve_id);
+
+    let mut global = GlobalStats {
+        cve_id: cve_id.to_string(),
+        ..Default::default()
+    };
+
+    // function aggregations
+    let mut function_total_callers: HashMap<String, usize> = HashMap::new();
+    let mut function_unique_paths: HashMap<String, BTreeSet<String>> = HashMap::new();
+
+    let mut function_path_constraints_values: HashMap<String, Vec<i64>> = HashMap::new();
+    let mut function_package_hops_values: HashMap<String, Vec<i64>> = HashMap::new();
+    let mut function_path_constraints_hist: HashMap<String, BTreeMap<i64, usize>> = HashMap::new();
+    let mut function_package_hops_hist: HashMap<String, BTreeMap<i64, usize>> = HashMap::new();
+    let mut function_top_constraints_samples: HashMap<String, Vec<CallerSample>> = HashMap::new();
+    let mut function_top_pkg_samples: HashMap<String, Vec<CallerSample>> = HashMap::new();
+    // (package_hops, path_constraints) -> callers, for callers that have both
+    let mut function_joint_hist: HashMap<String, BTreeMap<(i64, i64), usize>> = HashMap::new();
+    let mut global_joint_hist: BTreeMap<(i64, i64), usize> = BTreeMap::new();
+
+    let normalize_paths = normalize_caller_paths_enabled();
+
+    // subject aggregations
+    let mut subjects_map: BTreeMap<String, SubjectStats> = BTreeMap::new();
+
+    for (cnv, files) in load_result_files(&dir).await?.iter() {
+        let subject_entry = subjects_map
+            .entry(cnv.clone())
+            .or_insert_with(|| SubjectStats {
+                subject: cnv.clone(),
+                ..Default::default()
             });
 
         global.total_subjects += 1;
 
         // 当前结构：每个文件对象包含 file 与 file-content，后者含 target 与 callers[]
-        for file_obj in json.as_array().unwrap() {
+        for file_obj in files {
             global.total_function_result_files += 1;
-            let file_key = file_obj.get("file").and_then(|v| v.as_str()).unwrap_or("");
             let file_content = match file_obj.get("file-content") {
                 Some(v) => v,
                 None => continue,
             };
 
-            let func_key = file_content
-                .get("target")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| function_from_file_key(file_key));
+            let func_key = function_of_file(file_obj);
 
             let callers = file_content
                 .get("callers")
@@ -184,8 +1837,13 @@ pub async fn compute_and_write_stats(cve_id: &str) -> Result<()> {
                 .or_insert_with(BTreeSet::new);
 
             for caller in callers {
+                // samples keep the raw path, only the unique count is normalized
                 if let Some(path) = caller.get("path").and_then(|v| v.as_str()) {
-                    uniq_paths.insert(path.to_string());
+                    if normalize_paths {
+                        uniq_paths.insert(normalize_caller_path(path));
+                    } else {
+                        uniq_paths.insert(path.to_string());
+                    }
                 }
                 if let Some(pc) = caller.get("path_constraints").and_then(|v| v.as_i64()) {
                     // per-target histogram
@@ -245,127 +1903,98 @@ pub async fn compute_and_write_stats(cve_id: &str) -> Result<()> {
                             .push(sample);
                     }
                 }
+                if let Some(pc) = caller.get("path_constraints").and_then(|v| v.as_i64())
+                    && let Some(pkg) = caller.get("path_package_num").and_then(|v| v.as_i64())
+                {
+                    *function_joint_hist
+                        .entry(func_key.clone())
+                        .or_default()
+                        .entry((pkg, pc))
+                        .or_insert(0) += 1;
+                    *global_joint_hist.entry((pkg, pc)).or_insert(0) += 1;
+                }
             }
         }
     }
 
-    // finalize function stats
-    for (func_key, total_callers) in function_total_callers {
-        let unique_paths = function_unique_paths
-            .get(&func_key)
-            .map(|s| s.len())
-            .unwrap_or(0);
+    // finalize function stats, the functions are independent so their values
+    // are sorted in parallel; the BTreeMap keeps the output order deterministic
+    let values: Vec<FunctionValues> = function_total_callers
+        .into_iter()
+        .map(|(func_key, total_callers)| FunctionValues {
+            unique_paths: function_unique_paths
+                .remove(&func_key)
+                .map(|s| s.len())
+                .unwrap_or(0),
+            path_constraints: function_path_constraints_values
+                .remove(&func_key)
+                .unwrap_or_default(),
+            package_hops: function_package_hops_values
+                .remove(&func_key)
+                .unwrap_or_default(),
+            path_constraints_histogram: function_path_constraints_hist
+                .remove(&func_key)
+                .unwrap_or_default(),
+            package_hops_histogram: function_package_hops_hist
+                .remove(&func_key)
+                .unwrap_or_default(),
+            top_constraints: function_top_constraints_samples
+                .remove(&func_key)
+                .unwrap_or_default(),
+            top_package_hops: function_top_pkg_samples
+                .remove(&func_key)
+                .unwrap_or_default(),
+            func_key,
+            total_callers,
+        })
+        .collect();
+    global.functions = finalize_functions(values);
 
-        // path constraints stats
-        let (pc_min, pc_max, pc_avg) =
-            if let Some(vals) = function_path_constraints_values.get(&func_key) {
-                if vals.is_empty() {
-                    (None, None, 0.0)
-                } else {
-                    let min_v = *vals.iter().min().unwrap();
-                    let max_v = *vals.iter().max().unwrap();
-                    let sum: i64 = vals.iter().sum();
-                    let avg = sum as f64 / vals.len() as f64;
-                    (Some(min_v), Some(max_v), avg)
-                }
-            } else {
-                (None, None, 0.0)
-            };
+    let histogram_mode = HistogramMode::from_env();
+    global.histogram_mode = histogram_mode.to_string();
+    apply_histogram_mode(
+        histogram_mode,
+        &mut global.path_constraints_histogram,
+        &mut global.path_constraints_buckets,
+    );
+    apply_histogram_mode(
+        histogram_mode,
+        &mut global.package_hops_histogram,
+        &mut global.package_hops_buckets,
+    );
+    for fs in global.functions.values_mut() {
+        apply_histogram_mode(
+            histogram_mode,
+            &mut fs.path_constraints_histogram,
+            &mut fs.path_constraints_buckets,
+        );
+        apply_histogram_mode(
+            histogram_mode,
+            &mut fs.package_hops_histogram,
+            &mut fs.package_hops_buckets,
+        );
+    }
 
-        let pc_percentiles = |vals: &Vec<i64>| -> (Option<f64>, Option<f64>, Option<f64>, Option<f64>) {
-            if vals.is_empty() { return (None, None, None, None); }
-            let mut v = vals.clone();
-            v.sort_unstable();
-            let nth = |p: f64| -> f64 {
-                let idx = ((v.len() as f64 - 1.0) * p).round() as usize;
-                v[idx] as f64
-            };
-            (Some(nth(0.50)), Some(nth(0.90)), Some(nth(0.95)), Some(nth(0.99)))
-        };
-        let (pc_p50, pc_p90, pc_p95, pc_p99) = function_path_constraints_values
-            .get(&func_key)
-            .map(pc_percentiles)
-            .unwrap_or((None, None, None, None));
-
-        // package hops stats
-        let (pkg_min, pkg_max, pkg_avg_opt) =
-            if let Some(vals) = function_package_hops_values.get(&func_key) {
-                if vals.is_empty() {
-                    (None, None, None)
-                } else {
-                    let min_v = *vals.iter().min().unwrap();
-                    let max_v = *vals.iter().max().unwrap();
-                    let sum: i64 = vals.iter().sum();
-                    let avg = sum as f64 / vals.len() as f64;
-                    (Some(min_v), Some(max_v), Some(avg))
+    if annotate_crate_info_enabled() {
+        for subject in subjects_map.values_mut() {
+            match CrateInfo::load(cve_id, &subject.subject).await {
+                Ok(Some(info)) => {
+                    subject.edition = Some(info.edition);
+                    subject.rust_version = info.rust_version;
                 }
-            } else {
-                (None, None, None)
-            };
-
-        let pkg_percentiles = |vals: &Vec<i64>| -> (Option<f64>, Option<f64>, Option<f64>, Option<f64>) {
-            if vals.is_empty() { return (None, None, None, None); }
-            let mut v = vals.clone();
-            v.sort_unstable();
-            let nth = |p: f64| -> f64 {
-                let idx = ((v.len() as f64 - 1.0) * p).round() as usize;
-                v[idx] as f64
-            };
-            (Some(nth(0.50)), Some(nth(0.90)), Some(nth(0.95)), Some(nth(0.99)))
-        };
-        let (pkg_p50, pkg_p90, pkg_p95, pkg_p99) = function_package_hops_values
-            .get(&func_key)
-            .map(pkg_percentiles)
-            .unwrap_or((None, None, None, None));
-
-        // Top-N 样本（约束与包跳数各取前 10）
-        let mut top_constraints = function_top_constraints_samples
-            .get(&func_key)
-            .cloned()
-            .unwrap_or_default();
-        top_constraints.sort_by(|a, b| b.path_constraints.cmp(&a.path_constraints));
-        top_constraints.truncate(10);
-
-        let mut top_pkg = function_top_pkg_samples
-            .get(&func_key)
-            .cloned()
-            .unwrap_or_default();
-        top_pkg.sort_by(|a, b| b.path_package_num.cmp(&a.path_package_num));
-        top_pkg.truncate(10);
-
-        global.functions.insert(
-            func_key.clone(),
-            FunctionStats {
-                function_file: func_key.clone(),
-                total_callers,
-                unique_call_paths: unique_paths,
-
-                path_constraints_min: pc_min,
-                path_constraints_max: pc_max,
-                path_constraints_avg: pc_avg,
-                path_constraints_p50: pc_p50,
-                path_constraints_p90: pc_p90,
-                path_constraints_p95: pc_p95,
-                path_constraints_p99: pc_p99,
-                package_hops_min: pkg_min,
-                package_hops_max: pkg_max,
-                package_hops_avg: pkg_avg_opt,
-                package_hops_p50: pkg_p50,
-                package_hops_p90: pkg_p90,
-                package_hops_p95: pkg_p95,
-                package_hops_p99: pkg_p99,
-                path_constraints_histogram: function_path_constraints_hist
-                    .remove(&func_key)
-                    .unwrap_or_default(),
-                package_hops_histogram: function_package_hops_hist
-                    .remove(&func_key)
-                    .unwrap_or_default(),
-                top_callers_by_constraints: top_constraints,
-                top_callers_by_package_hops: top_pkg,
-            },
-        );
+                Ok(None) => {}
+                Err(e) => tracing::warn!("{}", e),
+            }
+        }
+        (global.editions, global.rust_versions) = crate_info_distribution(subjects_map.values());
     }
 
+    global.self_callers = subjects_map
+        .values()
+        .filter(|s| is_self_subject(&s.subject))
+        .map(|s| s.total_callers)
+        .sum();
+
     // subjects list and top N
     let mut subjects_vec: Vec<SubjectStats> = subjects_map.into_values().collect();
     subjects_vec.sort_by(|a, b| b.total_callers.cmp(&a.total_callers));
@@ -375,28 +2004,217 @@ pub async fn compute_and_write_stats(cve_id: &str) -> Result<()> {
         .map(|s| (s.subject.clone(), s.total_callers))
         .collect();
     global.top_subjects_by_callers = top_subjects_by_callers;
+    if collapse_subjects_enabled() {
+        global.crates = collapse_subjects(&subjects_vec);
+        global.top_crates_by_callers = global
+            .crates
+            .iter()
+            .take(20)
+            .map(|c| (c.crate_name.clone(), c.total_callers))
+            .collect();
+    }
     global.subjects = subjects_vec;
 
-    // write out
-    let out_json = serde_json::to_string_pretty(&global)?;
-    let out_json_path = dir.join(format!("stats-{}.json", cve_id));
-    tokio_fs::write(&out_json_path, out_json).await?;
+    let manifest = match RunManifest::load(cve_id).await {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            tracing::warn!("failed to load the run manifest of {}: {}", cve_id, e);
+            None
+        }
+    };
+    if let Some(manifest) = manifest {
+        global.unused_target_functions = manifest
+            .target_function_paths
+            .split(',')
+            .map(|f| f.trim())
+            .filter(|f| !f.is_empty())
+            .filter(|f| {
+                global
+                    .functions
+                    .get(*f)
+                    .is_none_or(|fs| fs.total_callers == 0)
+            })
+            .map(|f| f.to_string())
+            .collect();
+        global.run_info = Some(RunInfo {
+            tool_version: manifest.tool_version,
+            crate_name: manifest.crate_name,
+            version_range: manifest.version_range,
+            target_function_paths: manifest.target_function_paths,
+            started_at: manifest.started_at,
+            finished_at: manifest.finished_at,
+        });
+        global.run_metrics = manifest.run_metrics;
+    }
+    global.environment = match AnalysisEnvironment::load(cve_id).await {
+        Ok(environment) => environment,
+        Err(e) => {
+            tracing::warn!(
+                "failed to load the analysis environment of {}: {}",
+                cve_id,
+                e
+            );
+            None
+        }
+    };
+
+    global.confirmations = match CallerConfirmation::load_all(cve_id).await {
+        Ok(confirmations) => confirmations,
+        Err(e) => {
+            tracing::warn!("failed to load the confirmations of {}: {}", cve_id, e);
+            Vec::new()
+        }
+    };
+
+    let bfs_tree_path = BfsTreeNode::path(cve_id);
+    if let Ok(content) = tokio_fs::read_to_string(&bfs_tree_path).await {
+        match serde_json::from_str::<Vec<BfsTreeNode>>(&content) {
+            Ok(forest) => {
+                global.propagation_by_depth = propagation_by_depth(&forest);
+                global.callers_by_depth = callers_by_depth(&forest, &global.subjects);
+            }
+            Err(e) => tracing::warn!("Failed to parse {}: {}", bfs_tree_path.display(), e),
+        }
+    }
+
+    Ok((
+        global,
+        Heatmaps {
+            per_function: function_joint_hist,
+            global: global_joint_hist,
+        },
+    ))
+}
+
+/// A compact markdown for human reading, functions and subjects with fewer
+/// than `MIN_CALLERS` callers are elided
+pub fn render_markdown(global: &GlobalStats) -> String {
+    render_markdown_as(global, min_callers_threshold())
+}
 
-    // A compact markdown for human reading
+/// [`render_markdown`] with a `MIN_CALLERS` of `min_callers`
+pub(crate) fn render_markdown_as(global: &GlobalStats, min_callers: usize) -> String {
     let mut md = String::new();
-    md.push_str(&format!("# Stats for {}\n\n", cve_id));
+    md.push_str(&format!("# Stats for {}\n\n", global.cve_id));
+    if let Some(info) = &global.run_info {
+        md.push_str(&format!(
+            "- Vulnerable crate: {} {}\n",
+            info.crate_name, info.version_range
+        ));
+        md.push_str(&format!(
+            "- Target functions: {}\n",
+            info.target_function_paths
+        ));
+        md.push_str(&format!(
+            "- Analyzed: {} to {} (cvetracker4rs {})\n",
+            info.started_at,
+            info.finished_at.as_deref().unwrap_or("unfinished"),
+            if info.tool_version.is_empty() {
+                "unknown version"
+            } else {
+                &info.tool_version
+            }
+        ));
+    }
     md.push_str(&format!("- Total subjects: {}\n", global.total_subjects));
     md.push_str(&format!(
         "- Total function files: {}\n",
         global.total_function_result_files
     ));
     md.push_str(&format!("- Total callers: {}\n", global.total_callers));
+    if global.self_callers > 0 {
+        md.push_str(&format!(
+            "- Of which inside the vulnerable crate (`{}`): {}\n",
+            SELF_SUBJECT, global.self_callers
+        ));
+    }
+    if min_callers > 0 {
+        md.push_str(&format!(
+            "- Display threshold (MIN_CALLERS): {}\n",
+            min_callers
+        ));
+    }
+    if let Some(env) = &global.environment {
+        md.push_str("\n## Environment\n\n");
+        md.push_str(&format!(
+            "- cvetracker4rs {} on {}/{}\n",
+            env.tool_version, env.os, env.arch
+        ));
+        md.push_str(&format!(
+            "- rustc: {}\n",
+            env.rustc_version.as_deref().unwrap_or("unknown")
+        ));
+        md.push_str(&format!(
+            "- call-cg4rs: {}\n",
+            env.call_cg4rs_version.as_deref().unwrap_or("unknown")
+        ));
+    }
+    if let Some(metrics) = &global.run_metrics {
+        md.push_str("\n## Run metrics\n\n");
+        md.push_str(&format!("- Wall clock: {:.1}s\n", metrics.wall_clock_secs));
+        md.push_str(&format!(
+            "- Crates downloaded: {} ({} bytes)\n",
+            metrics.crates_downloaded, metrics.bytes_downloaded
+        ));
+        md.push_str(&format!(
+            "- call-cg4rs invocations: {} (timeouts: {}, failures: {}, of which build scripts: {}, retries: {})\n",
+            metrics.analyzer_invocations,
+            metrics.analyzer_timeouts,
+            metrics.analyzer_failures,
+            metrics.build_script_failures,
+            metrics.analyzer_retries
+        ));
+        if metrics.shared_analyses > 0 {
+            md.push_str(&format!(
+                "- Analyses shared with the other targets of the batch: {} (about {:.1}s of call-cg4rs saved)\n",
+                metrics.shared_analyses, metrics.shared_analysis_secs_saved
+            ));
+        }
+    }
     md.push_str("\n## Top subjects by callers\n\n");
+    let mut elided_subjects = 0;
     for (name, cnt) in &global.top_subjects_by_callers {
+        if *cnt < min_callers {
+            elided_subjects += 1;
+            continue;
+        }
         md.push_str(&format!("- {}: {}\n", name, cnt));
     }
+    if elided_subjects > 0 {
+        md.push_str(&format!(
+            "- ({} subjects with fewer than {} callers elided)\n",
+            elided_subjects, min_callers
+        ));
+    }
+    if !global.crates.is_empty() {
+        md.push_str("\n## Top crates by callers (versions collapsed)\n\n");
+        let mut elided_crates = 0;
+        for krate in global.crates.iter().take(20) {
+            if krate.total_callers < min_callers {
+                elided_crates += 1;
+                continue;
+            }
+            md.push_str(&format!(
+                "- {} ({}): {}\n",
+                krate.crate_name,
+                krate.versions.join(", "),
+                krate.total_callers
+            ));
+        }
+        if elided_crates > 0 {
+            md.push_str(&format!(
+                "- ({} crates with fewer than {} callers elided)\n",
+                elided_crates, min_callers
+            ));
+        }
+    }
     md.push_str("\n## Functions summary\n\n");
+    let mut elided_functions = 0;
     for (func, fs) in &global.functions {
+        if fs.total_callers < min_callers {
+            elided_functions += 1;
+            continue;
+        }
         let pkg_stats = match (
             fs.package_hops_min,
             fs.package_hops_max,
@@ -424,17 +2242,19 @@ pub async fn compute_and_write_stats(cve_id: &str) -> Result<()> {
             fs.package_hops_p99
         ));
 
-        if !fs.path_constraints_histogram.is_empty() {
+        let pc_hist = histogram_md(
+            &fs.path_constraints_histogram,
+            &fs.path_constraints_buckets,
+            "    ",
+        );
+        if !pc_hist.is_empty() {
             md.push_str("  - path_constraints histogram:\n");
-            for (k, v) in &fs.path_constraints_histogram {
-                md.push_str(&format!("    - {}: {}\n", k, v));
-            }
+            md.push_str(&pc_hist);
         }
-        if !fs.package_hops_histogram.is_empty() {
+        let pkg_hist = histogram_md(&fs.package_hops_histogram, &fs.package_hops_buckets, "    ");
+        if !pkg_hist.is_empty() {
             md.push_str("  - package_hops histogram:\n");
-            for (k, v) in &fs.package_hops_histogram {
-                md.push_str(&format!("    - {}: {}\n", k, v));
-            }
+            md.push_str(&pkg_hist);
         }
 
         if !fs.top_callers_by_constraints.is_empty() {
@@ -456,19 +2276,1163 @@ pub async fn compute_and_write_stats(cve_id: &str) -> Result<()> {
             }
         }
     }
+    if elided_functions > 0 {
+        md.push_str(&format!(
+            "- ({} functions with fewer than {} callers elided)\n",
+            elided_functions, min_callers
+        ));
+    }
+    if !global.unused_target_functions.is_empty() {
+        md.push_str("\n## Target functions without callers\n\n");
+        for func in &global.unused_target_functions {
+            md.push_str(&format!("- {}\n", func));
+        }
+    }
+    if !global.confirmations.is_empty() {
+        let count = |status| {
+            global
+                .confirmations
+                .iter()
+                .filter(|c| c.status == status)
+                .count()
+        };
+        md.push_str("\n## Caller confirmations\n\n");
+        md.push_str(&format!(
+            "- confirmed: {}, unconfirmed: {}, unknown: {}\n",
+            count(ConfirmationStatus::Confirmed),
+            count(ConfirmationStatus::Unconfirmed),
+            count(ConfirmationStatus::Unknown)
+        ));
+        for c in &global.confirmations {
+            md.push_str(&format!(
+                "- {} `{}` -> {}: {}\n",
+                c.subject, c.caller_path, c.function, c.status
+            ));
+        }
+    }
+    let width = chart_width();
+    if !global.propagation_by_depth.is_empty() {
+        md.push_str("\n## Propagation width by depth\n\n");
+        for w in &global.propagation_by_depth {
+            md.push_str(&format!(
+                "- depth {}: {} crate(s) with callers of {} processed\n",
+                w.depth, w.with_callers, w.processed
+            ));
+        }
+        let rows: Vec<(String, usize)> = global
+            .propagation_by_depth
+            .iter()
+            .map(|w| (format!("depth {}", w.depth), w.with_callers))
+            .collect();
+        let chart = bar_chart_md(&rows, width);
+        if !chart.is_empty() {
+            md.push('\n');
+            md.push_str(&chart);
+        }
+    }
+    if !global.callers_by_depth.is_empty() {
+        md.push_str("\n## Callers by depth\n\n");
+        for d in &global.callers_by_depth {
+            md.push_str(&format!(
+                "- depth {}: {} caller(s) in {} subject(s)\n",
+                d.depth, d.callers, d.subjects
+            ));
+        }
+    }
+    if !global.editions.is_empty() {
+        md.push_str("\n## Editions and MSRV of affected crates\n\n");
+        for (edition, count) in &global.editions {
+            md.push_str(&format!("- edition {}: {}\n", edition, count));
+        }
+        md.push('\n');
+        for (rust_version, count) in &global.rust_versions {
+            md.push_str(&format!("- MSRV {}: {}\n", rust_version, count));
+        }
+    }
     md.push_str("\n## Path constraints histogram\n\n");
-    for (k, v) in &global.path_constraints_histogram {
-        md.push_str(&format!("- {}: {}\n", k, v));
+    md.push_str(&histogram_md(
+        &global.path_constraints_histogram,
+        &global.path_constraints_buckets,
+        "",
+    ));
+    let chart = bar_chart_md(
+        &histogram_chart_rows(
+            &global.path_constraints_histogram,
+            &global.path_constraints_buckets,
+        ),
+        width,
+    );
+    if !chart.is_empty() {
+        md.push('\n');
+        md.push_str(&chart);
     }
-    if !global.package_hops_histogram.is_empty() {
+    let pkg_hist = histogram_md(
+        &global.package_hops_histogram,
+        &global.package_hops_buckets,
+        "",
+    );
+    if !pkg_hist.is_empty() {
         md.push_str("\n## Package hops (package_num) histogram\n\n");
-        for (k, v) in &global.package_hops_histogram {
-            md.push_str(&format!("- {}: {}\n", k, v));
+        md.push_str(&pkg_hist);
+    }
+    md
+}
+
+/// Which stats artifacts are written, selected with `--output-format` of the
+/// stats binary: a comma-separated list of `json`, `md`, `html` or `all`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputFormats {
+    /// stats-<cve>.json, the heatmap CSVs and locations-<cve>.json
+    pub json: bool,
+    /// stats-<cve>.md
+    pub markdown: bool,
+    /// report-<cve>.html, written by `export::html` from stats-<cve>.json,
+    /// so the JSON is written too
+    pub html: bool,
+}
+
+impl Default for OutputFormats {
+    fn default() -> Self {
+        Self {
+            json: true,
+            markdown: true,
+            html: false,
+        }
+    }
+}
+
+impl OutputFormats {
+    pub fn parse(list: &str) -> Result<Self> {
+        let mut formats = Self {
+            json: false,
+            markdown: false,
+            html: false,
+        };
+        for format in list.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            match format {
+                "json" => formats.json = true,
+                "md" | "markdown" => formats.markdown = true,
+                "html" => formats.html = true,
+                "all" => {
+                    formats = Self {
+                        json: true,
+                        markdown: true,
+                        html: true,
+                    }
+                }
+                other => {
+                    return Err(anyhow::anyhow!(
+                        "unknown output format `{}`, expected json, md, html or all",
+                        other
+                    ));
+                }
+            }
+        }
+        if !(formats.json || formats.markdown || formats.html) {
+            return Err(anyhow::anyhow!("no output format given"));
+        }
+        Ok(formats)
+    }
+}
+
+pub async fn compute_and_write_stats(cve_id: &str) -> Result<()> {
+    compute_and_write_stats_as(cve_id, OutputFormats::default()).await
+}
+
+/// [`compute_and_write_stats`] writing only the artifacts of `formats`; the
+/// HTML report is left to `export::html`
+pub async fn compute_and_write_stats_as(cve_id: &str, formats: OutputFormats) -> Result<()> {
+    let dir = analysis_results_dir().join(cve_id);
+    if !dir.exists() {
+        tracing::info!("analysis_results not found, skip stats");
+        return Ok(());
+    }
+
+    let (global, heatmaps) = compute(cve_id).await?;
+    reconcile_running_stats(&global).await;
+
+    // write out
+    if formats.json || formats.html {
+        let out_json = serde_json::to_string_pretty(&global)?;
+        let out_json_path = dir.join(format!("stats-{}.json", cve_id));
+        let out_json_path = utils::write_result_file(&out_json_path, &out_json).await?;
+        tracing::info!("stats written: {:?}", out_json_path);
+    }
+
+    if formats.json {
+        // heatmaps of path_constraints vs package_hops, per function and global
+        for (func_key, joint) in &heatmaps.per_function {
+            let path = dir.join(format!("heatmap-{}.csv", sanitize_file_component(func_key)));
+            utils::write_result_file(&path, &heatmap_csv(joint)).await?;
+        }
+        if !heatmaps.global.is_empty() {
+            let path = dir.join(format!("heatmap-{}.csv", cve_id));
+            utils::write_result_file(&path, &heatmap_csv(&heatmaps.global)).await?;
         }
+
+        let locations = caller_locations(cve_id).await?;
+        let locations_path = dir.join(format!("locations-{}.json", cve_id));
+        utils::write_result_file(&locations_path, &serde_json::to_string_pretty(&locations)?)
+            .await?;
     }
-    let out_md_path = dir.join(format!("stats-{}.md", cve_id));
-    tokio_fs::write(&out_md_path, md).await?;
 
-    tracing::info!("stats written: {:?}, {:?}", out_json_path, out_md_path);
+    if formats.markdown {
+        let out_md_path = dir.join(format!("stats-{}.md", cve_id));
+        tokio_fs::write(&out_md_path, render_markdown(&global)).await?;
+        tracing::info!("stats written: {:?}", out_md_path);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_caller_path_collapses_monomorphizations() {
+        assert_eq!(
+            normalize_caller_path("foo::bar::<u8>::baz::h0123456789abcdef"),
+            "foo::bar::baz"
+        );
+        assert_eq!(
+            normalize_caller_path("foo::Wrapper::<Vec<Option<u8>>>::new"),
+            "foo::Wrapper::new"
+        );
+        // the same caller instantiated twice is one path
+        assert_eq!(
+            normalize_caller_path("a::f::<u8>"),
+            normalize_caller_path("a::f::<String>")
+        );
+        // a segment that merely looks like a hash is kept
+        assert_eq!(normalize_caller_path("a::h0123"), "a::h0123");
+        assert_eq!(normalize_caller_path("a::<impl Foo for Bar>::f"), "a::f");
+    }
+
+    fn hist(values: &[(i64, usize)]) -> BTreeMap<i64, usize> {
+        values.iter().copied().collect()
+    }
+
+    fn buckets(bins: &[(i64, i64, usize)]) -> Vec<HistogramBucket> {
+        bins.iter()
+            .map(|&(lower, upper, count)| HistogramBucket {
+                lower,
+                upper,
+                count,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn histogram_modes_bin_the_exact_values() {
+        let exact = hist(&[(0, 2), (1, 1), (3, 4), (4, 1), (9, 2)]);
+        assert_eq!(
+            HistogramMode::Log2.bucket(&exact),
+            buckets(&[(0, 1, 2), (1, 2, 1), (2, 4, 4), (4, 8, 1), (8, 16, 2)])
+        );
+        assert_eq!(
+            HistogramMode::Linear(5).bucket(&exact),
+            buckets(&[(0, 5, 8), (5, 10, 2)])
+        );
+        assert_eq!(HistogramMode::Exact.bucket_of(7), (7, 8));
+
+        // the exact histogram is only kept in exact mode
+        let mut kept = exact.clone();
+        let mut bins = Vec::new();
+        apply_histogram_mode(HistogramMode::Exact, &mut kept, &mut bins);
+        assert_eq!((kept, bins.is_empty()), (exact.clone(), true));
+        let mut moved = exact;
+        apply_histogram_mode(HistogramMode::Linear(5), &mut moved, &mut bins);
+        assert!(moved.is_empty());
+        assert_eq!(bins.iter().map(|b| b.count).sum::<usize>(), 10);
+        assert_eq!(HistogramMode::Linear(5).to_string(), "linear:5");
+    }
+
+    #[test]
+    fn bar_chart_widths_scale_with_the_counts() {
+        let rows = [("a", 10), ("bb", 5), ("c", 1), ("d", 0)]
+            .map(|(label, count)| (label.to_string(), count));
+        let bar = |n| "█".repeat(n);
+        assert_eq!(
+            bar_chart_md(&rows, 20),
+            format!(
+                "```text\na  | {} 10\nbb | {} 5\nc  | {} 1\nd  | 0\n```\n",
+                bar(20),
+                bar(10),
+                // rounded up, a non-zero count is never invisible
+                bar(2)
+            )
+        );
+        assert_eq!(
+            bar_chart_md(&rows[..2], 4),
+            format!("```text\na  | {} 10\nbb | {} 5\n```\n", bar(4), bar(2))
+        );
+        // nothing to draw
+        assert_eq!(bar_chart_md(&rows[3..], 20), "");
+        assert_eq!(bar_chart_md(&rows, 0), "");
+        assert_eq!(bar_chart_md(&[], 20), "");
+    }
+
+    #[test]
+    fn histogram_summary_computes_the_percentiles() {
+        assert_eq!(histogram_summary(&BTreeMap::new()), None);
+        let (avg, [p50, p90, p95, p99]) =
+            histogram_summary(&hist(&[(1, 5), (2, 3), (10, 2)])).unwrap();
+        assert_eq!(avg, 3.1);
+        assert_eq!((p50, p90, p95, p99), (2.0, 10.0, 10.0, 10.0));
+    }
+
+    fn shard(subject: &str, path_constraints: &[(i64, usize)]) -> GlobalStats {
+        let callers = path_constraints.iter().map(|(_, count)| count).sum();
+        let histogram = hist(path_constraints);
+        let function = FunctionStats {
+            function_file: "callers-foo.json".to_string(),
+            total_callers: callers,
+            unique_call_paths: callers,
+            path_constraints_min: histogram.keys().next().copied(),
+            path_constraints_max: histogram.keys().next_back().copied(),
+            path_constraints_histogram: histogram.clone(),
+            ..Default::default()
+        };
+        GlobalStats {
+            cve_id: "CVE-0000-0001".to_string(),
+            total_subjects: 1,
+            total_function_result_files: 1,
+            total_callers: callers,
+            path_constraints_histogram: histogram,
+            functions: BTreeMap::from([("foo::f".to_string(), function)]),
+            subjects: vec![SubjectStats {
+                subject: subject.to_string(),
+                total_callers: callers,
+                per_function_callers: BTreeMap::from([("foo::f".to_string(), callers)]),
+                ..Default::default()
+            }],
+            propagation_by_depth: vec![DepthWidth {
+                depth: 1,
+                processed: 2,
+                with_callers: 1,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge_sums_the_shards_and_recomputes_the_summaries() {
+        let mut merged = shard("a-1.0.0", &[(1, 5), (2, 3)]);
+        merged.merge(shard("b-1.0.0", &[(10, 2)]));
+        merged.merge(shard("a-1.0.0", &[(2, 1)]));
+
+        assert_eq!(merged.total_subjects, 3);
+        assert_eq!(merged.total_callers, 11);
+        assert_eq!(
+            merged.path_constraints_histogram,
+            hist(&[(1, 5), (2, 4), (10, 2)])
+        );
+        // the same subject in two shards is one entry
+        let subjects = merged
+            .subjects
+            .iter()
+            .map(|s| (s.subject.as_str(), s.total_callers))
+            .collect::<Vec<_>>();
+        assert_eq!(subjects, [("a-1.0.0", 9), ("b-1.0.0", 2)]);
+        assert_eq!(
+            merged.top_subjects_by_callers[0],
+            ("a-1.0.0".to_string(), 9)
+        );
+
+        let function = &merged.functions["foo::f"];
+        assert_eq!(function.total_callers, 11);
+        assert_eq!(
+            (function.path_constraints_min, function.path_constraints_max),
+            (Some(1), Some(10))
+        );
+        assert_eq!(function.path_constraints_avg, 3.0);
+        assert_eq!(function.path_constraints_p50, Some(2.0));
+        assert_eq!(function.path_constraints_p99, Some(10.0));
+
+        assert_eq!(
+            merged.propagation_by_depth,
+            [DepthWidth {
+                depth: 1,
+                processed: 6,
+                with_callers: 3,
+            }]
+        );
+    }
+
+    #[test]
+    fn collapse_subjects_keeps_the_highest_callers_over_the_versions() {
+        let subject = |subject: &str, per_function: &[(&str, usize)]| SubjectStats {
+            subject: subject.to_string(),
+            total_callers: per_function.iter().map(|(_, count)| count).sum(),
+            per_function_callers: per_function
+                .iter()
+                .map(|(func, count)| (func.to_string(), *count))
+                .collect(),
+            ..Default::default()
+        };
+        let crates = collapse_subjects(&[
+            subject("baz-0.1.0", &[("foo::f", 1)]),
+            subject("foo-bar-1.10.0", &[("foo::f", 2)]),
+            subject("foo-bar-1.9.0", &[("foo::f", 1), ("foo::g", 2)]),
+        ]);
+        let summary = crates
+            .iter()
+            .map(|c| (c.crate_name.as_str(), c.versions.clone(), c.total_callers))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "foo-bar",
+                    vec!["1.9.0".to_string(), "1.10.0".to_string()],
+                    3
+                ),
+                ("baz", vec!["0.1.0".to_string()], 1),
+            ]
+        );
+        assert_eq!(
+            crates[0].per_function_callers,
+            BTreeMap::from([("foo::f".to_string(), 2), ("foo::g".to_string(), 2)])
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_result_files_are_reloaded_when_a_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a-1.0.0.txt");
+        let write = |caller: &str| {
+            let result = serde_json::json!([{"file": "callers-f.json", "file-content":
+                {"target": "vuln::f", "callers": [{"path": caller}]}}]);
+            std::fs::write(&path, result.to_string()).unwrap();
+        };
+        let callers = |files: &ResultFiles| {
+            files
+                .iter()
+                .map(|(subject, files)| {
+                    let caller = &files[0]["file-content"]["callers"][0]["path"];
+                    format!("{}:{}", subject, caller.as_str().unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+        write("a::one");
+        let first = load_result_files(dir.path()).await.unwrap();
+        assert_eq!(callers(&first), ["a-1.0.0:a::one"]);
+        // unchanged: the parsed files are shared
+        let cached = load_result_files(dir.path()).await.unwrap();
+        assert!(Arc::ptr_eq(&first, &cached));
+
+        // the same size, a later mtime
+        write("a::two");
+        let modified = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let reloaded = load_result_files(dir.path()).await.unwrap();
+        assert!(!Arc::ptr_eq(&cached, &reloaded));
+        assert_eq!(callers(&reloaded), ["a-1.0.0:a::two"]);
+
+        // another size, the same mtime
+        write("a::three");
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let resized = load_result_files(dir.path()).await.unwrap();
+        assert_eq!(callers(&resized), ["a-1.0.0:a::three"]);
+
+        // a new file
+        std::fs::copy(&path, dir.path().join("b-1.0.0.txt")).unwrap();
+        let added = load_result_files(dir.path()).await.unwrap();
+        assert_eq!(callers(&added), ["a-1.0.0:a::three", "b-1.0.0:a::three"]);
+        assert!(Arc::ptr_eq(
+            &added,
+            &load_result_files(dir.path()).await.unwrap()
+        ));
+    }
+
+    /// a crate processed by the BFS, reached from `parent`
+    fn tree_entry(subject: &str, parent: Option<&str>) -> crate::manifest::BfsTreeEntry {
+        let (name, version) = utils::split_subject(subject).unwrap();
+        crate::manifest::BfsTreeEntry {
+            name: name.to_string(),
+            version: version.to_string(),
+            parent: parent.map(str::to_string),
+            dependency_kind: parent.map(|_| "normal"),
+        }
+    }
+
+    /// two root versions: vuln-1.0.0 -> {a, b}, a -> c -> d, and vuln-2.0.0 -> a-2.0.0
+    fn synthetic_forest() -> Vec<BfsTreeNode> {
+        let entries = [
+            tree_entry("vuln-1.0.0", None),
+            tree_entry("vuln-2.0.0", None),
+            tree_entry("b-1.0.0", Some("vuln-1.0.0")),
+            tree_entry("a-1.0.0", Some("vuln-1.0.0")),
+            tree_entry("c-1.0.0", Some("a-1.0.0")),
+            tree_entry("d-1.0.0", Some("c-1.0.0")),
+            tree_entry("a-2.0.0", Some("vuln-2.0.0")),
+            // reached from a crate that was never recorded
+            tree_entry("x-1.0.0", Some("gone-1.0.0")),
+        ];
+        let callers = HashMap::from([
+            ("a-1.0.0".to_string(), 2),
+            ("d-1.0.0".to_string(), 1),
+            ("a-2.0.0".to_string(), 3),
+        ]);
+        BfsTreeNode::build_forest(&entries, &callers)
+    }
+
+    #[test]
+    fn propagation_by_depth_counts_the_crates_of_the_rebuilt_tree() {
+        let forest = synthetic_forest();
+        fn show(node: &BfsTreeNode) -> String {
+            let children = node.children.iter().map(show).collect::<Vec<_>>();
+            format!(
+                "{}-{}@{}:{}[{}]",
+                node.crate_name,
+                node.version,
+                node.depth,
+                node.callers_found,
+                children.join(" ")
+            )
+        }
+        assert_eq!(
+            forest.iter().map(show).collect::<Vec<_>>(),
+            [
+                "vuln-1.0.0@0:0[a-1.0.0@1:2[c-1.0.0@2:0[d-1.0.0@3:1[]]] b-1.0.0@1:0[]]",
+                "vuln-2.0.0@0:0[a-2.0.0@1:3[]]",
+            ]
+        );
+
+        let width = |depth, processed, with_callers| DepthWidth {
+            depth,
+            processed,
+            with_callers,
+        };
+        assert_eq!(
+            propagation_by_depth(&forest),
+            [
+                width(0, 2, 0),
+                width(1, 3, 2),
+                width(2, 1, 0),
+                width(3, 1, 1)
+            ]
+        );
+        assert!(propagation_by_depth(&[]).is_empty());
+    }
+
+    #[test]
+    fn callers_are_summed_at_the_depth_of_their_subject() {
+        let subject = |subject: &str, total_callers| SubjectStats {
+            subject: subject.to_string(),
+            total_callers,
+            ..Default::default()
+        };
+        let subjects = [
+            subject("a-1.0.0", 2),
+            subject("a-2.0.0", 3),
+            subject("b-1.0.0", 0),
+            subject("d-1.0.0", 1),
+            subject("__self__-1.0.0", 4),
+            // dropped from the rebuilt tree
+            subject("x-1.0.0", 5),
+        ];
+        let depth = |depth, callers, subjects| DepthCallers {
+            depth,
+            callers,
+            subjects,
+        };
+        assert_eq!(
+            callers_by_depth(&synthetic_forest(), &subjects),
+            [depth(0, 4, 1), depth(1, 5, 2), depth(3, 1, 1)]
+        );
+        assert!(callers_by_depth(&[], &subjects[..4]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn changing_a_baseline_value_reports_a_mismatch() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        install_fixture_results(sandbox, "FIXTURE-0001").await;
+        let baseline =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/stats/stats-FIXTURE-0001.json");
+        assert!(
+            verify_against_baseline("FIXTURE-0001", &baseline)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let mut changed: Value =
+            serde_json::from_str(&std::fs::read_to_string(&baseline).unwrap()).unwrap();
+        changed["total_callers"] = Value::from(7);
+        let dir = tempfile::tempdir().unwrap();
+        let changed_path = dir.path().join("stats-FIXTURE-0001.json");
+        std::fs::write(&changed_path, changed.to_string()).unwrap();
+        let mismatches = verify_against_baseline("FIXTURE-0001", &changed_path)
+            .await
+            .unwrap();
+        assert_eq!(
+            mismatches,
+            vec![BaselineMismatch {
+                path: "total_callers".to_string(),
+                expected: Value::from(7),
+                actual: Value::from(6),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_heatmap_counts_the_callers_of_each_bin() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        let dir = sandbox.results_dir("CVE-T619");
+        std::fs::create_dir_all(&dir).unwrap();
+        // (path_package_num, path_constraints) of the callers
+        let caller = |path: &str, pkg: i64, pc: i64| serde_json::json!({"path": path, "path_constraints": pc, "path_package_num": pkg});
+        let result = serde_json::json!([
+            {"file": "callers-f.json", "file-content": {"target": "vuln::f", "callers": [
+                caller("a::one", 1, 2),
+                caller("a::two", 1, 2),
+                caller("a::three", 3, 0),
+            ]}},
+            {"file": "callers-g.json", "file-content": {"target": "vuln::g", "callers": [
+                caller("a::four", 1, 5),
+                // without a package count the caller is not binned
+                serde_json::json!({"path": "a::five", "path_constraints": 1}),
+            ]}},
+        ]);
+        std::fs::write(dir.join("a-1.0.0.txt"), result.to_string()).unwrap();
+
+        let (_, heatmaps) = compute("CVE-T619").await.unwrap();
+        assert_eq!(
+            heatmap_csv(&heatmaps.global),
+            "package_hops\\path_constraints,0,2,5\n1,0,2,1\n3,1,0,0\n"
+        );
+        assert_eq!(
+            heatmap_csv(&heatmaps.per_function["vuln::f"]),
+            "package_hops\\path_constraints,0,2\n1,0,2\n3,1,0\n"
+        );
+        assert_eq!(
+            heatmap_csv(&heatmaps.per_function["vuln::g"]),
+            "package_hops\\path_constraints,5\n1,1\n"
+        );
+    }
+
+    /// copy the result files of fixtures/stats/FIXTURE-0001 to the results
+    /// directory of `cve_id` in the sandbox
+    async fn install_fixture_results(sandbox: &crate::test_support::Sandbox, cve_id: &str) {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/stats/FIXTURE-0001");
+        crate::utils::copy_dir(&fixture, &sandbox.results_dir(cve_id), true)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn load_callers_lists_every_caller_of_the_result_files() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        install_fixture_results(sandbox, "FIXTURE-0001").await;
+        let mut callers = load_callers("FIXTURE-0001")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|caller| {
+                (
+                    caller.subject,
+                    caller.function,
+                    caller.caller_path,
+                    caller.path_constraints,
+                    caller.path_package_num,
+                )
+            })
+            .collect::<Vec<_>>();
+        callers.sort();
+        let caller = |subject: &str, function: &str, path: &str, pc: i64, pkg: i64| {
+            (
+                subject.to_string(),
+                function.to_string(),
+                path.to_string(),
+                Some(pc),
+                Some(pkg),
+            )
+        };
+        assert_eq!(
+            callers,
+            [
+                caller(
+                    "baz-0.3.4+build.7",
+                    "vuln::hash::hasher",
+                    "baz::lib::hash_all",
+                    8,
+                    4
+                ),
+                caller("foo-1.0.0", "vuln::hash::bytes", "foo::main", 3, 2),
+                caller(
+                    "foo-1.0.0",
+                    "vuln::hash::bytes",
+                    "foo::util::<u8>::digest",
+                    1,
+                    1
+                ),
+                caller(
+                    "foo-bar-2.1.0-rc.1",
+                    "vuln::hash::bytes",
+                    "foo_bar::run",
+                    5,
+                    3
+                ),
+                caller(
+                    "foo-bar-2.1.0-rc.1",
+                    "vuln::hash::hasher",
+                    "foo_bar::init",
+                    0,
+                    1
+                ),
+                caller(
+                    "foo-bar-2.1.0-rc.1",
+                    "vuln::hash::hasher",
+                    "foo_bar::init::h0123456789abcdef",
+                    2,
+                    2
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn function_detail_lists_only_the_callers_of_the_function() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        install_fixture_results(sandbox, "FIXTURE-0001").await;
+
+        let detail = function_detail("FIXTURE-0001", "vuln::hash::hasher")
+            .await
+            .unwrap();
+        assert_eq!(detail.total_callers, 3);
+        assert_eq!(detail.subjects, ["baz-0.3.4+build.7", "foo-bar-2.1.0-rc.1"]);
+        assert_eq!(
+            render_function_detail("FIXTURE-0001", &detail),
+            "# vuln::hash::hasher in FIXTURE-0001\n\n\
+             - Total callers: 3\n\
+             - Subjects: 2\n\
+             \n## Callers\n\n\
+             - [baz-0.3.4+build.7] baz::lib::hash_all (pc=Some(8), pkg=Some(4))\n\
+             - [foo-bar-2.1.0-rc.1] foo_bar::init::h0123456789abcdef (pc=Some(2), pkg=Some(2))\n\
+             - [foo-bar-2.1.0-rc.1] foo_bar::init (pc=Some(0), pkg=Some(1))\n"
+        );
+
+        let bytes = function_detail("FIXTURE-0001", "vuln::hash::bytes")
+            .await
+            .unwrap();
+        assert_eq!(bytes.subjects, ["foo-1.0.0", "foo-bar-2.1.0-rc.1"]);
+        let paths = bytes
+            .callers
+            .iter()
+            .map(|c| c.caller_path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            ["foo_bar::run", "foo::main", "foo::util::<u8>::digest"]
+        );
+
+        // a function without callers gives an empty report
+        let none = function_detail("FIXTURE-0001", "vuln::hash::unused")
+            .await
+            .unwrap();
+        assert_eq!((none.total_callers, none.subjects.len()), (0, 0));
+        assert_eq!(
+            render_function_detail("FIXTURE-0001", &none),
+            "# vuln::hash::unused in FIXTURE-0001\n\nNo callers of this function were found.\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_run_metadata_round_trips_into_the_stats() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        install_fixture_results(sandbox, "CVE-T658").await;
+        let mut manifest = RunManifest::new("CVE-T658");
+        manifest.crate_name = "vuln".to_string();
+        manifest.version_range = "<1.2.0".to_string();
+        manifest.target_function_paths = "vuln::hash::bytes,vuln::hash::hasher".to_string();
+        manifest.finished_at = Some("2026-01-02T03:04:05+00:00".to_string());
+        manifest.write().await.unwrap();
+
+        let loaded = RunManifest::load("CVE-T658").await.unwrap().unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&manifest).unwrap()
+        );
+
+        let global = compute_stats("CVE-T658").await.unwrap();
+        let info = RunInfo {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            crate_name: "vuln".to_string(),
+            version_range: "<1.2.0".to_string(),
+            target_function_paths: "vuln::hash::bytes,vuln::hash::hasher".to_string(),
+            started_at: manifest.started_at.clone(),
+            finished_at: manifest.finished_at.clone(),
+        };
+        assert_eq!(global.run_info.as_ref(), Some(&info));
+        // and survives the stats file
+        let reread: GlobalStats =
+            serde_json::from_str(&serde_json::to_string(&global).unwrap()).unwrap();
+        assert_eq!(reread.run_info, Some(info));
+        assert!(render_markdown_as(&global, 0).contains(&format!(
+            "- Vulnerable crate: vuln <1.2.0\n\
+             - Target functions: vuln::hash::bytes,vuln::hash::hasher\n\
+             - Analyzed: {} to 2026-01-02T03:04:05+00:00 (cvetracker4rs {})\n",
+            manifest.started_at,
+            env!("CARGO_PKG_VERSION")
+        )));
+    }
+
+    #[tokio::test]
+    async fn a_target_function_without_callers_is_listed_as_unused() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        install_fixture_results(sandbox, "CVE-T635").await;
+        let mut manifest = RunManifest::new("CVE-T635");
+        manifest.target_function_paths =
+            "vuln::hash::bytes, vuln::hash::unused,vuln::hash::hasher".to_string();
+        manifest.write().await.unwrap();
+
+        let global = compute_stats("CVE-T635").await.unwrap();
+        assert_eq!(global.unused_target_functions, ["vuln::hash::unused"]);
+        assert!(
+            render_markdown_as(&global, 0)
+                .contains("\n## Target functions without callers\n\n- vuln::hash::unused\n")
+        );
+    }
+
+    /// the stats of fixtures/stats/FIXTURE-0001: subjects with 3, 2 and 1
+    /// callers, two functions with 3 callers each
+    fn fixture_stats() -> GlobalStats {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/stats/stats-FIXTURE-0001.json");
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn min_callers_elides_entries_but_not_the_totals() {
+        let global = fixture_stats();
+        let all = render_markdown_as(&global, 0);
+        let filtered = render_markdown_as(&global, 3);
+        for total in ["- Total subjects: 3\n", "- Total callers: 6\n"] {
+            assert!(all.contains(total) && filtered.contains(total), "{total}");
+        }
+        assert!(all.contains("- foo-1.0.0: 2\n"));
+        assert!(!filtered.contains("- foo-1.0.0: 2\n"));
+        assert!(filtered.contains("- foo-bar-2.1.0-rc.1: 3\n"));
+        assert!(filtered.contains("- (2 subjects with fewer than 3 callers elided)\n"));
+        assert!(!filtered.contains("functions with fewer than"));
+        let stricter = render_markdown_as(&global, 4);
+        assert!(stricter.contains("- Total callers: 6\n"));
+        assert!(stricter.contains("- (2 functions with fewer than 4 callers elided)\n"));
+    }
+
+    #[tokio::test]
+    async fn callers_are_grouped_by_their_location_or_counted_without_one() {
+        use serde_json::json;
+
+        assert_eq!(
+            caller_location(&json!({"path": "a::f", "location": "src/lib.rs:10:5"})).as_deref(),
+            Some("src/lib.rs")
+        );
+        assert_eq!(
+            caller_location(&json!({"path": "a::f", "span": "src/io.rs"})).as_deref(),
+            Some("src/io.rs")
+        );
+        assert_eq!(
+            caller_location(&json!({"path": "src/main.rs:3: a::main"})).as_deref(),
+            Some("src/main.rs")
+        );
+        // a `.rs` inside a path segment is not a location
+        assert_eq!(caller_location(&json!({"path": "a::rs_util::f"})), None);
+        assert_eq!(caller_location(&json!({"path": "a::f"})), None);
+
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        let dir = sandbox.results_dir("CVE-T669");
+        std::fs::create_dir_all(&dir).unwrap();
+        let result = json!([
+            {"file": "callers-f.json", "file-content": {"target": "vuln::f", "callers": [
+                {"path": "a::one", "location": "src/lib.rs:1:1"},
+                {"path": "a::two", "location": "src/lib.rs:9:1"},
+                {"path": "a::io::three", "file": "src/io.rs"},
+                {"path": "a::four"},
+            ]}},
+            {"file": "callers-g.json", "file-content": {"target": "vuln::g", "callers": [
+                {"path": "a::one", "location": "src/lib.rs:1:1"},
+                {"path": "a::five"},
+            ]}},
+        ]);
+        std::fs::write(dir.join("a-1.0.0.txt"), result.to_string()).unwrap();
+
+        let locations = caller_locations("CVE-T669").await.unwrap();
+        assert_eq!(locations.len(), 1);
+        let a = &locations[0];
+        assert_eq!(a.subject, "a-1.0.0");
+        assert_eq!(
+            a.callers_by_file,
+            BTreeMap::from([
+                ("src/io.rs".to_string(), vec!["a::io::three".to_string()]),
+                (
+                    "src/lib.rs".to_string(),
+                    vec!["a::one".to_string(), "a::two".to_string()]
+                ),
+            ])
+        );
+        assert_eq!(a.without_location, 2);
+    }
+
+    #[tokio::test]
+    async fn only_the_selected_output_formats_are_written() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        let written = async |cve_id: &str, list: &str| {
+            install_fixture_results(sandbox, cve_id).await;
+            let formats = OutputFormats::parse(list).unwrap();
+            compute_and_write_stats_as(cve_id, formats).await.unwrap();
+            let mut names: Vec<String> = std::fs::read_dir(sandbox.results_dir(cve_id))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .filter(|name| !name.ends_with(".txt"))
+                .collect();
+            names.sort();
+            names
+        };
+        let heatmaps = [
+            "heatmap-vuln__hash__bytes.csv",
+            "heatmap-vuln__hash__hasher.csv",
+        ];
+        assert_eq!(
+            written("CVE-T681-JSON", "json").await,
+            [
+                "heatmap-CVE-T681-JSON.csv",
+                heatmaps[0],
+                heatmaps[1],
+                "locations-CVE-T681-JSON.json",
+                "stats-CVE-T681-JSON.json",
+            ]
+        );
+        assert_eq!(written("CVE-T681-MD", "md").await, ["stats-CVE-T681-MD.md"]);
+        // the HTML report is rendered from the stats JSON by `export::html`
+        assert_eq!(
+            written("CVE-T681-HTML", "html").await,
+            ["stats-CVE-T681-HTML.json"]
+        );
+        assert_eq!(
+            written("CVE-T681-ALL", "md,all").await,
+            [
+                "heatmap-CVE-T681-ALL.csv",
+                heatmaps[0],
+                heatmaps[1],
+                "locations-CVE-T681-ALL.json",
+                "stats-CVE-T681-ALL.json",
+                "stats-CVE-T681-ALL.md",
+            ]
+        );
+        assert!(OutputFormats::parse("json,pdf").is_err());
+        assert!(OutputFormats::parse(" , ").is_err());
+    }
+
+    #[tokio::test]
+    async fn old_results_are_pruned_by_age_and_by_count() {
+        const DAY: u64 = 24 * 60 * 60;
+        // (cve id, age in days, whether its run finished)
+        let runs = [
+            ("CVE-NEW-1", 1, true),
+            ("CVE-NEW-2", 2, true),
+            ("CVE-NEW-3", 3, true),
+            ("CVE-RUNNING", 4, false),
+            ("CVE-OLD", 10, true),
+            ("CVE-CRASHED", 30, false),
+        ];
+        let pruned = async |days: Option<u64>, count: Option<usize>| {
+            let results = tempfile::tempdir().unwrap();
+            for (cve_id, age, finished) in runs {
+                let dir = results.path().join(cve_id);
+                std::fs::create_dir_all(&dir).unwrap();
+                let manifest = RunManifest {
+                    finished_at: finished.then(|| "2026-01-01T00:00:00+00:00".to_string()),
+                    ..RunManifest::new(cve_id)
+                };
+                let manifest_path = dir.join(format!("manifest-{}.json", cve_id));
+                std::fs::write(&manifest_path, serde_json::to_string(&manifest).unwrap()).unwrap();
+                let modified = SystemTime::now() - std::time::Duration::from_secs(age * DAY);
+                for path in [&manifest_path, &dir] {
+                    std::fs::File::open(path)
+                        .unwrap()
+                        .set_modified(modified)
+                        .unwrap();
+                }
+            }
+            let mut removed: Vec<String> = prune_old_results_in(results.path(), days, count)
+                .await
+                .unwrap()
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+                .collect();
+            removed.sort();
+            let mut kept: Vec<String> = std::fs::read_dir(results.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            kept.sort();
+            assert_eq!(kept.len() + removed.len(), runs.len());
+            removed
+        };
+
+        assert!(pruned(None, None).await.is_empty());
+        // a run without `finished_at` is kept until it expires
+        assert_eq!(pruned(Some(7), None).await, ["CVE-CRASHED", "CVE-OLD"]);
+        assert_eq!(pruned(None, Some(2)).await, ["CVE-NEW-3", "CVE-OLD"]);
+        assert_eq!(
+            pruned(Some(7), Some(2)).await,
+            ["CVE-CRASHED", "CVE-NEW-3", "CVE-OLD"]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_input_glob_ignores_the_files_it_does_not_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let result =
+            r#"[{"file": "callers-f.json", "file-content": {"target": "vuln::f", "callers": []}}]"#;
+        std::fs::write(dir.path().join("foo-1.0.0.txt"), result).unwrap();
+        crate::utils::write_result_file_as(&dir.path().join("bar-0.2.0.txt"), result, true)
+            .await
+            .unwrap();
+        // a note kept next to the results, with a name that parses as a subject
+        std::fs::write(dir.path().join("foo-1.0.0-notes.txt"), "[]").unwrap();
+        std::fs::write(dir.path().join("README.md"), "notes").unwrap();
+
+        let subjects = async |input_glob: Option<&str>| {
+            let input_glob = input_glob_as(input_glob).unwrap();
+            let mut subjects: Vec<String> = load_result_files_as(dir.path(), input_glob.as_ref())
+                .await
+                .unwrap()
+                .iter()
+                .map(|(subject, _)| subject.clone())
+                .collect();
+            subjects.sort();
+            subjects
+        };
+        assert_eq!(
+            subjects(None).await,
+            ["bar-0.2.0", "foo-1.0.0", "foo-1.0.0-notes"]
+        );
+        assert_eq!(subjects(Some("")).await, subjects(None).await);
+        assert_eq!(
+            subjects(Some("*[0-9].txt*")).await,
+            ["bar-0.2.0", "foo-1.0.0"]
+        );
+        assert!(input_glob_as(Some("[")).is_err());
+    }
+
+    #[tokio::test]
+    async fn the_running_aggregate_ends_equal_to_the_global_stats() {
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        let cve_id = "CVE-T692";
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/stats/FIXTURE-0001");
+        let dir = sandbox.results_dir(cve_id);
+        std::fs::create_dir_all(&dir).unwrap();
+        let copy = |name: &str| {
+            std::fs::copy(fixture.join(name), dir.join(name)).unwrap();
+            dir.join(name)
+        };
+
+        // one result file is there when the run starts, the others are written during it
+        copy("foo-1.0.0.txt");
+        let mut aggregate = RunningAggregate::new(cve_id, std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        for name in ["baz-0.3.4+build.7.txt", "foo-bar-2.1.0-rc.1.txt"] {
+            aggregate.add_result_file(&copy(name)).await.unwrap();
+        }
+        // a result file written again replaces its counts
+        aggregate
+            .add_result_file(&dir.join("foo-1.0.0.txt"))
+            .await
+            .unwrap();
+        aggregate.flush(true).await.unwrap();
+
+        let (global, _) = compute(cve_id).await.unwrap();
+        let expected = RunningStats::from_global(&global);
+        assert_eq!(expected.total_callers, 6);
+        assert_eq!(aggregate.stats(), expected);
+        assert_eq!(RunningStats::load(cve_id).await.unwrap(), Some(expected));
+    }
+
+    /// `functions` functions with `callers` callers each, of pseudo-random
+    /// path constraints and package hops with many ties
+    fn synthetic_function_values(functions: usize, callers: usize) -> Vec<FunctionValues> {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move |bound: i64| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            ((state >> 33) % bound as u64) as i64
+        };
+        (0..functions)
+            .map(|f| {
+                let func_key = format!("vuln::f{f}");
+                let mut values = FunctionValues {
+                    func_key: func_key.clone(),
+                    total_callers: callers,
+                    unique_paths: callers,
+                    path_constraints: Vec::with_capacity(callers),
+                    package_hops: Vec::with_capacity(callers),
+                    path_constraints_histogram: BTreeMap::new(),
+                    package_hops_histogram: BTreeMap::new(),
+                    top_constraints: Vec::with_capacity(callers),
+                    top_package_hops: Vec::with_capacity(callers),
+                };
+                for c in 0..callers {
+                    let (pc, pkg) = (next(64), next(8));
+                    values.path_constraints.push(pc);
+                    values.package_hops.push(pkg);
+                    *values.path_constraints_histogram.entry(pc).or_insert(0) += 1;
+                    *values.package_hops_histogram.entry(pkg).or_insert(0) += 1;
+                    let sample = CallerSample {
+                        subject: format!("d{}-1.0.0", c % 17),
+                        caller_path: format!("d::caller{c}"),
+                        path_constraints: pc,
+                        path_package_num: Some(pkg),
+                    };
+                    values.top_constraints.push(sample.clone());
+                    values.top_package_hops.push(sample);
+                }
+                values
+            })
+            .collect()
+    }
+
+    #[test]
+    fn serial_and_parallel_finalization_give_the_same_stats() {
+        let finalized = |threads: Option<usize>| {
+            let stats = finalize_functions_as(synthetic_function_values(24, 500), threads);
+            assert_eq!(stats.len(), 24);
+            serde_json::to_value(&stats).unwrap()
+        };
+        let serial = finalized(Some(1));
+        assert_eq!(finalized(Some(4)), serial);
+        assert_eq!(finalized(None), serial);
+    }
+
+    /// `cargo test --release --lib finalize_functions_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn finalize_functions_benchmark() {
+        for (functions, callers) in [(2000, 1000), (20, 200_000)] {
+            let mut line = format!("{functions} functions x {callers} callers:");
+            for threads in [Some(1), None] {
+                let values = synthetic_function_values(functions, callers);
+                let started = std::time::Instant::now();
+                let stats = finalize_functions_as(values, threads);
+                let elapsed = started.elapsed();
+                assert_eq!(stats.len(), functions);
+                line.push_str(&format!(" {:?} threads {:?};", threads, elapsed));
+            }
+            println!("{line}");
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! A sandbox for the tests that run the BFS without the network or `call-cg4rs`:
+//! crates are published as `.crate` archives to a local registry directory and
+//! fetched from it through `CRATE_DOWNLOAD_URL=file://...`, the reverse
+//! dependencies come from a [`FileMetadataSource`] and the callers from a
+//! [`MockCallerFinder`]-like finder.
+
+use crate::callgraph::CallgraphFile;
+use crate::metadata::FileMetadataSource;
+use crate::model::Krate;
+use flate2::{Compression, write::GzEncoder};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::sync::{Mutex, MutexGuard};
+
+pub(crate) struct Sandbox {
+    pub root: PathBuf,
+    registry: PathBuf,
+}
+
+/// a `(dependent, version, req)` of the reverse-dependencies file
+pub(crate) type Dependent<'a> = (&'a str, &'a str, &'a str);
+
+static SANDBOX: OnceLock<Sandbox> = OnceLock::new();
+static RUN_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// The sandbox shared by every test, with a guard serializing the runs: the
+/// environment and the run counters are process-wide. Each test uses its own
+/// CVE id, so their working and result directories do not overlap.
+pub(crate) async fn sandbox() -> (&'static Sandbox, MutexGuard<'static, ()>) {
+    let sandbox = SANDBOX.get_or_init(|| {
+        let root = tempfile::tempdir().unwrap().keep();
+        let registry = root.join("registry");
+        std::fs::create_dir_all(&registry).unwrap();
+        // SAFETY: set once, before any test of the sandbox reads them
+        unsafe {
+            std::env::set_var("DOWNLOAD_DIR", root.join("downloads"));
+            std::env::set_var("WORKING_DIR", root.join("working"));
+            std::env::set_var("ANALYSIS_RESULTS_DIR", root.join("results"));
+            std::env::set_var(
+                "CRATE_DOWNLOAD_URL",
+                format!("file://{}/{{crate}}-{{version}}.crate", registry.display()),
+            );
+        }
+        Sandbox { root, registry }
+    });
+    (sandbox, RUN_LOCK.lock().await)
+}
+
+impl Sandbox {
+    /// Publish `name` `version` with a `src/lib.rs` of `lib` and the
+    /// `(name, requirement)` dependencies
+    pub fn publish(&self, name: &str, version: &str, deps: &[(&str, &str)], lib: &str) {
+        let mut manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2021\"\n\n[dependencies]\n",
+            name, version
+        );
+        for (dep, req) in deps {
+            manifest.push_str(&format!("{} = \"{}\"\n", dep, req));
+        }
+        self.publish_files(
+            name,
+            version,
+            &[("Cargo.toml", manifest.as_str()), ("src/lib.rs", lib)],
+        );
+    }
+
+    /// Publish `name` `version` with the given `(path, content)` files
+    pub fn publish_files(&self, name: &str, version: &str, files: &[(&str, &str)]) {
+        let mut tar = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(
+                &mut header,
+                format!("{}-{}/{}", name, version, path),
+                content.as_bytes(),
+            )
+            .unwrap();
+        }
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&tar.into_inner().unwrap()).unwrap();
+        std::fs::write(
+            self.registry.join(format!("{}-{}.crate", name, version)),
+            gzip.finish().unwrap(),
+        )
+        .unwrap();
+    }
+
+    /// A metadata source with the `(crate, versions)` and the
+    /// `(crate, [(dependent, version, req)])` of a test
+    pub async fn metadata(
+        &self,
+        cve_id: &str,
+        versions: &[(&str, &[&str])],
+        dependents: &[(&str, &[Dependent<'_>])],
+    ) -> FileMetadataSource {
+        let versions: serde_json::Map<String, serde_json::Value> = versions
+            .iter()
+            .map(|(name, versions)| (name.to_string(), serde_json::json!(versions)))
+            .collect();
+        let dependents: serde_json::Map<String, serde_json::Value> = dependents
+            .iter()
+            .map(|(name, dependents)| {
+                let entries: Vec<serde_json::Value> = dependents
+                    .iter()
+                    .map(|(name, version, req)| {
+                        serde_json::json!({"name": name, "version": version, "req": req})
+                    })
+                    .collect();
+                (name.to_string(), serde_json::Value::from(entries))
+            })
+            .collect();
+        let dir = self.root.join("metadata").join(cve_id);
+        std::fs::create_dir_all(&dir).unwrap();
+        let versions_file = dir.join("versions.json");
+        let dependents_file = dir.join("reverse-deps.json");
+        std::fs::write(&versions_file, serde_json::to_string(&versions).unwrap()).unwrap();
+        std::fs::write(
+            &dependents_file,
+            serde_json::to_string(&dependents).unwrap(),
+        )
+        .unwrap();
+        FileMetadataSource::load(&dependents_file, &versions_file)
+            .await
+            .unwrap()
+    }
+
+    /// analysis_results/<cve_id> of the sandbox
+    pub fn results_dir(&self, cve_id: &str) -> PathBuf {
+        crate::stats::analysis_results_dir().join(cve_id)
+    }
+}
+
+/// A `callers-*.json` of `target` with callers at `paths`
+pub(crate) fn callers_file(target: &str, paths: &[&str]) -> CallgraphFile {
+    let callers: Vec<serde_json::Value> = paths
+        .iter()
+        .map(|path| serde_json::json!({"path": path, "path_constraints": 1, "path_package_num": 1}))
+        .collect();
+    CallgraphFile {
+        file: format!("callers-{}.json", target.replace("::", "_")),
+        file_content: serde_json::json!({"target": target, "callers": callers}),
+    }
+}
+
+/// the subjects with a result file in `dir`
+pub(crate) fn result_subjects(dir: &Path) -> Vec<String> {
+    let mut subjects: Vec<String> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    entry
+                        .file_name()
+                        .to_str()
+                        .and_then(|name| name.strip_suffix(".txt"))
+                        .map(str::to_string)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    subjects.sort();
+    subjects
+}
+
+/// A `<name>-1.0.0` krate already unpacked at `package_dir`
+pub(crate) fn krate_at(name: &str, package_dir: &Path) -> Krate {
+    Krate {
+        name: name.to_string(),
+        version: "1.0.0".to_string(),
+        dir_idx: 0,
+        working_dir: package_dir.to_path_buf(),
+        working_src_code_dir: package_dir.to_path_buf(),
+        package_dir: package_dir.to_path_buf(),
+        download_dir: PathBuf::new(),
+    }
+}
+
+/// Write the `(path, content)` files under `dir`
+pub(crate) fn write_files(dir: &Path, files: &[(&str, &str)]) {
+    for (path, content) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+}
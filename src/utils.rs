@@ -1,38 +1,143 @@
 use anyhow::Context;
-use futures::stream::{self as futures_stream, StreamExt};
+use chrono::NaiveDate;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use futures::stream::{self as futures_stream, BoxStream, StreamExt};
 use semver::{Version, VersionReq};
-use std::{collections::VecDeque, path::Path};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
 use tokio::fs as tokio_fs;
 use tokio::process::Command;
 use toml_edit::DocumentMut;
 
 use crate::{
-    database::Database,
+    callgraph::CallgraphFile,
+    database::{
+        ALL_DEPENDENCY_KINDS, DEPENDENCY_KIND_BUILD, DEPENDENCY_KIND_DEV, DEPENDENCY_KIND_NORMAL,
+    },
+    manifest::CrateInfo,
+    metadata::MetadataSource,
     model::{Krate, ReverseDependency},
 };
 
+/// Which kinds of dependents are followed, configured by `DEPENDENCY_KINDS` as a
+/// comma-separated list of `normal`, `build`, `dev`, or `all`. Default `normal`,
+/// since build- and dev-dependents rarely mean runtime exposure.
+pub(crate) fn dependency_kinds() -> Vec<i32> {
+    dependency_kinds_as(crate::config::var("DEPENDENCY_KINDS").ok().as_deref())
+}
+
+/// [`dependency_kinds`] of a `DEPENDENCY_KINDS` value, `None` if unset
+fn dependency_kinds_as(value: Option<&str>) -> Vec<i32> {
+    let Some(value) = value else {
+        return vec![DEPENDENCY_KIND_NORMAL];
+    };
+    let mut kinds = Vec::new();
+    for kind in value.split(',').map(|k| k.trim()) {
+        match kind {
+            "normal" => kinds.push(DEPENDENCY_KIND_NORMAL),
+            "build" => kinds.push(DEPENDENCY_KIND_BUILD),
+            "dev" => kinds.push(DEPENDENCY_KIND_DEV),
+            "all" => kinds.extend(ALL_DEPENDENCY_KINDS),
+            "" => {}
+            other => tracing::warn!("unknown dependency kind {} in DEPENDENCY_KINDS", other),
+        }
+    }
+    if kinds.is_empty() {
+        kinds.push(DEPENDENCY_KIND_NORMAL);
+    }
+    kinds
+}
+
 /// Get reverse dependencies for a krate in range of its version
 /// every reverse dependency will yield two versions,
 /// one is the oldest version and the other is the newest version
+/// If `since` is set, only dependent versions published on or after that date are considered
+/// If `vulnerable_range` is set, a dependent is kept when its requirement could resolve to
+/// any vulnerable version, rather than only to the precise version of the krate
 pub(crate) async fn get_reverse_deps_for_krate(
-    database: &Database,
+    database: &dyn MetadataSource,
     krate: &Krate,
+    since: Option<NaiveDate>,
+    vulnerable_range: Option<&VulnerableRange>,
 ) -> anyhow::Result<Vec<ReverseDependency>> {
-    let precise_version = &krate.version;
+    let precheck_url = precheck_crate_exists_enabled().then(crate_download_url_template);
+    get_reverse_deps_for_krate_as(
+        database,
+        krate,
+        since,
+        vulnerable_range,
+        precheck_url.as_deref(),
+    )
+    .await
+}
 
-    let reverse_deps = database.query_dependents(&krate.name).await?;
-    let reverse_deps_for_certain_version =
-        filter_dependents_by_version_req(reverse_deps, precise_version).await?;
+/// [`get_reverse_deps_for_krate`] prechecking the dependents against the
+/// download URL template `precheck_url`, or not at all if `None`
+async fn get_reverse_deps_for_krate_as(
+    database: &dyn MetadataSource,
+    krate: &Krate,
+    since: Option<NaiveDate>,
+    vulnerable_range: Option<&VulnerableRange>,
+    precheck_url: Option<&str>,
+) -> anyhow::Result<Vec<ReverseDependency>> {
+    let selected = get_reverse_deps(
+        database,
+        &krate.name,
+        &krate.version,
+        since,
+        vulnerable_range,
+    )
+    .await?;
+    let Some(precheck_url) = precheck_url else {
+        return Ok(selected);
+    };
+    // drop versions that are gone from the registry before they are downloaded and retried
+    let total = selected.len();
+    let existing: Vec<ReverseDependency> = futures_stream::iter(selected)
+        .map(async |revdep| {
+            crate_version_exists(precheck_url, &revdep.name, &revdep.version)
+                .await
+                .then_some(revdep)
+        })
+        .buffered(8)
+        .filter_map(async |revdep| revdep)
+        .collect()
+        .await;
+    if existing.len() < total {
+        tracing::info!(
+            "[{}:{}] {} of {} dependent version(s) are not downloadable, skipped",
+            krate.name,
+            krate.version,
+            total - existing.len(),
+            total
+        );
+    }
+    Ok(existing)
+}
 
-    let mut dependents_map: std::collections::HashMap<String, Vec<ReverseDependency>> =
-        std::collections::HashMap::new();
+/// [`get_reverse_deps_for_krate`] by name and version, without a downloaded krate
+pub(crate) async fn get_reverse_deps(
+    database: &dyn MetadataSource,
+    name: &str,
+    version: &str,
+    since: Option<NaiveDate>,
+    vulnerable_range: Option<&VulnerableRange>,
+) -> anyhow::Result<Vec<ReverseDependency>> {
+    let precise_version = parse_version(version)
+        .with_context(|| format!("{} {} is not a semver version", name, version))?;
 
-    for revdep in reverse_deps_for_certain_version {
-        dependents_map
-            .entry(revdep.name.clone())
-            .or_default()
-            .push(revdep.clone());
-    }
+    // rows are filtered and grouped as they arrive, the full list is never materialized
+    let kinds = dependency_kinds();
+    let mut dependents_map = group_dependents(
+        database.stream_dependents(name, since, &kinds),
+        &precise_version,
+        vulnerable_range,
+    )
+    .await?;
 
     let mut selected_dependents = futures_stream::iter(dependents_map.iter_mut())
         .then(|(_, revdeps)| async move {
@@ -54,23 +159,139 @@ pub(crate) async fn get_reverse_deps_for_krate(
         .flatten()
         .collect::<Vec<_>>();
 
+    // which version of `name` each selected dependent would resolve to today
+    if !selected_dependents.is_empty() {
+        let published: Vec<Version> = database
+            .query_crate_versions(name)
+            .await?
+            .iter()
+            .filter_map(|v| parse_version(v))
+            .collect();
+        for revdep in &mut selected_dependents {
+            revdep.resolved_target_version =
+                resolve_req(&revdep.req, &published).map(|v| v.to_string());
+        }
+    }
+
     selected_dependents.sort();
     Ok(selected_dependents)
 }
 
-pub(crate) async fn filter_dependents_by_version_req(
-    dependents: Vec<ReverseDependency>,
-    precise_version: &str,
-) -> anyhow::Result<Vec<ReverseDependency>> {
-    let precise_version = semver::Version::parse(precise_version)?;
-    Ok(dependents
-        .into_iter()
-        .filter(|dep| {
-            semver::VersionReq::parse(dep.req.as_str())
-                .map(|req| req.matches(&precise_version))
-                .unwrap_or(false)
+/// The dependents of `rows` which may use `precise_version`, or any vulnerable
+/// version if `vulnerable_range` is set, grouped by dependent name
+async fn group_dependents(
+    mut rows: BoxStream<'_, anyhow::Result<ReverseDependency>>,
+    precise_version: &Version,
+    vulnerable_range: Option<&VulnerableRange>,
+) -> anyhow::Result<HashMap<String, Vec<ReverseDependency>>> {
+    let mut dependents_map: HashMap<String, Vec<ReverseDependency>> = HashMap::new();
+    while let Some(revdep) = rows.next().await {
+        let revdep = revdep?;
+        let matched = match vulnerable_range {
+            Some(range) => range.admits(&revdep),
+            None => dependent_matches_version(&revdep, precise_version),
+        };
+        if matched {
+            dependents_map
+                .entry(revdep.name.clone())
+                .or_default()
+                .push(revdep);
+        }
+    }
+    Ok(dependents_map)
+}
+
+/// Parse a crate version. Versions are kept as the strings crates.io publishes,
+/// which are the canonical semver form (`Version`'s `Display`) with pre-release
+/// and build metadata, e.g. `1.2.3-rc.1+build.5`. Result file names
+/// (`<crate>-<version>.txt`), BFS keys and database rows all use that form, and
+/// [`split_subject`] turns a `<crate>-<version>` subject back into its parts.
+pub(crate) fn parse_version(version: &str) -> Option<Version> {
+    Version::parse(version).ok()
+}
+
+/// the canonical form of `version`, or `version` unchanged if it is not semver
+pub(crate) fn normalize_version(version: &str) -> String {
+    parse_version(version)
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| version.to_string())
+}
+
+/// Split a `<crate>-<version>` subject at the first `-` followed by a valid
+/// version, so hyphens in the crate name and in a pre-release both work:
+/// `foo-bar-1.0.0-rc.1` is `("foo-bar", "1.0.0-rc.1")`.
+pub(crate) fn split_subject(subject: &str) -> Option<(&str, &str)> {
+    subject
+        .match_indices('-')
+        .map(|(i, _)| (&subject[..i], &subject[i + 1..]))
+        .find(|(_, version)| parse_version(version).is_some())
+}
+
+/// the newest of `published` matching the requirement `req`, which is what
+/// cargo picks when resolving it without a lockfile
+pub(crate) fn resolve_req<'a>(req: &str, published: &'a [Version]) -> Option<&'a Version> {
+    let req = VersionReq::parse(req).ok()?;
+    published.iter().filter(|v| req.matches(v)).max()
+}
+
+/// whether the dependent's requirement matches the precise version of the krate
+pub(crate) fn dependent_matches_version(
+    dependent: &ReverseDependency,
+    precise_version: &Version,
+) -> bool {
+    semver::VersionReq::parse(dependent.req.as_str())
+        .map(|req| req.matches(precise_version))
+        .unwrap_or(false)
+}
+
+/// the published versions of the vulnerable crate that fall in the CVE's
+/// version range, used to decide whether a requirement overlaps the range
+#[derive(Debug, Clone)]
+pub(crate) struct VulnerableRange {
+    pub versions: Vec<Version>,
+    /// every published version, vulnerable or not
+    pub published: Vec<Version>,
+}
+
+impl VulnerableRange {
+    pub fn new(version_range: &str, published_versions: &[String]) -> anyhow::Result<Self> {
+        let req = VersionReq::parse(version_range)?;
+        let published: Vec<Version> = published_versions
+            .iter()
+            .filter_map(|v| parse_version(v))
+            .collect();
+        let versions = published
+            .iter()
+            .filter(|v| req.matches(v))
+            .cloned()
+            .collect();
+        Ok(Self {
+            versions,
+            published,
         })
-        .collect())
+    }
+
+    /// whether some published vulnerable version satisfies `dep_req`,
+    /// i.e. the intersection of `dep_req` and the vulnerable range is non-empty
+    pub fn overlaps(&self, dep_req: &VersionReq) -> bool {
+        self.versions.iter().any(|v| dep_req.matches(v))
+    }
+
+    /// whether the dependent's requirement could resolve to any vulnerable version,
+    /// e.g. `^0.40` overlaps `<0.41.0` even if the oldest vulnerable version is `0.1.0`
+    pub fn admits(&self, dependent: &ReverseDependency) -> bool {
+        VersionReq::parse(dependent.req.as_str())
+            .map(|req| self.overlaps(&req))
+            .unwrap_or(false)
+    }
+
+    /// whether the newest published version satisfying the dependent's requirement
+    /// is vulnerable, i.e. resolving the dependent today would still pull a
+    /// vulnerable version, e.g. `^0.40` does not if `0.40.1` is fixed
+    pub fn latest_resolution_vulnerable(&self, dependent: &ReverseDependency) -> bool {
+        resolve_req(&dependent.req, &self.published)
+            .is_some_and(|latest| self.versions.contains(latest))
+    }
 }
 
 pub(crate) async fn select_two_end_vers(
@@ -78,14 +299,47 @@ pub(crate) async fn select_two_end_vers(
     version_range: &str,
 ) -> Vec<(usize, semver::Version)> {
     let filtered_versions = filter_versions_by_version_range(versions, version_range).await;
+    // an exact requirement such as `=0.40.3` names the one version to analyze,
+    // whatever VERSION_ENDPOINTS is
+    if let Some(exact) = exact_version(version_range) {
+        let selected: Vec<(usize, semver::Version)> = filtered_versions
+            .into_iter()
+            .enumerate()
+            .filter(|(_, v)| v.cmp_precedence(&exact).is_eq())
+            .take(1)
+            .collect();
+        if selected.is_empty() {
+            tracing::warn!("version {} is not published", exact);
+        }
+        return selected;
+    }
     let (oldest_version, newest_version) =
-        select_oldest_and_newest_versions(filtered_versions).await;
+        select_oldest_and_newest_versions(filtered_versions, VersionEndpoints::from_env()).await;
     vec![oldest_version, newest_version]
         .into_iter()
         .flatten()
         .collect::<Vec<_>>()
 }
 
+/// the version of an exact requirement like `=1.2.3`, `None` for a range or
+/// a partial one like `=1.2`
+fn exact_version(version_range: &str) -> Option<semver::Version> {
+    let req = VersionReq::parse(version_range).ok()?;
+    let [comparator] = req.comparators.as_slice() else {
+        return None;
+    };
+    if comparator.op != semver::Op::Exact {
+        return None;
+    }
+    Some(semver::Version {
+        major: comparator.major,
+        minor: comparator.minor?,
+        patch: comparator.patch?,
+        pre: comparator.pre.clone(),
+        build: semver::BuildMetadata::EMPTY,
+    })
+}
+
 async fn filter_versions_by_version_range(
     versions: Vec<String>,
     version_range: &str,
@@ -94,7 +348,7 @@ async fn filter_versions_by_version_range(
     versions
         .into_iter()
         .filter_map(|version| {
-            let parsed_version = Version::parse(&version).ok()?;
+            let parsed_version = parse_version(&version)?;
             version_req
                 .matches(&parsed_version)
                 .then_some(parsed_version)
@@ -102,8 +356,32 @@ async fn filter_versions_by_version_range(
         .collect::<Vec<_>>()
 }
 
+/// which end(s) of a version list are analyzed, configured by
+/// `VERSION_ENDPOINTS={oldest,newest,both}` (default `both`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VersionEndpoints {
+    Oldest,
+    Newest,
+    Both,
+}
+
+impl VersionEndpoints {
+    pub fn from_env() -> Self {
+        match crate::config::var("VERSION_ENDPOINTS").as_deref() {
+            Ok("oldest") => Self::Oldest,
+            Ok("newest") => Self::Newest,
+            Ok("both") | Err(_) => Self::Both,
+            Ok(other) => {
+                tracing::warn!("unknown VERSION_ENDPOINTS={}, use both", other);
+                Self::Both
+            }
+        }
+    }
+}
+
 async fn select_oldest_and_newest_versions(
     versions: Vec<semver::Version>,
+    endpoints: VersionEndpoints,
 ) -> (
     Option<(usize, semver::Version)>,
     Option<(usize, semver::Version)>,
@@ -117,13 +395,20 @@ async fn select_oldest_and_newest_versions(
 
     let mut result = (None, None);
 
-    if let Some(oldest) = versions_with_index.first() {
-        result.0 = Some(oldest.clone());
-    }
+    match endpoints {
+        VersionEndpoints::Oldest => result.0 = versions_with_index.first().cloned(),
+        // a single version is both the oldest and the newest
+        VersionEndpoints::Newest => result.1 = versions_with_index.last().cloned(),
+        VersionEndpoints::Both => {
+            if let Some(oldest) = versions_with_index.first() {
+                result.0 = Some(oldest.clone());
+            }
 
-    if versions_with_index.len() > 1 {
-        if let Some(newest) = versions_with_index.last() {
-            result.1 = Some(newest.clone());
+            if versions_with_index.len() > 1
+                && let Some(newest) = versions_with_index.last()
+            {
+                result.1 = Some(newest.clone());
+            }
         }
     }
 
@@ -136,6 +421,83 @@ async fn select_oldest_and_newest_versions(
     result
 }
 
+/// Resolve after `RUN_TIMEOUT_SECS` seconds with the configured timeout,
+/// never resolve if it is not set.
+pub(crate) async fn run_timeout() -> u64 {
+    match crate::config::var("RUN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+    {
+        Some(secs) => {
+            tokio::time::sleep(std::time::Duration::from_secs(secs)).await;
+            secs
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Periodically check the resident memory of this process and resolve with
+/// `(limit_mb, rss_mb)` once it exceeds `RUN_MEMORY_MB`. Never resolve if the
+/// limit is not set or the platform has no `/proc/self/status` (Linux only).
+pub(crate) async fn memory_watchdog() -> (u64, u64) {
+    let Some(limit_mb) = crate::config::var("RUN_MEMORY_MB")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+    else {
+        return std::future::pending().await;
+    };
+    loop {
+        let Some(rss_mb) = current_rss_mb().await else {
+            tracing::warn!("RUN_MEMORY_MB is set but /proc/self/status is unavailable, ignore it");
+            return std::future::pending().await;
+        };
+        if rss_mb > limit_mb {
+            return (limit_mb, rss_mb);
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    }
+}
+
+/// resident set size of the current process in MB, read from `/proc/self/status`
+async fn current_rss_mb() -> Option<u64> {
+    let status = tokio_fs::read_to_string("/proc/self/status").await.ok()?;
+    let rss_kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(rss_kb / 1024)
+}
+
+/// Deterministically select `cap` nodes of a BFS level. Nodes are ranked by a
+/// FNV-1a hash of the seed and their key, so the same seed always selects the
+/// same nodes regardless of the order they were discovered in.
+pub(crate) fn sample_bfs_level<T>(
+    level: Vec<T>,
+    cap: usize,
+    seed: u64,
+    key: impl Fn(&T) -> String,
+) -> Vec<T> {
+    let fnv1a = |key: &str| {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in seed.to_le_bytes().iter().chain(key.as_bytes()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    };
+    let mut ranked = level
+        .into_iter()
+        .map(|node| (fnv1a(&key(&node)), node))
+        .collect::<Vec<_>>();
+    ranked.sort_by_key(|(hash, _)| *hash);
+    ranked.truncate(cap);
+    ranked.into_iter().map(|(_, node)| node).collect()
+}
+
 pub(crate) async fn pop_bfs_level<T>(queue: &mut VecDeque<T>) -> Vec<T> {
     let current_level: Vec<_> = queue.drain(..).collect();
     tracing::info!("BFS pop a level, {} nodes", current_level.len());
@@ -257,10 +619,7 @@ pub async fn vendor_and_patch_dep(
 
         // Download to a local archive inside vendor_root
         let archive_path = vendor_root.join(format!("{}-{}.crate", dep_name, dep_version));
-        let download_url = format!(
-            "https://crates.io/api/v1/crates/{}/{}/download",
-            dep_name, dep_version
-        );
+        let download_url = crate_download_url(dep_name, dep_version);
 
         tracing::info!(
             "Vendoring {}:{} -> {}",
@@ -273,6 +632,7 @@ pub async fn vendor_and_patch_dep(
         if !archive_path.exists() {
             let output = Command::new("curl")
                 .args(["-fL", &download_url, "-o", &archive_path.to_string_lossy()])
+                .args(crate_download_curl_args())
                 .output()
                 .await
                 .context("Failed to execute curl for vendoring")?;
@@ -288,24 +648,7 @@ pub async fn vendor_and_patch_dep(
         }
 
         // Extract into vendor_root (archive contains <name>-<version>/)
-        let output = Command::new("tar")
-            .args([
-                "-xzf",
-                &archive_path.to_string_lossy(),
-                "-C",
-                &vendor_root.to_string_lossy(),
-            ])
-            .output()
-            .await
-            .context("Failed to execute tar for vendoring")?;
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!(
-                "tar failed extracting {}: {}",
-                archive_path.display(),
-                stderr
-            ));
-        }
+        extract_crate_archive(&archive_path, &vendor_root).await?;
 
         // Basic validation
         if !vendor_cargo.exists() {
@@ -365,6 +708,482 @@ pub async fn vendor_and_patch_dep(
     Ok(original_content)
 }
 
+/// The User-Agent sent to crates.io, which asks crawlers to identify themselves.
+/// Set `CRATESIO_USER_AGENT` to include a contact, e.g. `cvetracker4rs (me@example.com)`.
+pub(crate) fn crates_io_user_agent() -> String {
+    crate::config::var("CRATESIO_USER_AGENT").unwrap_or_else(|_| {
+        format!(
+            "cvetracker4rs/{} (+https://github.com/xizheyin/cvetracker4rs)",
+            env!("CARGO_PKG_VERSION")
+        )
+    })
+}
+
+/// The URL a crate archive is downloaded from, `CRATE_DOWNLOAD_URL` with
+/// `{crate}` and `{version}` replaced (default crates.io), so crates can be
+/// fetched from a private registry.
+pub(crate) fn crate_download_url(name: &str, version: &str) -> String {
+    crate_download_url_as(&crate_download_url_template(), name, version)
+}
+
+fn crate_download_url_template() -> String {
+    crate::config::var("CRATE_DOWNLOAD_URL").unwrap_or_else(|_| {
+        "https://crates.io/api/v1/crates/{crate}/{version}/download".to_string()
+    })
+}
+
+fn crate_download_url_as(template: &str, name: &str, version: &str) -> String {
+    template
+        .replace("{crate}", name)
+        .replace("{version}", version)
+}
+
+/// The curl arguments every request to the registry sends: the User-Agent
+fn curl_user_agent_args() -> Vec<String> {
+    vec!["-A".to_string(), crates_io_user_agent()]
+}
+
+/// Extra curl arguments for downloading crate archives: the User-Agent and,
+/// if `CRATE_DOWNLOAD_TOKEN` is set, the `Authorization` header a private
+/// registry expects (the token is sent as is, like cargo does). Only the
+/// archive downloads send the token, not the index or HEAD requests.
+pub(crate) fn crate_download_curl_args() -> Vec<String> {
+    crate_download_curl_args_as(crate::config::var("CRATE_DOWNLOAD_TOKEN").ok())
+}
+
+fn crate_download_curl_args_as(token: Option<String>) -> Vec<String> {
+    let mut args = curl_user_agent_args();
+    if let Some(token) = token.filter(|t| !t.is_empty()) {
+        args.push("-H".to_string());
+        args.push(format!("Authorization: {}", token));
+    }
+    args
+}
+
+/// The largest crate archive that is downloaded and analyzed, in bytes,
+/// configured by `MAX_CRATE_SIZE_MB` (unset or 0: no limit)
+pub(crate) fn max_crate_size_bytes() -> Option<u64> {
+    crate::config::var("MAX_CRATE_SIZE_MB")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|mb| *mb > 0)
+        .map(|mb| mb * 1024 * 1024)
+}
+
+/// whether the selected dependent versions are checked to be downloadable
+/// before they are enqueued, controlled by `PRECHECK_CRATE_EXISTS=1`
+pub(crate) fn precheck_crate_exists_enabled() -> bool {
+    crate::config::var("PRECHECK_CRATE_EXISTS")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// results of [`crate_version_exists`] by `(name, version)`, so a missing
+/// version reached from several parents is requested once
+static CRATE_EXISTS_CACHE: once_cell::sync::Lazy<
+    std::sync::Mutex<std::collections::HashMap<(String, String), bool>>,
+> = once_cell::sync::Lazy::new(Default::default);
+
+/// Whether `name`-`version` can be downloaded: it is already in `DOWNLOAD_DIR`,
+/// or a HEAD request to its URL from the download URL `template` is not
+/// answered with 404, 410 or 403 (what S3-backed registries answer for a
+/// missing file). Network errors count as existing, the download reports them.
+async fn crate_version_exists(template: &str, name: &str, version: &str) -> bool {
+    let key = (name.to_string(), version.to_string());
+    if let Some(exists) = CRATE_EXISTS_CACHE.lock().unwrap().get(&key) {
+        return *exists;
+    }
+    let cached = crate::config::current()
+        .download_dir()
+        .join(name)
+        .join(format!("{}-{}.crate", name, version));
+    if tokio_fs::metadata(&cached).await.is_ok() {
+        return true;
+    }
+
+    let status = Command::new("curl")
+        .args(["-sIL", "-o", "/dev/null", "-w", "%{http_code}"])
+        .arg(crate_download_url_as(template, name, version))
+        .args(curl_user_agent_args())
+        .output()
+        .await
+        .ok()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    let exists = !matches!(status.as_deref(), Some("404" | "410" | "403"));
+    if !exists {
+        tracing::debug!("{}-{} is not downloadable ({:?})", name, version, status);
+    }
+    CRATE_EXISTS_CACHE.lock().unwrap().insert(key, exists);
+    exists
+}
+
+/// The `Content-Length` of `url` after redirects, from a HEAD request, or
+/// `None` if the server does not announce it
+pub(crate) async fn remote_content_length(url: &str) -> Option<u64> {
+    let output = Command::new("curl")
+        .args(["-sfIL", url])
+        .args(curl_user_agent_args())
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // with redirects there is one header block per response, the last one counts
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<u64>().ok())
+                .flatten()
+        })
+        .next_back()
+}
+
+/// The rustup toolchain `call-cg4rs` is run with, configured by `RUST_TOOLCHAIN`
+/// (unset: whatever toolchain is active in the crate directory)
+pub(crate) fn rust_toolchain() -> Option<String> {
+    crate::config::var("RUST_TOOLCHAIN")
+        .ok()
+        .filter(|t| !t.is_empty())
+}
+
+/// Check that `toolchain` is installed, return its `rustc --version`
+pub(crate) async fn check_rust_toolchain(toolchain: &str) -> anyhow::Result<String> {
+    let output = Command::new("rustup")
+        .args(["run", toolchain, "rustc", "--version"])
+        .output()
+        .await
+        .context("Failed to execute rustup")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "RUST_TOOLCHAIN={} is not usable, install it with `rustup toolchain install {}`: {}",
+            toolchain,
+            toolchain,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The first line `program --version` prints, run with `RUST_TOOLCHAIN` if
+/// set like call-cg4rs is. `None` if it cannot be run, fails or prints nothing.
+pub(crate) async fn tool_version(program: &str) -> Option<String> {
+    let mut cmd = Command::new(program);
+    if let Some(toolchain) = rust_toolchain() {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    let output = match cmd.arg("--version").output().await {
+        Ok(output) => output,
+        Err(e) => {
+            tracing::debug!("Failed to execute {} --version: {}", program, e);
+            return None;
+        }
+    };
+    if !output.status.success() {
+        tracing::debug!(
+            "{} --version exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_string())
+}
+
+/// Read the edition and MSRV of a crate from its Cargo.toml, a missing
+/// `edition` means 2015. Published manifests are normalized by cargo, so
+/// neither is ever inherited from a workspace here.
+pub(crate) async fn crate_info(cargo_toml_path: &Path) -> anyhow::Result<CrateInfo> {
+    let content = tokio_fs::read_to_string(cargo_toml_path)
+        .await
+        .with_context(|| format!("Failed to read {}", cargo_toml_path.display()))?;
+    let doc = content
+        .parse::<DocumentMut>()
+        .with_context(|| format!("Failed to parse {}", cargo_toml_path.display()))?;
+    let package = doc.get("package");
+    Ok(CrateInfo {
+        edition: package
+            .and_then(|p| p.get("edition"))
+            .and_then(|e| e.as_str())
+            .unwrap_or("2015")
+            .to_string(),
+        rust_version: package
+            .and_then(|p| p.get("rust-version"))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string()),
+    })
+}
+
+/// Read the edition of a crate from its Cargo.toml, see [`crate_info`]
+pub(crate) async fn crate_edition(cargo_toml_path: &Path) -> anyhow::Result<String> {
+    Ok(crate_info(cargo_toml_path).await?.edition)
+}
+
+/// The directory holding the sources of the package in `package_dir`:
+/// `src` if it contains any file, otherwise the directory of the `[lib]`
+/// target path (then of the first `[[bin]]` path) declared in Cargo.toml,
+/// e.g. `lib` for `path = "lib/foo.rs"`. `None` if no source can be found.
+pub(crate) async fn resolve_src_dir(package_dir: &Path) -> Option<PathBuf> {
+    let src_dir = package_dir.join("src");
+    if let Ok(mut entries) = tokio_fs::read_dir(&src_dir).await
+        && entries.next_entry().await.ok().flatten().is_some()
+    {
+        return Some(src_dir);
+    }
+
+    let content = tokio_fs::read_to_string(package_dir.join("Cargo.toml"))
+        .await
+        .ok()?;
+    let doc = content.parse::<DocumentMut>().ok()?;
+    let lib_path = doc
+        .get("lib")
+        .and_then(|lib| lib.get("path"))
+        .and_then(|p| p.as_str());
+    let bin_paths = doc
+        .get("bin")
+        .and_then(|bins| bins.as_array_of_tables())
+        .into_iter()
+        .flat_map(|bins| bins.iter())
+        .filter_map(|bin| bin.get("path").and_then(|p| p.as_str()));
+    lib_path
+        .into_iter()
+        .chain(bin_paths)
+        .map(|path| package_dir.join(path))
+        .find(|path| path.is_file())
+        .and_then(|path| path.parent().map(|dir| dir.to_path_buf()))
+}
+
+/// If `crate_dir` holds a virtual workspace manifest (`[workspace]` without
+/// `[package]`), return the directory of the member package named `crate_name`.
+/// Members are found by scanning up to three levels of subdirectories, which
+/// covers both listed paths and globs like `crates/*`.
+/// Return `None` for a normal package or when no member matches.
+pub(crate) async fn resolve_workspace_member(
+    crate_dir: &Path,
+    crate_name: &str,
+) -> Option<PathBuf> {
+    let manifest = |dir: &Path| {
+        let path = dir.join("Cargo.toml");
+        async move {
+            tokio_fs::read_to_string(&path)
+                .await
+                .ok()
+                .and_then(|content| content.parse::<DocumentMut>().ok())
+        }
+    };
+
+    let root = manifest(crate_dir).await?;
+    if root.contains_key("package") || !root.contains_key("workspace") {
+        return None;
+    }
+
+    let mut queue = VecDeque::from([(crate_dir.to_path_buf(), 0)]);
+    while let Some((dir, depth)) = queue.pop_front() {
+        let Ok(mut entries) = tokio_fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !path.is_dir() || name.starts_with('.') || name == "target" || name == "vendor" {
+                continue;
+            }
+            if let Some(doc) = manifest(&path).await
+                && doc
+                    .get("package")
+                    .and_then(|p| p.get("name"))
+                    .and_then(|n| n.as_str())
+                    == Some(crate_name)
+            {
+                tracing::info!(
+                    "{} is a virtual workspace, use member {}",
+                    crate_dir.display(),
+                    path.display()
+                );
+                return Some(path);
+            }
+            if depth + 1 < 3 {
+                queue.push_back((path, depth + 1));
+            }
+        }
+    }
+    tracing::warn!(
+        "{} is a virtual workspace without a member named {}",
+        crate_dir.display(),
+        crate_name
+    );
+    None
+}
+
+/// whether direct dependents whose requirement now resolves to a fixed version
+/// are dropped, controlled by `RESOLVE_LATEST=1`
+pub(crate) fn resolve_latest_enabled() -> bool {
+    crate::config::var("RESOLVE_LATEST")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// whether cargo must not touch the network, controlled by `OFFLINE_BUILD=1`;
+/// passes `--offline` and `CARGO_NET_OFFLINE=true` to every cargo invocation that resolves dependencies
+pub(crate) fn offline_build_enabled() -> bool {
+    crate::config::var("OFFLINE_BUILD")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// whether the extract directory of a crate under DOWNLOAD_DIR is removed once it
+/// has been copied into the working directory, controlled by `PRUNE_EXTRACT_AFTER_COPY=1`.
+/// The downloaded `.crate` archive is kept, so the crate is re-extracted rather
+/// than re-downloaded when it is needed again.
+pub(crate) fn prune_extract_after_copy_enabled() -> bool {
+    crate::config::var("PRUNE_EXTRACT_AFTER_COPY")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// whether dependency resolution should be frozen with a lockfile, controlled by `LOCK_DEPS=1`
+pub(crate) fn lock_deps_enabled() -> bool {
+    crate::config::var("LOCK_DEPS")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// Add `features` to every entry of `dep_name` in the normal dependency tables
+/// of `crate_dir/Cargo.toml` (`[dependencies]` and `[target.*.dependencies]`,
+/// renamed entries included), keeping the features already listed. A `"1.0"`
+/// shorthand entry is turned into `{ version = "1.0", features = [...] }`.
+/// Return the number of entries changed.
+pub(crate) async fn enable_dep_features(
+    crate_dir: &Path,
+    dep_name: &str,
+    features: &[String],
+) -> anyhow::Result<usize> {
+    let cargo_toml_path = crate_dir.join("Cargo.toml");
+    let content = tokio_fs::read_to_string(&cargo_toml_path)
+        .await
+        .with_context(|| format!("Failed to read {}", cargo_toml_path.display()))?;
+    let mut doc = content
+        .parse::<DocumentMut>()
+        .context("Failed to parse Cargo.toml")?;
+
+    let mut tables: Vec<&mut toml_edit::Item> = Vec::new();
+    let (root, targets) = {
+        let table = doc.as_table_mut();
+        let mut root = None;
+        let mut targets = None;
+        for (key, item) in table.iter_mut() {
+            match key.get() {
+                "dependencies" => root = Some(item),
+                "target" => targets = Some(item),
+                _ => {}
+            }
+        }
+        (root, targets)
+    };
+    tables.extend(root);
+    if let Some(targets) = targets.and_then(|t| t.as_table_like_mut()) {
+        for (_, target) in targets.iter_mut() {
+            if let Some(deps) = target
+                .as_table_like_mut()
+                .and_then(|t| t.get_mut("dependencies"))
+            {
+                tables.push(deps);
+            }
+        }
+    }
+
+    let mut changed = 0;
+    for deps in tables {
+        let Some(deps) = deps.as_table_like_mut() else {
+            continue;
+        };
+        for (key, entry) in deps.iter_mut() {
+            let package = entry
+                .as_table_like()
+                .and_then(|t| t.get("package"))
+                .and_then(|p| p.as_str());
+            if key.get() != dep_name && package != Some(dep_name) {
+                continue;
+            }
+            // `dep = "1.0"` -> `dep = { version = "1.0" }`
+            if let Some(version) = entry.as_str().map(|v| v.to_string()) {
+                let mut inline = toml_edit::InlineTable::new();
+                inline.insert("version", version.into());
+                *entry = toml_edit::Item::Value(toml_edit::Value::InlineTable(inline));
+            }
+            let Some(table) = entry.as_table_like_mut() else {
+                continue;
+            };
+            let mut list = table
+                .get("features")
+                .and_then(|f| f.as_array())
+                .cloned()
+                .unwrap_or_default();
+            for feature in features {
+                if !list.iter().any(|f| f.as_str() == Some(feature.as_str())) {
+                    list.push(feature.as_str());
+                }
+            }
+            table.insert("features", toml_edit::value(list));
+            if let Some(inline) = entry.as_inline_table_mut() {
+                inline.fmt();
+            }
+            changed += 1;
+        }
+    }
+
+    tokio_fs::write(&cargo_toml_path, doc.to_string())
+        .await
+        .context("Failed to write back Cargo.toml with the root features")?;
+    Ok(changed)
+}
+
+/// whether absolute paths in the result files are rewritten relative to the
+/// crate root, controlled by `RELATIVE_PATHS=1`
+pub(crate) fn relative_paths_enabled() -> bool {
+    crate::config::var("RELATIVE_PATHS")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// Resolve the dependency tree of the (already patched) crate once with
+/// `cargo generate-lockfile`, so that the following build runs `--locked`
+/// against it. Return the path of the generated `Cargo.lock`.
+pub(crate) async fn generate_lockfile(crate_dir: &Path) -> anyhow::Result<PathBuf> {
+    let cargo_toml_path = crate_dir.join("Cargo.toml");
+    let mut cmd = Command::new("cargo");
+    cmd.args([
+        "generate-lockfile",
+        "--manifest-path",
+        &cargo_toml_path.to_string_lossy(),
+    ]);
+    if offline_build_enabled() {
+        cmd.arg("--offline").env("CARGO_NET_OFFLINE", "true");
+    }
+    let output = cmd
+        .output()
+        .await
+        .context("Failed to execute cargo generate-lockfile")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!(
+            "cargo generate-lockfile failed in {}: {}",
+            crate_dir.display(),
+            stderr
+        ));
+    }
+    Ok(crate_dir.join("Cargo.lock"))
+}
+
 pub async fn copy_dir(from: &Path, to: &Path, overwrite: bool) -> anyhow::Result<()> {
     let from_path = from.to_path_buf();
     let to_path = to.to_path_buf();
@@ -525,3 +1344,927 @@ async fn validate_copied_files(from: &Path, to: &Path) -> bool {
 
     true
 }
+
+/// whether result files should be gzip-compressed on write, controlled by `COMPRESS_RESULTS=1`
+pub(crate) fn compress_results_enabled() -> bool {
+    crate::config::var("COMPRESS_RESULTS")
+        .map(|v| v == "1")
+        .unwrap_or(false)
+}
+
+/// Write `content` to a hidden temporary file next to `path`, then rename it
+/// over `path`, so readers never see a partially written file. A crash before
+/// the rename leaves only the temporary file, which no loader picks up.
+pub(crate) async fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    static TMP_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("{} is not a file path", path.display()))?;
+    let tmp_path = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    ));
+    tokio_fs::write(&tmp_path, content)
+        .await
+        .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+    if let Err(e) = tokio_fs::rename(&tmp_path, path).await {
+        let _ = tokio_fs::remove_file(&tmp_path).await;
+        return Err(e).with_context(|| format!("Failed to rename into {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Write a result file atomically. When `COMPRESS_RESULTS=1`, the content is
+/// gzip-compressed and `.gz` is appended to the file name. Return the path actually written.
+pub(crate) async fn write_result_file(path: &Path, content: &str) -> anyhow::Result<PathBuf> {
+    write_result_file_as(path, content, compress_results_enabled()).await
+}
+
+/// [`write_result_file`], compressed or not whatever `COMPRESS_RESULTS` is
+pub(crate) async fn write_result_file_as(
+    path: &Path,
+    content: &str,
+    compress: bool,
+) -> anyhow::Result<PathBuf> {
+    if !compress {
+        write_atomic(path, content).await?;
+        return Ok(path.to_path_buf());
+    }
+
+    let mut gz_path = path.as_os_str().to_owned();
+    gz_path.push(".gz");
+    let gz_path = PathBuf::from(gz_path);
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content.as_bytes())?;
+    let compressed = encoder.finish()?;
+    write_atomic(&gz_path, compressed).await?;
+    Ok(gz_path)
+}
+
+/// Write the callgraph files of a result like [`write_result_file`], merging
+/// them into the result file already at `path` (compressed or not) if there
+/// is one: the caller sets are unioned, see [`merge_callers`]. Return the
+/// path written and, if an existing file was merged, the number of callers added to it.
+///
+/// [`merge_callers`]: crate::callgraph::merge_callers
+pub(crate) async fn write_merged_result_file(
+    path: &Path,
+    files: Vec<CallgraphFile>,
+) -> anyhow::Result<(PathBuf, Option<usize>)> {
+    write_merged_result_file_as(path, files, compress_results_enabled()).await
+}
+
+/// [`write_merged_result_file`], compressed or not whatever `COMPRESS_RESULTS` is
+async fn write_merged_result_file_as(
+    path: &Path,
+    mut files: Vec<CallgraphFile>,
+    compress: bool,
+) -> anyhow::Result<(PathBuf, Option<usize>)> {
+    let mut gz_path = path.as_os_str().to_owned();
+    gz_path.push(".gz");
+    let existing_paths = [path.to_path_buf(), PathBuf::from(gz_path)]
+        .into_iter()
+        .filter(|p| p.exists())
+        .collect::<Vec<_>>();
+    let mut added = None;
+    for existing_path in &existing_paths {
+        let mut existing: Vec<CallgraphFile> =
+            serde_json::from_str(&read_result_file(existing_path).await?)
+                .with_context(|| format!("Failed to parse {}", existing_path.display()))?;
+        let count = crate::callgraph::merge_callers(&mut existing, files);
+        files = existing;
+        *added.get_or_insert(0) += count;
+    }
+    let written =
+        write_result_file_as(path, &serde_json::to_string_pretty(&files)?, compress).await?;
+    // the other form would be read as a second result of the subject
+    for existing_path in existing_paths.iter().filter(|p| **p != written) {
+        tokio_fs::remove_file(existing_path)
+            .await
+            .with_context(|| format!("Failed to remove {}", existing_path.display()))?;
+    }
+    Ok((written, added))
+}
+
+/// Compression of a crate archive. crates.io serves gzip'd tarballs, but
+/// private registries sometimes serve zstd or uncompressed ones, so the
+/// format is detected from the magic number rather than the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArchiveCompression {
+    Gzip,
+    Zstd,
+    Plain,
+}
+
+impl ArchiveCompression {
+    pub(crate) fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else if bytes.get(257..262) == Some(b"ustar".as_slice()) {
+            Some(Self::Plain)
+        } else {
+            None
+        }
+    }
+
+    /// wrap `bytes` in a reader yielding the uncompressed tarball
+    fn decoder<'a>(self, bytes: &'a [u8]) -> std::io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::Gzip => Box::new(GzDecoder::new(bytes)),
+            Self::Zstd => Box::new(
+                ruzstd::decoding::StreamingDecoder::new(bytes).map_err(std::io::Error::other)?,
+            ),
+            Self::Plain => Box::new(bytes),
+        })
+    }
+}
+
+/// Read a crate archive and detect its compression, failing on unknown formats.
+async fn read_crate_archive(path: &Path) -> anyhow::Result<(Vec<u8>, ArchiveCompression)> {
+    let bytes = tokio_fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let compression = ArchiveCompression::detect(&bytes).with_context(|| {
+        format!(
+            "{} is neither a gzip, zstd nor plain tar archive",
+            path.display()
+        )
+    })?;
+    Ok((bytes, compression))
+}
+
+/// Check that a downloaded `.crate` is complete: compare its SHA-256 with
+/// `expected_cksum` from the registry index if known, and decompress it fully
+/// so a truncated or corrupted file fails the checksum of the compression.
+pub(crate) async fn verify_crate_archive(
+    path: &Path,
+    expected_cksum: Option<&str>,
+) -> anyhow::Result<()> {
+    if let Some(expected) = expected_cksum {
+        let bytes = tokio_fs::read(path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let actual = sha256_hex(&bytes);
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(anyhow::anyhow!(
+                "{} has SHA-256 {}, the index expects {}",
+                path.display(),
+                actual,
+                expected
+            ));
+        }
+    }
+    let (bytes, compression) = read_crate_archive(path).await?;
+    tokio::task::spawn_blocking(move || {
+        std::io::copy(&mut compression.decoder(&bytes)?, &mut std::io::sink()).map(|_| ())
+    })
+    .await?
+    .with_context(|| {
+        format!(
+            "{} is not a valid {:?} archive",
+            path.display(),
+            compression
+        )
+    })
+}
+
+/// the lowercase hex SHA-256 of `bytes`, the `cksum` format of the registry index
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// The root of the sparse registry index the checksums of the downloaded
+/// crates are read from, configured by `CRATE_INDEX_URL` (empty: no checksum
+/// check). Defaults to the crates.io index, unless `CRATE_DOWNLOAD_URL`
+/// points to another registry.
+pub(crate) fn crate_index_url() -> Option<String> {
+    match crate::config::var("CRATE_INDEX_URL") {
+        Ok(url) => Some(url).filter(|url| !url.is_empty()),
+        Err(_) if crate::config::var("CRATE_DOWNLOAD_URL").is_ok() => None,
+        Err(_) => Some("https://index.crates.io".to_string()),
+    }
+}
+
+/// the path of a crate in a sparse index, e.g. `3/s/syn` or `se/rd/serde`
+pub(crate) fn sparse_index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+/// the `cksum` of `version` in the index entry of a crate, one JSON object per version
+pub(crate) fn index_cksum(entry: &str, version: &str) -> Option<String> {
+    entry
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|v| v.get("vers").and_then(|v| v.as_str()) == Some(version))
+        .and_then(|v| v.get("cksum").and_then(|c| c.as_str()).map(str::to_string))
+}
+
+/// The SHA-256 the registry index records for `name`-`version`, `None` when
+/// there is no index to ask or it does not answer, then only the archive
+/// itself is checked
+pub(crate) async fn expected_crate_cksum(name: &str, version: &str) -> Option<String> {
+    let index = crate_index_url()?;
+    let url = format!(
+        "{}/{}",
+        index.trim_end_matches('/'),
+        sparse_index_path(name)
+    );
+    let output = Command::new("curl")
+        .args(["-sfL", &url])
+        .args(curl_user_agent_args())
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        tracing::warn!(
+            "Failed to fetch the index entry {}, the checksum of {}-{} is not checked",
+            url,
+            name,
+            version
+        );
+        return None;
+    }
+    index_cksum(&String::from_utf8_lossy(&output.stdout), version)
+}
+
+/// Unpack a crate archive into `dest`, whatever its compression.
+pub(crate) async fn extract_crate_archive(archive: &Path, dest: &Path) -> anyhow::Result<()> {
+    let (bytes, compression) = read_crate_archive(archive).await?;
+    let dest_owned = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        tar::Archive::new(compression.decoder(&bytes)?).unpack(&dest_owned)
+    })
+    .await?
+    .with_context(|| {
+        format!(
+            "Failed extracting {} ({:?}) into {}",
+            archive.display(),
+            compression,
+            dest.display()
+        )
+    })
+}
+
+/// Read a result file, transparently decompressing it if the name ends with `.gz`.
+pub(crate) async fn read_result_file(path: &Path) -> anyhow::Result<String> {
+    let bytes = tokio_fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        let mut content = String::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut content)
+            .with_context(|| format!("Failed to decompress {}", path.display()))?;
+        Ok(content)
+    } else {
+        String::from_utf8(bytes).with_context(|| format!("{} is not valid UTF-8", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn result_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = "[{\"file\": \"callers-foo.json\", \"file-content\": {}}]";
+        for compress in [false, true] {
+            let path = dir.path().join("foo-1.0.0.txt");
+            let written = write_result_file_as(&path, content, compress)
+                .await
+                .unwrap();
+            assert_eq!(written.extension().is_some_and(|ext| ext == "gz"), compress);
+            assert_eq!(read_result_file(&written).await.unwrap(), content);
+        }
+        // no temporary file is left next to the results
+        let mut names = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["foo-1.0.0.txt", "foo-1.0.0.txt.gz"]);
+    }
+
+    fn dependent(req: &str) -> ReverseDependency {
+        ReverseDependency::new(
+            "dependent".to_string(),
+            "1.0.0".to_string(),
+            req.to_string(),
+            DEPENDENCY_KIND_NORMAL,
+        )
+    }
+
+    fn published(versions: &[&str]) -> Vec<String> {
+        versions.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn vulnerable_range_admits_overlapping_requirements() {
+        let range = VulnerableRange::new(
+            "<0.41.0",
+            &published(&["0.1.0", "0.40.0", "0.40.1", "0.41.0", "0.42.0"]),
+        )
+        .unwrap();
+        assert!(range.admits(&dependent("^0.40")));
+        assert!(range.admits(&dependent("0.1")));
+        assert!(range.admits(&dependent(">=0.40.1, <0.42")));
+        assert!(!range.admits(&dependent("^0.41")));
+        assert!(!range.admits(&dependent("=0.42.0")));
+        // an unparsable requirement is never followed
+        assert!(!range.admits(&dependent("not a req")));
+    }
+
+    #[test]
+    fn latest_resolution_follows_the_newest_matching_version() {
+        let range = VulnerableRange::new(
+            "<0.40.1",
+            &published(&["0.39.0", "0.40.0", "0.40.1", "0.41.0"]),
+        )
+        .unwrap();
+        // `^0.40` resolves to the fixed 0.40.1 today
+        assert!(range.admits(&dependent("^0.40")));
+        assert!(!range.latest_resolution_vulnerable(&dependent("^0.40")));
+        // pinned to a vulnerable version
+        assert!(range.latest_resolution_vulnerable(&dependent("=0.40.0")));
+        assert!(range.latest_resolution_vulnerable(&dependent("^0.39")));
+        // nothing published matches
+        assert!(!range.latest_resolution_vulnerable(&dependent("^1")));
+    }
+
+    #[test]
+    fn sample_bfs_level_is_capped_and_deterministic() {
+        let level = (0..20)
+            .map(|i| format!("crate{}-1.0.0", i))
+            .collect::<Vec<_>>();
+        let sampled = sample_bfs_level(level.clone(), 5, 42, |k| k.clone());
+        assert_eq!(sampled.len(), 5);
+        // the discovery order does not matter
+        let mut reversed = level.clone();
+        reversed.reverse();
+        assert_eq!(sample_bfs_level(reversed, 5, 42, |k| k.clone()), sampled);
+        // another seed selects other nodes
+        assert_ne!(
+            sample_bfs_level(level.clone(), 5, 7, |k| k.clone()),
+            sampled
+        );
+        // a level under the cap is kept whole
+        assert_eq!(
+            sample_bfs_level(level.clone(), 50, 42, |k| k.clone()).len(),
+            20
+        );
+    }
+
+    #[tokio::test]
+    async fn streamed_and_loaded_dependents_are_grouped_alike() {
+        let dir = tempfile::tempdir().unwrap();
+        let dependents_file = dir.path().join("reverse-deps.json");
+        let versions_file = dir.path().join("versions.json");
+        std::fs::write(
+            &dependents_file,
+            r#"{"vuln": [
+                {"name": "a", "version": "1.0.0", "req": "^1"},
+                {"name": "b", "version": "0.1.0", "req": "^1.0"},
+                {"name": "a", "version": "1.1.0", "req": "^1.2"},
+                {"name": "c", "version": "2.0.0", "req": "^2"},
+                {"name": "b", "version": "0.2.0", "req": "=1.0.0", "kind": "build"},
+                {"name": "a", "version": "1.2.0", "req": ">=1.0, <2"}
+            ]}"#,
+        )
+        .unwrap();
+        std::fs::write(&versions_file, r#"{"vuln": ["1.0.0"]}"#).unwrap();
+        let source = crate::metadata::FileMetadataSource::load(&dependents_file, &versions_file)
+            .await
+            .unwrap();
+        let version = Version::parse("1.0.0").unwrap();
+        let kinds = ALL_DEPENDENCY_KINDS;
+
+        let streamed = group_dependents(
+            source.stream_dependents("vuln", None, &kinds),
+            &version,
+            None,
+        )
+        .await
+        .unwrap();
+        let loaded = source.query_dependents("vuln", None, &kinds).await.unwrap();
+        let loaded = group_dependents(
+            futures_stream::iter(loaded.into_iter().map(Ok)).boxed(),
+            &version,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(streamed, loaded);
+
+        let grouped = |name: &str| {
+            streamed[name]
+                .iter()
+                .map(|revdep| revdep.version.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(grouped("a"), ["1.0.0", "1.2.0"]);
+        assert_eq!(grouped("b"), ["0.1.0", "0.2.0"]);
+        // `^2` and `^1.2` cannot use 1.0.0
+        assert!(!streamed.contains_key("c"));
+    }
+
+    #[tokio::test]
+    async fn build_only_dependents_are_not_followed_by_default() {
+        assert_eq!(dependency_kinds_as(None), [DEPENDENCY_KIND_NORMAL]);
+        assert_eq!(
+            dependency_kinds_as(Some("normal, build")),
+            [DEPENDENCY_KIND_NORMAL, DEPENDENCY_KIND_BUILD]
+        );
+        assert_eq!(dependency_kinds_as(Some("all")), ALL_DEPENDENCY_KINDS);
+        // nothing usable falls back to the default
+        assert_eq!(
+            dependency_kinds_as(Some("runtime")),
+            [DEPENDENCY_KIND_NORMAL]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let dependents_file = dir.path().join("reverse-deps.json");
+        let versions_file = dir.path().join("versions.json");
+        std::fs::write(
+            &dependents_file,
+            r#"{"vuln": [
+                {"name": "app", "version": "1.0.0", "req": "^1"},
+                {"name": "codegen", "version": "0.1.0", "req": "^1", "kind": "build"},
+                {"name": "bench", "version": "0.1.0", "req": "^1", "kind": "dev"}
+            ]}"#,
+        )
+        .unwrap();
+        std::fs::write(&versions_file, r#"{"vuln": ["1.0.0"]}"#).unwrap();
+        let source = crate::metadata::FileMetadataSource::load(&dependents_file, &versions_file)
+            .await
+            .unwrap();
+        let dependents = get_reverse_deps(&source, "vuln", "1.0.0", None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|revdep| revdep.name)
+            .collect::<Vec<_>>();
+        assert_eq!(dependents, ["app"]);
+    }
+
+    fn versions(versions: &[&str]) -> Vec<Version> {
+        versions
+            .iter()
+            .map(|v| Version::parse(v).unwrap())
+            .collect()
+    }
+
+    /// the `index:version` of the selected ends
+    async fn ends(
+        listed: &[&str],
+        endpoints: VersionEndpoints,
+    ) -> (Option<String>, Option<String>) {
+        let (oldest, newest) = select_oldest_and_newest_versions(versions(listed), endpoints).await;
+        let show = |end: Option<(usize, Version)>| end.map(|(i, v)| format!("{}:{}", i, v));
+        (show(oldest), show(newest))
+    }
+
+    #[tokio::test]
+    async fn version_endpoints_select_the_requested_ends() {
+        let listed = ["0.3.0", "0.1.0", "0.2.0"];
+        let oldest = Some("1:0.1.0".to_string());
+        let newest = Some("0:0.3.0".to_string());
+        assert_eq!(
+            ends(&listed, VersionEndpoints::Both).await,
+            (oldest.clone(), newest.clone())
+        );
+        assert_eq!(
+            ends(&listed, VersionEndpoints::Oldest).await,
+            (oldest, None)
+        );
+        assert_eq!(
+            ends(&listed, VersionEndpoints::Newest).await,
+            (None, newest)
+        );
+        // a single version is analyzed once
+        assert_eq!(
+            ends(&["1.0.0"], VersionEndpoints::Both).await,
+            (Some("0:1.0.0".to_string()), None)
+        );
+        assert_eq!(ends(&[], VersionEndpoints::Both).await, (None, None));
+    }
+
+    #[test]
+    fn exact_version_only_for_a_full_exact_requirement() {
+        assert_eq!(
+            exact_version("=0.40.3"),
+            Some(Version::parse("0.40.3").unwrap())
+        );
+        assert_eq!(
+            exact_version("=1.0.0-rc.1"),
+            Some(Version::parse("1.0.0-rc.1").unwrap())
+        );
+        assert_eq!(exact_version("=0.40"), None);
+        assert_eq!(exact_version("<0.41.0"), None);
+        assert_eq!(exact_version(">=0.40.0, <0.41.0"), None);
+    }
+
+    #[tokio::test]
+    async fn select_two_end_vers_analyzes_only_an_exact_version() {
+        let listed = published(&["0.41.0", "0.40.3", "0.40.2", "0.1.0"]);
+        let selected = select_two_end_vers(listed.clone(), "=0.40.3").await;
+        assert_eq!(selected, vec![(0, Version::parse("0.40.3").unwrap())]);
+        assert!(select_two_end_vers(listed, "=0.40.4").await.is_empty());
+    }
+
+    #[test]
+    fn normalize_version_and_split_subject() {
+        assert_eq!(
+            normalize_version("1.2.3-rc.1+build.5"),
+            "1.2.3-rc.1+build.5"
+        );
+        assert_eq!(normalize_version("not-semver"), "not-semver");
+        assert_eq!(split_subject("foo-1.0.0"), Some(("foo", "1.0.0")));
+        assert_eq!(
+            split_subject("foo-bar-1.0.0-rc.1"),
+            Some(("foo-bar", "1.0.0-rc.1"))
+        );
+        assert_eq!(
+            split_subject("baz-0.3.4+build.7"),
+            Some(("baz", "0.3.4+build.7"))
+        );
+        assert_eq!(
+            split_subject("__self__-0.40.0"),
+            Some(("__self__", "0.40.0"))
+        );
+        assert_eq!(split_subject("no-version"), None);
+        // a subject built from a name and a normalized version splits back into them
+        let version = normalize_version("0.40.0-alpha.2");
+        let subject = format!("gix-features-{}", version);
+        assert_eq!(
+            split_subject(&subject),
+            Some(("gix-features", version.as_str()))
+        );
+    }
+
+    #[test]
+    fn resolve_req_picks_the_newest_matching_version() {
+        let published = versions(&["0.1.0", "0.40.0", "0.40.2", "0.41.0", "1.0.0-rc.1"]);
+        let resolved = |req: &str| resolve_req(req, &published).map(|v| v.to_string());
+        assert_eq!(resolved("^0.40").as_deref(), Some("0.40.2"));
+        assert_eq!(resolved(">=0.40, <1").as_deref(), Some("0.41.0"));
+        assert_eq!(resolved("=0.40.0").as_deref(), Some("0.40.0"));
+        // pre-releases only match a requirement naming one
+        assert_eq!(resolved(">=0.41").as_deref(), Some("0.41.0"));
+        assert_eq!(resolved("^2").as_deref(), None);
+        assert_eq!(resolved("not a req").as_deref(), None);
+    }
+
+    /// a tarball holding `foo-1.0.0/Cargo.toml`
+    fn crate_tarball() -> Vec<u8> {
+        let content = b"[package]\nname = \"foo\"\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder
+            .append_data(&mut header, "foo-1.0.0/Cargo.toml", content.as_slice())
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn archives_are_detected_verified_and_extracted_by_magic_bytes() {
+        let tarball = crate_tarball();
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&tarball).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let zstd = ruzstd::encoding::compress_to_vec(
+            tarball.as_slice(),
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        for (bytes, compression) in [
+            (&gzip, ArchiveCompression::Gzip),
+            (&zstd, ArchiveCompression::Zstd),
+            (&tarball, ArchiveCompression::Plain),
+        ] {
+            assert_eq!(ArchiveCompression::detect(bytes), Some(compression));
+            // the extension says nothing about the format
+            let archive = dir.path().join(format!("{:?}.crate", compression));
+            std::fs::write(&archive, bytes).unwrap();
+            verify_crate_archive(&archive, Some(&sha256_hex(bytes)))
+                .await
+                .unwrap();
+            let dest = dir.path().join(format!("{:?}", compression));
+            extract_crate_archive(&archive, &dest).await.unwrap();
+            assert!(dest.join("foo-1.0.0/Cargo.toml").is_file());
+        }
+
+        assert_eq!(ArchiveCompression::detect(b"<html>not found</html>"), None);
+        let truncated = dir.path().join("truncated.crate");
+        std::fs::write(&truncated, &gzip[..gzip.len() / 2]).unwrap();
+        assert!(verify_crate_archive(&truncated, None).await.is_err());
+        // a valid archive that is not the one the index describes
+        let gzip_archive = dir.path().join("Gzip.crate");
+        assert!(
+            verify_crate_archive(&gzip_archive, Some(&sha256_hex(&zstd)))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn the_sparse_index_gives_the_checksum_of_a_version() {
+        assert_eq!(sparse_index_path("a"), "1/a");
+        assert_eq!(sparse_index_path("cc"), "2/cc");
+        assert_eq!(sparse_index_path("syn"), "3/s/syn");
+        assert_eq!(sparse_index_path("Serde_JSON"), "se/rd/serde_json");
+        let entry = concat!(
+            r#"{"name":"foo","vers":"1.0.0","deps":[],"cksum":"aa11","features":{},"yanked":false}"#,
+            "\n",
+            r#"{"name":"foo","vers":"1.0.1","deps":[],"cksum":"bb22","features":{},"yanked":true}"#,
+            "\n",
+        );
+        assert_eq!(index_cksum(entry, "1.0.1"), Some("bb22".to_string()));
+        assert_eq!(index_cksum(entry, "2.0.0"), None);
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    fn cargo_toml(dir: &Path, content: &str) -> PathBuf {
+        let path = dir.join("Cargo.toml");
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn crate_edition_defaults_to_2015() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = cargo_toml(
+            dir.path(),
+            "[package]\nname = \"foo\"\nedition = \"2021\"\n",
+        );
+        assert_eq!(crate_edition(&path).await.unwrap(), "2021");
+        let path = cargo_toml(dir.path(), "[package]\nname = \"foo\"\n");
+        assert_eq!(crate_edition(&path).await.unwrap(), "2015");
+        let path = cargo_toml(dir.path(), "[package\n");
+        assert!(crate_edition(&path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn crate_info_reads_the_msrv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = cargo_toml(
+            dir.path(),
+            "[package]\nname = \"foo\"\nedition = \"2024\"\nrust-version = \"1.85\"\n",
+        );
+        assert_eq!(
+            crate_info(&path).await.unwrap(),
+            CrateInfo {
+                edition: "2024".to_string(),
+                rust_version: Some("1.85".to_string()),
+            }
+        );
+        let path = cargo_toml(dir.path(), "[package]\nname = \"foo\"\n");
+        assert_eq!(
+            crate_info(&path).await.unwrap(),
+            CrateInfo {
+                edition: "2015".to_string(),
+                rust_version: None,
+            }
+        );
+    }
+
+    fn callgraph_file(file: &str, caller_paths: &[&str]) -> CallgraphFile {
+        CallgraphFile {
+            file: file.to_string(),
+            file_content: serde_json::json!({
+                "target": file.trim_start_matches("callers-").trim_end_matches(".json"),
+                "callers": caller_paths
+                    .iter()
+                    .map(|path| serde_json::json!({ "path": path, "path_constraints": 1 }))
+                    .collect::<Vec<_>>(),
+            }),
+        }
+    }
+
+    /// the caller paths of each callgraph file of a result file
+    async fn caller_paths(path: &Path) -> Vec<(String, Vec<String>)> {
+        let files: Vec<CallgraphFile> =
+            serde_json::from_str(&read_result_file(path).await.unwrap()).unwrap();
+        files
+            .into_iter()
+            .map(|f| {
+                let paths = f.file_content["callers"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|c| c["path"].as_str().unwrap().to_string())
+                    .collect();
+                (f.file, paths)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn writing_a_subject_twice_merges_the_callers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dependent-1.0.0.txt");
+        let first = vec![callgraph_file("callers-a.json", &["x::f", "x::g"])];
+        let (written, added) = write_merged_result_file_as(&path, first, false)
+            .await
+            .unwrap();
+        assert_eq!((written.as_path(), added), (path.as_path(), None));
+
+        let second = vec![
+            callgraph_file("callers-a.json", &["x::g", "x::h"]),
+            callgraph_file("callers-b.json", &["y::f"]),
+        ];
+        let (written, added) = write_merged_result_file_as(&path, second, false)
+            .await
+            .unwrap();
+        assert_eq!(added, Some(2));
+        let union = vec![
+            (
+                "callers-a.json".to_string(),
+                vec!["x::f".to_string(), "x::g".to_string(), "x::h".to_string()],
+            ),
+            ("callers-b.json".to_string(), vec!["y::f".to_string()]),
+        ];
+        assert_eq!(caller_paths(&written).await, union);
+
+        // a compressed write replaces the plain file it merged
+        let third = vec![callgraph_file("callers-a.json", &["x::f"])];
+        let (written, added) = write_merged_result_file_as(&path, third, true)
+            .await
+            .unwrap();
+        assert_eq!(added, Some(0));
+        assert!(written.extension().is_some_and(|ext| ext == "gz"));
+        assert!(!path.exists());
+        assert_eq!(caller_paths(&written).await, union);
+    }
+
+    #[tokio::test]
+    async fn features_are_enabled_in_the_workspace_member() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .unwrap();
+        let member = root.path().join("crates/foo");
+        std::fs::create_dir_all(&member).unwrap();
+        std::fs::write(
+            member.join("Cargo.toml"),
+            "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n\n[dependencies]\nvuln = \"1.0\"\n",
+        )
+        .unwrap();
+
+        let package_dir = resolve_workspace_member(root.path(), "foo").await.unwrap();
+        assert_eq!(package_dir, member);
+        let features = vec!["unsafe-fast".to_string()];
+        assert_eq!(
+            enable_dep_features(&package_dir, "vuln", &features)
+                .await
+                .unwrap(),
+            1
+        );
+        let manifest = std::fs::read_to_string(member.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("features = [\"unsafe-fast\"]"));
+        assert!(resolve_workspace_member(&member, "foo").await.is_none());
+    }
+
+    #[test]
+    fn only_the_archive_download_sends_the_token() {
+        let headers = |args: &[String]| {
+            args.windows(2)
+                .filter(|w| w[0] == "-H")
+                .map(|w| w[1].clone())
+                .collect::<Vec<_>>()
+        };
+        let download = crate_download_curl_args_as(Some("secret".to_string()));
+        assert_eq!(headers(&download), ["Authorization: secret"]);
+        assert!(download.contains(&"-A".to_string()));
+
+        // the index lookup, the size check and PRECHECK_CRATE_EXISTS
+        let other = curl_user_agent_args();
+        assert!(headers(&other).is_empty());
+        assert_eq!(other, ["-A".to_string(), crates_io_user_agent()]);
+
+        assert!(headers(&crate_download_curl_args_as(None)).is_empty());
+        assert!(headers(&crate_download_curl_args_as(Some(String::new()))).is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_precheck_drops_a_dependent_answered_with_404() {
+        use std::io::{BufRead, BufReader};
+
+        let (sandbox, _guard) = crate::test_support::sandbox().await;
+        let source = sandbox
+            .metadata(
+                "CVE-T668",
+                &[("t668-vuln", &["1.0.0"])],
+                &[(
+                    "t668-vuln",
+                    &[("t668-kept", "1.0.0", "^1"), ("t668-gone", "1.0.0", "^1")],
+                )],
+            )
+            .await;
+        let krate = crate::test_support::krate_at("t668-vuln", &sandbox.root);
+
+        // a registry that lost every `-gone` archive
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                    line.clear();
+                }
+                let status = if request.contains("-gone") {
+                    "404 Not Found"
+                } else {
+                    "200 OK"
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                let _ = stream.write_all(head.as_bytes());
+            }
+        });
+        let url = format!("http://{}/{{crate}}-{{version}}.crate", addr);
+
+        let names = |dependents: Vec<ReverseDependency>| {
+            let mut names: Vec<String> = dependents.into_iter().map(|d| d.name).collect();
+            names.sort();
+            names
+        };
+        let unchecked = get_reverse_deps_for_krate_as(&source, &krate, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(names(unchecked), ["t668-gone", "t668-kept"]);
+        let prechecked = get_reverse_deps_for_krate_as(&source, &krate, None, None, Some(&url))
+            .await
+            .unwrap();
+        assert_eq!(names(prechecked), ["t668-kept"]);
+    }
+
+    #[tokio::test]
+    async fn write_atomic_never_shows_a_partial_file() {
+        const LEN: usize = 4 * 1024 * 1024;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("result.txt");
+        write_atomic(&path, vec![b'a'; LEN]).await.unwrap();
+
+        // a reader polling the file while it is rewritten only ever sees a whole version
+        let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let reader = {
+            let (path, done) = (path.clone(), done.clone());
+            std::thread::spawn(move || {
+                let mut reads = 0;
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    let content = std::fs::read(&path).unwrap();
+                    assert_eq!(content.len(), LEN);
+                    assert!(content.iter().all(|b| *b == content[0]));
+                    reads += 1;
+                }
+                reads
+            })
+        };
+        for i in 0..100u8 {
+            write_atomic(&path, vec![b'a' + i % 2; LEN]).await.unwrap();
+        }
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert!(reader.join().unwrap() > 0);
+
+        // a failed rename removes its temporary file
+        let occupied = dir.path().join("occupied");
+        std::fs::create_dir(&occupied).unwrap();
+        std::fs::write(occupied.join("keep"), "").unwrap();
+        assert!(write_atomic(&occupied, "content").await.is_err());
+
+        let mut names = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["occupied", "result.txt"]);
+    }
+}